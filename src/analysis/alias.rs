@@ -0,0 +1,27 @@
+use crate::ir::function::{Function, ValueDef};
+use crate::ir::inst::{InstKind, Operand};
+
+pub fn is_alloca(function: &Function, operand: &Operand) -> bool {
+    let Operand::Value(value) = operand else {
+        return false;
+    };
+
+    match function.values[value.0].def {
+        ValueDef::Inst(inst) => matches!(function.insts[inst.0].kind, InstKind::Alloca { .. }),
+        _ => false,
+    }
+}
+
+// Only two different allocas are known to be apart, anything else may be the same memory
+pub fn may_alias(function: &Function, a: &Operand, b: &Operand) -> bool {
+    a == b || !(is_alloca(function, a) && is_alloca(function, b))
+}
+
+// Whether the instruction may change the memory behind `ptr`
+pub fn may_write(function: &Function, kind: &InstKind, ptr: &Operand) -> bool {
+    match kind {
+        InstKind::Store { ptr: target, .. } => may_alias(function, target, ptr),
+        InstKind::Call { .. } | InstKind::Syscall { .. } => true,
+        _ => false,
+    }
+}
@@ -0,0 +1,221 @@
+use crate::ir::cfg::ControlFlowGraph;
+use crate::ir::function::{Block, Function};
+
+// Immediate dominators computed with the Cooper-Harvey-Kennedy algorithm, indexed by block id
+#[derive(Debug, Clone, Default)]
+pub struct DomTree {
+    idom: Vec<Option<Block>>,
+    children: Vec<Vec<Block>>,
+    // Position in the reverse postorder, None for unreachable blocks
+    rpo_index: Vec<Option<usize>>,
+    rpo: Vec<Block>,
+    // Preorder and postorder numbers of the tree, they answer `dominates` in constant time
+    pre: Vec<usize>,
+    post: Vec<usize>,
+}
+
+impl DomTree {
+    pub fn new(function: &Function, cfg: &ControlFlowGraph) -> Self {
+        let rpo = cfg.reverse_postorder(function);
+        let mut rpo_index = vec![None; function.blocks.len()];
+
+        for (i, block) in rpo.iter().enumerate() {
+            rpo_index[block.0] = Some(i);
+        }
+
+        let mut idom: Vec<Option<Block>> = vec![None; function.blocks.len()];
+
+        if let Some(entry) = rpo.first() {
+            // The entry is its own dominator while iterating, it is cleared at the end
+            idom[entry.0] = Some(*entry);
+        }
+
+        let mut changed = true;
+
+        while changed {
+            changed = false;
+
+            for block in rpo.iter().skip(1) {
+                let mut new: Option<Block> = None;
+
+                for pred in cfg.preds(*block) {
+                    if idom[pred.0].is_none() {
+                        continue;
+                    }
+
+                    new = Some(match new {
+                        Some(other) => intersect(&idom, &rpo_index, *pred, other),
+                        None => *pred,
+                    });
+                }
+
+                if new.is_some() && idom[block.0] != new {
+                    idom[block.0] = new;
+                    changed = true;
+                }
+            }
+        }
+
+        let mut children = vec![Vec::new(); function.blocks.len()];
+
+        if let Some(entry) = rpo.first() {
+            idom[entry.0] = None;
+        }
+
+        for block in &rpo {
+            if let Some(parent) = idom[block.0] {
+                children[parent.0].push(*block);
+            }
+        }
+
+        let mut tree = Self {
+            idom,
+            children,
+            rpo_index,
+            rpo,
+            pre: vec![0; function.blocks.len()],
+            post: vec![0; function.blocks.len()],
+        };
+
+        tree.number();
+        tree
+    }
+
+    fn number(&mut self) {
+        let Some(entry) = self.rpo.first().copied() else {
+            return;
+        };
+
+        let (mut pre, mut post) = (0, 0);
+        let mut stack = vec![(entry, 0)];
+        self.pre[entry.0] = pre;
+
+        while let Some((block, next)) = stack.pop() {
+            match self.children[block.0].get(next) {
+                Some(child) => {
+                    stack.push((block, next + 1));
+                    pre += 1;
+                    self.pre[child.0] = pre;
+                    stack.push((*child, 0));
+                }
+                None => {
+                    self.post[block.0] = post;
+                    post += 1;
+                }
+            }
+        }
+    }
+
+    pub fn is_reachable(&self, block: Block) -> bool {
+        self.rpo_index[block.0].is_some()
+    }
+
+    // None for the entry and unreachable blocks
+    pub fn idom(&self, block: Block) -> Option<Block> {
+        self.idom[block.0]
+    }
+
+    pub fn children(&self, block: Block) -> &[Block] {
+        &self.children[block.0]
+    }
+
+    // Reachable blocks, each one before its successors except on back edges
+    pub fn reverse_postorder(&self) -> &[Block] {
+        &self.rpo
+    }
+
+    // Reachable blocks, each one after its immediate dominator
+    pub fn preorder(&self) -> Vec<Block> {
+        let mut blocks = self.rpo.clone();
+        blocks.sort_by_key(|block| self.pre[block.0]);
+
+        blocks
+    }
+
+    // Every block dominates itself, anything dominates unreachable code
+    pub fn dominates(&self, a: Block, b: Block) -> bool {
+        if !self.is_reachable(b) {
+            return true;
+        }
+
+        if !self.is_reachable(a) {
+            return false;
+        }
+
+        self.pre[a.0] <= self.pre[b.0] && self.post[b.0] <= self.post[a.0]
+    }
+
+    pub fn strictly_dominates(&self, a: Block, b: Block) -> bool {
+        a != b && self.dominates(a, b)
+    }
+}
+
+// Walks up from both blocks until they meet at their closest common dominator
+fn intersect(
+    idom: &[Option<Block>],
+    rpo_index: &[Option<usize>],
+    mut a: Block,
+    mut b: Block,
+) -> Block {
+    let index = |block: Block| rpo_index[block.0].expect("processed blocks are reachable");
+
+    while a != b {
+        while index(a) > index(b) {
+            a = idom[a.0].expect("processed blocks have a dominator");
+        }
+
+        while index(b) > index(a) {
+            b = idom[b.0].expect("processed blocks have a dominator");
+        }
+    }
+
+    a
+}
+
+// Blocks where the dominance of each block ends, where SSA construction places the phis
+#[derive(Debug, Clone, Default)]
+pub struct DominanceFrontiers {
+    frontiers: Vec<Vec<Block>>,
+}
+
+impl DominanceFrontiers {
+    pub fn new(cfg: &ControlFlowGraph, domtree: &DomTree) -> Self {
+        let mut frontiers: Vec<Vec<Block>> = vec![Vec::new(); domtree.idom.len()];
+
+        for block in domtree.reverse_postorder() {
+            let preds = cfg.preds(*block);
+
+            if preds.len() < 2 {
+                continue;
+            }
+
+            let idom = domtree.idom(*block);
+
+            for pred in preds {
+                if !domtree.is_reachable(*pred) {
+                    continue;
+                }
+
+                let mut runner = Some(*pred);
+
+                while let Some(current) = runner {
+                    if runner == idom {
+                        break;
+                    }
+
+                    if !frontiers[current.0].contains(block) {
+                        frontiers[current.0].push(*block);
+                    }
+
+                    runner = domtree.idom(current);
+                }
+            }
+        }
+
+        Self { frontiers }
+    }
+
+    pub fn frontier(&self, block: Block) -> &[Block] {
+        &self.frontiers[block.0]
+    }
+}
@@ -0,0 +1,121 @@
+use super::loops::LoopData;
+use crate::ir::function::{Block, Function, Value, ValueDef};
+use crate::ir::inst::{BinaryOp, Flags, InstKind, Operand};
+use crate::ir::types::Ty;
+use crate::parser::types::{OverflowMode, Type};
+use std::collections::HashSet;
+
+// A header phi growing by a constant on every iteration,
+// `%i = phi [ start, preheader ], [ %next, latch ]` with `%next = add %i, step`
+#[derive(Debug, Clone)]
+pub struct Induction {
+    pub phi: Value,
+    pub start: Operand,
+    pub next: Value,
+    // What is added on every iteration, negative when the variable decreases
+    pub step: Type,
+    pub ty: Ty,
+    // Flags of the increment, a `sub` is seen as adding the negated step
+    pub op: BinaryOp,
+    pub flags: Flags,
+}
+
+impl Induction {
+    pub fn step_value(&self) -> i128 {
+        self.step.to_signed().as_i128().unwrap_or_default()
+    }
+
+    // Whether the increment never wraps around the signed or unsigned range of the type
+    pub fn no_wrap(&self, signed: bool) -> bool {
+        if signed {
+            self.flags.nsw
+        } else {
+            // Adding a negative step to an unsigned value always wraps
+            self.flags.nuw && (self.op == BinaryOp::Add) == (self.step_value() > 0)
+        }
+    }
+}
+
+// Values defined by the instructions of the loop
+pub fn loop_values(function: &Function, data: &LoopData) -> HashSet<Value> {
+    data.blocks
+        .iter()
+        .flat_map(|block| &function.blocks[block.0].insts)
+        .filter_map(|inst| function.insts[inst.0].result)
+        .collect()
+}
+
+// Induction variables of a loop with a preheader and a single latch
+pub fn inductions(function: &Function, data: &LoopData, preheader: Block) -> Vec<Induction> {
+    let [latch] = data.latches[..] else {
+        return Vec::new();
+    };
+
+    let mut found = Vec::new();
+
+    for inst in &function.blocks[data.header.0].insts {
+        let InstKind::Phi { ty, incoming } = &function.insts[inst.0].kind else {
+            continue;
+        };
+
+        let Some(phi) = function.insts[inst.0].result else {
+            continue;
+        };
+
+        let entry = incoming.iter().find(|(_, pred)| *pred == preheader);
+        let back = incoming.iter().find(|(_, pred)| *pred == latch);
+
+        let (Some((start, _)), Some((Operand::Value(next), _))) = (entry, back) else {
+            continue;
+        };
+
+        let ValueDef::Inst(increment) = function.values[next.0].def else {
+            continue;
+        };
+
+        let InstKind::Binary {
+            op,
+            flags,
+            lhs,
+            rhs,
+            ..
+        } = &function.insts[increment.0].kind
+        else {
+            continue;
+        };
+
+        let this = Operand::Value(phi);
+
+        let step = match (op, lhs, rhs) {
+            (BinaryOp::Add, lhs, Operand::Const(step)) if *lhs == this => step.clone(),
+            (BinaryOp::Add, Operand::Const(step), rhs) if *rhs == this => step.clone(),
+            (BinaryOp::Sub, lhs, Operand::Const(step)) if *lhs == this => {
+                let Some(mut negated) = ty.zero() else {
+                    continue;
+                };
+
+                if negated
+                    .try_sub(step.clone(), OverflowMode::Wrapping)
+                    .is_err()
+                {
+                    continue;
+                }
+
+                negated
+            }
+            _ => continue,
+        };
+
+        found.push(Induction {
+            phi,
+            start: start.clone(),
+            next: *next,
+            step,
+            ty: *ty,
+            op: *op,
+            flags: *flags,
+        });
+    }
+
+    found
+}
@@ -0,0 +1,106 @@
+use crate::ir::cfg::ControlFlowGraph;
+use crate::ir::function::{Block, Function, Value};
+use crate::ir::inst::{InstKind, Operand};
+use std::collections::BTreeSet;
+
+// Values live at the start and at the end of each block, indexed by block id.
+// A phi operand is live out of its incoming block and the phi result is defined at the
+// start of its own block, so neither of them is live in that block
+#[derive(Debug, Clone, Default)]
+pub struct Liveness {
+    live_in: Vec<BTreeSet<Value>>,
+    live_out: Vec<BTreeSet<Value>>,
+}
+
+impl Liveness {
+    pub fn new(function: &Function, cfg: &ControlFlowGraph) -> Self {
+        let count = function.blocks.len();
+        // Values read before being defined in the block and the values it defines
+        let mut uses = vec![BTreeSet::new(); count];
+        let mut defs = vec![BTreeSet::new(); count];
+        // Phi operands read on the edge from each predecessor
+        let mut edge_uses = vec![BTreeSet::new(); count];
+
+        for block in &function.layout {
+            for inst in &function.blocks[block.0].insts {
+                let data = &function.insts[inst.0];
+
+                if let InstKind::Phi { incoming, .. } = &data.kind {
+                    for (operand, pred) in incoming {
+                        if let Operand::Value(value) = operand {
+                            edge_uses[pred.0].insert(*value);
+                        }
+                    }
+                } else {
+                    for operand in data.kind.operands() {
+                        if let Operand::Value(value) = operand {
+                            if !defs[block.0].contains(value) {
+                                uses[block.0].insert(*value);
+                            }
+                        }
+                    }
+                }
+
+                if let Some(result) = data.result {
+                    defs[block.0].insert(result);
+                }
+            }
+        }
+
+        let mut liveness = Self {
+            live_in: vec![BTreeSet::new(); count],
+            live_out: vec![BTreeSet::new(); count],
+        };
+
+        // Backwards dataflow, visiting the blocks in postorder converges faster
+        let mut order = cfg.reverse_postorder(function);
+        order.reverse();
+
+        for block in &function.layout {
+            if !order.contains(block) {
+                order.push(*block);
+            }
+        }
+
+        let mut changed = true;
+
+        while changed {
+            changed = false;
+
+            for block in &order {
+                let mut out = edge_uses[block.0].clone();
+
+                for succ in cfg.succs(*block) {
+                    out.extend(liveness.live_in[succ.0].iter().copied());
+                }
+
+                let mut live_in = uses[block.0].clone();
+                live_in.extend(out.difference(&defs[block.0]).copied());
+
+                if live_in != liveness.live_in[block.0] || out != liveness.live_out[block.0] {
+                    liveness.live_in[block.0] = live_in;
+                    liveness.live_out[block.0] = out;
+                    changed = true;
+                }
+            }
+        }
+
+        liveness
+    }
+
+    pub fn live_in(&self, block: Block) -> &BTreeSet<Value> {
+        &self.live_in[block.0]
+    }
+
+    pub fn live_out(&self, block: Block) -> &BTreeSet<Value> {
+        &self.live_out[block.0]
+    }
+
+    pub fn is_live_in(&self, block: Block, value: Value) -> bool {
+        self.live_in[block.0].contains(&value)
+    }
+
+    pub fn is_live_out(&self, block: Block, value: Value) -> bool {
+        self.live_out[block.0].contains(&value)
+    }
+}
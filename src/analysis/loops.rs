@@ -0,0 +1,172 @@
+use super::dominators::DomTree;
+use crate::ir::cfg::ControlFlowGraph;
+use crate::ir::function::Block;
+use std::collections::{HashMap, HashSet};
+
+// Ids are indexes into the loops of the LoopInfo
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Loop(pub usize);
+
+#[derive(Debug, Clone)]
+pub struct LoopData {
+    pub header: Block,
+    // Blocks of the loop in reverse postorder, the header is the first one
+    pub blocks: Vec<Block>,
+    // Blocks with a back edge to the header
+    pub latches: Vec<Block>,
+    pub parent: Option<Loop>,
+    pub children: Vec<Loop>,
+    // Outermost loops have depth 1
+    pub depth: usize,
+    members: HashSet<Block>,
+}
+
+impl LoopData {
+    pub fn contains(&self, block: Block) -> bool {
+        self.members.contains(&block)
+    }
+}
+
+// Natural loops of a function, loops sharing a header are merged
+#[derive(Debug, Clone, Default)]
+pub struct LoopInfo {
+    loops: Vec<LoopData>,
+    // Innermost loop of each block in a loop
+    innermost: HashMap<Block, Loop>,
+}
+
+impl LoopInfo {
+    pub fn new(cfg: &ControlFlowGraph, domtree: &DomTree) -> Self {
+        let rpo = domtree.reverse_postorder();
+        let mut loops = Vec::new();
+
+        for header in rpo {
+            // An edge is a back edge when its target dominates its source
+            let latches: Vec<Block> = cfg
+                .preds(*header)
+                .iter()
+                .copied()
+                .filter(|pred| domtree.is_reachable(*pred) && domtree.dominates(*header, *pred))
+                .collect();
+
+            if latches.is_empty() {
+                continue;
+            }
+
+            // The body is everything reaching a latch without going through the header
+            let mut members = HashSet::from([*header]);
+            let mut stack = latches.clone();
+
+            while let Some(block) = stack.pop() {
+                if !members.insert(block) {
+                    continue;
+                }
+
+                for pred in cfg.preds(block) {
+                    if domtree.is_reachable(*pred) {
+                        stack.push(*pred);
+                    }
+                }
+            }
+
+            let blocks = rpo
+                .iter()
+                .copied()
+                .filter(|block| members.contains(block))
+                .collect();
+
+            loops.push(LoopData {
+                header: *header,
+                blocks,
+                latches,
+                parent: None,
+                children: Vec::new(),
+                depth: 1,
+                members,
+            });
+        }
+
+        // Headers come in reverse postorder, so every loop comes after the loops containing it
+        for i in 0..loops.len() {
+            let parent = (0..i).rev().find(|j| loops[*j].contains(loops[i].header));
+
+            if let Some(parent) = parent {
+                loops[i].parent = Some(Loop(parent));
+                loops[i].depth = loops[parent].depth + 1;
+                loops[parent].children.push(Loop(i));
+            }
+        }
+
+        // Inner loops come later and overwrite the loops around them
+        let mut innermost = HashMap::new();
+
+        for (i, data) in loops.iter().enumerate() {
+            for block in &data.blocks {
+                innermost.insert(*block, Loop(i));
+            }
+        }
+
+        Self { loops, innermost }
+    }
+
+    pub fn loops(&self) -> impl Iterator<Item = Loop> {
+        (0..self.loops.len()).map(Loop)
+    }
+
+    pub fn data(&self, lp: Loop) -> &LoopData {
+        &self.loops[lp.0]
+    }
+
+    // Loops that are not inside another one
+    pub fn top_level(&self) -> Vec<Loop> {
+        self.loops()
+            .filter(|lp| self.loops[lp.0].parent.is_none())
+            .collect()
+    }
+
+    pub fn innermost(&self, block: Block) -> Option<Loop> {
+        self.innermost.get(&block).copied()
+    }
+
+    // Number of loops around the block, 0 outside of any loop
+    pub fn depth(&self, block: Block) -> usize {
+        self.innermost(block).map_or(0, |lp| self.loops[lp.0].depth)
+    }
+
+    pub fn is_header(&self, block: Block) -> bool {
+        self.innermost(block)
+            .is_some_and(|lp| self.loops[lp.0].header == block)
+    }
+
+    // The only block entering the loop, when it leads nowhere else
+    pub fn preheader(&self, lp: Loop, cfg: &ControlFlowGraph) -> Option<Block> {
+        let data = &self.loops[lp.0];
+        let outside: Vec<Block> = cfg
+            .preds(data.header)
+            .iter()
+            .copied()
+            .filter(|pred| !data.contains(*pred))
+            .collect();
+
+        match outside.as_slice() {
+            [pred] if cfg.succs(*pred) == [data.header] => Some(*pred),
+            _ => None,
+        }
+    }
+
+    // Blocks outside of the loop with a predecessor inside it
+    pub fn exits(&self, lp: Loop, cfg: &ControlFlowGraph) -> Vec<Block> {
+        let data = &self.loops[lp.0];
+        let mut exits = Vec::new();
+
+        for block in &data.blocks {
+            for succ in cfg.succs(*block) {
+                if !data.contains(*succ) && !exits.contains(succ) {
+                    exits.push(*succ);
+                }
+            }
+        }
+
+        exits
+    }
+}
@@ -0,0 +1,100 @@
+pub mod alias;
+pub mod dominators;
+pub mod induction;
+pub mod liveness;
+pub mod loops;
+
+use crate::ir::cfg::ControlFlowGraph;
+use crate::ir::function::Function;
+use dominators::{DomTree, DominanceFrontiers};
+use liveness::Liveness;
+use loops::LoopInfo;
+use std::rc::Rc;
+
+// What a pass kept intact, the analyses depending on anything else are dropped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preserved {
+    // The function was not changed
+    All,
+    // Instructions changed but the blocks and their edges did not
+    Cfg,
+    None,
+}
+
+// Analyses of one function, computed on first use and cached until a pass invalidates them.
+// They are shared through Rc so a pass can hold several while it changes the function
+#[derive(Debug, Clone, Default)]
+pub struct AnalysisManager {
+    cfg: Option<Rc<ControlFlowGraph>>,
+    domtree: Option<Rc<DomTree>>,
+    frontiers: Option<Rc<DominanceFrontiers>>,
+    loops: Option<Rc<LoopInfo>>,
+    liveness: Option<Rc<Liveness>>,
+}
+
+impl AnalysisManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cfg(&mut self, function: &Function) -> Rc<ControlFlowGraph> {
+        self.cfg
+            .get_or_insert_with(|| Rc::new(ControlFlowGraph::new(function)))
+            .clone()
+    }
+
+    pub fn domtree(&mut self, function: &Function) -> Rc<DomTree> {
+        if let Some(domtree) = &self.domtree {
+            return domtree.clone();
+        }
+
+        let cfg = self.cfg(function);
+        self.domtree
+            .insert(Rc::new(DomTree::new(function, &cfg)))
+            .clone()
+    }
+
+    pub fn frontiers(&mut self, function: &Function) -> Rc<DominanceFrontiers> {
+        if let Some(frontiers) = &self.frontiers {
+            return frontiers.clone();
+        }
+
+        let (cfg, domtree) = (self.cfg(function), self.domtree(function));
+        self.frontiers
+            .insert(Rc::new(DominanceFrontiers::new(&cfg, &domtree)))
+            .clone()
+    }
+
+    pub fn loops(&mut self, function: &Function) -> Rc<LoopInfo> {
+        if let Some(loops) = &self.loops {
+            return loops.clone();
+        }
+
+        let (cfg, domtree) = (self.cfg(function), self.domtree(function));
+        self.loops
+            .insert(Rc::new(LoopInfo::new(&cfg, &domtree)))
+            .clone()
+    }
+
+    pub fn liveness(&mut self, function: &Function) -> Rc<Liveness> {
+        if let Some(liveness) = &self.liveness {
+            return liveness.clone();
+        }
+
+        let cfg = self.cfg(function);
+        self.liveness
+            .insert(Rc::new(Liveness::new(function, &cfg)))
+            .clone()
+    }
+
+    // Drops the analyses a change made stale, call it after every pass that changed the function
+    pub fn invalidate(&mut self, preserved: Preserved) {
+        match preserved {
+            Preserved::All => {}
+            Preserved::Cfg => {
+                self.liveness = None;
+            }
+            Preserved::None => *self = Self::default(),
+        }
+    }
+}
@@ -1,11 +1,13 @@
 use super::symbol;
 use super::{Binary, Section};
+use object::elf::{
+    R_RISCV_PCREL_HI20, R_RISCV_PCREL_LO12_I, STB_GLOBAL, STB_LOCAL, STT_FUNC, STT_OBJECT,
+};
 use object::write::Object;
 use object::{
     write::{SectionId, SectionKind},
     Architecture, BinaryFormat, Endianness,
 };
-use std::fs::File;
 use std::io::Write;
 use thiserror::Error;
 
@@ -16,13 +18,19 @@ pub enum ElfError {
 
     #[error("Object Error {0}")]
     ObjectError(#[from] object::write::Error),
+
+    #[error("Relocation against {0}, which was not written yet")]
+    UnknownSymbol(String),
 }
 
 // High level abstraccion of Object
 pub struct Elf<'a> {
     pub object: Object<'a>,
     pub text_id: Option<SectionId>,
+    pub rodata_id: Option<SectionId>,
     pub current_tvalue: u64,
+    // Count of the labels made for relocations
+    labels: usize,
 }
 
 impl<'a> Elf<'a> {
@@ -32,53 +40,125 @@ impl<'a> Elf<'a> {
         Self {
             object: obj,
             text_id: None,
+            rodata_id: None,
             current_tvalue: 0,
+            labels: 0,
         }
     }
-    fn wsection(&mut self, section: Section, symbol: symbol::Symbol) {
-        match section {
+    fn wsection(&mut self, section: Section, symbol: symbol::Symbol) -> Result<(), ElfError> {
+        let (id, kind, align) = match section {
             Section::Text => {
-                if let None = self.text_id {
-                    self.asection("text".to_string(), SectionKind::Text);
+                if self.text_id.is_none() {
+                    self.text_id = Some(self.asection(".text".to_string(), SectionKind::Text));
+                }
+
+                (self.text_id.unwrap(), object::SymbolKind::Text, 4)
+            }
+            Section::Rodata => {
+                if self.rodata_id.is_none() {
+                    let id = self.asection(".rodata".to_string(), SectionKind::ReadOnlyData);
+                    self.rodata_id = Some(id);
                 }
 
-                let text_id = self.text_id.unwrap();
+                (self.rodata_id.unwrap(), object::SymbolKind::Data, 8)
+            }
+            _ => return Ok(()),
+        };
+
+        let st_type = if kind == object::SymbolKind::Text {
+            STT_FUNC
+        } else {
+            STT_OBJECT
+        };
 
-                let (st_info, scope) = if symbol.symbol_type == symbol::SymbolType::Private {
-                    (0x10, object::SymbolScope::Compilation)
-                } else {
-                    (0x12, object::SymbolScope::Linkage)
+        // Private symbols are local, so they stay out of the way of other objects
+        let (bind, scope) = if symbol.symbol_type == symbol::SymbolType::Private {
+            (STB_LOCAL, object::SymbolScope::Compilation)
+        } else {
+            (STB_GLOBAL, object::SymbolScope::Linkage)
+        };
+        let st_info = bind << 4 | st_type;
+
+        let value = self
+            .object
+            .section_mut(id)
+            .append_data(&symbol.content, align);
+
+        self.object.add_symbol(object::write::Symbol {
+            section: object::write::SymbolSection::Section(id),
+            name: symbol.name.as_bytes().to_vec(),
+            kind,
+            size: symbol.content.len() as u64,
+            weak: false,
+            value,
+            scope,
+            flags: object::SymbolFlags::Elf {
+                st_info,
+                st_other: 0,
+            },
+        });
+
+        if Some(id) == self.text_id {
+            self.current_tvalue = value + symbol.content.len() as u64;
+        }
+
+        for relocation in &symbol.relocations {
+            self.relocate(id, value, relocation)?;
+        }
+
+        Ok(())
+    }
+    // The symbol must be written before anything that refers to it
+    fn relocate(
+        &mut self,
+        id: SectionId,
+        start: u64,
+        relocation: &symbol::Relocation,
+    ) -> Result<(), ElfError> {
+        let (symbol, addend, r_type) = match relocation.kind {
+            symbol::RelocationKind::PcrelHi20 => {
+                let Some(symbol) = self.object.symbol_id(relocation.symbol.as_bytes()) else {
+                    return Err(ElfError::UnknownSymbol(relocation.symbol.clone()));
                 };
 
-                self.object.add_symbol(object::write::Symbol {
-                    section: object::write::SymbolSection::Section(text_id),
-                    name: symbol.name.as_bytes().to_vec(),
-                    kind: object::SymbolKind::Text,
-                    size: symbol.content.len() as u64,
+                (symbol, relocation.addend, R_RISCV_PCREL_HI20)
+            }
+            // The low part refers to the `auipc`, which gets a local label
+            symbol::RelocationKind::PcrelLo12I(auipc) => {
+                let name = format!(".Lpcrel_hi{}", self.labels);
+                self.labels += 1;
+
+                let label = self.object.add_symbol(object::write::Symbol {
+                    section: object::write::SymbolSection::Section(id),
+                    name: name.into_bytes(),
+                    kind: object::SymbolKind::Label,
+                    size: 0,
                     weak: false,
-                    value: self.current_tvalue,
-                    scope,
-                    flags: object::SymbolFlags::Elf {
-                        st_info,
-                        st_other: 0,
-                    },
+                    value: start + auipc as u64,
+                    scope: object::SymbolScope::Compilation,
+                    flags: object::SymbolFlags::None,
                 });
 
-                self.current_tvalue += symbol.content.len() as u64;
-
-                self.object
-                    .section_mut(text_id)
-                    .append_data(&symbol.content, 4);
+                (label, 0, R_RISCV_PCREL_LO12_I)
             }
-            _ => {}
-        }
+        };
+
+        self.object.add_relocation(
+            id,
+            object::write::Relocation {
+                offset: start + relocation.offset as u64,
+                symbol,
+                addend,
+                flags: object::RelocationFlags::Elf { r_type },
+            },
+        )?;
+
+        Ok(())
     }
-    fn asection(&mut self, name: String, kind: SectionKind) {
+    fn asection(&mut self, name: String, kind: SectionKind) -> SectionId {
         let n = name.as_bytes().to_vec();
 
-        let id = self.object.add_section(vec![], n, kind);
-
-        self.text_id = Some(id);
+        self.object.add_section(vec![], n, kind)
     }
 }
 
@@ -88,21 +168,35 @@ impl Binary for Elf<'_> {
     fn get(&self) -> Result<Vec<u8>, Self::Error> {
         Ok(self.object.write()?)
     }
-    fn save(&self, target: &mut File) -> Result<(), Self::Error> {
+    fn save<W: Write>(&self, target: &mut W) -> Result<(), Self::Error> {
         let content = self.get()?;
         target.write_all(&content)?;
         Ok(())
     }
-    fn write_section(&mut self, section: Section, symbol: symbol::Symbol) {
-        self.wsection(section, symbol);
+    fn write_section(&mut self, section: Section, symbol: symbol::Symbol) -> Result<(), ElfError> {
+        self.wsection(section, symbol)
     }
     fn create_section(&mut self, section: Section) {
         match section {
-            Section::Text => self.asection("text".to_string(), SectionKind::Text),
-            Section::Data => self.asection("data".to_string(), SectionKind::Data),
-            Section::Note => self.asection("note".to_string(), SectionKind::Note),
-            Section::Bss => self.asection("bss".to_string(), SectionKind::UninitializedData),
-            Section::Other(name, id) => self.asection(name, SectionKind::Unknown),
+            Section::Text => {
+                self.text_id = Some(self.asection(".text".to_string(), SectionKind::Text));
+            }
+            Section::Data => {
+                self.asection(".data".to_string(), SectionKind::Data);
+            }
+            Section::Rodata => {
+                let id = self.asection(".rodata".to_string(), SectionKind::ReadOnlyData);
+                self.rodata_id = Some(id);
+            }
+            Section::Note => {
+                self.asection(".note".to_string(), SectionKind::Note);
+            }
+            Section::Bss => {
+                self.asection(".bss".to_string(), SectionKind::UninitializedData);
+            }
+            Section::Other(name, _) => {
+                self.asection(name, SectionKind::Unknown);
+            }
         }
     }
 }
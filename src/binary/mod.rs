@@ -2,13 +2,14 @@ pub mod elf;
 pub mod symbol;
 
 use object::write::SectionId;
-use std::fs::File;
+use std::io::Write;
 use symbol::Symbol;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Section {
     Text,
     Data,
+    Rodata,
     Bss,
     Note,
     Other(String, Option<SectionId>),
@@ -18,7 +19,7 @@ pub trait Binary {
     type Error;
 
     fn get(&self) -> Result<Vec<u8>, Self::Error>;
-    fn write_section(&mut self, section: Section, symbol: Symbol);
+    fn write_section(&mut self, section: Section, symbol: Symbol) -> Result<(), Self::Error>;
     fn create_section(&mut self, section: Section);
-    fn save(&self, target: &mut File) -> Result<(), Self::Error>;
+    fn save<W: Write>(&self, target: &mut W) -> Result<(), Self::Error>;
 }
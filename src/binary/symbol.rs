@@ -16,12 +16,31 @@ pub enum SymbolError {
     DecodeError(#[from] DecodeError),
 }
 
+// How the instruction at a relocation is patched once the symbol has an address
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelocationKind {
+    // Upper 20 bits of the distance from an `auipc` to the symbol
+    PcrelHi20,
+    // Low 12 bits of an I-type instruction, paired with the `auipc` at this offset
+    PcrelLo12I(usize),
+}
+
+#[derive(Debug, Clone)]
+pub struct Relocation {
+    pub offset: usize,
+    pub kind: RelocationKind,
+    pub symbol: String,
+    pub addend: i64,
+}
+
 #[derive(Debug)]
 pub struct Symbol {
     pub name: String,
     pub section: Section,
     pub symbol_type: SymbolType,
     pub content: Vec<u8>,
+    // Offsets are relative to the start of the content
+    pub relocations: Vec<Relocation>,
 }
 
 #[derive(Debug)]
@@ -29,6 +48,12 @@ pub struct SymbolBuilder {
     pub symbol: Symbol,
 }
 
+impl Default for SymbolBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl SymbolBuilder {
     pub fn new() -> Self {
         SymbolBuilder {
@@ -37,6 +62,7 @@ impl SymbolBuilder {
                 symbol_type: SymbolType::Private,
                 content: Vec::new(),
                 section: Section::Note,
+                relocations: Vec::new(),
             },
         }
     }
@@ -56,25 +82,27 @@ impl SymbolBuilder {
         self
     }
     #[must_use]
+    pub fn set_relocations(mut self, relocations: Vec<Relocation>) -> Self {
+        self.symbol.relocations = relocations;
+        self
+    }
+    #[must_use]
     pub fn set_type(mut self, new_type: SymbolType) -> Self {
         self.symbol.symbol_type = new_type;
         self
     }
-    #[must_use]
     pub fn from_ast(
         mut self,
         node: &AstNode,
         functions: &HashMap<String, u64>,
     ) -> Result<Self, SymbolError> {
-        match node {
-            AstNode::Function {
-                name, body, stype, ..
-            } => {
-                self.symbol.name = name.to_string();
-                self.symbol.symbol_type = *stype;
-                self.symbol.content = from_nodes(body.to_vec(), functions)?;
-            }
-            _ => {}
+        if let AstNode::Function {
+            name, body, stype, ..
+        } = node
+        {
+            self.symbol.name = name.to_string();
+            self.symbol.symbol_type = *stype;
+            self.symbol.content = from_nodes(body.to_vec(), functions)?;
         }
 
         Ok(self)
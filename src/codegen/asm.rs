@@ -0,0 +1,92 @@
+use super::{constant_pool, lower_module, CodegenError, CompiledFunction, POOL};
+use crate::binary::symbol::SymbolType;
+use crate::ir::Module;
+use crate::riscv::machine::MachineInst;
+use std::collections::HashMap;
+use std::fmt::Display;
+
+// Assembly of a module for GNU as. Jumps and calls keep their labels and symbols, so
+// the assembler lays the code out and relaxes them instead of the backend
+pub struct Assembly {
+    functions: Vec<CompiledFunction>,
+    pool: Vec<u8>,
+    entries: HashMap<i64, i64>,
+}
+
+pub fn assembly(module: &Module) -> Result<Assembly, CodegenError> {
+    let functions = lower_module(module)?;
+    let (pool, entries) = constant_pool(&functions);
+
+    Ok(Assembly {
+        functions,
+        pool,
+        entries,
+    })
+}
+
+impl Display for Assembly {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "    .text")?;
+
+        // Every `auipc` of a constant gets a label for its `ld` to refer to
+        let mut loads = 0;
+
+        for function in &self.functions {
+            let mut labels: HashMap<usize, Vec<usize>> = HashMap::new();
+
+            for (label, at) in &function.labels {
+                labels.entry(*at).or_default().push(label.0);
+            }
+
+            let constants: HashMap<usize, i64> = function.constants.iter().copied().collect();
+
+            writeln!(f)?;
+
+            if function.linkage == SymbolType::Global {
+                writeln!(f, "    .globl {}", function.name)?;
+            }
+
+            writeln!(f, "{}:", function.name)?;
+
+            for at in 0..=function.insts.len() {
+                if let Some(labels) = labels.get_mut(&at) {
+                    labels.sort_unstable();
+
+                    for label in labels {
+                        writeln!(f, ".L{}:", label)?;
+                    }
+                }
+
+                let Some(inst) = function.insts.get(at) else {
+                    break;
+                };
+
+                match inst {
+                    MachineInst::Auipc { rd, .. } if constants.contains_key(&at) => {
+                        let offset = self.entries[&constants[&at]];
+                        writeln!(f, ".Lpcrel{}:", loads)?;
+                        writeln!(f, "    auipc {}, %pcrel_hi({}+{})", rd, POOL, offset)?;
+                    }
+                    MachineInst::Load { rd, base, .. }
+                        if at > 0 && constants.contains_key(&(at - 1)) =>
+                    {
+                        writeln!(f, "    ld {}, %pcrel_lo(.Lpcrel{})({})", rd, loads, base)?;
+                        loads += 1;
+                    }
+                    _ => writeln!(f, "    {}", inst)?,
+                }
+            }
+        }
+
+        if !self.pool.is_empty() {
+            writeln!(f, "\n    .section .rodata\n    .p2align 3\n{}:", POOL)?;
+
+            for value in self.pool.chunks(8) {
+                let value = i64::from_le_bytes(value.try_into().map_err(|_| std::fmt::Error)?);
+                writeln!(f, "    .dword {}", value)?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,1686 @@
+use super::regalloc::{allocate, in_register, Allocation};
+use super::{CodegenError, CompiledFunction};
+use crate::ir::function::{Block, Function, Inst, Value};
+use crate::ir::inst::{BinaryOp, CastOp, FloatCC, InstKind, IntCC, Operand};
+use crate::ir::types::Ty;
+use crate::parser::types::Type;
+use crate::riscv::constant::{self, sequence};
+use crate::riscv::float::{FloatFormat, IntFormat, RoundingMode};
+use crate::riscv::machine::{
+    AluOp, BranchCond, FloatCmp, FloatOp, ImmOp, Label, MachineInst, Target,
+};
+use crate::riscv::regs::{FReg, Reg};
+use std::collections::{HashMap, HashSet};
+
+const INT_ARGS: [Reg; 8] = [
+    Reg::A0,
+    Reg::A1,
+    Reg::A2,
+    Reg::A3,
+    Reg::A4,
+    Reg::A5,
+    Reg::A6,
+    Reg::A7,
+];
+
+const FLOAT_ARGS: [FReg; 8] = [
+    FReg::Fa0,
+    FReg::Fa1,
+    FReg::Fa2,
+    FReg::Fa3,
+    FReg::Fa4,
+    FReg::Fa5,
+    FReg::Fa6,
+    FReg::Fa7,
+];
+
+enum ArgReg {
+    Int(Reg),
+    // Low half first
+    Pair(Reg, Reg),
+    Float(FReg, FloatFormat),
+}
+
+// Registers of the arguments of a call to `callee`. 128 bit integers take the next two
+// integer registers
+fn argument_regs(callee: &str, tys: impl Iterator<Item = Ty>) -> Result<Vec<ArgReg>, CodegenError> {
+    let (mut ints, mut floats) = (0, 0);
+    let mut regs = Vec::new();
+    let too_many = || CodegenError::TooManyArguments(callee.to_string());
+
+    for ty in tys {
+        if let Some(fmt) = float_format(ty) {
+            let reg = FLOAT_ARGS.get(floats).ok_or_else(too_many)?;
+            floats += 1;
+            regs.push(ArgReg::Float(*reg, fmt));
+        } else if ty.bits() > 64 {
+            // Like the psABI, pairs start at an even register
+            ints += ints % 2;
+            let (Some(lo), Some(hi)) = (INT_ARGS.get(ints), INT_ARGS.get(ints + 1)) else {
+                return Err(too_many());
+            };
+
+            ints += 2;
+            regs.push(ArgReg::Pair(*lo, *hi));
+        } else {
+            let reg = INT_ARGS.get(ints).ok_or_else(too_many)?;
+            ints += 1;
+            regs.push(ArgReg::Int(*reg));
+        }
+    }
+
+    Ok(regs)
+}
+
+// Values live in the registers given by the allocator, or in their stack slot, and are
+// loaded into temporaries for the instructions that need it. Floats are kept as their
+// bits. Integers narrower than 64 bits are kept extended, 32 bit ones and signed ones
+// sign extended and the rest zero extended. Labels are numbered from `labels`, which
+// is left past the last one so they stay unique in the module
+pub fn compile_function(
+    function: &Function,
+    labels: &mut usize,
+) -> Result<CompiledFunction, CodegenError> {
+    let order = block_order(function);
+    let mut emitter = Emitter::new(function, allocate(function, &order), *labels);
+
+    emitter.prologue()?;
+
+    for (index, block) in order.iter().enumerate() {
+        emitter.next = order.get(index + 1).copied();
+        emitter.block(*block)?;
+    }
+
+    *labels = emitter.next_label;
+
+    Ok(CompiledFunction {
+        name: function.name.clone(),
+        linkage: function.linkage,
+        insts: emitter.code,
+        labels: emitter.labels,
+        constants: emitter.constants,
+    })
+}
+
+// Places each block right after one that jumps to it when possible, so the jump becomes
+// a fall-through. Branches continue with the else side, the entry stays first
+fn block_order(function: &Function) -> Vec<Block> {
+    let mut placed = HashSet::new();
+    let mut order = Vec::new();
+
+    for block in &function.layout {
+        let mut current = Some(*block);
+
+        while let Some(block) = current.filter(|block| placed.insert(*block)) {
+            order.push(block);
+
+            let Some(terminator) = function.terminator(block) else {
+                break;
+            };
+
+            current = match function.insts[terminator.0].kind {
+                InstKind::Jump { target } => Some(target),
+                InstKind::Branch {
+                    then_dest,
+                    else_dest,
+                    ..
+                } if placed.contains(&else_dest) => Some(then_dest),
+                InstKind::Branch { else_dest, .. } => Some(else_dest),
+                _ => None,
+            };
+        }
+    }
+
+    order
+}
+
+fn float_format(ty: Ty) -> Option<FloatFormat> {
+    match ty {
+        Ty::F16 => Some(FloatFormat::H),
+        Ty::F32 => Some(FloatFormat::S),
+        Ty::F64 => Some(FloatFormat::D),
+        _ => None,
+    }
+}
+
+// Whether the register holding a value of `ty` is sign extended
+fn sign_extended(ty: Ty) -> bool {
+    match ty {
+        Ty::Int { bits: 32, .. } => true,
+        Ty::Int { bits, signed } => signed && bits > 1,
+        _ => false,
+    }
+}
+
+// log2 of the bytes a load or store of `ty` moves, other sizes are split by legalize
+fn access_width(ty: Ty) -> Option<u32> {
+    match ty.bits().div_ceil(8) {
+        1 => Some(0),
+        2 => Some(1),
+        4 => Some(2),
+        8 => Some(3),
+        _ => None,
+    }
+}
+
+// Bits of a constant as they are kept in a register
+fn const_bits(constant: &Type) -> Option<i64> {
+    match constant {
+        Type::Int { bits, signed, .. }
+            if sign_extended(Ty::Int {
+                bits: *bits,
+                signed: *signed,
+            }) =>
+        {
+            Some(constant.as_i128()? as i64)
+        }
+        Type::Int { value, .. } => Some(*value as i64),
+        Type::F16(bits) => Some(*bits as i64),
+        Type::F32(bits) => Some(*bits as i64),
+        Type::F64(bits) => Some(*bits as i64),
+        Type::Value(_) => None,
+    }
+}
+
+// Bits of a 128 bit constant, like the high half in registers unsigned pairs are zero
+// extended
+fn pair_bits(constant: &Type) -> i128 {
+    match constant {
+        Type::Int { signed: false, .. } => constant.as_u128().unwrap_or_default() as i128,
+        _ => constant.as_i128().unwrap_or_default(),
+    }
+}
+
+// The instruction taking the constant rhs of a binary as an immediate, when it fits
+fn binary_imm(op: BinaryOp, word: bool, rd: Reg, rs1: Reg, rhs: &Operand) -> Option<MachineInst> {
+    let Operand::Const(constant) = rhs else {
+        return None;
+    };
+
+    let imm = const_bits(constant)?;
+    let fits = (-2048..2048).contains(&imm);
+    // Only the low bits of a shift amount are read, larger amounts are not defined anyway
+    let shamt = imm & if word { 31 } else { 63 };
+
+    let (op, imm) = match (op, word) {
+        (BinaryOp::Add, false) if fits => (ImmOp::Addi, imm),
+        (BinaryOp::Add, true) if fits => (ImmOp::Addiw, imm),
+        (BinaryOp::Sub, false) if fits && imm != -2048 => (ImmOp::Addi, -imm),
+        (BinaryOp::Sub, true) if fits && imm != -2048 => (ImmOp::Addiw, -imm),
+        (BinaryOp::And, _) if fits => (ImmOp::Andi, imm),
+        (BinaryOp::Or, _) if fits => (ImmOp::Ori, imm),
+        (BinaryOp::Xor, _) if fits => (ImmOp::Xori, imm),
+        (BinaryOp::Shl, false) => (ImmOp::Slli, shamt),
+        (BinaryOp::Shl, true) => (ImmOp::Slliw, shamt),
+        (BinaryOp::Lshr, false) => (ImmOp::Srli, shamt),
+        (BinaryOp::Lshr, true) => (ImmOp::Srliw, shamt),
+        (BinaryOp::Ashr, false) => (ImmOp::Srai, shamt),
+        (BinaryOp::Ashr, true) => (ImmOp::Sraiw, shamt),
+        _ => return None,
+    };
+
+    Some(MachineInst::imm(op, rd, rs1, imm))
+}
+
+fn slot_size(ty: Ty) -> i64 {
+    if ty.bits() > 64 {
+        16
+    } else {
+        8
+    }
+}
+
+// Where a parallel move reads and writes, stack places are offsets from sp
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Place {
+    Reg(Reg),
+    Stack(i64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Source {
+    Place(Place),
+    Const(i64),
+}
+
+struct Emitter<'a> {
+    function: &'a Function,
+    code: Vec<MachineInst>,
+    allocation: Allocation,
+    // Position of the instruction being emitted
+    position: usize,
+    // Stack offset of the slot of each spilled or 128 bit value
+    slots: HashMap<Value, i64>,
+    // Stack offset of the memory of each alloca
+    allocas: HashMap<Value, i64>,
+    frame: i64,
+    // Makes no calls, so ra keeps the return address and needs no slot. Tail calls
+    // count as calls here, they are only known while emitting
+    leaf: bool,
+    // Label of the first block, the others follow in the order of their index
+    first_label: usize,
+    next_label: usize,
+    // Instruction each label is placed before
+    labels: HashMap<Label, usize>,
+    // Block emitted after the current one, jumps to it fall through
+    next: Option<Block>,
+    constants: Vec<(usize, i64)>,
+}
+
+impl<'a> Emitter<'a> {
+    fn new(function: &'a Function, allocation: Allocation, first_label: usize) -> Self {
+        let mut offset = 0;
+        let mut slots = HashMap::new();
+        let mut allocas = HashMap::new();
+
+        let mut reserve = |size: i64| {
+            let start = offset;
+            offset += size;
+            start
+        };
+
+        let values = function.params.iter().copied().chain(
+            function
+                .layout
+                .iter()
+                .flat_map(|block| &function.blocks[block.0].insts)
+                .filter_map(|inst| function.insts[inst.0].result),
+        );
+
+        for value in values {
+            if allocation.spilled.contains(&value) || !in_register(function, value) {
+                slots.insert(value, reserve(slot_size(function.values[value.0].ty)));
+            }
+        }
+
+        let mut leaf = true;
+
+        for block in &function.layout {
+            for inst in &function.blocks[block.0].insts {
+                let data = &function.insts[inst.0];
+
+                match (&data.kind, data.result) {
+                    (InstKind::Alloca { ty }, Some(result)) => {
+                        let bytes = (ty.bits() as i64 + 7) / 8;
+                        allocas.insert(result, reserve((bytes + 7) / 8 * 8));
+                    }
+                    (InstKind::Call { .. }, _) => leaf = false,
+                    _ => {}
+                }
+            }
+        }
+
+        // The saved registers and ra go on top, sp stays 16 byte aligned
+        let saved = 8 * (allocation.used.len() as i64 + i64::from(!leaf));
+        let frame = (offset + saved + 15) / 16 * 16;
+
+        Self {
+            function,
+            code: Vec::new(),
+            allocation,
+            position: 0,
+            slots,
+            allocas,
+            frame,
+            leaf,
+            first_label,
+            next_label: first_label + function.blocks.len(),
+            labels: HashMap::new(),
+            next: None,
+            constants: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, inst: MachineInst) {
+        self.code.push(inst);
+    }
+
+    fn op(&mut self, op: AluOp, rd: Reg, rs1: Reg, rs2: Reg) {
+        self.push(MachineInst::op(op, rd, rs1, rs2));
+    }
+
+    fn imm(&mut self, op: ImmOp, rd: Reg, rs1: Reg, imm: i64) {
+        self.push(MachineInst::imm(op, rd, rs1, imm));
+    }
+
+    fn mv(&mut self, rd: Reg, rs: Reg) {
+        self.imm(ImmOp::Addi, rd, rs, 0);
+    }
+
+    // rd = base + offset, through rd when the offset does not fit in 12 bits
+    fn add_offset(&mut self, rd: Reg, base: Reg, offset: i64) {
+        if (-2048..2048).contains(&offset) {
+            self.imm(ImmOp::Addi, rd, base, offset);
+        } else {
+            self.li(rd, offset);
+            self.op(AluOp::Add, rd, rd, base);
+        }
+    }
+
+    // Offsets past 12 bits in large frames are added to the base first, loads do it in
+    // their destination and stores in t6, which no value is kept in across a store
+    fn ld(&mut self, rd: Reg, base: Reg, offset: i64) {
+        if (-2048..2048).contains(&offset) {
+            self.push(MachineInst::load(3, false, rd, base, offset));
+        } else {
+            self.add_offset(rd, base, offset);
+            self.push(MachineInst::load(3, false, rd, rd, 0));
+        }
+    }
+
+    fn sd(&mut self, src: Reg, base: Reg, offset: i64) {
+        if (-2048..2048).contains(&offset) {
+            self.push(MachineInst::store(3, src, base, offset));
+        } else {
+            self.add_offset(Reg::T6, base, offset);
+            self.push(MachineInst::store(3, src, Reg::T6, 0));
+        }
+    }
+
+    // Moves sp by `offset`, through t0 when it does not fit in 12 bits
+    fn adjust_sp(&mut self, offset: i64) {
+        if offset == 0 {
+            return;
+        }
+
+        if (-2048..2048).contains(&offset) {
+            self.imm(ImmOp::Addi, Reg::Sp, Reg::Sp, offset);
+        } else if offset < 0 {
+            self.li(Reg::T0, -offset);
+            self.op(AluOp::Sub, Reg::Sp, Reg::Sp, Reg::T0);
+        } else {
+            self.li(Reg::T0, offset);
+            self.op(AluOp::Add, Reg::Sp, Reg::Sp, Reg::T0);
+        }
+    }
+
+    fn block_label(&self, block: Block) -> Label {
+        Label(self.first_label + block.0)
+    }
+
+    fn label(&mut self) -> Label {
+        self.next_label += 1;
+        Label(self.next_label - 1)
+    }
+
+    // The label goes before the next instruction
+    fn place(&mut self, label: Label) {
+        self.labels.insert(label, self.code.len());
+    }
+
+    fn branch_to(&mut self, cond: BranchCond, rs1: Reg, rs2: Reg, label: Label) {
+        self.push(MachineInst::branch(cond, rs1, rs2, Target::Label(label)));
+    }
+
+    fn unsupported_inst(&self, inst: Inst) -> CodegenError {
+        CodegenError::UnsupportedInst(
+            self.function.name.clone(),
+            self.function.inst_to_string(inst),
+        )
+    }
+
+    fn unsupported_type(&self, ty: Ty) -> CodegenError {
+        CodegenError::UnsupportedType(self.function.name.clone(), ty)
+    }
+
+    fn value_ty(&self, value: Value) -> Ty {
+        self.function.values[value.0].ty
+    }
+
+    // Loads any 64 bit constant, from the pool when building it takes more than four
+    // instructions since the load only takes two and 8 bytes
+    fn li(&mut self, reg: Reg, value: i64) {
+        let steps = sequence(value);
+
+        if steps.len() > 4 {
+            self.constants.push((self.code.len(), value));
+            self.push(MachineInst::Auipc {
+                rd: reg.into(),
+                imm: 0,
+            });
+            self.ld(reg, reg, 0);
+            return;
+        }
+
+        for (index, step) in steps.into_iter().enumerate() {
+            self.push(constant::instruction(reg, step, index == 0));
+        }
+    }
+
+    // Register of the value at the current instruction, if it is in one
+    fn home(&self, value: Value) -> Option<Reg> {
+        self.allocation.location(value, self.position)
+    }
+
+    fn operand(&mut self, reg: Reg, operand: &Operand) {
+        match operand {
+            Operand::Value(value) => match self.home(*value) {
+                Some(home) if home == reg => {}
+                Some(home) => self.mv(reg, home),
+                None => {
+                    let slot = self.slots[value];
+                    self.ld(reg, Reg::Sp, slot);
+                }
+            },
+            Operand::Const(constant) => {
+                let bits = const_bits(constant).unwrap_or_default();
+                self.li(reg, bits);
+            }
+        }
+    }
+
+    // Register holding the operand, `scratch` unless it is a value already in a register
+    fn source(&mut self, scratch: Reg, operand: &Operand) -> Reg {
+        if let Some(home) = match operand {
+            Operand::Value(value) => self.home(*value),
+            Operand::Const(_) => None,
+        } {
+            return home;
+        }
+
+        self.operand(scratch, operand);
+        scratch
+    }
+
+    // Register to compute the value into, its own one or `scratch` when it is spilled
+    fn target(&self, scratch: Reg, value: Value) -> Reg {
+        self.home(value).unwrap_or(scratch)
+    }
+
+    // Both halves of a 128 bit operand
+    fn operand_pair(&mut self, lo: Reg, hi: Reg, operand: &Operand) {
+        match operand {
+            Operand::Value(value) => {
+                let slot = self.slots[value];
+                self.ld(lo, Reg::Sp, slot);
+                self.ld(hi, Reg::Sp, slot + 8);
+            }
+            Operand::Const(constant) => {
+                let value = pair_bits(constant);
+                self.li(lo, value as i64);
+                self.li(hi, (value >> 64) as i64);
+            }
+        }
+    }
+
+    // Where a parallel move reads the operand
+    fn source_of(&self, operand: &Operand) -> Source {
+        match operand {
+            Operand::Value(value) => match self.home(*value) {
+                Some(home) => Source::Place(Place::Reg(home)),
+                None => Source::Place(Place::Stack(self.slots[value])),
+            },
+            Operand::Const(constant) => Source::Const(const_bits(constant).unwrap_or_default()),
+        }
+    }
+
+    // Both halves of a 128 bit operand, low half first
+    fn pair_sources(&self, operand: &Operand) -> [Source; 2] {
+        match operand {
+            Operand::Value(value) => {
+                let slot = self.slots[value];
+                [slot, slot + 8].map(|offset| Source::Place(Place::Stack(offset)))
+            }
+            Operand::Const(constant) => {
+                let value = pair_bits(constant);
+                [value as i64, (value >> 64) as i64].map(Source::Const)
+            }
+        }
+    }
+
+    fn float_operand(&mut self, reg: FReg, operand: &Operand, fmt: FloatFormat) {
+        self.operand(Reg::T0, operand);
+        self.push(MachineInst::FmvFromInt {
+            fmt,
+            rd: reg.into(),
+            rs1: Reg::T0.into(),
+        });
+    }
+
+    // Spilled values are stored when they are defined, so the slot is valid wherever
+    // they are in the stack
+    fn result(&mut self, reg: Reg, value: Value) {
+        match self.home(value) {
+            Some(home) if home != reg => self.mv(home, reg),
+            _ => {}
+        }
+
+        if let Some(slot) = self.slots.get(&value).copied() {
+            self.sd(reg, Reg::Sp, slot);
+        }
+    }
+
+    fn result_pair(&mut self, lo: Reg, hi: Reg, value: Value) {
+        let slot = self.slots[&value];
+        self.sd(lo, Reg::Sp, slot);
+        self.sd(hi, Reg::Sp, slot + 8);
+    }
+
+    fn float_result(&mut self, reg: FReg, value: Value, fmt: FloatFormat) {
+        self.push(MachineInst::FmvToInt {
+            fmt,
+            rd: Reg::T0.into(),
+            rs1: reg.into(),
+        });
+        self.result(Reg::T0, value);
+    }
+
+    fn shift_pair(&mut self, reg: Reg, amount: u32, arithmetic: bool) {
+        let right = if arithmetic { ImmOp::Srai } else { ImmOp::Srli };
+
+        self.imm(ImmOp::Slli, reg, reg, amount.into());
+        self.imm(right, reg, reg, amount.into());
+    }
+
+    // Extends the low bits of the register back to the representation of `ty`
+    fn normalize(&mut self, reg: Reg, ty: Ty) {
+        match ty {
+            Ty::Int { bits: 64.., .. } => {}
+            Ty::Int { bits: 32, .. } => self.imm(ImmOp::Addiw, reg, reg, 0),
+            Ty::Int { bits, .. } => self.shift_pair(reg, 64 - bits, sign_extended(ty)),
+            _ => {}
+        }
+    }
+
+    fn sign_extend_from(&mut self, reg: Reg, ty: Ty) {
+        if ty.bits() < 64 && !sign_extended(ty) {
+            self.shift_pair(reg, 64 - ty.bits(), true);
+        }
+    }
+
+    fn zero_extend_from(&mut self, reg: Reg, ty: Ty) {
+        if ty.bits() < 64 && sign_extended(ty) {
+            self.shift_pair(reg, 64 - ty.bits(), false);
+        }
+    }
+
+    // Extends the high half in t1 from the bits of `ty` above 64. Unlike single registers,
+    // pairs follow the signedness of the type, like their constants
+    fn extend_high(&mut self, ty: Ty, signed: bool) {
+        if ty.bits() < 128 {
+            self.shift_pair(Reg::T1, 128 - ty.bits(), signed);
+        }
+    }
+
+    // The callee-saved registers in use go right below ra, or on top in leaf functions
+    fn saved(&self) -> Vec<(Reg, i64)> {
+        let used = self.allocation.used.iter();
+        let first = if self.leaf { 1 } else { 2 };
+        let offsets = (first..).map(|slot| self.frame - 8 * slot);
+
+        used.copied().zip(offsets).collect()
+    }
+
+    fn prologue(&mut self) -> Result<(), CodegenError> {
+        let frame = self.frame;
+        self.adjust_sp(-frame);
+
+        if !self.leaf {
+            self.sd(Reg::Ra, Reg::Sp, frame - 8);
+        }
+
+        for (reg, offset) in self.saved() {
+            self.sd(reg, Reg::Sp, offset);
+        }
+
+        let params = &self.function.params;
+        let tys = params.iter().map(|param| self.value_ty(*param));
+        let regs = argument_regs(&self.function.name, tys)?;
+        let mut moves = Vec::new();
+        let mut floats = Vec::new();
+
+        for (param, reg) in params.iter().zip(regs) {
+            match reg {
+                ArgReg::Int(reg) => {
+                    let from = Source::Place(Place::Reg(reg));
+
+                    if let Some(home) = self.home(*param) {
+                        moves.push((Place::Reg(home), from));
+                    }
+
+                    if let Some(slot) = self.slots.get(param) {
+                        moves.push((Place::Stack(*slot), from));
+                    }
+                }
+                ArgReg::Pair(lo, hi) => {
+                    let slot = self.slots[param];
+                    moves.push((Place::Stack(slot), Source::Place(Place::Reg(lo))));
+                    moves.push((Place::Stack(slot + 8), Source::Place(Place::Reg(hi))));
+                }
+                ArgReg::Float(reg, fmt) => floats.push((reg, *param, fmt)),
+            }
+        }
+
+        // Parameters can live in the registers of other parameters, so they all move at
+        // once. The floats go through t0 afterwards, their registers are never allocated
+        self.parallel_move(moves);
+
+        for (reg, param, fmt) in floats {
+            self.float_result(reg, param, fmt);
+        }
+
+        Ok(())
+    }
+
+    // Gives the frame back, leaving ra and the callee-saved registers as they were on entry
+    fn restore(&mut self) {
+        for (reg, offset) in self.saved() {
+            self.ld(reg, Reg::Sp, offset);
+        }
+
+        let frame = self.frame;
+
+        if !self.leaf {
+            self.ld(Reg::Ra, Reg::Sp, frame - 8);
+        }
+
+        self.adjust_sp(frame);
+    }
+
+    fn epilogue(&mut self) {
+        self.restore();
+        self.push(MachineInst::Jalr {
+            rd: Reg::Zero.into(),
+            rs1: Reg::Ra.into(),
+            offset: 0,
+        });
+    }
+
+    fn block(&mut self, block: Block) -> Result<(), CodegenError> {
+        self.place(self.block_label(block));
+
+        let insts = &self.function.blocks[block.0].insts;
+
+        for (index, inst) in insts.iter().enumerate() {
+            let data = &self.function.insts[inst.0];
+            let kind = &data.kind;
+
+            self.position = self.allocation.positions[inst];
+
+            // Written by the predecessors
+            if let InstKind::Phi { .. } = kind {
+                continue;
+            }
+
+            for (value, reg) in self.allocation.reloads(self.position).to_vec() {
+                let slot = self.slots[&value];
+                self.ld(reg, Reg::Sp, slot);
+            }
+
+            if let InstKind::Call {
+                callee,
+                ret,
+                args,
+                musttail,
+            } = kind
+            {
+                match self.tail_position(block, *ret, data.result, insts.get(index + 1)) {
+                    // The return is part of the tail call
+                    Ok(()) => return self.tail_call(callee, args),
+                    Err(reason) if *musttail => {
+                        return Err(CodegenError::MustTail(
+                            self.function.name.clone(),
+                            callee.clone(),
+                            reason,
+                        ));
+                    }
+                    Err(_) => {}
+                }
+            }
+
+            self.inst(block, *inst)?;
+        }
+
+        Ok(())
+    }
+
+    // A call can jump to the callee instead when the caller returns its result right
+    // after, the callee then returns to the caller of this function
+    fn tail_position(
+        &self,
+        block: Block,
+        ret: Ty,
+        result: Option<Value>,
+        next: Option<&Inst>,
+    ) -> Result<(), &'static str> {
+        let mut returned = next.map(|next| &self.function.insts[next.0].kind);
+        let mut phis = HashMap::new();
+
+        // A jump to a block that only returns, through its phis, counts as a return
+        if let Some(InstKind::Jump { target }) = returned {
+            let insts = &self.function.blocks[target.0].insts;
+
+            for inst in insts {
+                let data = &self.function.insts[inst.0];
+
+                match (&data.kind, data.result) {
+                    (InstKind::Phi { incoming, .. }, Some(phi)) => {
+                        if let Some((value, _)) = incoming.iter().find(|(_, pred)| *pred == block) {
+                            phis.insert(phi, value.clone());
+                        }
+                    }
+                    (kind, _) => {
+                        returned = Some(kind);
+                        break;
+                    }
+                }
+            }
+        }
+
+        let Some(InstKind::Ret { ty, value }) = returned else {
+            return Err("it is not followed by a return");
+        };
+
+        let value = match value {
+            Some(Operand::Value(value)) => match phis.get(value) {
+                Some(Operand::Value(value)) => Some(*value),
+                Some(Operand::Const(_)) => return Err("the return does not use its result"),
+                None => Some(*value),
+            },
+            Some(Operand::Const(_)) => return Err("the return does not use its result"),
+            None => None,
+        };
+
+        if *ty != ret || value != result {
+            return Err("the return does not use its result");
+        }
+
+        // Pointers to the frame could reach the callee, which would find it gone
+        if !self.allocas.is_empty() {
+            return Err("the caller has stack memory");
+        }
+
+        Ok(())
+    }
+
+    fn tail_call(&mut self, callee: &str, args: &[(Ty, Operand)]) -> Result<(), CodegenError> {
+        self.arguments(callee, args)?;
+        self.restore();
+        self.push(MachineInst::jal(
+            Reg::Zero,
+            Target::Symbol(callee.to_string()),
+        ));
+
+        Ok(())
+    }
+
+    // Moves into the places the successor expects: its phis and the values that change
+    // register or leave the stack on the edge
+    fn edge_moves(&self, pred: Block, succ: Block) -> Vec<(Place, Source)> {
+        let end = self.position;
+        let start = self.allocation.bounds[&succ].0;
+        let mut moves = Vec::new();
+
+        let place = |value: Value, position: usize| match self.allocation.location(value, position)
+        {
+            Some(reg) => Place::Reg(reg),
+            None => Place::Stack(self.slots[&value]),
+        };
+
+        for inst in &self.function.blocks[succ.0].insts {
+            let data = &self.function.insts[inst.0];
+
+            let (InstKind::Phi { ty, incoming }, Some(result)) = (&data.kind, data.result) else {
+                break;
+            };
+
+            let Some((value, _)) = incoming.iter().find(|(_, from)| *from == pred) else {
+                continue;
+            };
+
+            let halves = if ty.bits() > 64 { 2 } else { 1 };
+
+            for half in 0..halves {
+                let source = match value {
+                    Operand::Value(value) => match place(*value, end) {
+                        Place::Stack(offset) => Source::Place(Place::Stack(offset + 8 * half)),
+                        place => Source::Place(place),
+                    },
+                    Operand::Const(constant) if halves == 2 => {
+                        Source::Const((pair_bits(constant) >> (64 * half)) as i64)
+                    }
+                    Operand::Const(constant) => {
+                        Source::Const(const_bits(constant).unwrap_or_default())
+                    }
+                };
+
+                if let Some(slot) = self.slots.get(&result) {
+                    moves.push((Place::Stack(slot + 8 * half), source));
+                }
+
+                if let Some(reg) = self.allocation.location(result, start) {
+                    moves.push((Place::Reg(reg), source));
+                }
+            }
+        }
+
+        for value in self.allocation.live_in(succ).iter().copied() {
+            if let Some(reg) = self.allocation.location(value, start) {
+                moves.push((Place::Reg(reg), Source::Place(place(value, end))));
+            }
+        }
+
+        moves.retain(|(to, from)| Source::Place(*to) != *from);
+        moves
+    }
+
+    fn move_to(&mut self, to: Place, from: Source) {
+        match (to, from) {
+            (Place::Reg(to), Source::Const(value)) => self.li(to, value),
+            (Place::Reg(to), Source::Place(Place::Reg(from))) => self.mv(to, from),
+            (Place::Reg(to), Source::Place(Place::Stack(from))) => self.ld(to, Reg::Sp, from),
+            (Place::Stack(to), Source::Place(Place::Reg(from))) => self.sd(from, Reg::Sp, to),
+            (Place::Stack(to), from) => {
+                self.move_to(Place::Reg(Reg::T0), from);
+                self.sd(Reg::T0, Reg::Sp, to);
+            }
+        }
+    }
+
+    // Performs the moves as if they all read before any of them writes. A move goes once
+    // nothing else reads its destination, and when every move waits on another they form
+    // cycles, which are broken by saving one destination in t1
+    fn parallel_move(&mut self, mut moves: Vec<(Place, Source)>) {
+        moves.retain(|(to, from)| Source::Place(*to) != *from);
+
+        while !moves.is_empty() {
+            let ready = moves
+                .iter()
+                .position(|(to, _)| moves.iter().all(|(_, from)| *from != Source::Place(*to)));
+
+            if let Some(index) = ready {
+                let (to, from) = moves.remove(index);
+                self.move_to(to, from);
+                continue;
+            }
+
+            let saved = moves[0].0;
+            self.move_to(Place::Reg(Reg::T1), Source::Place(saved));
+
+            for (_, from) in &mut moves {
+                if *from == Source::Place(saved) {
+                    *from = Source::Place(Place::Reg(Reg::T1));
+                }
+            }
+        }
+    }
+
+    fn inst(&mut self, block: Block, inst: Inst) -> Result<(), CodegenError> {
+        let data = &self.function.insts[inst.0];
+        let result = data.result;
+
+        match &data.kind {
+            InstKind::Binary {
+                op, ty, lhs, rhs, ..
+            } => self.binary(inst, *op, *ty, lhs, rhs, result)?,
+            InstKind::Icmp { cond, ty, lhs, rhs } => {
+                self.icmp(inst, *cond, *ty, lhs, rhs, result)?
+            }
+            InstKind::Fcmp { cond, ty, lhs, rhs } => {
+                let fmt = float_format(*ty).ok_or_else(|| self.unsupported_type(*ty))?;
+
+                if let Some(result) = result {
+                    let target = self.target(Reg::T0, result);
+                    self.fcmp(target, *cond, fmt, lhs, rhs);
+                    self.result(target, result);
+                }
+            }
+            InstKind::Cast {
+                op,
+                from,
+                value,
+                to,
+            } => self.cast(inst, *op, *from, value, *to, result)?,
+            InstKind::Alloca { .. } => {
+                if let Some(result) = result {
+                    let memory = self.allocas[&result];
+                    self.add_offset(Reg::T0, Reg::Sp, memory);
+                    self.result(Reg::T0, result);
+                }
+            }
+            InstKind::Load { ty, ptr } => {
+                self.operand(Reg::T1, ptr);
+
+                let Some(result) = result else {
+                    return Ok(());
+                };
+
+                if ty.bits() > 120 {
+                    self.ld(Reg::T0, Reg::T1, 0);
+                    self.ld(Reg::T2, Reg::T1, 8);
+                    self.result_pair(Reg::T0, Reg::T2, result);
+                } else {
+                    let width = access_width(*ty).ok_or_else(|| self.unsupported_type(*ty))?;
+                    // A whole register has no unsigned load, nor needs one
+                    let unsigned = width < 3 && ty.is_integer() && !sign_extended(*ty);
+                    self.push(MachineInst::load(width, unsigned, Reg::T0, Reg::T1, 0));
+                    self.normalize(Reg::T0, *ty);
+                    self.result(Reg::T0, result);
+                }
+            }
+            InstKind::Store { ty, value, ptr } => {
+                self.operand(Reg::T1, ptr);
+
+                if ty.bits() > 120 {
+                    self.operand_pair(Reg::T0, Reg::T2, value);
+                    self.sd(Reg::T0, Reg::T1, 0);
+                    self.sd(Reg::T2, Reg::T1, 8);
+                } else {
+                    let width = access_width(*ty).ok_or_else(|| self.unsupported_type(*ty))?;
+                    self.operand(Reg::T0, value);
+                    self.push(MachineInst::store(width, Reg::T0, Reg::T1, 0));
+                }
+            }
+            // Addresses are plain 64 bit integers
+            InstKind::PtrAdd { ptr, offset } => {
+                self.binary(inst, BinaryOp::Add, Ty::I64, ptr, offset, result)?
+            }
+            InstKind::Call {
+                callee, ret, args, ..
+            } => {
+                self.call(callee, *ret, args, result)?;
+            }
+            InstKind::Syscall { args } => {
+                let regs = [Reg::A7].iter().chain(&INT_ARGS);
+                let moves = regs
+                    .zip(args)
+                    .map(|(reg, arg)| (Place::Reg(*reg), self.source_of(arg)))
+                    .collect();
+
+                self.parallel_move(moves);
+                self.push(MachineInst::Ecall);
+
+                if let Some(result) = result {
+                    self.result(Reg::A0, result);
+                }
+            }
+            // Written by the predecessors
+            InstKind::Phi { .. } => {}
+            InstKind::Jump { target } => {
+                let moves = self.edge_moves(block, *target);
+                self.parallel_move(moves);
+                self.jump(*target);
+            }
+            InstKind::Branch {
+                cond,
+                then_dest,
+                else_dest,
+            } => self.branch(block, cond, *then_dest, *else_dest),
+            InstKind::Ret { ty, value } => {
+                if let Some(value) = value {
+                    match float_format(*ty) {
+                        Some(fmt) => self.float_operand(FReg::Fa0, value, fmt),
+                        None if ty.bits() > 64 => self.operand_pair(Reg::A0, Reg::A1, value),
+                        None => self.operand(Reg::A0, value),
+                    }
+                }
+
+                self.epilogue();
+            }
+        }
+
+        Ok(())
+    }
+
+    // An edge with moves gets them after the branch, a branch goes straight to the
+    // successor only when its edge has none
+    fn branch(&mut self, block: Block, cond: &Operand, then_dest: Block, else_dest: Block) {
+        let then_moves = self.edge_moves(block, then_dest);
+        let else_moves = self.edge_moves(block, else_dest);
+
+        let cond = self.source(Reg::T0, cond);
+
+        if then_moves.is_empty() && (self.next != Some(then_dest) || !else_moves.is_empty()) {
+            let then_label = self.block_label(then_dest);
+            self.branch_to(BranchCond::Ne, cond, Reg::Zero, then_label);
+            self.parallel_move(else_moves);
+            self.jump(else_dest);
+        } else if else_moves.is_empty() {
+            // Inverted when the then side comes next or has moves
+            let else_label = self.block_label(else_dest);
+            self.branch_to(BranchCond::Eq, cond, Reg::Zero, else_label);
+            self.parallel_move(then_moves);
+            self.jump(then_dest);
+        } else {
+            // Both edges have moves, the branch skips over the moves of one side to the
+            // other, which goes last so it can fall through
+            let (first, second, skip_if) = if self.next == Some(then_dest) {
+                (
+                    (else_dest, else_moves),
+                    (then_dest, then_moves),
+                    BranchCond::Ne,
+                )
+            } else {
+                (
+                    (then_dest, then_moves),
+                    (else_dest, else_moves),
+                    BranchCond::Eq,
+                )
+            };
+
+            let skip = self.label();
+            self.branch_to(skip_if, cond, Reg::Zero, skip);
+            self.parallel_move(first.1);
+            self.jump(first.0);
+
+            self.place(skip);
+            self.parallel_move(second.1);
+            self.jump(second.0);
+        }
+    }
+
+    fn jump(&mut self, target: Block) {
+        if self.next != Some(target) {
+            let label = self.block_label(target);
+            self.push(MachineInst::jal(Reg::Zero, Target::Label(label)));
+        }
+    }
+
+    fn binary(
+        &mut self,
+        inst: Inst,
+        op: BinaryOp,
+        ty: Ty,
+        lhs: &Operand,
+        rhs: &Operand,
+        result: Option<Value>,
+    ) -> Result<(), CodegenError> {
+        let Some(result) = result else {
+            return Ok(());
+        };
+
+        if let Some(fmt) = float_format(ty) {
+            self.float_operand(FReg::Ft0, lhs, fmt);
+            self.float_operand(FReg::Ft1, rhs, fmt);
+
+            let op = match op {
+                BinaryOp::Fadd => FloatOp::Add,
+                BinaryOp::Fsub => FloatOp::Sub,
+                BinaryOp::Fmul => FloatOp::Mul,
+                BinaryOp::Fdiv => FloatOp::Div,
+                _ => return Err(self.unsupported_inst(inst)),
+            };
+
+            self.push(MachineInst::FloatOp {
+                op,
+                fmt,
+                rd: FReg::Ft0.into(),
+                rs1: FReg::Ft0.into(),
+                rs2: FReg::Ft1.into(),
+            });
+
+            self.float_result(FReg::Ft0, result, fmt);
+            return Ok(());
+        }
+
+        if ty.bits() == 128 {
+            return self.binary_pair(inst, op, lhs, rhs, result);
+        }
+
+        let word = match ty {
+            Ty::Int { bits: 32, .. } => true,
+            Ty::Int { bits: 64, .. } => false,
+            _ => return Err(self.unsupported_type(ty)),
+        };
+
+        let lhs = self.source(Reg::T0, lhs);
+        let target = self.target(Reg::T0, result);
+
+        if let Some(inst) = binary_imm(op, word, target, lhs, rhs) {
+            self.push(inst);
+            self.result(target, result);
+            return Ok(());
+        }
+
+        let rhs = self.source(Reg::T1, rhs);
+
+        let alu = match (op, word) {
+            (BinaryOp::Add, false) => AluOp::Add,
+            (BinaryOp::Add, true) => AluOp::Addw,
+            (BinaryOp::Sub, false) => AluOp::Sub,
+            (BinaryOp::Sub, true) => AluOp::Subw,
+            (BinaryOp::Mul, false) => AluOp::Mul,
+            (BinaryOp::Mul, true) => AluOp::Mulw,
+            (BinaryOp::Smulh, false) => AluOp::Mulh,
+            // Both halves are sign extended, so the 64 bit product is exact
+            (BinaryOp::Smulh, true) => {
+                self.op(AluOp::Mul, target, lhs, rhs);
+                self.imm(ImmOp::Srai, target, target, 32);
+                self.result(target, result);
+                return Ok(());
+            }
+            (BinaryOp::Sdiv, false) => AluOp::Div,
+            (BinaryOp::Sdiv, true) => AluOp::Divw,
+            (BinaryOp::Udiv, false) => AluOp::Divu,
+            (BinaryOp::Udiv, true) => AluOp::Divuw,
+            (BinaryOp::Srem, false) => AluOp::Rem,
+            (BinaryOp::Srem, true) => AluOp::Remw,
+            (BinaryOp::Urem, false) => AluOp::Remu,
+            (BinaryOp::Urem, true) => AluOp::Remuw,
+            (BinaryOp::And, _) => AluOp::And,
+            (BinaryOp::Or, _) => AluOp::Or,
+            (BinaryOp::Xor, _) => AluOp::Xor,
+            (BinaryOp::Shl, false) => AluOp::Sll,
+            (BinaryOp::Shl, true) => AluOp::Sllw,
+            (BinaryOp::Lshr, false) => AluOp::Srl,
+            (BinaryOp::Lshr, true) => AluOp::Srlw,
+            (BinaryOp::Ashr, false) => AluOp::Sra,
+            (BinaryOp::Ashr, true) => AluOp::Sraw,
+            _ => return Err(self.unsupported_inst(inst)),
+        };
+
+        self.op(alu, target, lhs, rhs);
+        self.result(target, result);
+        Ok(())
+    }
+
+    // 128 bit arithmetic on register pairs, t0/t1 hold the lhs and t2/t3 the rhs
+    fn binary_pair(
+        &mut self,
+        inst: Inst,
+        op: BinaryOp,
+        lhs: &Operand,
+        rhs: &Operand,
+        result: Value,
+    ) -> Result<(), CodegenError> {
+        self.operand_pair(Reg::T0, Reg::T1, lhs);
+        self.operand_pair(Reg::T2, Reg::T3, rhs);
+
+        let (t0, t1, t2, t3, t4, t5) = (Reg::T0, Reg::T1, Reg::T2, Reg::T3, Reg::T4, Reg::T5);
+
+        match op {
+            BinaryOp::Add => {
+                self.op(AluOp::Add, t4, t0, t2);
+                // Carry out of the low half
+                self.op(AluOp::Sltu, t0, t4, t0);
+                self.op(AluOp::Add, t1, t1, t3);
+                self.op(AluOp::Add, t1, t1, t0);
+                self.mv(t0, t4);
+            }
+            BinaryOp::Sub => {
+                // Borrow out of the low half
+                self.op(AluOp::Sltu, t4, t0, t2);
+                self.op(AluOp::Sub, t0, t0, t2);
+                self.op(AluOp::Sub, t1, t1, t3);
+                self.op(AluOp::Sub, t1, t1, t4);
+            }
+            // The cross products only reach the high half
+            BinaryOp::Mul => {
+                self.op(AluOp::Mul, t4, t0, t3);
+                self.op(AluOp::Mul, t5, t1, t2);
+                self.op(AluOp::Add, t4, t4, t5);
+                self.op(AluOp::Mulhu, t5, t0, t2);
+                self.op(AluOp::Add, t1, t4, t5);
+                self.op(AluOp::Mul, t0, t0, t2);
+            }
+            BinaryOp::And => {
+                self.op(AluOp::And, t0, t0, t2);
+                self.op(AluOp::And, t1, t1, t3);
+            }
+            BinaryOp::Or => {
+                self.op(AluOp::Or, t0, t0, t2);
+                self.op(AluOp::Or, t1, t1, t3);
+            }
+            BinaryOp::Xor => {
+                self.op(AluOp::Xor, t0, t0, t2);
+                self.op(AluOp::Xor, t1, t1, t3);
+            }
+            BinaryOp::Shl | BinaryOp::Lshr | BinaryOp::Ashr => self.shift_wide(op),
+            BinaryOp::Udiv | BinaryOp::Urem => self.divide_wide(),
+            BinaryOp::Sdiv | BinaryOp::Srem => self.divide_wide_signed(),
+            _ => return Err(self.unsupported_inst(inst)),
+        }
+
+        match op {
+            BinaryOp::Urem | BinaryOp::Srem => self.result_pair(Reg::T4, Reg::T5, result),
+            _ => self.result_pair(Reg::T0, Reg::T1, result),
+        }
+
+        Ok(())
+    }
+
+    // Shifts t0/t1 by the low 7 bits of t2. Both halves are computed for an amount
+    // below 64 and for one of 64 or more, then the mask in t6 picks one of them
+    fn shift_wide(&mut self, op: BinaryOp) {
+        let (t0, t1, t2, t4, t5, t6) = (Reg::T0, Reg::T1, Reg::T2, Reg::T4, Reg::T5, Reg::T6);
+
+        // Whatever crosses between the halves, with one of the shifts split in two so
+        // an amount of 0 moves nothing
+        self.imm(ImmOp::Xori, t6, t2, -1);
+
+        if op == BinaryOp::Shl {
+            self.op(AluOp::Sll, t4, t0, t2);
+            self.imm(ImmOp::Srli, t5, t0, 1);
+            self.op(AluOp::Srl, t5, t5, t6);
+            self.op(AluOp::Sll, t1, t1, t2);
+            self.op(AluOp::Or, t1, t1, t5);
+        } else {
+            let shift = if op == BinaryOp::Ashr {
+                AluOp::Sra
+            } else {
+                AluOp::Srl
+            };
+
+            self.op(shift, t4, t1, t2);
+            self.imm(ImmOp::Slli, t5, t1, 1);
+            self.op(AluOp::Sll, t5, t5, t6);
+            self.op(AluOp::Srl, t0, t0, t2);
+            self.op(AluOp::Or, t0, t0, t5);
+        }
+
+        // All ones when the amount is 64 or more
+        self.imm(ImmOp::Srli, t6, t2, 6);
+        self.imm(ImmOp::Andi, t6, t6, 1);
+        self.op(AluOp::Sub, t6, Reg::Zero, t6);
+
+        // x ^ ((x ^ y) & mask) is y under the mask and x elsewhere
+        match op {
+            BinaryOp::Shl => {
+                self.op(AluOp::Xor, t5, t1, t4);
+                self.op(AluOp::And, t5, t5, t6);
+                self.op(AluOp::Xor, t1, t1, t5);
+                self.op(AluOp::And, t5, t4, t6);
+                self.op(AluOp::Xor, t0, t4, t5);
+            }
+            _ => {
+                self.op(AluOp::Xor, t5, t0, t4);
+                self.op(AluOp::And, t5, t5, t6);
+                self.op(AluOp::Xor, t0, t0, t5);
+
+                // The high half of a shift by 64 or more is all sign bits or zero
+                let fill = if op == BinaryOp::Ashr {
+                    self.imm(ImmOp::Srai, t1, t1, 63);
+                    t1
+                } else {
+                    Reg::Zero
+                };
+
+                self.op(AluOp::Xor, t5, t4, fill);
+                self.op(AluOp::And, t5, t5, t6);
+                self.op(AluOp::Xor, t1, t4, t5);
+            }
+        }
+    }
+
+    // Two's complement of a pair, the borrow goes to the high half unless the low is 0
+    fn negate_pair(&mut self, lo: Reg, hi: Reg) {
+        let done = self.label();
+
+        self.op(AluOp::Sub, lo, Reg::Zero, lo);
+        self.op(AluOp::Sub, hi, Reg::Zero, hi);
+        self.branch_to(BranchCond::Eq, lo, Reg::Zero, done);
+        self.imm(ImmOp::Addi, hi, hi, -1);
+        self.place(done);
+    }
+
+    // Restoring division of t0/t1 by t2/t3, one quotient bit per round. The dividend
+    // shifts into the remainder in t4/t5 and the quotient bits into the dividend, t6
+    // counts the rounds
+    fn divide_wide(&mut self) {
+        let (t0, t1, t2, t3, t4, t5, t6) = (
+            Reg::T0,
+            Reg::T1,
+            Reg::T2,
+            Reg::T3,
+            Reg::T4,
+            Reg::T5,
+            Reg::T6,
+        );
+
+        self.mv(t4, Reg::Zero);
+        self.mv(t5, Reg::Zero);
+        self.imm(ImmOp::Addi, t6, Reg::Zero, 128);
+
+        let round = self.label();
+        self.place(round);
+
+        // Shifts the 256 bits of t5:t4:t1:t0 left by one
+        for (hi, lo) in [(t5, t4), (t4, t1), (t1, t0)] {
+            let positive = self.label();
+
+            self.imm(ImmOp::Slli, hi, hi, 1);
+            self.branch_to(BranchCond::Ge, lo, Reg::Zero, positive);
+            self.imm(ImmOp::Ori, hi, hi, 1);
+            self.place(positive);
+        }
+
+        self.imm(ImmOp::Slli, t0, t0, 1);
+
+        // Subtracts when the remainder is at least the divisor
+        let (subtract, borrow, next) = (self.label(), self.label(), self.label());
+        self.branch_to(BranchCond::Ltu, t5, t3, next);
+        self.branch_to(BranchCond::Ne, t5, t3, subtract);
+        self.branch_to(BranchCond::Ltu, t4, t2, next);
+
+        self.place(subtract);
+        self.op(AluOp::Sub, t5, t5, t3);
+        self.branch_to(BranchCond::Geu, t4, t2, borrow);
+        self.imm(ImmOp::Addi, t5, t5, -1);
+        self.place(borrow);
+        self.op(AluOp::Sub, t4, t4, t2);
+        self.imm(ImmOp::Ori, t0, t0, 1);
+
+        self.place(next);
+        self.imm(ImmOp::Addi, t6, t6, -1);
+        self.branch_to(BranchCond::Ne, t6, Reg::Zero, round);
+    }
+
+    // Divides the magnitudes. The quotient is negative when the signs differ and the
+    // remainder takes the sign of the dividend, both signs wait below sp meanwhile
+    fn divide_wide_signed(&mut self) {
+        let (t0, t1, t2, t3, t4, t5) = (Reg::T0, Reg::T1, Reg::T2, Reg::T3, Reg::T4, Reg::T5);
+
+        self.op(AluOp::Xor, t4, t1, t3);
+        self.imm(ImmOp::Addi, Reg::Sp, Reg::Sp, -16);
+        self.sd(t4, Reg::Sp, 0);
+        self.sd(t1, Reg::Sp, 8);
+
+        for (lo, hi) in [(t0, t1), (t2, t3)] {
+            let positive = self.label();
+            self.branch_to(BranchCond::Ge, hi, Reg::Zero, positive);
+            self.negate_pair(lo, hi);
+            self.place(positive);
+        }
+
+        self.divide_wide();
+
+        for (offset, lo, hi) in [(0, t0, t1), (8, t4, t5)] {
+            let positive = self.label();
+            self.ld(Reg::T6, Reg::Sp, offset);
+            self.branch_to(BranchCond::Ge, Reg::T6, Reg::Zero, positive);
+            self.negate_pair(lo, hi);
+            self.place(positive);
+        }
+
+        self.imm(ImmOp::Addi, Reg::Sp, Reg::Sp, 16);
+    }
+
+    fn icmp(
+        &mut self,
+        inst: Inst,
+        cond: IntCC,
+        ty: Ty,
+        lhs: &Operand,
+        rhs: &Operand,
+        result: Option<Value>,
+    ) -> Result<(), CodegenError> {
+        let Some(result) = result else {
+            return Ok(());
+        };
+
+        let pair = ty.bits() > 64;
+
+        if ty.is_integer() && !matches!(ty.bits(), 32 | 64 | 128) {
+            return Err(self.unsupported_inst(inst));
+        }
+
+        // Single registers are compared where they are, pairs are loaded into t0/t1 and
+        // t2/t3. The result goes straight to its register
+        let (a, b) = if pair {
+            self.operand_pair(Reg::T0, Reg::T1, lhs);
+            self.operand_pair(Reg::T2, Reg::T3, rhs);
+            ((Reg::T0, Reg::T1), (Reg::T2, Reg::T3))
+        } else {
+            let lhs = self.source(Reg::T0, lhs);
+            let rhs = self.source(Reg::T2, rhs);
+            ((lhs, Reg::Zero), (rhs, Reg::Zero))
+        };
+
+        let target = self.target(Reg::T0, result);
+        let signed = matches!(cond, IntCC::Slt | IntCC::Sle | IntCC::Sgt | IntCC::Sge);
+
+        match cond {
+            IntCC::Eq | IntCC::Ne => {
+                if pair {
+                    self.op(AluOp::Xor, Reg::T0, a.0, b.0);
+                    self.op(AluOp::Xor, Reg::T1, a.1, b.1);
+                    self.op(AluOp::Or, target, Reg::T0, Reg::T1);
+                } else {
+                    self.op(AluOp::Xor, target, a.0, b.0);
+                }
+
+                if cond == IntCC::Eq {
+                    self.imm(ImmOp::Sltiu, target, target, 1);
+                } else {
+                    self.op(AluOp::Sltu, target, Reg::Zero, target);
+                }
+            }
+            // a < b, b > a and their negations
+            _ => {
+                let swap = matches!(cond, IntCC::Sgt | IntCC::Ugt | IntCC::Sle | IntCC::Ule);
+                let negate = matches!(cond, IntCC::Sle | IntCC::Ule | IntCC::Sge | IntCC::Uge);
+                let (a, b) = if swap { (b, a) } else { (a, b) };
+
+                self.less(target, signed, pair, a, b);
+
+                if negate {
+                    self.imm(ImmOp::Xori, target, target, 1);
+                }
+            }
+        }
+
+        self.result(target, result);
+        Ok(())
+    }
+
+    // rd = a < b, pairs go through t4-t6 and only write rd at the end
+    fn less(&mut self, rd: Reg, signed: bool, pair: bool, a: (Reg, Reg), b: (Reg, Reg)) {
+        let compare = if signed { AluOp::Slt } else { AluOp::Sltu };
+        let (t4, t5, t6) = (Reg::T4, Reg::T5, Reg::T6);
+
+        if !pair {
+            self.op(compare, rd, a.0, b.0);
+            return;
+        }
+
+        // The high halves decide unless they are equal
+        self.op(compare, t4, a.1, b.1);
+        self.op(AluOp::Xor, t5, a.1, b.1);
+        self.imm(ImmOp::Sltiu, t5, t5, 1);
+        self.op(AluOp::Sltu, t6, a.0, b.0);
+        self.op(AluOp::And, t5, t5, t6);
+        self.op(AluOp::Or, rd, t4, t5);
+    }
+
+    fn float_cmp(&mut self, cond: FloatCmp, fmt: FloatFormat, rd: Reg, rs1: FReg, rs2: FReg) {
+        self.push(MachineInst::FloatCmp {
+            cond,
+            fmt,
+            rd: rd.into(),
+            rs1: rs1.into(),
+            rs2: rs2.into(),
+        });
+    }
+
+    // rd = the condition, ordered conditions are false and unordered ones true with NaNs.
+    // The operands go through ft0 and ft1, t1 holds the second comparison of the
+    // conditions that take two
+    fn fcmp(&mut self, rd: Reg, cond: FloatCC, fmt: FloatFormat, lhs: &Operand, rhs: &Operand) {
+        self.float_operand(FReg::Ft0, lhs, fmt);
+        self.float_operand(FReg::Ft1, rhs, fmt);
+
+        let t1 = Reg::T1;
+        let (a, b) = (FReg::Ft0, FReg::Ft1);
+
+        let negate = matches!(
+            cond,
+            FloatCC::Uno
+                | FloatCC::Ueq
+                | FloatCC::Une
+                | FloatCC::Ult
+                | FloatCC::Ule
+                | FloatCC::Ugt
+                | FloatCC::Uge
+        );
+
+        match cond {
+            FloatCC::Oeq | FloatCC::Une => self.float_cmp(FloatCmp::Eq, fmt, rd, a, b),
+            FloatCC::Olt | FloatCC::Uge => self.float_cmp(FloatCmp::Lt, fmt, rd, a, b),
+            FloatCC::Ole | FloatCC::Ugt => self.float_cmp(FloatCmp::Le, fmt, rd, a, b),
+            FloatCC::Ogt | FloatCC::Ule => self.float_cmp(FloatCmp::Lt, fmt, rd, b, a),
+            FloatCC::Oge | FloatCC::Ult => self.float_cmp(FloatCmp::Le, fmt, rd, b, a),
+            FloatCC::Ord | FloatCC::Uno => {
+                self.float_cmp(FloatCmp::Eq, fmt, rd, a, a);
+                self.float_cmp(FloatCmp::Eq, fmt, t1, b, b);
+                self.op(AluOp::And, rd, rd, t1);
+            }
+            FloatCC::One | FloatCC::Ueq => {
+                self.float_cmp(FloatCmp::Lt, fmt, rd, a, b);
+                self.float_cmp(FloatCmp::Lt, fmt, t1, b, a);
+                self.op(AluOp::Or, rd, rd, t1);
+            }
+        }
+
+        if negate {
+            self.imm(ImmOp::Xori, rd, rd, 1);
+        }
+    }
+
+    fn cast(
+        &mut self,
+        inst: Inst,
+        op: CastOp,
+        from: Ty,
+        value: &Operand,
+        to: Ty,
+        result: Option<Value>,
+    ) -> Result<(), CodegenError> {
+        let Some(result) = result else {
+            return Ok(());
+        };
+
+        match op {
+            CastOp::Trunc if to.bits() > 64 => {
+                self.operand_pair(Reg::T0, Reg::T1, value);
+                self.extend_high(to, to.is_signed());
+                self.result_pair(Reg::T0, Reg::T1, result);
+            }
+            CastOp::Trunc => {
+                self.operand(Reg::T0, value);
+                self.normalize(Reg::T0, to);
+                self.result(Reg::T0, result);
+            }
+            CastOp::Zext | CastOp::Sext if from.bits() > 64 => {
+                self.operand_pair(Reg::T0, Reg::T1, value);
+                self.extend_high(from, op == CastOp::Sext);
+                self.extend_high(to, to.is_signed());
+                self.result_pair(Reg::T0, Reg::T1, result);
+            }
+            CastOp::Zext | CastOp::Sext => {
+                self.operand(Reg::T0, value);
+
+                if op == CastOp::Zext {
+                    self.zero_extend_from(Reg::T0, from);
+                } else {
+                    self.sign_extend_from(Reg::T0, from);
+                }
+
+                if to.bits() > 64 {
+                    if op == CastOp::Zext {
+                        self.mv(Reg::T1, Reg::Zero);
+                    } else {
+                        self.imm(ImmOp::Srai, Reg::T1, Reg::T0, 63);
+                    }
+
+                    self.result_pair(Reg::T0, Reg::T1, result);
+                } else {
+                    self.normalize(Reg::T0, to);
+                    self.result(Reg::T0, result);
+                }
+            }
+            CastOp::Fptosi => {
+                let fmt = float_format(from).ok_or_else(|| self.unsupported_type(from))?;
+
+                if to.bits() > 64 {
+                    return Err(self.unsupported_inst(inst));
+                }
+
+                // Unsigned targets take the unsigned conversion, as constant folding does
+                let int = match to {
+                    Ty::Int { signed: false, .. } => IntFormat::Lu,
+                    _ => IntFormat::L,
+                };
+
+                self.float_operand(FReg::Ft0, value, fmt);
+                self.push(MachineInst::FcvtToInt {
+                    fmt,
+                    int,
+                    rm: RoundingMode::Rtz,
+                    rd: Reg::T0.into(),
+                    rs1: FReg::Ft0.into(),
+                });
+                self.normalize(Reg::T0, to);
+                self.result(Reg::T0, result);
+            }
+            CastOp::Sitofp => {
+                let fmt = float_format(to).ok_or_else(|| self.unsupported_type(to))?;
+
+                if from.bits() > 64 {
+                    return Err(self.unsupported_inst(inst));
+                }
+
+                self.operand(Reg::T0, value);
+                self.sign_extend_from(Reg::T0, from);
+                self.push(MachineInst::FcvtFromInt {
+                    fmt,
+                    int: IntFormat::L,
+                    rm: RoundingMode::Dyn,
+                    rd: FReg::Ft0.into(),
+                    rs1: Reg::T0.into(),
+                });
+                self.float_result(FReg::Ft0, result, fmt);
+            }
+        }
+
+        Ok(())
+    }
+
+    // Loads the arguments of a call into a0-a7 and fa0-fa7. The floats go first through
+    // t0, then the integer registers in one parallel move, as the arguments can live in
+    // each other's registers
+    fn arguments(&mut self, callee: &str, args: &[(Ty, Operand)]) -> Result<(), CodegenError> {
+        let regs = argument_regs(callee, args.iter().map(|(ty, _)| *ty))?;
+        let mut moves = Vec::new();
+
+        for ((_, arg), reg) in args.iter().zip(regs) {
+            match reg {
+                ArgReg::Int(reg) => moves.push((Place::Reg(reg), self.source_of(arg))),
+                ArgReg::Pair(lo, hi) => {
+                    let [lo_source, hi_source] = self.pair_sources(arg);
+                    moves.push((Place::Reg(lo), lo_source));
+                    moves.push((Place::Reg(hi), hi_source));
+                }
+                ArgReg::Float(reg, fmt) => self.float_operand(reg, arg, fmt),
+            }
+        }
+
+        self.parallel_move(moves);
+        Ok(())
+    }
+
+    fn call(
+        &mut self,
+        callee: &str,
+        ret: Ty,
+        args: &[(Ty, Operand)],
+        result: Option<Value>,
+    ) -> Result<(), CodegenError> {
+        self.arguments(callee, args)?;
+        self.push(MachineInst::jal(
+            Reg::Ra,
+            Target::Symbol(callee.to_string()),
+        ));
+
+        let Some(result) = result else {
+            return Ok(());
+        };
+
+        match float_format(ret) {
+            Some(fmt) => self.float_result(FReg::Fa0, result, fmt),
+            None if ret.bits() > 64 => self.result_pair(Reg::A0, Reg::A1, result),
+            None => self.result(Reg::A0, result),
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,148 @@
+pub mod asm;
+pub mod emit;
+pub mod regalloc;
+pub mod relax;
+
+use crate::binary::symbol::{Relocation, RelocationKind, Symbol, SymbolBuilder, SymbolType};
+use crate::binary::Section;
+use crate::ir::types::Ty;
+use crate::ir::Module;
+use crate::riscv::immediate::ImmediateError;
+use crate::riscv::jmp::JmpError;
+use crate::riscv::machine::{Label, MachineError, MachineInst};
+use relax::relax;
+use std::collections::HashMap;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum CodegenError {
+    #[error("In function @{0}, type {1} is not supported by the backend")]
+    UnsupportedType(String, Ty),
+
+    #[error("In function @{0}, `{1}` is not supported by the backend")]
+    UnsupportedInst(String, String),
+
+    #[error("Function @{0} takes more than 8 integer or 8 float arguments")]
+    TooManyArguments(String),
+
+    #[error("In function @{0}, the musttail call to @{1} can not be a tail call: {2}")]
+    MustTail(String, String, &'static str),
+
+    #[error("Function @{0} does not exist")]
+    UnknownFunction(String),
+
+    #[error("{0}")]
+    JmpError(#[from] JmpError),
+
+    #[error("{0}")]
+    ImmediateError(#[from] ImmediateError),
+
+    #[error("{0}")]
+    Machine(#[from] MachineError),
+}
+
+// Instructions of a function, jumps and calls keep their labels and symbols until the
+// module is laid out
+#[derive(Debug)]
+pub struct CompiledFunction {
+    pub name: String,
+    pub linkage: SymbolType,
+    pub insts: Vec<MachineInst>,
+    // Index of the instruction each label is placed before
+    pub labels: HashMap<Label, usize>,
+    // Index of each `auipc` and `ld` pair that loads a constant from the pool
+    pub constants: Vec<(usize, i64)>,
+}
+
+// Constants too long to build with instructions, shared by the whole module
+const POOL: &str = ".Lconstants";
+
+// Generates the instructions of every function, labels are numbered across the module
+pub fn lower_module(module: &Module) -> Result<Vec<CompiledFunction>, CodegenError> {
+    let mut functions = Vec::new();
+    let mut labels = 0;
+
+    for function in &module.functions {
+        functions.push(emit::compile_function(function, &mut labels)?);
+    }
+
+    Ok(functions)
+}
+
+// Contents of the constant pool and the offset of each value in it
+fn constant_pool(functions: &[CompiledFunction]) -> (Vec<u8>, HashMap<i64, i64>) {
+    let mut pool = Vec::new();
+    let mut entries = HashMap::new();
+
+    for function in functions {
+        for (_, value) in &function.constants {
+            entries.entry(*value).or_insert_with(|| {
+                pool.extend(value.to_le_bytes());
+                pool.len() as i64 - 8
+            });
+        }
+    }
+
+    (pool, entries)
+}
+
+// Generates every function, resolves the jumps and calls and encodes the result, the
+// functions are meant to be placed one after the other in .text. The constant pool
+// comes first, when there is one, since the functions refer to it
+pub fn compile_module(module: &Module) -> Result<Vec<Symbol>, CodegenError> {
+    let mut functions = lower_module(module)?;
+    relax(&mut functions)?;
+
+    let mut symbols = Vec::new();
+    let (pool, entries) = constant_pool(&functions);
+
+    if !pool.is_empty() {
+        symbols.push(
+            SymbolBuilder::new()
+                .set_name(POOL.to_string())
+                .set_type(SymbolType::Private)
+                .set_section(Section::Rodata)
+                .set_content(pool)
+                .build(),
+        );
+    }
+
+    for function in functions {
+        let mut code = Vec::with_capacity(function.insts.len() * 4);
+
+        for inst in &function.insts {
+            code.extend(inst.encode()?.to_le_bytes());
+        }
+
+        let mut relocations = Vec::new();
+
+        for (index, value) in &function.constants {
+            let offset = index * 4;
+
+            relocations.push(Relocation {
+                offset,
+                kind: RelocationKind::PcrelHi20,
+                symbol: POOL.to_string(),
+                addend: entries[value],
+            });
+            relocations.push(Relocation {
+                offset: offset + 4,
+                kind: RelocationKind::PcrelLo12I(offset),
+                symbol: POOL.to_string(),
+                addend: 0,
+            });
+        }
+
+        symbols.push(
+            SymbolBuilder::new()
+                .set_name(function.name)
+                .set_type(function.linkage)
+                .set_section(Section::Text)
+                .set_content(code)
+                .set_relocations(relocations)
+                .build(),
+        );
+    }
+
+    Ok(symbols)
+}
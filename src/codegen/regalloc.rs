@@ -0,0 +1,634 @@
+use crate::analysis::liveness::Liveness;
+use crate::ir::cfg::ControlFlowGraph;
+use crate::ir::function::{Block, Function, Inst, Value};
+use crate::ir::inst::{InstKind, Operand};
+use crate::riscv::regs::Reg;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+// The argument registers hold values that are not live across a call and the
+// callee-saved registers the rest. The argument registers come first, so functions
+// without calls save nothing. The temporaries are left as scratch for the
+// instructions. Sp, Gp and Tp are never written
+pub const ALLOCATABLE: [Reg; 20] = [
+    Reg::A0,
+    Reg::A1,
+    Reg::A2,
+    Reg::A3,
+    Reg::A4,
+    Reg::A5,
+    Reg::A6,
+    Reg::A7,
+    Reg::S1,
+    Reg::S2,
+    Reg::S3,
+    Reg::S4,
+    Reg::S5,
+    Reg::S6,
+    Reg::S7,
+    Reg::S8,
+    Reg::S9,
+    Reg::S10,
+    Reg::S11,
+    Reg::S0,
+];
+
+fn caller_saved(reg: Reg) -> bool {
+    matches!(
+        reg,
+        Reg::A0 | Reg::A1 | Reg::A2 | Reg::A3 | Reg::A4 | Reg::A5 | Reg::A6 | Reg::A7
+    )
+}
+
+// Whether the value fits in one register, wider ones always live in the stack
+pub fn in_register(function: &Function, value: Value) -> bool {
+    function.values[value.0].ty.bits() <= 64
+}
+
+// Part of the lifetime of a value, `ranges` are half open and sorted. Every part sits
+// in one register or in the stack slot of the value
+#[derive(Debug, Clone)]
+struct Interval {
+    value: Value,
+    ranges: Vec<(usize, usize)>,
+    uses: Vec<usize>,
+    reg: Option<Reg>,
+}
+
+impl Interval {
+    fn start(&self) -> usize {
+        self.ranges[0].0
+    }
+
+    fn end(&self) -> usize {
+        self.ranges[self.ranges.len() - 1].1
+    }
+
+    fn covers(&self, position: usize) -> bool {
+        self.ranges
+            .iter()
+            .any(|(from, to)| (*from..*to).contains(&position))
+    }
+
+    fn next_use(&self, position: usize) -> Option<usize> {
+        self.uses.iter().copied().find(|at| *at >= position)
+    }
+
+    // First position where both are live
+    fn intersection(&self, other: &Interval) -> Option<usize> {
+        let mut first: Option<usize> = None;
+
+        for (from, to) in &self.ranges {
+            for (other_from, other_to) in &other.ranges {
+                let start = (*from).max(*other_from);
+
+                if start < (*to).min(*other_to) {
+                    first = Some(first.map_or(start, |first| first.min(start)));
+                }
+            }
+        }
+
+        first
+    }
+
+    // Keeps the part before `position` and returns the rest
+    fn split(&mut self, position: usize) -> Interval {
+        let mut before = Vec::new();
+        let mut after = Vec::new();
+
+        for (from, to) in &self.ranges {
+            if *to <= position {
+                before.push((*from, *to));
+            } else if *from >= position {
+                after.push((*from, *to));
+            } else {
+                before.push((*from, position));
+                after.push((position, *to));
+            }
+        }
+
+        let uses = self.uses.iter().copied().filter(|at| *at >= position);
+        let rest = Interval {
+            value: self.value,
+            ranges: after,
+            uses: uses.collect(),
+            reg: None,
+        };
+
+        self.ranges = before;
+        self.uses.retain(|at| *at < position);
+        rest
+    }
+}
+
+// Instructions are numbered in the order they are emitted, two apart. Phis and the
+// parameters are defined at the start of their block, before its first instruction
+#[derive(Debug)]
+pub struct Allocation {
+    pub positions: HashMap<Inst, usize>,
+    // First position of each block and the one after its terminator
+    pub bounds: HashMap<Block, (usize, usize)>,
+    pieces: HashMap<Value, Vec<Interval>>,
+    // Values that are in the stack at some point, they are stored when defined
+    pub spilled: HashSet<Value>,
+    // Callee-saved registers the prologue has to save
+    pub used: Vec<Reg>,
+    // Values loaded back into a register right before an instruction
+    reloads: HashMap<usize, Vec<(Value, Reg)>>,
+    // Values in registers live at the start of each block, not counting its phis
+    live_in: HashMap<Block, Vec<Value>>,
+}
+
+impl Allocation {
+    // Register of the value at `position`, None when it is in the stack. The value has
+    // to be live there, the emitter only asks where it reads, writes or moves a value
+    pub fn location(&self, value: Value, position: usize) -> Option<Reg> {
+        let piece = self
+            .pieces
+            .get(&value)?
+            .iter()
+            .find(|piece| piece.covers(position));
+
+        debug_assert!(piece.is_some(), "{value:?} is not live at {position}");
+        piece?.reg
+    }
+
+    pub fn reloads(&self, position: usize) -> &[(Value, Reg)] {
+        self.reloads.get(&position).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn live_in(&self, block: Block) -> &[Value] {
+        self.live_in.get(&block).map_or(&[], Vec::as_slice)
+    }
+}
+
+fn number(function: &Function, order: &[Block]) -> Allocation {
+    let mut positions = HashMap::new();
+    let mut bounds = HashMap::new();
+    let mut position = 0;
+
+    for block in order {
+        let from = position;
+
+        for inst in &function.blocks[block.0].insts {
+            if let InstKind::Phi { .. } = function.insts[inst.0].kind {
+                positions.insert(*inst, from);
+            } else {
+                position += 2;
+                positions.insert(*inst, position);
+            }
+        }
+
+        position += 2;
+        bounds.insert(*block, (from, position));
+    }
+
+    Allocation {
+        positions,
+        bounds,
+        pieces: HashMap::new(),
+        spilled: HashSet::new(),
+        used: Vec::new(),
+        reloads: HashMap::new(),
+        live_in: HashMap::new(),
+    }
+}
+
+#[derive(Default)]
+struct Lifetimes {
+    ranges: HashMap<Value, Vec<(usize, usize)>>,
+    uses: HashMap<Value, Vec<usize>>,
+}
+
+impl Lifetimes {
+    fn add(&mut self, value: Value, from: usize, to: usize) {
+        self.ranges.entry(value).or_default().push((from, to));
+    }
+
+    fn read(&mut self, value: Value, from: usize, position: usize) {
+        self.add(value, from, position + 1);
+        self.uses.entry(value).or_default().push(position);
+    }
+
+    // The value is live from its definition, which is in the block starting at `from`
+    fn define(&mut self, value: Value, from: usize, position: usize) {
+        let ranges = self.ranges.entry(value).or_default();
+        let mut found = false;
+
+        for range in ranges.iter_mut().filter(|range| range.0 == from) {
+            range.0 = position;
+            found = true;
+        }
+
+        // Never read, it still takes a register at its definition
+        if !found {
+            ranges.push((position, position + 1));
+        }
+    }
+}
+
+// Live ranges from the liveness of each block, walking the blocks and their
+// instructions backwards
+fn lifetimes(
+    function: &Function,
+    order: &[Block],
+    allocation: &Allocation,
+    liveness: &Liveness,
+) -> Vec<Interval> {
+    let mut lifetimes = Lifetimes::default();
+    let is_register = |operand: &Operand| match operand {
+        Operand::Value(value) if in_register(function, *value) => Some(*value),
+        _ => None,
+    };
+
+    for block in order.iter().rev() {
+        let (from, to) = allocation.bounds[block];
+
+        for value in liveness.live_out(*block) {
+            if in_register(function, *value) {
+                lifetimes.add(*value, from, to);
+            }
+        }
+
+        // Phi operands are read by the copies before the terminator
+        if let Some(terminator) = function.terminator(*block) {
+            let position = allocation.positions[&terminator];
+
+            for succ in function.successors(*block) {
+                for inst in &function.blocks[succ.0].insts {
+                    let InstKind::Phi { incoming, .. } = &function.insts[inst.0].kind else {
+                        break;
+                    };
+
+                    for (operand, _) in incoming.iter().filter(|(_, pred)| pred == block) {
+                        if let Some(value) = is_register(operand) {
+                            lifetimes.read(value, from, position);
+                        }
+                    }
+                }
+            }
+        }
+
+        for inst in function.blocks[block.0].insts.iter().rev() {
+            let data = &function.insts[inst.0];
+            let position = allocation.positions[inst];
+
+            if let Some(result) = data.result.filter(|result| in_register(function, *result)) {
+                lifetimes.define(result, from, position);
+            }
+
+            if let InstKind::Phi { .. } = data.kind {
+                continue;
+            }
+
+            for operand in data.kind.operands() {
+                if let Some(value) = is_register(operand) {
+                    lifetimes.read(value, from, position);
+                }
+            }
+        }
+    }
+
+    if let Some(entry) = order.first() {
+        let from = allocation.bounds[entry].0;
+
+        for param in &function.params {
+            if in_register(function, *param) {
+                lifetimes.define(*param, from, from);
+            }
+        }
+    }
+
+    let mut intervals = Vec::new();
+
+    // In the order of the values, so the ids that break ties between intervals starting
+    // at the same position are the same on every run
+    let mut ranges: Vec<_> = lifetimes.ranges.into_iter().collect();
+    ranges.sort_unstable_by_key(|(value, _)| *value);
+
+    for (value, mut ranges) in ranges {
+        ranges.sort_unstable();
+
+        let mut merged: Vec<(usize, usize)> = Vec::new();
+
+        for (from, to) in ranges {
+            match merged.last_mut() {
+                Some(last) if from <= last.1 => last.1 = last.1.max(to),
+                _ => merged.push((from, to)),
+            }
+        }
+
+        let mut uses = lifetimes.uses.remove(&value).unwrap_or_default();
+        uses.sort_unstable();
+        uses.dedup();
+
+        intervals.push(Interval {
+            value,
+            ranges: merged,
+            uses,
+            reg: None,
+        });
+    }
+
+    intervals
+}
+
+struct Scan {
+    intervals: Vec<Interval>,
+    unhandled: BinaryHeap<Reverse<(usize, usize)>>,
+    active: Vec<usize>,
+    inactive: Vec<usize>,
+    // Position and result of the calls and syscalls, in order. They read their
+    // arguments first and then overwrite the argument registers
+    calls: Vec<(usize, Option<Value>)>,
+}
+
+impl Scan {
+    fn push(&mut self, interval: Interval) {
+        let id = self.intervals.len();
+        self.unhandled.push(Reverse((interval.start(), id)));
+        self.intervals.push(interval);
+    }
+
+    // The part from `position` goes to the stack up to its next read, the rest waits
+    // for a register again
+    fn spill_from(&mut self, id: usize, position: usize) {
+        let mut rest = self.intervals[id].split(position);
+
+        if rest.ranges.is_empty() {
+            return;
+        }
+
+        let next = rest.uses.iter().copied().find(|at| *at > rest.start());
+
+        if let Some(next) = next.filter(|next| *next < rest.end()) {
+            let later = rest.split(next);
+
+            if !later.ranges.is_empty() {
+                self.push(later);
+            }
+        }
+
+        if !rest.ranges.is_empty() {
+            self.intervals.push(rest);
+        }
+    }
+
+    // First position where the interval can not be in a caller-saved register, right
+    // after the first call it is live past
+    fn clobbered(&self, current: usize) -> usize {
+        let interval = &self.intervals[current];
+        let (start, end) = (interval.start(), interval.end());
+
+        self.calls
+            .iter()
+            .filter(|(at, result)| *at > start || *at == start && *result != Some(interval.value))
+            .map(|(at, _)| at + 1)
+            .find(|after| *after < end)
+            .unwrap_or(usize::MAX)
+    }
+
+    fn free_register(&mut self, current: usize) -> bool {
+        let clobbered = self.clobbered(current);
+        let mut free_until: HashMap<Reg, usize> = ALLOCATABLE
+            .iter()
+            .map(|reg| {
+                (
+                    *reg,
+                    if caller_saved(*reg) {
+                        clobbered
+                    } else {
+                        usize::MAX
+                    },
+                )
+            })
+            .collect();
+
+        for id in &self.active {
+            free_until.insert(self.intervals[*id].reg.unwrap(), 0);
+        }
+
+        for id in &self.inactive {
+            let interval = &self.intervals[*id];
+
+            if let Some(at) = interval.intersection(&self.intervals[current]) {
+                let reg = interval.reg.unwrap();
+                free_until.insert(reg, free_until[&reg].min(at));
+            }
+        }
+
+        let (reg, until) = ALLOCATABLE.iter().map(|reg| (*reg, free_until[reg])).fold(
+            (Reg::S1, 0),
+            |best, next| if next.1 > best.1 { next } else { best },
+        );
+
+        if until == 0 {
+            return false;
+        }
+
+        self.intervals[current].reg = Some(reg);
+
+        if until < self.intervals[current].end() {
+            self.spill_from(current, until);
+        }
+
+        true
+    }
+
+    // Every register is taken, the interval read the farthest away goes to the stack
+    fn blocked_register(&mut self, current: usize) {
+        let position = self.intervals[current].start();
+        let clobbered = self.clobbered(current);
+        let mut use_at: HashMap<Reg, usize> = ALLOCATABLE
+            .iter()
+            .map(|reg| {
+                (
+                    *reg,
+                    if caller_saved(*reg) {
+                        clobbered
+                    } else {
+                        usize::MAX
+                    },
+                )
+            })
+            .collect();
+
+        for id in self.active.iter().chain(&self.inactive) {
+            let interval = &self.intervals[*id];
+            let reg = interval.reg.unwrap();
+
+            if self.inactive.contains(id)
+                && interval.intersection(&self.intervals[current]).is_none()
+            {
+                continue;
+            }
+
+            let next = interval.next_use(position).unwrap_or(usize::MAX);
+            use_at.insert(reg, use_at[&reg].min(next));
+        }
+
+        let (reg, farthest) =
+            ALLOCATABLE
+                .iter()
+                .map(|reg| (*reg, use_at[reg]))
+                .fold(
+                    (Reg::S1, 0),
+                    |best, next| if next.1 > best.1 { next } else { best },
+                );
+
+        let first_use = self.intervals[current].next_use(position);
+
+        match first_use {
+            Some(first_use) if farthest > first_use => {}
+            _ => {
+                self.spill_from(current, position);
+                return;
+            }
+        }
+
+        self.intervals[current].reg = Some(reg);
+
+        if caller_saved(reg) && clobbered < self.intervals[current].end() {
+            self.spill_from(current, clobbered);
+        }
+
+        let holders: Vec<usize> = self
+            .active
+            .iter()
+            .chain(&self.inactive)
+            .copied()
+            .filter(|id| self.intervals[*id].reg == Some(reg))
+            .collect();
+
+        for id in holders {
+            if self.active.contains(&id) {
+                self.active.retain(|active| *active != id);
+                self.spill_from(id, position);
+            } else if let Some(at) = self.intervals[id].intersection(&self.intervals[current]) {
+                self.spill_from(id, at);
+            }
+        }
+    }
+
+    fn run(&mut self) {
+        while let Some(Reverse((position, current))) = self.unhandled.pop() {
+            let (mut active, mut inactive) = (Vec::new(), Vec::new());
+
+            for id in self.active.iter().chain(&self.inactive) {
+                let interval = &self.intervals[*id];
+
+                if interval.end() <= position {
+                    continue;
+                }
+
+                if interval.covers(position) {
+                    active.push(*id);
+                } else {
+                    inactive.push(*id);
+                }
+            }
+
+            self.active = active;
+            self.inactive = inactive;
+
+            if !self.free_register(current) {
+                self.blocked_register(current);
+            }
+
+            if self.intervals[current].reg.is_some() {
+                self.active.push(current);
+            }
+        }
+    }
+}
+
+// Linear scan over the blocks in `order`, splitting the values that do not fit in the
+// registers into parts in registers and parts in the stack
+pub fn allocate(function: &Function, order: &[Block]) -> Allocation {
+    let mut allocation = number(function, order);
+    let cfg = ControlFlowGraph::new(function);
+    let liveness = Liveness::new(function, &cfg);
+
+    for block in order {
+        let live_in = liveness.live_in(*block).iter().copied();
+        let values = live_in.filter(|value| in_register(function, *value));
+        allocation.live_in.insert(*block, values.collect());
+    }
+
+    let mut calls = Vec::new();
+
+    for block in order {
+        for inst in &function.blocks[block.0].insts {
+            let data = &function.insts[inst.0];
+
+            if let InstKind::Call { .. } | InstKind::Syscall { .. } = data.kind {
+                calls.push((allocation.positions[inst], data.result));
+            }
+        }
+    }
+
+    let mut scan = Scan {
+        intervals: Vec::new(),
+        unhandled: BinaryHeap::new(),
+        active: Vec::new(),
+        inactive: Vec::new(),
+        calls,
+    };
+
+    for interval in lifetimes(function, order, &allocation, &liveness) {
+        scan.push(interval);
+    }
+
+    scan.run();
+
+    let blocks: HashSet<usize> = allocation.bounds.values().map(|(from, _)| *from).collect();
+    let mut used = HashSet::new();
+
+    for interval in scan.intervals {
+        if interval.ranges.is_empty() {
+            continue;
+        }
+
+        match interval.reg {
+            Some(reg) => {
+                used.insert(reg);
+            }
+            None => {
+                allocation.spilled.insert(interval.value);
+            }
+        }
+
+        allocation
+            .pieces
+            .entry(interval.value)
+            .or_default()
+            .push(interval);
+    }
+
+    for (value, pieces) in &mut allocation.pieces {
+        pieces.sort_by_key(Interval::start);
+
+        // Moving into a register at the start of a block is left to the edges
+        for piece in &pieces[1..] {
+            if let Some(reg) = piece.reg.filter(|_| !blocks.contains(&piece.start())) {
+                allocation
+                    .reloads
+                    .entry(piece.start())
+                    .or_default()
+                    .push((*value, reg));
+            }
+        }
+    }
+
+    for reloads in allocation.reloads.values_mut() {
+        reloads.sort_unstable_by_key(|(value, _)| *value);
+    }
+
+    allocation.used = ALLOCATABLE
+        .iter()
+        .copied()
+        .filter(|reg| used.contains(reg) && !caller_saved(*reg))
+        .collect();
+
+    allocation
+}
@@ -0,0 +1,196 @@
+use super::{CodegenError, CompiledFunction};
+use crate::riscv::jmp::JmpError;
+use crate::riscv::machine::{MReg, MachineInst, Target};
+use crate::riscv::regs::Reg;
+use std::collections::HashMap;
+
+// Jumps and branches start as one instruction and grow when their target is out of
+// range: a branch becomes the inverted branch over a `jal`, then over `auipc` and
+// `jalr`, and `jal` becomes `auipc` and `jalr`
+fn max_size(inst: &MachineInst) -> u64 {
+    match inst {
+        MachineInst::Branch { .. } => 12,
+        MachineInst::Jal { .. } => 8,
+        _ => 4,
+    }
+}
+
+// `auipc` and `jalr` through a scratch register, for targets beyond the ±1 MiB of `jal`.
+// Calls use ra as the scratch and the rest t1, like the `call` and `tail`
+// pseudoinstructions
+fn far_jump(rd: MReg, offset: i64) -> Result<Vec<MachineInst>, CodegenError> {
+    let scratch = if rd == Reg::Ra.into() {
+        Reg::Ra
+    } else {
+        Reg::T1
+    };
+
+    // The low part is sign extended by jalr, which the upper part rounds for
+    let hi = offset.wrapping_add(0x800) >> 12;
+
+    if !(-0x80000..=0x7FFFF).contains(&hi) {
+        return Err(JmpError::OutOfRange("auipc", offset).into());
+    }
+
+    Ok(vec![
+        MachineInst::Auipc {
+            rd: scratch.into(),
+            imm: hi,
+        },
+        MachineInst::Jalr {
+            rd,
+            rs1: scratch.into(),
+            offset: offset - (hi << 12),
+        },
+    ])
+}
+
+// The instructions of `inst` in `size` bytes, `offset` goes from the first one to the
+// target
+fn expand(inst: &MachineInst, size: u64, offset: i64) -> Result<Vec<MachineInst>, CodegenError> {
+    match inst {
+        MachineInst::Branch { cond, rs1, rs2, .. } if size > 4 => {
+            let mut code = vec![MachineInst::Branch {
+                cond: cond.invert(),
+                rs1: *rs1,
+                rs2: *rs2,
+                target: Target::Offset(size as i64),
+            }];
+
+            let jump = MachineInst::jal(Reg::Zero, Target::Offset(0));
+            code.extend(expand(&jump, size - 4, offset - 4)?);
+            Ok(code)
+        }
+        MachineInst::Jal { rd, .. } if size > 4 => far_jump(*rd, offset),
+        _ => {
+            let mut inst = inst.clone();
+
+            if let Some(target) = inst.target_mut() {
+                *target = Target::Offset(offset);
+            }
+
+            Ok(vec![inst])
+        }
+    }
+}
+
+fn fits(inst: &MachineInst, size: u64, offset: i64) -> bool {
+    expand(inst, size, offset).is_ok_and(|code| code.iter().all(|inst| inst.encode().is_ok()))
+}
+
+// Address of every function and offset of every instruction in its function, with one
+// more for the end
+struct Layout {
+    starts: HashMap<String, u64>,
+    offsets: Vec<Vec<u64>>,
+}
+
+fn layout(functions: &[CompiledFunction], sizes: &[Vec<u64>]) -> Layout {
+    let mut starts = HashMap::new();
+    let mut offsets = Vec::new();
+    let mut pc = 0;
+
+    for (function, sizes) in functions.iter().zip(sizes) {
+        starts.insert(function.name.clone(), pc);
+
+        let mut offset = 0;
+        let mut function_offsets = vec![0];
+
+        for size in sizes {
+            offset += size;
+            function_offsets.push(offset);
+        }
+
+        offsets.push(function_offsets);
+        pc += offset;
+    }
+
+    Layout { starts, offsets }
+}
+
+impl Layout {
+    // Offset from the instruction at `at` in `function` to its target
+    fn offset(
+        &self,
+        index: usize,
+        function: &CompiledFunction,
+        at: usize,
+        target: &Target,
+    ) -> Result<i64, CodegenError> {
+        let start = self.starts[&function.name];
+        let pc = start + self.offsets[index][at];
+
+        let address = match target {
+            Target::Label(label) => start + self.offsets[index][function.labels[label]],
+            Target::Symbol(name) => match self.starts.get(name) {
+                Some(address) => *address,
+                None => return Err(CodegenError::UnknownFunction(name.to_string())),
+            },
+            Target::Offset(offset) => return Ok(*offset),
+        };
+
+        Ok(address.wrapping_sub(pc) as i64)
+    }
+}
+
+// Grows the jumps and branches that do not reach their targets until all of them do,
+// then replaces the targets with offsets. Every pass grows all the sites out of range
+// at once, and sites only grow, so this ends
+pub fn relax(functions: &mut [CompiledFunction]) -> Result<(), CodegenError> {
+    let mut sizes: Vec<Vec<u64>> = functions
+        .iter()
+        .map(|function| vec![4; function.insts.len()])
+        .collect();
+
+    let layout = loop {
+        let layout = layout(functions, &sizes);
+        let mut changed = false;
+
+        for (index, function) in functions.iter().enumerate() {
+            for (at, inst) in function.insts.iter().enumerate() {
+                let Some(target) = inst.target() else {
+                    continue;
+                };
+
+                let size = &mut sizes[index][at];
+                let offset = layout.offset(index, function, at, target)?;
+
+                if *size < max_size(inst) && !fits(inst, *size, offset) {
+                    *size += 4;
+                    changed = true;
+                }
+            }
+        }
+
+        if !changed {
+            break layout;
+        }
+    };
+
+    for (index, function) in functions.iter_mut().enumerate() {
+        let offsets = &layout.offsets[index];
+        let mut insts = Vec::with_capacity(offsets[offsets.len() - 1] as usize / 4);
+
+        for (at, inst) in function.insts.iter().enumerate() {
+            let offset = match inst.target() {
+                Some(target) => layout.offset(index, function, at, target)?,
+                None => 0,
+            };
+
+            insts.extend(expand(inst, sizes[index][at], offset)?);
+        }
+
+        function.insts = insts;
+
+        // Instructions that did not grow keep their place in the new list
+        for at in function.labels.values_mut() {
+            *at = offsets[*at] as usize / 4;
+        }
+
+        for (at, _) in &mut function.constants {
+            *at = offsets[*at] as usize / 4;
+        }
+    }
+
+    Ok(())
+}
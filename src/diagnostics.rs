@@ -0,0 +1,94 @@
+use crate::binary::elf::ElfError;
+use crate::codegen::CodegenError;
+use crate::ir::lower::LowerError;
+use crate::ir::verify::VerifyError;
+use crate::opt::PassError;
+use crate::parser::ast::AstError;
+use crate::parser::ir::IrParseError;
+use crate::parser::token::LexerError;
+use std::fmt::Display;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Diagnostic {
+    #[error("Lexer error: {0}")]
+    Lexer(#[from] LexerError),
+
+    #[error("Parse error: {0}")]
+    Parse(#[from] IrParseError),
+
+    #[error("Invalid IR: {0}")]
+    Verify(#[from] VerifyError),
+
+    #[error("Ast error: {0}")]
+    Ast(#[from] AstError),
+
+    #[error("Lowering error: {0}")]
+    Lower(#[from] LowerError),
+
+    #[error("Pass error: {0}")]
+    Pass(#[from] PassError),
+
+    #[error("Codegen error: {0}")]
+    Codegen(#[from] CodegenError),
+
+    #[error("Elf error: {0}")]
+    Elf(#[from] ElfError),
+}
+
+// Every problem found while compiling, stages that can keep going report more than one
+#[derive(Error, Debug, Default)]
+pub struct Diagnostics {
+    pub errors: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    pub fn push(&mut self, error: impl Into<Diagnostic>) {
+        self.errors.push(error.into());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+impl Display for Diagnostics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, error) in self.errors.iter().enumerate() {
+            if i != 0 {
+                writeln!(f)?;
+            }
+
+            write!(f, "{}", error)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Lets `?` turn the error of a single stage into Diagnostics
+macro_rules! diagnostics_from {
+    ($($error:ty),*) => {
+        $(
+            impl From<$error> for Diagnostics {
+                fn from(error: $error) -> Self {
+                    Diagnostics {
+                        errors: vec![error.into()],
+                    }
+                }
+            }
+        )*
+    };
+}
+
+diagnostics_from!(
+    Diagnostic,
+    LexerError,
+    IrParseError,
+    VerifyError,
+    AstError,
+    LowerError,
+    PassError,
+    CodegenError,
+    ElfError
+);
@@ -0,0 +1,285 @@
+use super::function::{Attributes, Block, Function, Value};
+use super::inst::{BinaryOp, CastOp, Flags, FloatCC, InstKind, IntCC, Operand};
+use super::types::Ty;
+use super::verify::{verify_function_body, verify_module, VerifyError, VerifyErrorKind};
+use super::Module;
+use crate::binary::symbol::SymbolType;
+
+#[derive(Debug, Default)]
+pub struct ModuleBuilder {
+    pub module: Module,
+}
+
+impl ModuleBuilder {
+    pub fn new() -> Self {
+        ModuleBuilder {
+            module: Module::default(),
+        }
+    }
+    #[must_use]
+    pub fn add_function(mut self, function: Function) -> Self {
+        self.module.functions.push(function);
+        self
+    }
+    // The module is verified as a whole so calls between functions are checked too
+    pub fn build(self) -> Result<Module, VerifyError> {
+        verify_module(&self.module)?;
+
+        Ok(self.module)
+    }
+}
+
+#[derive(Debug)]
+pub struct FunctionBuilder {
+    pub function: Function,
+    // Block where the InstBuilder appends, the last created block by default
+    current: Option<Block>,
+    // The first operand whose type could not be known, reported by build
+    error: Option<VerifyError>,
+}
+
+impl FunctionBuilder {
+    pub fn new(name: String) -> Self {
+        FunctionBuilder {
+            function: Function::new(name, SymbolType::Global, Ty::Void),
+            current: None,
+            error: None,
+        }
+    }
+    #[must_use]
+    pub fn set_name(mut self, name: String) -> Self {
+        self.function.name = name;
+        self
+    }
+    #[must_use]
+    pub fn set_linkage(mut self, linkage: SymbolType) -> Self {
+        self.function.linkage = linkage;
+        self
+    }
+    #[must_use]
+    pub fn set_ret(mut self, ret: Ty) -> Self {
+        self.function.ret = ret;
+        self
+    }
+    #[must_use]
+    pub fn set_attributes(mut self, attributes: Attributes) -> Self {
+        self.function.attributes = attributes;
+        self
+    }
+    pub fn add_param(&mut self, ty: Ty, name: Option<String>) -> Value {
+        self.function.add_param(ty, name)
+    }
+    // Creates a block at the end of the layout and moves the insertion point to it
+    pub fn create_block(&mut self, name: String) -> Block {
+        let block = self.function.create_block(name);
+        self.function.append_block(block);
+        self.current = Some(block);
+
+        block
+    }
+    pub fn switch_to_block(&mut self, block: Block) {
+        self.current = Some(block);
+    }
+    pub fn current_block(&self) -> Option<Block> {
+        self.current
+    }
+    // Instructions are appended to the current block, an entry block is created if there is none
+    pub fn ins(&mut self) -> InstBuilder<'_> {
+        let block = match self.current {
+            Some(block) => block,
+            None => self.create_block("entry".to_string()),
+        };
+
+        InstBuilder {
+            function: &mut self.function,
+            block,
+            error: &mut self.error,
+        }
+    }
+    // Calls are checked once the function is in a module
+    pub fn build(self) -> Result<Function, VerifyError> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+
+        verify_function_body(&self.function)?;
+
+        Ok(self.function)
+    }
+}
+
+pub struct InstBuilder<'a> {
+    function: &'a mut Function,
+    block: Block,
+    error: &'a mut Option<VerifyError>,
+}
+
+impl InstBuilder<'_> {
+    // Type::Value is not a constant and has no type, build fails with the first one
+    fn operand_ty(&mut self, operand: &Operand) -> Ty {
+        if let Some(ty) = self.function.operand_ty(operand) {
+            return ty;
+        }
+
+        if let (None, Operand::Const(constant)) = (&self.error, operand) {
+            *self.error = Some(VerifyError {
+                function: self.function.name.clone(),
+                location: format!("{}:", self.function.blocks[self.block.0].name),
+                kind: VerifyErrorKind::InvalidConstant(constant.clone()),
+            });
+        }
+
+        Ty::Void
+    }
+
+    fn push(self, kind: InstKind) -> Value {
+        let result = self.function.add_value(kind.result_ty(), None);
+        self.function.append_inst(self.block, kind, Some(result));
+
+        result
+    }
+
+    fn push_void(self, kind: InstKind) {
+        self.function.append_inst(self.block, kind, None);
+    }
+
+    pub fn binary(self, op: BinaryOp, lhs: impl Into<Operand>, rhs: impl Into<Operand>) -> Value {
+        self.binary_flags(op, Flags::default(), lhs, rhs)
+    }
+
+    pub fn binary_flags(
+        mut self,
+        op: BinaryOp,
+        flags: Flags,
+        lhs: impl Into<Operand>,
+        rhs: impl Into<Operand>,
+    ) -> Value {
+        let lhs = lhs.into();
+        let ty = self.operand_ty(&lhs);
+
+        self.push(InstKind::Binary {
+            op,
+            flags,
+            ty,
+            lhs,
+            rhs: rhs.into(),
+        })
+    }
+
+    pub fn icmp(mut self, cond: IntCC, lhs: impl Into<Operand>, rhs: impl Into<Operand>) -> Value {
+        let lhs = lhs.into();
+        let ty = self.operand_ty(&lhs);
+
+        self.push(InstKind::Icmp {
+            cond,
+            ty,
+            lhs,
+            rhs: rhs.into(),
+        })
+    }
+
+    pub fn fcmp(
+        mut self,
+        cond: FloatCC,
+        lhs: impl Into<Operand>,
+        rhs: impl Into<Operand>,
+    ) -> Value {
+        let lhs = lhs.into();
+        let ty = self.operand_ty(&lhs);
+
+        self.push(InstKind::Fcmp {
+            cond,
+            ty,
+            lhs,
+            rhs: rhs.into(),
+        })
+    }
+
+    pub fn cast(mut self, op: CastOp, value: impl Into<Operand>, to: Ty) -> Value {
+        let value = value.into();
+        let from = self.operand_ty(&value);
+
+        self.push(InstKind::Cast {
+            op,
+            from,
+            value,
+            to,
+        })
+    }
+
+    pub fn alloca(self, ty: Ty) -> Value {
+        self.push(InstKind::Alloca { ty })
+    }
+
+    pub fn load(self, ty: Ty, ptr: impl Into<Operand>) -> Value {
+        self.push(InstKind::Load {
+            ty,
+            ptr: ptr.into(),
+        })
+    }
+
+    pub fn store(mut self, value: impl Into<Operand>, ptr: impl Into<Operand>) {
+        let value = value.into();
+        let ty = self.operand_ty(&value);
+
+        self.push_void(InstKind::Store {
+            ty,
+            value,
+            ptr: ptr.into(),
+        })
+    }
+
+    pub fn ptradd(self, ptr: impl Into<Operand>, offset: impl Into<Operand>) -> Value {
+        self.push(InstKind::PtrAdd {
+            ptr: ptr.into(),
+            offset: offset.into(),
+        })
+    }
+
+    // Calls returning void define no value
+    pub fn call(mut self, callee: String, ret: Ty, args: Vec<Operand>) -> Option<Value> {
+        let args = args
+            .into_iter()
+            .map(|arg| (self.operand_ty(&arg), arg))
+            .collect();
+        let kind = InstKind::Call {
+            callee,
+            ret,
+            args,
+            musttail: false,
+        };
+
+        if ret == Ty::Void {
+            self.push_void(kind);
+            None
+        } else {
+            Some(self.push(kind))
+        }
+    }
+
+    pub fn phi(self, ty: Ty, incoming: Vec<(Operand, Block)>) -> Value {
+        self.push(InstKind::Phi { ty, incoming })
+    }
+
+    pub fn syscall(self, args: Vec<Operand>) -> Value {
+        self.push(InstKind::Syscall { args })
+    }
+
+    pub fn jump(self, target: Block) {
+        self.push_void(InstKind::Jump { target })
+    }
+
+    pub fn branch(self, cond: impl Into<Operand>, then_dest: Block, else_dest: Block) {
+        self.push_void(InstKind::Branch {
+            cond: cond.into(),
+            then_dest,
+            else_dest,
+        })
+    }
+
+    pub fn ret(self, value: Option<Operand>) {
+        let ty = self.function.ret;
+
+        self.push_void(InstKind::Ret { ty, value })
+    }
+}
@@ -0,0 +1,69 @@
+use super::function::{Block, Function};
+
+// Edges between the blocks of the layout, indexed by block id
+#[derive(Debug, Clone, Default)]
+pub struct ControlFlowGraph {
+    preds: Vec<Vec<Block>>,
+    succs: Vec<Vec<Block>>,
+}
+
+impl ControlFlowGraph {
+    pub fn new(function: &Function) -> Self {
+        let mut cfg = Self {
+            preds: vec![Vec::new(); function.blocks.len()],
+            succs: vec![Vec::new(); function.blocks.len()],
+        };
+
+        for block in &function.layout {
+            for succ in function.successors(*block) {
+                // `br i1 %c, label %a, label %a` is a single edge
+                if cfg.succs[block.0].contains(&succ) {
+                    continue;
+                }
+
+                cfg.succs[block.0].push(succ);
+                cfg.preds[succ.0].push(*block);
+            }
+        }
+
+        cfg
+    }
+
+    pub fn preds(&self, block: Block) -> &[Block] {
+        &self.preds[block.0]
+    }
+
+    pub fn succs(&self, block: Block) -> &[Block] {
+        &self.succs[block.0]
+    }
+
+    // Blocks reachable from the entry, each one before its successors except on back edges
+    pub fn reverse_postorder(&self, function: &Function) -> Vec<Block> {
+        let Some(entry) = function.entry() else {
+            return Vec::new();
+        };
+
+        let mut visited = vec![false; self.succs.len()];
+        let mut postorder = Vec::new();
+        // Each entry is a block and the index of the next successor to visit
+        let mut stack = vec![(entry, 0)];
+        visited[entry.0] = true;
+
+        while let Some((block, next)) = stack.pop() {
+            match self.succs[block.0].get(next) {
+                Some(succ) => {
+                    stack.push((block, next + 1));
+
+                    if !visited[succ.0] {
+                        visited[succ.0] = true;
+                        stack.push((*succ, 0));
+                    }
+                }
+                None => postorder.push(block),
+            }
+        }
+
+        postorder.reverse();
+        postorder
+    }
+}
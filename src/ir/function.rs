@@ -0,0 +1,415 @@
+use super::inst::{InstKind, Operand};
+use super::types::Ty;
+use crate::binary::symbol::SymbolType;
+use crate::parser::types::{f16_to_f32, sign_extend, Type};
+use std::fmt::Display;
+
+// Ids are indexes into the arenas of the Function, they stay stable while the function changes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Value(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Block(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Inst(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueDef {
+    Param(usize),
+    Inst(Inst),
+    // Used before being defined, a valid function has none of these
+    None,
+}
+
+#[derive(Debug, Clone)]
+pub struct ValueData {
+    pub name: Option<String>,
+    pub ty: Ty,
+    pub def: ValueDef,
+}
+
+#[derive(Debug, Clone)]
+pub struct BlockData {
+    pub name: String,
+    pub insts: Vec<Inst>,
+}
+
+#[derive(Debug, Clone)]
+pub struct InstData {
+    pub kind: InstKind,
+    pub result: Option<Value>,
+}
+
+// Written after the parameters, `define i64 @f(i64 %x) noinline {`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Attributes {
+    pub noinline: bool,
+    pub alwaysinline: bool,
+}
+
+impl Attributes {
+    // Sets the attribute with that name, false if there is no such attribute
+    pub fn set(&mut self, name: &str) -> bool {
+        match name {
+            "noinline" => self.noinline = true,
+            "alwaysinline" => self.alwaysinline = true,
+            _ => return false,
+        }
+
+        true
+    }
+}
+
+impl Display for Attributes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.noinline {
+            write!(f, " noinline")?;
+        }
+
+        if self.alwaysinline {
+            write!(f, " alwaysinline")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub name: String,
+    pub linkage: SymbolType,
+    pub ret: Ty,
+    pub attributes: Attributes,
+    pub params: Vec<Value>,
+    // Order in which the blocks are emitted, the first one is the entry
+    pub layout: Vec<Block>,
+    pub blocks: Vec<BlockData>,
+    pub insts: Vec<InstData>,
+    pub values: Vec<ValueData>,
+}
+
+impl Function {
+    pub fn new(name: String, linkage: SymbolType, ret: Ty) -> Self {
+        Self {
+            name,
+            linkage,
+            ret,
+            attributes: Attributes::default(),
+            params: Vec::new(),
+            layout: Vec::new(),
+            blocks: Vec::new(),
+            insts: Vec::new(),
+            values: Vec::new(),
+        }
+    }
+
+    pub fn add_value(&mut self, ty: Ty, name: Option<String>) -> Value {
+        self.values.push(ValueData {
+            name,
+            ty,
+            def: ValueDef::None,
+        });
+
+        Value(self.values.len() - 1)
+    }
+
+    pub fn add_param(&mut self, ty: Ty, name: Option<String>) -> Value {
+        let value = self.add_value(ty, name);
+        self.values[value.0].def = ValueDef::Param(self.params.len());
+        self.params.push(value);
+
+        value
+    }
+
+    // Creates a block without placing it in the layout
+    pub fn create_block(&mut self, name: String) -> Block {
+        self.blocks.push(BlockData {
+            name,
+            insts: Vec::new(),
+        });
+
+        Block(self.blocks.len() - 1)
+    }
+
+    // A block name not taken yet, `base` followed by a number if needed
+    pub fn unique_block_name(&self, base: &str) -> String {
+        let taken = |name: &str| self.blocks.iter().any(|block| block.name == name);
+        let mut name = base.to_string();
+        let mut suffix = 1;
+
+        while taken(&name) {
+            name = format!("{}.{}", base, suffix);
+            suffix += 1;
+        }
+
+        name
+    }
+
+    // A value name not taken yet, `base` followed by a number if needed
+    pub fn unique_value_name(&self, base: &str) -> String {
+        let taken = |name: &str| {
+            self.values
+                .iter()
+                .any(|value| value.name.as_deref() == Some(name))
+        };
+        let mut name = base.to_string();
+        let mut suffix = 1;
+
+        while taken(&name) {
+            name = format!("{}.{}", base, suffix);
+            suffix += 1;
+        }
+
+        name
+    }
+
+    pub fn append_block(&mut self, block: Block) {
+        self.layout.push(block);
+    }
+
+    pub fn append_inst(&mut self, block: Block, kind: InstKind, result: Option<Value>) -> Inst {
+        let inst = Inst(self.insts.len());
+
+        if let Some(value) = result {
+            self.values[value.0].def = ValueDef::Inst(inst);
+        }
+
+        self.insts.push(InstData { kind, result });
+        self.blocks[block.0].insts.push(inst);
+
+        inst
+    }
+
+    // Inserts the instruction before another one of the block
+    pub fn insert_inst(
+        &mut self,
+        block: Block,
+        before: Inst,
+        kind: InstKind,
+        result: Option<Value>,
+    ) -> Inst {
+        let inst = Inst(self.insts.len());
+
+        if let Some(value) = result {
+            self.values[value.0].def = ValueDef::Inst(inst);
+        }
+
+        self.insts.push(InstData { kind, result });
+
+        let insts = &mut self.blocks[block.0].insts;
+        let index = insts
+            .iter()
+            .position(|i| *i == before)
+            .unwrap_or(insts.len());
+        insts.insert(index, inst);
+
+        inst
+    }
+
+    // Removes the instruction from the block, its data stays in the arena
+    pub fn remove_inst(&mut self, block: Block, inst: Inst) {
+        self.blocks[block.0].insts.retain(|i| *i != inst);
+    }
+
+    // Removes the block from the layout, its data stays in the arena
+    pub fn remove_block(&mut self, block: Block) {
+        self.layout.retain(|b| *b != block);
+    }
+
+    pub fn replace_uses(&mut self, value: Value, with: Operand) {
+        for block in &self.layout {
+            for inst in &self.blocks[block.0].insts {
+                for operand in self.insts[inst.0].kind.operands_mut() {
+                    if *operand == Operand::Value(value) {
+                        *operand = with.clone();
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn entry(&self) -> Option<Block> {
+        self.layout.first().copied()
+    }
+
+    pub fn terminator(&self, block: Block) -> Option<Inst> {
+        let last = *self.blocks[block.0].insts.last()?;
+
+        if self.insts[last.0].kind.is_terminator() {
+            Some(last)
+        } else {
+            None
+        }
+    }
+
+    pub fn successors(&self, block: Block) -> Vec<Block> {
+        match self.terminator(block) {
+            Some(inst) => self.insts[inst.0].kind.successors(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn operand_ty(&self, operand: &Operand) -> Option<Ty> {
+        match operand {
+            Operand::Value(value) => Some(self.values[value.0].ty),
+            Operand::Const(constant) => Ty::of(constant),
+        }
+    }
+
+    pub fn value_name(&self, value: Value) -> String {
+        match &self.values[value.0].name {
+            Some(name) => format!("%{}", name),
+            None => format!("%{}", value.0),
+        }
+    }
+
+    pub fn block_name(&self, block: Block) -> String {
+        format!("%{}", self.blocks[block.0].name)
+    }
+
+    pub fn operand_to_string(&self, operand: &Operand) -> String {
+        match operand {
+            Operand::Value(value) => self.value_name(*value),
+            Operand::Const(constant) => const_to_string(constant),
+        }
+    }
+
+    pub fn inst_to_string(&self, inst: Inst) -> String {
+        let data = &self.insts[inst.0];
+        let op = |operand: &Operand| self.operand_to_string(operand);
+
+        let body = match &data.kind {
+            InstKind::Binary {
+                op: bop,
+                flags,
+                ty,
+                lhs,
+                rhs,
+            } => {
+                format!("{}{} {} {}, {}", bop, flags, ty, op(lhs), op(rhs))
+            }
+            InstKind::Icmp { cond, ty, lhs, rhs } => {
+                format!("icmp {} {} {}, {}", cond, ty, op(lhs), op(rhs))
+            }
+            InstKind::Fcmp { cond, ty, lhs, rhs } => {
+                format!("fcmp {} {} {}, {}", cond, ty, op(lhs), op(rhs))
+            }
+            InstKind::Cast {
+                op: cop,
+                from,
+                value,
+                to,
+            } => format!("{} {} {} to {}", cop, from, op(value), to),
+            InstKind::Alloca { ty } => format!("alloca {}", ty),
+            InstKind::Load { ty, ptr } => format!("load {}, ptr {}", ty, op(ptr)),
+            InstKind::Store { ty, value, ptr } => {
+                format!("store {} {}, ptr {}", ty, op(value), op(ptr))
+            }
+            InstKind::PtrAdd { ptr, offset } => {
+                format!("ptradd ptr {}, i64 {}", op(ptr), op(offset))
+            }
+            InstKind::Call {
+                callee,
+                ret,
+                args,
+                musttail,
+            } => {
+                let args = args
+                    .iter()
+                    .map(|(ty, arg)| format!("{} {}", ty, op(arg)))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+
+                let tail = if *musttail { "musttail " } else { "" };
+
+                format!("{}call {} @{}({})", tail, ret, callee, args)
+            }
+            InstKind::Phi { ty, incoming } => {
+                let incoming = incoming
+                    .iter()
+                    .map(|(value, block)| format!("[ {}, {} ]", op(value), self.block_name(*block)))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+
+                format!("phi {} {}", ty, incoming)
+            }
+            InstKind::Syscall { args } => {
+                let args = args.iter().map(op).collect::<Vec<_>>().join(", ");
+
+                format!("syscall i64 {}", args)
+            }
+            InstKind::Jump { target } => format!("br label {}", self.block_name(*target)),
+            InstKind::Branch {
+                cond,
+                then_dest,
+                else_dest,
+            } => format!(
+                "br i1 {}, label {}, label {}",
+                op(cond),
+                self.block_name(*then_dest),
+                self.block_name(*else_dest)
+            ),
+            InstKind::Ret { ty, value } => match value {
+                Some(value) => format!("ret {} {}", ty, op(value)),
+                None => format!("ret {}", ty),
+            },
+        };
+
+        match data.result {
+            Some(result) => format!("{} = {}", self.value_name(result), body),
+            None => body,
+        }
+    }
+}
+
+fn const_to_string(constant: &Type) -> String {
+    match constant {
+        Type::Int { bits: 1, value, .. } => (*value != 0).to_string(),
+        Type::Int {
+            bits,
+            signed: true,
+            value,
+        } => sign_extend(*value, *bits).to_string(),
+        Type::Int { value, .. } => value.to_string(),
+        // Non finite floats are written with their bits
+        Type::F16(v) if !f16_to_f32(*v).is_finite() => format!("0x{:04X}", v),
+        Type::F32(v) if !f32::from_bits(*v).is_finite() => format!("0x{:08X}", v),
+        Type::F64(v) if !f64::from_bits(*v).is_finite() => format!("0x{:016X}", v),
+        Type::F16(v) => format!("{:?}", f16_to_f32(*v)),
+        Type::F32(v) => format!("{:?}", f32::from_bits(*v)),
+        Type::F64(v) => format!("{:?}", f64::from_bits(*v)),
+        Type::Value(v) => v.to_string(),
+    }
+}
+
+impl Display for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let linkage = match self.linkage {
+            SymbolType::Global => "",
+            SymbolType::Private => "private ",
+        };
+        let params = self
+            .params
+            .iter()
+            .map(|param| format!("{} {}", self.values[param.0].ty, self.value_name(*param)))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        writeln!(
+            f,
+            "define {}{} @{}({}){} {{",
+            linkage, self.ret, self.name, params, self.attributes
+        )?;
+
+        for block in &self.layout {
+            writeln!(f, "{}:", self.blocks[block.0].name)?;
+
+            for inst in &self.blocks[block.0].insts {
+                writeln!(f, "    {}", self.inst_to_string(*inst))?;
+            }
+        }
+
+        write!(f, "}}")
+    }
+}
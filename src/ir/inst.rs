@@ -0,0 +1,515 @@
+use super::function::{Block, Value};
+use super::types::Ty;
+use crate::parser::types::Type;
+use std::str::FromStr;
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub enum Operand {
+    Value(Value),
+    Const(Type),
+}
+
+impl From<Value> for Operand {
+    fn from(value: Value) -> Self {
+        Operand::Value(value)
+    }
+}
+
+impl From<Type> for Operand {
+    fn from(value: Type) -> Self {
+        Operand::Const(value)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    // High half of the signed product at twice the width, only for 32 and 64 bits
+    Smulh,
+    Sdiv,
+    Udiv,
+    Srem,
+    Urem,
+    And,
+    Or,
+    Xor,
+    Shl,
+    Lshr,
+    Ashr,
+    Fadd,
+    Fsub,
+    Fmul,
+    Fdiv,
+}
+
+// Overflow flags, a flagged operation that overflows has no defined result
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Default)]
+pub struct Flags {
+    pub nsw: bool,
+    pub nuw: bool,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum IntCC {
+    Eq,
+    Ne,
+    Slt,
+    Sle,
+    Sgt,
+    Sge,
+    Ult,
+    Ule,
+    Ugt,
+    Uge,
+}
+
+// Ordered conditions are false when an operand is NaN, unordered ones are true
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum FloatCC {
+    Oeq,
+    One,
+    Olt,
+    Ole,
+    Ogt,
+    Oge,
+    Ord,
+    Ueq,
+    Une,
+    Ult,
+    Ule,
+    Ugt,
+    Uge,
+    Uno,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum CastOp {
+    Fptosi,
+    Sitofp,
+    Trunc,
+    Zext,
+    Sext,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub enum InstKind {
+    Binary {
+        op: BinaryOp,
+        flags: Flags,
+        ty: Ty,
+        lhs: Operand,
+        rhs: Operand,
+    },
+    Icmp {
+        cond: IntCC,
+        ty: Ty,
+        lhs: Operand,
+        rhs: Operand,
+    },
+    Fcmp {
+        cond: FloatCC,
+        ty: Ty,
+        lhs: Operand,
+        rhs: Operand,
+    },
+    Cast {
+        op: CastOp,
+        from: Ty,
+        value: Operand,
+        to: Ty,
+    },
+    Alloca {
+        ty: Ty,
+    },
+    Load {
+        ty: Ty,
+        ptr: Operand,
+    },
+    Store {
+        ty: Ty,
+        value: Operand,
+        ptr: Operand,
+    },
+    // The address `offset` bytes past `ptr`, the offset is an i64
+    PtrAdd {
+        ptr: Operand,
+        offset: Operand,
+    },
+    Call {
+        callee: String,
+        ret: Ty,
+        args: Vec<(Ty, Operand)>,
+        // Compilation fails when the call can not be made a tail call
+        musttail: bool,
+    },
+    Phi {
+        ty: Ty,
+        incoming: Vec<(Operand, Block)>,
+    },
+    // ecall, the first operand is the syscall number and the rest go in a0 to a5
+    Syscall {
+        args: Vec<Operand>,
+    },
+    Jump {
+        target: Block,
+    },
+    Branch {
+        cond: Operand,
+        then_dest: Block,
+        else_dest: Block,
+    },
+    Ret {
+        ty: Ty,
+        value: Option<Operand>,
+    },
+}
+
+impl InstKind {
+    pub fn is_terminator(&self) -> bool {
+        matches!(
+            self,
+            InstKind::Jump { .. } | InstKind::Branch { .. } | InstKind::Ret { .. }
+        )
+    }
+
+    // Instructions that must stay even when their result is unused
+    pub fn has_side_effects(&self) -> bool {
+        self.is_terminator()
+            || matches!(
+                self,
+                InstKind::Store { .. } | InstKind::Call { .. } | InstKind::Syscall { .. }
+            )
+    }
+
+    // The result only depends on the operands, so equal instructions give equal results
+    pub fn is_pure(&self) -> bool {
+        matches!(
+            self,
+            InstKind::Binary { .. }
+                | InstKind::Icmp { .. }
+                | InstKind::Fcmp { .. }
+                | InstKind::Cast { .. }
+                | InstKind::PtrAdd { .. }
+        )
+    }
+
+    pub fn successors(&self) -> Vec<Block> {
+        match self {
+            InstKind::Jump { target } => vec![*target],
+            InstKind::Branch {
+                then_dest,
+                else_dest,
+                ..
+            } => vec![*then_dest, *else_dest],
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn successors_mut(&mut self) -> Vec<&mut Block> {
+        match self {
+            InstKind::Jump { target } => vec![target],
+            InstKind::Branch {
+                then_dest,
+                else_dest,
+                ..
+            } => vec![then_dest, else_dest],
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn operands(&self) -> Vec<&Operand> {
+        match self {
+            InstKind::Binary { lhs, rhs, .. }
+            | InstKind::Icmp { lhs, rhs, .. }
+            | InstKind::Fcmp { lhs, rhs, .. } => vec![lhs, rhs],
+            InstKind::Cast { value, .. } => vec![value],
+            InstKind::Alloca { .. } | InstKind::Jump { .. } => Vec::new(),
+            InstKind::Load { ptr, .. } => vec![ptr],
+            InstKind::Store { value, ptr, .. } => vec![value, ptr],
+            InstKind::PtrAdd { ptr, offset } => vec![ptr, offset],
+            InstKind::Call { args, .. } => args.iter().map(|(_, arg)| arg).collect(),
+            InstKind::Syscall { args } => args.iter().collect(),
+            InstKind::Phi { incoming, .. } => incoming.iter().map(|(value, _)| value).collect(),
+            InstKind::Branch { cond, .. } => vec![cond],
+            InstKind::Ret { value, .. } => value.iter().collect(),
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+        match self {
+            InstKind::Binary { lhs, rhs, .. }
+            | InstKind::Icmp { lhs, rhs, .. }
+            | InstKind::Fcmp { lhs, rhs, .. } => vec![lhs, rhs],
+            InstKind::Cast { value, .. } => vec![value],
+            InstKind::Alloca { .. } | InstKind::Jump { .. } => Vec::new(),
+            InstKind::Load { ptr, .. } => vec![ptr],
+            InstKind::Store { value, ptr, .. } => vec![value, ptr],
+            InstKind::PtrAdd { ptr, offset } => vec![ptr, offset],
+            InstKind::Call { args, .. } => args.iter_mut().map(|(_, arg)| arg).collect(),
+            InstKind::Syscall { args } => args.iter_mut().collect(),
+            InstKind::Phi { incoming, .. } => incoming.iter_mut().map(|(value, _)| value).collect(),
+            InstKind::Branch { cond, .. } => vec![cond],
+            InstKind::Ret { value, .. } => value.iter_mut().collect(),
+        }
+    }
+
+    // Type of the value this instruction defines, Void if it defines none
+    pub fn result_ty(&self) -> Ty {
+        match self {
+            InstKind::Binary { ty, .. } | InstKind::Load { ty, .. } | InstKind::Phi { ty, .. } => {
+                *ty
+            }
+            InstKind::Icmp { .. } | InstKind::Fcmp { .. } => Ty::I1,
+            InstKind::Cast { to, .. } => *to,
+            InstKind::Alloca { .. } | InstKind::PtrAdd { .. } => Ty::Ptr,
+            InstKind::Syscall { .. } => Ty::I64,
+            InstKind::Call { ret, .. } => *ret,
+            InstKind::Store { .. }
+            | InstKind::Jump { .. }
+            | InstKind::Branch { .. }
+            | InstKind::Ret { .. } => Ty::Void,
+        }
+    }
+}
+
+impl BinaryOp {
+    pub fn is_float(&self) -> bool {
+        matches!(
+            self,
+            BinaryOp::Fadd | BinaryOp::Fsub | BinaryOp::Fmul | BinaryOp::Fdiv
+        )
+    }
+
+    pub fn is_commutative(&self) -> bool {
+        matches!(
+            self,
+            BinaryOp::Add
+                | BinaryOp::Mul
+                | BinaryOp::Smulh
+                | BinaryOp::And
+                | BinaryOp::Or
+                | BinaryOp::Xor
+                | BinaryOp::Fadd
+                | BinaryOp::Fmul
+        )
+    }
+}
+
+impl FromStr for BinaryOp {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "add" => Ok(BinaryOp::Add),
+            "sub" => Ok(BinaryOp::Sub),
+            "mul" => Ok(BinaryOp::Mul),
+            "smulh" => Ok(BinaryOp::Smulh),
+            "sdiv" => Ok(BinaryOp::Sdiv),
+            "udiv" => Ok(BinaryOp::Udiv),
+            "srem" => Ok(BinaryOp::Srem),
+            "urem" => Ok(BinaryOp::Urem),
+            "and" => Ok(BinaryOp::And),
+            "or" => Ok(BinaryOp::Or),
+            "xor" => Ok(BinaryOp::Xor),
+            "shl" => Ok(BinaryOp::Shl),
+            "lshr" => Ok(BinaryOp::Lshr),
+            "ashr" => Ok(BinaryOp::Ashr),
+            "fadd" => Ok(BinaryOp::Fadd),
+            "fsub" => Ok(BinaryOp::Fsub),
+            "fmul" => Ok(BinaryOp::Fmul),
+            "fdiv" => Ok(BinaryOp::Fdiv),
+            _ => Err(format!("Unknown binary operation: {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for Flags {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.nuw {
+            write!(f, " nuw")?;
+        }
+
+        if self.nsw {
+            write!(f, " nsw")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            BinaryOp::Add => "add",
+            BinaryOp::Sub => "sub",
+            BinaryOp::Mul => "mul",
+            BinaryOp::Smulh => "smulh",
+            BinaryOp::Sdiv => "sdiv",
+            BinaryOp::Udiv => "udiv",
+            BinaryOp::Srem => "srem",
+            BinaryOp::Urem => "urem",
+            BinaryOp::And => "and",
+            BinaryOp::Or => "or",
+            BinaryOp::Xor => "xor",
+            BinaryOp::Shl => "shl",
+            BinaryOp::Lshr => "lshr",
+            BinaryOp::Ashr => "ashr",
+            BinaryOp::Fadd => "fadd",
+            BinaryOp::Fsub => "fsub",
+            BinaryOp::Fmul => "fmul",
+            BinaryOp::Fdiv => "fdiv",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+impl IntCC {
+    // The condition with the operands in the other order, `a < b` is `b > a`
+    pub fn swapped(&self) -> IntCC {
+        match self {
+            IntCC::Eq | IntCC::Ne => *self,
+            IntCC::Slt => IntCC::Sgt,
+            IntCC::Sle => IntCC::Sge,
+            IntCC::Sgt => IntCC::Slt,
+            IntCC::Sge => IntCC::Sle,
+            IntCC::Ult => IntCC::Ugt,
+            IntCC::Ule => IntCC::Uge,
+            IntCC::Ugt => IntCC::Ult,
+            IntCC::Uge => IntCC::Ule,
+        }
+    }
+
+    // The condition that is true when this one is false
+    pub fn inverse(&self) -> IntCC {
+        match self {
+            IntCC::Eq => IntCC::Ne,
+            IntCC::Ne => IntCC::Eq,
+            IntCC::Slt => IntCC::Sge,
+            IntCC::Sle => IntCC::Sgt,
+            IntCC::Sgt => IntCC::Sle,
+            IntCC::Sge => IntCC::Slt,
+            IntCC::Ult => IntCC::Uge,
+            IntCC::Ule => IntCC::Ugt,
+            IntCC::Ugt => IntCC::Ule,
+            IntCC::Uge => IntCC::Ult,
+        }
+    }
+}
+
+impl FromStr for IntCC {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "eq" => Ok(IntCC::Eq),
+            "ne" => Ok(IntCC::Ne),
+            "slt" => Ok(IntCC::Slt),
+            "sle" => Ok(IntCC::Sle),
+            "sgt" => Ok(IntCC::Sgt),
+            "sge" => Ok(IntCC::Sge),
+            "ult" => Ok(IntCC::Ult),
+            "ule" => Ok(IntCC::Ule),
+            "ugt" => Ok(IntCC::Ugt),
+            "uge" => Ok(IntCC::Uge),
+            _ => Err(format!("Unknown condition: {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for IntCC {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            IntCC::Eq => "eq",
+            IntCC::Ne => "ne",
+            IntCC::Slt => "slt",
+            IntCC::Sle => "sle",
+            IntCC::Sgt => "sgt",
+            IntCC::Sge => "sge",
+            IntCC::Ult => "ult",
+            IntCC::Ule => "ule",
+            IntCC::Ugt => "ugt",
+            IntCC::Uge => "uge",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for FloatCC {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "oeq" => Ok(FloatCC::Oeq),
+            "one" => Ok(FloatCC::One),
+            "olt" => Ok(FloatCC::Olt),
+            "ole" => Ok(FloatCC::Ole),
+            "ogt" => Ok(FloatCC::Ogt),
+            "oge" => Ok(FloatCC::Oge),
+            "ord" => Ok(FloatCC::Ord),
+            "ueq" => Ok(FloatCC::Ueq),
+            "une" => Ok(FloatCC::Une),
+            "ult" => Ok(FloatCC::Ult),
+            "ule" => Ok(FloatCC::Ule),
+            "ugt" => Ok(FloatCC::Ugt),
+            "uge" => Ok(FloatCC::Uge),
+            "uno" => Ok(FloatCC::Uno),
+            _ => Err(format!("Unknown condition: {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for FloatCC {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            FloatCC::Oeq => "oeq",
+            FloatCC::One => "one",
+            FloatCC::Olt => "olt",
+            FloatCC::Ole => "ole",
+            FloatCC::Ogt => "ogt",
+            FloatCC::Oge => "oge",
+            FloatCC::Ord => "ord",
+            FloatCC::Ueq => "ueq",
+            FloatCC::Une => "une",
+            FloatCC::Ult => "ult",
+            FloatCC::Ule => "ule",
+            FloatCC::Ugt => "ugt",
+            FloatCC::Uge => "uge",
+            FloatCC::Uno => "uno",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for CastOp {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fptosi" => Ok(CastOp::Fptosi),
+            "sitofp" => Ok(CastOp::Sitofp),
+            "trunc" => Ok(CastOp::Trunc),
+            "zext" => Ok(CastOp::Zext),
+            "sext" => Ok(CastOp::Sext),
+            _ => Err(format!("Unknown cast: {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for CastOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            CastOp::Fptosi => "fptosi",
+            CastOp::Sitofp => "sitofp",
+            CastOp::Trunc => "trunc",
+            CastOp::Zext => "zext",
+            CastOp::Sext => "sext",
+        };
+
+        write!(f, "{}", name)
+    }
+}
@@ -0,0 +1,201 @@
+use super::builder::{FunctionBuilder, ModuleBuilder};
+use super::function::Function;
+use super::inst::{BinaryOp, Operand};
+use super::types::Ty;
+use super::verify::VerifyError;
+use super::Module;
+use crate::binary::symbol::SymbolType;
+use crate::parser::ast::AstNode;
+use crate::parser::types::{OverflowMode, Type, TypeError};
+use crate::riscv::regs::Reg;
+use std::collections::{HashMap, HashSet};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum LowerError {
+    #[error("Invalid Register {0}")]
+    InvalidRegister(String),
+
+    #[error("Target function {0}, not found")]
+    FnNotFound(String),
+
+    #[error("Nested functions are not allowed")]
+    NestedFunction,
+
+    #[error("Code outside of Function")]
+    OutsideOfFunction,
+
+    #[error("{0}")]
+    TypeError(#[from] TypeError),
+
+    #[error("{0}")]
+    Verify(#[from] VerifyError),
+}
+
+// Registers that carry the arguments in and a0 the result out
+const ARGUMENTS: [Reg; 8] = [
+    Reg::A0,
+    Reg::A1,
+    Reg::A2,
+    Reg::A3,
+    Reg::A4,
+    Reg::A5,
+    Reg::A6,
+    Reg::A7,
+];
+
+// Lowers the AST into the IR, every function becomes `i64 @name(i64 %a0, ..., i64 %a7)`
+// and the physical registers it names become SSA values
+pub fn lower_ast(nodes: &[AstNode]) -> Result<Module, LowerError> {
+    let names: HashSet<&str> = nodes
+        .iter()
+        .filter_map(|node| match node {
+            AstNode::Function { name, .. } => Some(name.as_str()),
+            _ => None,
+        })
+        .collect();
+
+    let mut module = ModuleBuilder::new();
+
+    for node in nodes {
+        match node {
+            AstNode::Function {
+                name, stype, body, ..
+            } => {
+                let function = Lowering::new(name, *stype, &names).function(body)?;
+                module = module.add_function(function);
+            }
+            AstNode::Nop => {}
+            _ => return Err(LowerError::OutsideOfFunction),
+        }
+    }
+
+    Ok(module.module)
+}
+
+struct Lowering<'a> {
+    builder: FunctionBuilder,
+    // Current value of each register, by register number
+    registers: HashMap<u64, Operand>,
+    functions: &'a HashSet<&'a str>,
+}
+
+impl<'a> Lowering<'a> {
+    fn new(name: &str, stype: SymbolType, functions: &'a HashSet<&'a str>) -> Self {
+        let mut builder = FunctionBuilder::new(name.to_string())
+            .set_linkage(stype)
+            .set_ret(Ty::I64);
+        let mut registers = HashMap::new();
+
+        for reg in &ARGUMENTS {
+            let name = format!("{:?}", reg).to_lowercase();
+            let param = builder.add_param(Ty::I64, Some(name));
+            registers.insert(u64::from(reg), Operand::Value(param));
+        }
+
+        Self {
+            builder,
+            registers,
+            functions,
+        }
+    }
+
+    fn register(name: &str) -> Result<Reg, LowerError> {
+        Reg::try_from(&name.replace('%', "")).map_err(|_| LowerError::InvalidRegister(name.into()))
+    }
+
+    // Registers that were never written read as zero
+    fn get(&self, reg: &Reg) -> Operand {
+        self.registers
+            .get(&u64::from(reg))
+            .cloned()
+            .unwrap_or(Operand::Const(Type::from(0i64)))
+    }
+
+    // Writes to zero are discarded
+    fn set(&mut self, reg: &Reg, value: impl Into<Operand>) {
+        if *reg != Reg::Zero {
+            self.registers.insert(u64::from(reg), value.into());
+        }
+    }
+
+    fn arguments(&self) -> Vec<Operand> {
+        ARGUMENTS.iter().map(|reg| self.get(reg)).collect()
+    }
+
+    fn function(mut self, body: &[AstNode]) -> Result<Function, LowerError> {
+        for node in body {
+            match node {
+                AstNode::Function { .. } => return Err(LowerError::NestedFunction),
+                AstNode::Sum { numbers, dist, t } => {
+                    let reg = Self::register(dist)?;
+                    let mut result = Type::parse(t, "0")?;
+                    let mut regs = Vec::new();
+
+                    for n in numbers {
+                        match n {
+                            Type::Value(v) if v == "%zero" => {}
+                            Type::Value(v) => regs.push(Self::register(v)?),
+                            num => result.try_add(num.clone(), OverflowMode::Checked)?,
+                        }
+                    }
+
+                    let value = result
+                        .as_i128()
+                        .ok_or_else(|| TypeError::CannotCast(result.clone()))?;
+                    let mut sum = Operand::Const(Type::from(value as i64));
+
+                    for r in regs {
+                        let rhs = self.get(&r);
+                        sum = Operand::Value(self.builder.ins().binary(BinaryOp::Add, sum, rhs));
+                    }
+
+                    self.set(&reg, sum);
+                }
+                AstNode::Load { dist, value } => {
+                    let reg = Self::register(dist)?;
+                    self.set(&reg, Type::from(*value));
+                }
+                AstNode::Radd { target, rs1 } | AstNode::Rsub { target, rs1 } => {
+                    let op = match node {
+                        AstNode::Radd { .. } => BinaryOp::Add,
+                        _ => BinaryOp::Sub,
+                    };
+                    let target = Self::register(target)?;
+                    let rs1 = Self::register(rs1)?;
+                    let (lhs, rhs) = (self.get(&target), self.get(&rs1));
+
+                    let value = self.builder.ins().binary(op, lhs, rhs);
+                    self.set(&target, value);
+                }
+                AstNode::Syscall => {
+                    let mut args = vec![self.get(&Reg::A7)];
+                    args.extend(ARGUMENTS[..6].iter().map(|reg| self.get(reg)));
+
+                    let value = self.builder.ins().syscall(args);
+                    self.set(&Reg::A0, value);
+                }
+                AstNode::Go { target, .. } => {
+                    if !self.functions.contains(target.as_str()) {
+                        return Err(LowerError::FnNotFound(target.to_string()));
+                    }
+
+                    let args = self.arguments();
+                    // Only a0 comes back from the callee
+                    if let Some(value) = self.builder.ins().call(target.to_string(), Ty::I64, args)
+                    {
+                        self.set(&Reg::A0, value);
+                    }
+                }
+                // Anything after the return is unreachable
+                AstNode::Ret => break,
+                AstNode::Nop => {}
+            }
+        }
+
+        let result = self.get(&Reg::A0);
+        self.builder.ins().ret(Some(result));
+
+        Ok(self.builder.build()?)
+    }
+}
@@ -0,0 +1,36 @@
+pub mod builder;
+pub mod cfg;
+pub mod function;
+pub mod inst;
+pub mod lower;
+pub mod types;
+pub mod uses;
+pub mod verify;
+
+use function::Function;
+use std::fmt::Display;
+
+#[derive(Debug, Clone, Default)]
+pub struct Module {
+    pub functions: Vec<Function>,
+}
+
+impl Module {
+    pub fn get_function(&self, name: &str) -> Option<&Function> {
+        self.functions.iter().find(|function| function.name == name)
+    }
+}
+
+impl Display for Module {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, function) in self.functions.iter().enumerate() {
+            if i != 0 {
+                writeln!(f)?;
+            }
+
+            writeln!(f, "{}", function)?;
+        }
+
+        Ok(())
+    }
+}
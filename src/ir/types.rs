@@ -0,0 +1,117 @@
+use crate::parser::types::{int_type, Type};
+use std::fmt::Display;
+use std::str::FromStr;
+
+// Types of IR values, unlike parser::types::Type these carry no constant
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum Ty {
+    Void,
+
+    // Integers of 1 to 128 bits, written i24 when signed and u24 when unsigned
+    Int { bits: u32, signed: bool },
+
+    F16,
+    F32,
+    F64,
+
+    Ptr,
+}
+
+impl Ty {
+    pub const I1: Ty = Ty::Int {
+        bits: 1,
+        signed: true,
+    };
+
+    pub const I64: Ty = Ty::Int {
+        bits: 64,
+        signed: true,
+    };
+
+    pub const I128: Ty = Ty::Int {
+        bits: 128,
+        signed: true,
+    };
+
+    pub fn is_integer(&self) -> bool {
+        matches!(self, Ty::Int { .. })
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, Ty::F16 | Ty::F32 | Ty::F64)
+    }
+
+    pub fn is_signed(&self) -> bool {
+        matches!(self, Ty::Int { signed: true, .. })
+    }
+
+    pub fn bits(&self) -> u32 {
+        match self {
+            Ty::Void => 0,
+            Ty::Int { bits, .. } => *bits,
+            Ty::F16 => 16,
+            Ty::F32 => 32,
+            Ty::F64 | Ty::Ptr => 64,
+        }
+    }
+
+    // The zero constant of the type, void and pointers have no constants
+    pub fn zero(&self) -> Option<Type> {
+        match self {
+            Ty::Int { bits, signed } => Some(Type::int(*bits, *signed, 0)),
+            Ty::F16 => Some(Type::F16(0)),
+            Ty::F32 => Some(Type::F32(0)),
+            Ty::F64 => Some(Type::F64(0)),
+            Ty::Void | Ty::Ptr => None,
+        }
+    }
+
+    // Returns the type of a constant, Type::Value is not a constant so it has none
+    pub fn of(value: &Type) -> Option<Ty> {
+        match value {
+            Type::Int { bits, signed, .. } => Some(Ty::Int {
+                bits: *bits,
+                signed: *signed,
+            }),
+            Type::F16(_) => Some(Ty::F16),
+            Type::F32(_) => Some(Ty::F32),
+            Type::F64(_) => Some(Ty::F64),
+            Type::Value(_) => None,
+        }
+    }
+}
+
+impl FromStr for Ty {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "void" => Ok(Ty::Void),
+            "half" => Ok(Ty::F16),
+            "float" => Ok(Ty::F32),
+            "double" => Ok(Ty::F64),
+            "ptr" => Ok(Ty::Ptr),
+            _ => match int_type(s) {
+                Some((bits, signed)) => Ok(Ty::Int { bits, signed }),
+                None => Err(format!("Unknown type: {}", s)),
+            },
+        }
+    }
+}
+
+impl Display for Ty {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Ty::Void => write!(f, "void"),
+            Ty::Int { bits, signed: true } => write!(f, "i{}", bits),
+            Ty::Int {
+                bits,
+                signed: false,
+            } => write!(f, "u{}", bits),
+            Ty::F16 => write!(f, "half"),
+            Ty::F32 => write!(f, "float"),
+            Ty::F64 => write!(f, "double"),
+            Ty::Ptr => write!(f, "ptr"),
+        }
+    }
+}
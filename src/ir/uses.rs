@@ -0,0 +1,37 @@
+use super::function::{Function, Inst, Value};
+use super::inst::Operand;
+
+// Instructions using each value, indexed by value id
+#[derive(Debug, Clone, Default)]
+pub struct UseLists {
+    uses: Vec<Vec<Inst>>,
+}
+
+impl UseLists {
+    pub fn new(function: &Function) -> Self {
+        let mut uses = vec![Vec::new(); function.values.len()];
+
+        for block in &function.layout {
+            for inst in &function.blocks[block.0].insts {
+                for operand in function.insts[inst.0].kind.operands() {
+                    if let Operand::Value(value) = operand {
+                        // An instruction using the value twice appears once
+                        if uses[value.0].last() != Some(inst) {
+                            uses[value.0].push(*inst);
+                        }
+                    }
+                }
+            }
+        }
+
+        Self { uses }
+    }
+
+    pub fn uses(&self, value: Value) -> &[Inst] {
+        &self.uses[value.0]
+    }
+
+    pub fn is_unused(&self, value: Value) -> bool {
+        self.uses[value.0].is_empty()
+    }
+}
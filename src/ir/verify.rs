@@ -0,0 +1,514 @@
+use super::cfg::ControlFlowGraph;
+use super::function::{Block, Function, Inst, Value, ValueDef};
+use super::inst::{BinaryOp, CastOp, InstKind, Operand};
+use super::types::Ty;
+use super::Module;
+use crate::analysis::dominators::DomTree;
+use crate::parser::types::Type;
+use std::collections::{HashMap, HashSet};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum VerifyErrorKind {
+    #[error("the function has no blocks")]
+    EmptyFunction,
+
+    #[error("the function can not be noinline and alwaysinline")]
+    ConflictingAttributes,
+
+    #[error("block {0} does not end in a terminator")]
+    MissingTerminator(String),
+
+    #[error("terminator in the middle of block {0}")]
+    MisplacedTerminator(String),
+
+    #[error("phi after a non phi instruction")]
+    MisplacedPhi,
+
+    #[error("block {0} appears more than once in the layout")]
+    DuplicatedBlock(String),
+
+    #[error("branch target {0} does not exist")]
+    UnknownBlock(String),
+
+    #[error("the entry block can not be a branch target")]
+    EntryHasPredecessors,
+
+    #[error("{0} is defined more than once")]
+    MultipleDefinitions(String),
+
+    #[error("{0} is never defined")]
+    UndefinedValue(String),
+
+    #[error("{0} does not dominate this use")]
+    NotDominated(String),
+
+    #[error("expected type {expected}, found {found}")]
+    MismatchedTypes { expected: Ty, found: Ty },
+
+    #[error("type {0} is not valid here")]
+    InvalidType(Ty),
+
+    #[error("{0} is not a constant")]
+    InvalidConstant(Type),
+
+    #[error("function @{0} does not exist")]
+    UnknownFunction(String),
+
+    #[error("expected {expected} arguments, found {found}")]
+    ArgumentCount { expected: usize, found: usize },
+
+    #[error("the incoming blocks do not match the predecessors of {0}")]
+    PhiPredecessors(String),
+}
+
+#[derive(Error, Debug)]
+#[error("In function @{function}, `{location}`: {kind}")]
+pub struct VerifyError {
+    pub function: String,
+    pub location: String,
+    pub kind: VerifyErrorKind,
+}
+
+pub fn verify_module(module: &Module) -> Result<(), VerifyError> {
+    for function in &module.functions {
+        verify_function(module, function)?;
+    }
+
+    Ok(())
+}
+
+pub fn verify_function(module: &Module, function: &Function) -> Result<(), VerifyError> {
+    Verifier::new(Some(module), function).run()
+}
+
+// Everything but the calls, which need the other functions of the module
+pub fn verify_function_body(function: &Function) -> Result<(), VerifyError> {
+    Verifier::new(None, function).run()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DefSite {
+    Param,
+    Inst(Block, usize),
+}
+
+struct Verifier<'a> {
+    module: Option<&'a Module>,
+    function: &'a Function,
+    preds: HashMap<Block, Vec<Block>>,
+    defs: HashMap<Value, DefSite>,
+    domtree: DomTree,
+}
+
+impl<'a> Verifier<'a> {
+    fn new(module: Option<&'a Module>, function: &'a Function) -> Self {
+        Self {
+            module,
+            function,
+            preds: HashMap::new(),
+            defs: HashMap::new(),
+            domtree: DomTree::default(),
+        }
+    }
+
+    fn run(mut self) -> Result<(), VerifyError> {
+        let Some(entry) = self.function.entry() else {
+            return Err(self.at_function(VerifyErrorKind::EmptyFunction));
+        };
+
+        let attributes = self.function.attributes;
+
+        if attributes.noinline && attributes.alwaysinline {
+            return Err(self.at_function(VerifyErrorKind::ConflictingAttributes));
+        }
+
+        self.check_structure()?;
+        self.check_edges(entry)?;
+        self.check_definitions()?;
+        // The edges were checked, so every successor is a placed block
+        self.domtree = DomTree::new(self.function, &ControlFlowGraph::new(self.function));
+
+        for block in &self.function.layout {
+            for inst in &self.function.blocks[block.0].insts {
+                self.check_inst(*block, *inst)
+                    .map_err(|kind| self.at_inst(*inst, kind))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn at_function(&self, kind: VerifyErrorKind) -> VerifyError {
+        VerifyError {
+            function: self.function.name.clone(),
+            location: format!("define @{}", self.function.name),
+            kind,
+        }
+    }
+
+    fn at_block(&self, block: Block, kind: VerifyErrorKind) -> VerifyError {
+        VerifyError {
+            function: self.function.name.clone(),
+            location: format!("{}:", self.function.blocks[block.0].name),
+            kind,
+        }
+    }
+
+    fn at_inst(&self, inst: Inst, kind: VerifyErrorKind) -> VerifyError {
+        VerifyError {
+            function: self.function.name.clone(),
+            location: self.function.inst_to_string(inst),
+            kind,
+        }
+    }
+
+    // Every block is placed once, ends in a terminator and starts with its phis
+    fn check_structure(&self) -> Result<(), VerifyError> {
+        let mut placed = HashSet::new();
+
+        for block in &self.function.layout {
+            let name = self.function.block_name(*block);
+
+            if !placed.insert(*block) {
+                return Err(self.at_block(*block, VerifyErrorKind::DuplicatedBlock(name)));
+            }
+
+            let insts = &self.function.blocks[block.0].insts;
+            let mut phis_allowed = true;
+
+            for (i, inst) in insts.iter().enumerate() {
+                let kind = &self.function.insts[inst.0].kind;
+
+                if kind.is_terminator() && i != insts.len() - 1 {
+                    return Err(self.at_inst(*inst, VerifyErrorKind::MisplacedTerminator(name)));
+                }
+
+                match kind {
+                    InstKind::Phi { .. } if !phis_allowed => {
+                        return Err(self.at_inst(*inst, VerifyErrorKind::MisplacedPhi));
+                    }
+                    InstKind::Phi { .. } => {}
+                    _ => phis_allowed = false,
+                }
+            }
+
+            if self.function.terminator(*block).is_none() {
+                return Err(self.at_block(*block, VerifyErrorKind::MissingTerminator(name)));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check_edges(&mut self, entry: Block) -> Result<(), VerifyError> {
+        let placed: HashSet<Block> = self.function.layout.iter().copied().collect();
+
+        for block in &self.function.layout {
+            self.preds.entry(*block).or_default();
+        }
+
+        for block in &self.function.layout {
+            let terminator = self.function.terminator(*block).unwrap();
+
+            for succ in self.function.successors(*block) {
+                if !placed.contains(&succ) {
+                    let name = self.function.block_name(succ);
+                    return Err(self.at_inst(terminator, VerifyErrorKind::UnknownBlock(name)));
+                }
+
+                if succ == entry {
+                    return Err(self.at_inst(terminator, VerifyErrorKind::EntryHasPredecessors));
+                }
+
+                let preds = self.preds.get_mut(&succ).unwrap();
+
+                if !preds.contains(block) {
+                    preds.push(*block);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check_definitions(&mut self) -> Result<(), VerifyError> {
+        for (i, param) in self.function.params.iter().enumerate() {
+            if self.function.values[param.0].def != ValueDef::Param(i)
+                || self.defs.insert(*param, DefSite::Param).is_some()
+            {
+                let name = self.function.value_name(*param);
+                return Err(self.at_function(VerifyErrorKind::MultipleDefinitions(name)));
+            }
+        }
+
+        for block in &self.function.layout {
+            for (i, inst) in self.function.blocks[block.0].insts.iter().enumerate() {
+                let data = &self.function.insts[inst.0];
+                let expected = data.kind.result_ty();
+
+                let Some(result) = data.result else {
+                    // Calls may drop their result
+                    if expected != Ty::Void && !matches!(data.kind, InstKind::Call { .. }) {
+                        return Err(self.at_inst(*inst, VerifyErrorKind::InvalidType(Ty::Void)));
+                    }
+                    continue;
+                };
+
+                if self.function.values[result.0].def != ValueDef::Inst(*inst)
+                    || self.defs.insert(result, DefSite::Inst(*block, i)).is_some()
+                {
+                    let name = self.function.value_name(result);
+                    return Err(self.at_inst(*inst, VerifyErrorKind::MultipleDefinitions(name)));
+                }
+
+                let found = self.function.values[result.0].ty;
+
+                if expected == Ty::Void {
+                    return Err(self.at_inst(*inst, VerifyErrorKind::InvalidType(found)));
+                }
+
+                if found != expected {
+                    return Err(
+                        self.at_inst(*inst, VerifyErrorKind::MismatchedTypes { expected, found })
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Checks that `value` is available at position `index` of `block`
+    fn check_use(&self, value: Value, block: Block, index: usize) -> Result<(), VerifyErrorKind> {
+        let name = || self.function.value_name(value);
+
+        match self.defs.get(&value) {
+            None => Err(VerifyErrorKind::UndefinedValue(name())),
+            Some(DefSite::Param) => Ok(()),
+            Some(DefSite::Inst(def_block, def_index)) => {
+                let available = if *def_block == block {
+                    // Anything dominates unreachable code
+                    *def_index < index || !self.domtree.is_reachable(block)
+                } else {
+                    self.domtree.dominates(*def_block, block)
+                };
+
+                if available {
+                    Ok(())
+                } else {
+                    Err(VerifyErrorKind::NotDominated(name()))
+                }
+            }
+        }
+    }
+
+    fn operand_ty(&self, operand: &Operand) -> Result<Ty, VerifyErrorKind> {
+        self.function
+            .operand_ty(operand)
+            .ok_or_else(|| match operand {
+                Operand::Const(constant) => VerifyErrorKind::InvalidConstant(constant.clone()),
+                Operand::Value(value) => {
+                    VerifyErrorKind::UndefinedValue(self.function.value_name(*value))
+                }
+            })
+    }
+
+    fn expect_ty(&self, operand: &Operand, expected: Ty) -> Result<(), VerifyErrorKind> {
+        let found = self.operand_ty(operand)?;
+
+        if found != expected {
+            return Err(VerifyErrorKind::MismatchedTypes { expected, found });
+        }
+
+        Ok(())
+    }
+
+    fn check_inst(&self, block: Block, inst: Inst) -> Result<(), VerifyErrorKind> {
+        let kind = &self.function.insts[inst.0].kind;
+        let index = self.function.blocks[block.0]
+            .insts
+            .iter()
+            .position(|i| *i == inst)
+            .unwrap();
+
+        if let InstKind::Phi { incoming, .. } = kind {
+            for (operand, pred) in incoming {
+                if let Operand::Value(value) = operand {
+                    let end = self.function.blocks[pred.0].insts.len();
+                    self.check_use(*value, *pred, end)?;
+                }
+            }
+        } else {
+            for operand in kind.operands() {
+                if let Operand::Value(value) = operand {
+                    self.check_use(*value, block, index)?;
+                }
+            }
+        }
+
+        match kind {
+            InstKind::Binary { ty, lhs, rhs, .. }
+            | InstKind::Icmp { ty, lhs, rhs, .. }
+            | InstKind::Fcmp { ty, lhs, rhs, .. } => {
+                let valid = match kind {
+                    InstKind::Binary { op, .. } if op.is_float() => ty.is_float(),
+                    InstKind::Binary {
+                        op: BinaryOp::Smulh,
+                        ..
+                    } => ty.is_integer() && matches!(ty.bits(), 32 | 64),
+                    InstKind::Icmp { .. } => ty.is_integer() || *ty == Ty::Ptr,
+                    InstKind::Fcmp { .. } => ty.is_float(),
+                    _ => ty.is_integer(),
+                };
+
+                if !valid {
+                    return Err(VerifyErrorKind::InvalidType(*ty));
+                }
+
+                self.expect_ty(lhs, *ty)?;
+                self.expect_ty(rhs, *ty)?;
+            }
+            InstKind::Cast {
+                op,
+                from,
+                value,
+                to,
+            } => {
+                let (from_valid, to_valid) = match op {
+                    CastOp::Fptosi => (from.is_float(), to.is_integer()),
+                    CastOp::Sitofp => (from.is_integer(), to.is_float()),
+                    CastOp::Trunc => (
+                        from.is_integer(),
+                        to.is_integer() && to.bits() < from.bits(),
+                    ),
+                    CastOp::Zext | CastOp::Sext => (
+                        from.is_integer(),
+                        to.is_integer() && to.bits() > from.bits(),
+                    ),
+                };
+
+                if !from_valid {
+                    return Err(VerifyErrorKind::InvalidType(*from));
+                }
+
+                if !to_valid {
+                    return Err(VerifyErrorKind::InvalidType(*to));
+                }
+
+                self.expect_ty(value, *from)?;
+            }
+            InstKind::Alloca { ty } => {
+                if *ty == Ty::Void {
+                    return Err(VerifyErrorKind::InvalidType(*ty));
+                }
+            }
+            InstKind::Load { ty, ptr } => {
+                if *ty == Ty::Void {
+                    return Err(VerifyErrorKind::InvalidType(*ty));
+                }
+
+                self.expect_ty(ptr, Ty::Ptr)?;
+            }
+            InstKind::Store { ty, value, ptr } => {
+                if *ty == Ty::Void {
+                    return Err(VerifyErrorKind::InvalidType(*ty));
+                }
+
+                self.expect_ty(value, *ty)?;
+                self.expect_ty(ptr, Ty::Ptr)?;
+            }
+            InstKind::PtrAdd { ptr, offset } => {
+                self.expect_ty(ptr, Ty::Ptr)?;
+                self.expect_ty(offset, Ty::I64)?;
+            }
+            InstKind::Call {
+                callee, ret, args, ..
+            } => {
+                // A function verified on its own can not see the callee, the module checks it
+                if let Some(module) = self.module {
+                    let Some(target) = module.get_function(callee) else {
+                        return Err(VerifyErrorKind::UnknownFunction(callee.to_string()));
+                    };
+
+                    if target.ret != *ret {
+                        return Err(VerifyErrorKind::MismatchedTypes {
+                            expected: target.ret,
+                            found: *ret,
+                        });
+                    }
+
+                    if target.params.len() != args.len() {
+                        return Err(VerifyErrorKind::ArgumentCount {
+                            expected: target.params.len(),
+                            found: args.len(),
+                        });
+                    }
+
+                    for (param, (ty, _)) in target.params.iter().zip(args) {
+                        let expected = target.values[param.0].ty;
+
+                        if expected != *ty {
+                            return Err(VerifyErrorKind::MismatchedTypes {
+                                expected,
+                                found: *ty,
+                            });
+                        }
+                    }
+                }
+
+                for (ty, arg) in args {
+                    self.expect_ty(arg, *ty)?;
+                }
+            }
+            InstKind::Phi { ty, incoming } => {
+                let preds = &self.preds[&block];
+                let blocks: Vec<Block> = incoming.iter().map(|(_, block)| *block).collect();
+                let unique: HashSet<&Block> = blocks.iter().collect();
+
+                if blocks.len() != preds.len()
+                    || unique.len() != blocks.len()
+                    || !preds.iter().all(|pred| unique.contains(pred))
+                {
+                    let name = self.function.block_name(block);
+                    return Err(VerifyErrorKind::PhiPredecessors(name));
+                }
+
+                for (operand, _) in incoming {
+                    self.expect_ty(operand, *ty)?;
+                }
+            }
+            InstKind::Syscall { args } => {
+                // The number and at most six arguments
+                if args.is_empty() || args.len() > 7 {
+                    return Err(VerifyErrorKind::ArgumentCount {
+                        expected: args.len().clamp(1, 7),
+                        found: args.len(),
+                    });
+                }
+
+                for arg in args {
+                    self.expect_ty(arg, Ty::I64)?;
+                }
+            }
+            InstKind::Jump { .. } => {}
+            InstKind::Branch { cond, .. } => self.expect_ty(cond, Ty::I1)?,
+            InstKind::Ret { ty, value } => {
+                if *ty != self.function.ret {
+                    return Err(VerifyErrorKind::MismatchedTypes {
+                        expected: self.function.ret,
+                        found: *ty,
+                    });
+                }
+
+                match value {
+                    Some(value) => self.expect_ty(value, *ty)?,
+                    None if *ty != Ty::Void => return Err(VerifyErrorKind::InvalidType(Ty::Void)),
+                    None => {}
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
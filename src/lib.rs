@@ -0,0 +1,125 @@
+pub mod analysis;
+pub mod binary;
+pub mod codegen;
+pub mod diagnostics;
+pub mod ir;
+pub mod opt;
+pub mod parser;
+pub mod riscv;
+
+pub use diagnostics::{Diagnostic, Diagnostics};
+pub use opt::OptLevel;
+
+use binary::{elf::Elf, Binary, Section};
+use ir::lower::lower_ast;
+use ir::verify::verify_function;
+use ir::Module;
+use object::{Architecture, Endianness};
+use opt::pass::get_pass;
+use opt::PassManager;
+use parser::ast::get_from_tokens;
+use parser::ir::get_module;
+use parser::token::{get_tokens, Token};
+use tracing::{debug, info};
+
+#[derive(Debug, Clone)]
+pub struct CompileOptions {
+    // Verifies the IR after every pass, on by default in debug builds
+    pub verify_passes: bool,
+    pub opt_level: OptLevel,
+    // Replaces the pipeline of the optimization level
+    pub passes: Option<Vec<String>>,
+    // Passes whose result is logged, "all" logs after every pass
+    pub print_after: Vec<String>,
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        Self {
+            verify_passes: cfg!(debug_assertions),
+            opt_level: OptLevel::default(),
+            passes: None,
+            print_after: Vec::new(),
+        }
+    }
+}
+
+// Compiles the source into the bytes of a RISC-V ELF object
+pub fn compile(source: &str, options: &CompileOptions) -> Result<Vec<u8>, Diagnostics> {
+    compile_module(&parse(source)?, options)
+}
+
+// Compiles the source into RISC-V assembly for GNU as
+pub fn compile_to_assembly(source: &str, options: &CompileOptions) -> Result<String, Diagnostics> {
+    let module = optimize(&parse(source)?, options)?;
+    Ok(codegen::asm::assembly(&module)?.to_string())
+}
+
+// Compiles a module built in memory, it is verified and optimized like a parsed one
+pub fn compile_module(module: &Module, options: &CompileOptions) -> Result<Vec<u8>, Diagnostics> {
+    let module = optimize(module, options)?;
+    let symbols = codegen::compile_module(&module)?;
+
+    let mut elf = Elf::new(Architecture::Riscv64, Endianness::Little);
+    info!("Generating dist file");
+
+    elf.create_section(Section::Text);
+
+    for symbol in symbols {
+        elf.write_section(symbol.section.clone(), symbol)?;
+    }
+
+    Ok(elf.get()?)
+}
+
+fn parse(source: &str) -> Result<Module, Diagnostics> {
+    let tokens = get_tokens(source.to_string())?;
+    debug!("{:?}", tokens);
+
+    // .tir files start with a definition, anything else is the assembly-like syntax
+    if tokens.first() == Some(&Token::Define) {
+        Ok(get_module(tokens)?)
+    } else {
+        let (ast, _) = get_from_tokens(tokens)?;
+        Ok(lower_ast(&ast)?)
+    }
+}
+
+fn optimize(module: &Module, options: &CompileOptions) -> Result<Module, Diagnostics> {
+    verify(module)?;
+
+    let mut module = module.clone();
+
+    let passes = match &options.passes {
+        Some(names) => PassManager::from_names(names)?.set_level(options.opt_level),
+        None => PassManager::from_level(options.opt_level),
+    };
+
+    // The backend only handles legal types, so legalize runs even at -O0
+    passes
+        .add_pass(get_pass("legalize")?)
+        .set_print_after(options.print_after.clone())
+        .set_verify(options.verify_passes)
+        .run(&mut module)?;
+
+    debug!("Optimized IR:\n{}", module);
+
+    Ok(module)
+}
+
+// Verifies every function so all the invalid ones are reported at once
+fn verify(module: &Module) -> Result<(), Diagnostics> {
+    let mut diagnostics = Diagnostics::default();
+
+    for function in &module.functions {
+        if let Err(e) = verify_function(module, function) {
+            diagnostics.push(e);
+        }
+    }
+
+    if diagnostics.is_empty() {
+        Ok(())
+    } else {
+        Err(diagnostics)
+    }
+}
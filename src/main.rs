@@ -1,13 +1,5 @@
-mod binary;
-mod parser;
-mod riscv;
-
-use binary::symbol::SymbolBuilder;
-use binary::{elf::Elf, Binary, Section};
 use clap::Parser;
-use object::{Architecture, Endianness};
-use parser::ast::get_from_tokens;
-use parser::token::get_tokens;
+use tinity::{compile, compile_to_assembly, CompileOptions, OptLevel};
 use tracing::Level;
 use tracing::{error, info};
 use tracing_subscriber::FmtSubscriber;
@@ -18,6 +10,22 @@ struct Args {
 
     #[clap(short, long)]
     output: Option<String>,
+
+    // -O0, -O1, -O2 or -Os
+    #[clap(short = 'O', default_value = "0")]
+    opt_level: OptLevel,
+
+    // Runs these passes instead of the pipeline of the -O level
+    #[clap(long, value_delimiter = ',')]
+    passes: Option<Vec<String>>,
+
+    // Logs the IR after these passes, "all" logs after every pass
+    #[clap(long, value_delimiter = ',')]
+    print_after: Vec<String>,
+
+    // Writes assembly instead of an object
+    #[clap(short = 'S')]
+    assembly: bool,
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
@@ -32,32 +40,36 @@ fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let args = Args::parse();
 
     let input = std::fs::read_to_string(args.file)?;
-    let tokens = get_tokens(input)?;
-    println!("{:?}", tokens);
-    let (ast, functions) = get_from_tokens(tokens)?;
-
-    let mut elf = Elf::new(Architecture::Riscv64, Endianness::Little);
-    info!("Generating dist file");
+    let default = if args.assembly {
+        "output.s"
+    } else {
+        "output.elf"
+    };
+    let output = args.output.unwrap_or(default.to_string());
+
+    let options = CompileOptions {
+        opt_level: args.opt_level,
+        passes: args.passes,
+        print_after: args.print_after,
+        ..CompileOptions::default()
+    };
+
+    let result = if args.assembly {
+        compile_to_assembly(&input, &options).map(String::into_bytes)
+    } else {
+        compile(&input, &options)
+    };
+
+    match result {
+        Ok(content) => std::fs::write(output, content)?,
+        Err(diagnostics) => {
+            for e in diagnostics.errors {
+                error!("{}", e);
+            }
 
-    let output = args.output.unwrap_or("output.elf".to_string());
-
-    let mut f = std::fs::File::create(output)?;
-    elf.create_section(Section::Text);
-
-    ast.iter().for_each(|node| {
-        let builder = SymbolBuilder::new().from_ast(&node, &functions);
-
-        if let Ok(builder) = builder {
-            let symbol = builder.build();
-
-            elf.write_section(Section::Text, symbol);
-        } else if let Err(e) = builder {
-            error!("Error processing AST: {}", e);
             std::process::exit(1);
         }
-    });
-
-    elf.save(&mut f).unwrap();
+    }
 
     info!("Compiled successfully");
 
@@ -0,0 +1,317 @@
+use crate::ir::function::{Block, Function};
+use crate::ir::inst::{BinaryOp, CastOp, Flags, FloatCC, InstKind, IntCC, Operand};
+use crate::ir::types::Ty;
+use crate::parser::types::{f64_to_f16, OverflowMode, Type};
+use std::cmp::Ordering;
+
+// Gives the result the signedness of the original operand
+fn like(result: Type, original: &Type) -> Type {
+    if original.to_unsigned() == *original {
+        result.to_unsigned()
+    } else {
+        result.to_signed()
+    }
+}
+
+fn overflows(op: BinaryOp, lhs: Type, rhs: Type) -> bool {
+    let mut result = lhs;
+
+    let checked = match op {
+        BinaryOp::Add => result.try_add(rhs, OverflowMode::Checked),
+        BinaryOp::Sub => result.try_sub(rhs, OverflowMode::Checked),
+        BinaryOp::Mul => result.try_mul(rhs, OverflowMode::Checked),
+        _ => Ok(()),
+    };
+
+    checked.is_err()
+}
+
+// Evaluates a binary operation the way the hardware does, None if the result is not defined
+pub fn fold_binary(op: BinaryOp, flags: Flags, lhs: &Type, rhs: &Type) -> Option<Type> {
+    if flags.nsw && overflows(op, lhs.to_signed(), rhs.to_signed()) {
+        return None;
+    }
+
+    if flags.nuw && overflows(op, lhs.to_unsigned(), rhs.to_unsigned()) {
+        return None;
+    }
+
+    if op.is_float() {
+        let mut result = lhs.clone();
+
+        match op {
+            BinaryOp::Fadd => result.try_fadd(rhs.clone()),
+            BinaryOp::Fsub => result.try_fsub(rhs.clone()),
+            BinaryOp::Fmul => result.try_fmul(rhs.clone()),
+            _ => result.try_fdiv(rhs.clone()),
+        }
+        .ok()?;
+
+        return Some(result);
+    }
+
+    let (mut result, rhs) = match op {
+        BinaryOp::Sdiv | BinaryOp::Srem | BinaryOp::Ashr => (lhs.to_signed(), rhs.to_signed()),
+        BinaryOp::Udiv | BinaryOp::Urem | BinaryOp::Lshr => (lhs.to_unsigned(), rhs.to_unsigned()),
+        _ => (lhs.clone(), rhs.clone()),
+    };
+    let wrapping = OverflowMode::Wrapping;
+
+    match op {
+        BinaryOp::Add => result.try_add(rhs, wrapping),
+        BinaryOp::Sub => result.try_sub(rhs, wrapping),
+        BinaryOp::Mul => result.try_mul(rhs, wrapping),
+        // The product of two 64 bit values still fits in an i128
+        BinaryOp::Smulh => {
+            let Type::Int {
+                bits: bits @ ..=64, ..
+            } = result
+            else {
+                return None;
+            };
+            let product = result.as_i128()? * rhs.as_i128()?;
+
+            result = Type::int(bits, true, (product >> bits) as u128);
+            Ok(())
+        }
+        BinaryOp::Sdiv | BinaryOp::Udiv => result.try_div(rhs, wrapping),
+        BinaryOp::Srem | BinaryOp::Urem => result.try_rem(rhs, wrapping),
+        BinaryOp::And => result.try_and(rhs),
+        BinaryOp::Or => result.try_or(rhs),
+        BinaryOp::Xor => result.try_xor(rhs),
+        // Shifting by the width of the type or more is not defined
+        BinaryOp::Shl => result.try_shl(rhs, OverflowMode::Checked),
+        BinaryOp::Lshr | BinaryOp::Ashr => result.try_shr(rhs, OverflowMode::Checked),
+        BinaryOp::Fadd | BinaryOp::Fsub | BinaryOp::Fmul | BinaryOp::Fdiv => unreachable!(),
+    }
+    .ok()?;
+
+    Some(like(result, lhs))
+}
+
+pub fn fold_icmp(cond: IntCC, lhs: &Type, rhs: &Type) -> Option<Type> {
+    let ordering = match cond {
+        IntCC::Eq | IntCC::Ne => lhs.try_cmp(rhs),
+        IntCC::Slt | IntCC::Sle | IntCC::Sgt | IntCC::Sge => {
+            lhs.to_signed().try_cmp(&rhs.to_signed())
+        }
+        IntCC::Ult | IntCC::Ule | IntCC::Ugt | IntCC::Uge => {
+            lhs.to_unsigned().try_cmp(&rhs.to_unsigned())
+        }
+    }
+    .ok()?;
+
+    let result = match cond {
+        IntCC::Eq => ordering == Ordering::Equal,
+        IntCC::Ne => ordering != Ordering::Equal,
+        IntCC::Slt | IntCC::Ult => ordering == Ordering::Less,
+        IntCC::Sle | IntCC::Ule => ordering != Ordering::Greater,
+        IntCC::Sgt | IntCC::Ugt => ordering == Ordering::Greater,
+        IntCC::Sge | IntCC::Uge => ordering != Ordering::Less,
+    };
+
+    Some(Type::bool(result))
+}
+
+pub fn fold_fcmp(cond: FloatCC, lhs: &Type, rhs: &Type) -> Option<Type> {
+    let result = match lhs.try_fcmp(rhs).ok()? {
+        None => matches!(
+            cond,
+            FloatCC::Ueq
+                | FloatCC::Une
+                | FloatCC::Ult
+                | FloatCC::Ule
+                | FloatCC::Ugt
+                | FloatCC::Uge
+                | FloatCC::Uno
+        ),
+        Some(ordering) => match cond {
+            FloatCC::Oeq | FloatCC::Ueq => ordering == Ordering::Equal,
+            FloatCC::One | FloatCC::Une => ordering != Ordering::Equal,
+            FloatCC::Olt | FloatCC::Ult => ordering == Ordering::Less,
+            FloatCC::Ole | FloatCC::Ule => ordering != Ordering::Greater,
+            FloatCC::Ogt | FloatCC::Ugt => ordering == Ordering::Greater,
+            FloatCC::Oge | FloatCC::Uge => ordering != Ordering::Less,
+            FloatCC::Ord => true,
+            FloatCC::Uno => false,
+        },
+    };
+
+    Some(Type::bool(result))
+}
+
+// The integer of the truncated float, when it is in the range of the type
+fn int_const(ty: Ty, value: f64) -> Option<Type> {
+    let Ty::Int { bits, signed } = ty else {
+        return None;
+    };
+
+    let (min, max) = if signed {
+        let limit = 2f64.powi(bits as i32 - 1);
+        (-limit, limit)
+    } else {
+        (0.0, 2f64.powi(bits as i32))
+    };
+
+    if !(min..max).contains(&value) {
+        return None;
+    }
+
+    let raw = if signed {
+        value as i128 as u128
+    } else {
+        value as u128
+    };
+
+    Some(Type::int(bits, signed, raw))
+}
+
+pub fn fold_cast(op: CastOp, value: &Type, to: Ty) -> Option<Type> {
+    let Ty::Int { bits, signed } = to else {
+        return fold_float_cast(op, value, to);
+    };
+
+    match op {
+        CastOp::Trunc | CastOp::Zext => Some(Type::int(bits, signed, value.as_u128()?)),
+        CastOp::Sext => Some(Type::int(bits, signed, value.as_i128()? as u128)),
+        _ => fold_float_cast(op, value, to),
+    }
+}
+
+fn fold_float_cast(op: CastOp, value: &Type, to: Ty) -> Option<Type> {
+    match op {
+        // Values that do not fit in the integer have no defined result, unsigned targets
+        // take the unsigned range
+        CastOp::Fptosi => int_const(to, value.to_f64()?.trunc()),
+        CastOp::Sitofp => {
+            let value = value.as_i128()?;
+
+            match to {
+                Ty::F16 => Some(Type::F16(f64_to_f16(value as f64))),
+                Ty::F32 => Some(Type::from(value as f32)),
+                Ty::F64 => Some(Type::from(value as f64)),
+                _ => None,
+            }
+        }
+        CastOp::Trunc | CastOp::Zext | CastOp::Sext => None,
+    }
+}
+
+pub fn fold_inst(kind: &InstKind) -> Option<Type> {
+    match kind {
+        InstKind::Binary {
+            op,
+            flags,
+            lhs: Operand::Const(lhs),
+            rhs: Operand::Const(rhs),
+            ..
+        } => fold_binary(*op, *flags, lhs, rhs),
+        InstKind::Icmp {
+            cond,
+            lhs: Operand::Const(lhs),
+            rhs: Operand::Const(rhs),
+            ..
+        } => fold_icmp(*cond, lhs, rhs),
+        InstKind::Fcmp {
+            cond,
+            lhs: Operand::Const(lhs),
+            rhs: Operand::Const(rhs),
+            ..
+        } => fold_fcmp(*cond, lhs, rhs),
+        InstKind::Cast {
+            op,
+            value: Operand::Const(value),
+            to,
+            ..
+        } => fold_cast(*op, value, *to),
+        // A phi that receives the same constant from every predecessor
+        InstKind::Phi { incoming, .. } => {
+            let (Operand::Const(first), _) = incoming.first()? else {
+                return None;
+            };
+
+            incoming
+                .iter()
+                .all(|(value, _)| *value == Operand::Const(first.clone()))
+                .then(|| first.clone())
+        }
+        _ => None,
+    }
+}
+
+// The operand an operation with a neutral constant gives back, `x + 0` is `x`
+pub(crate) fn identity(kind: &InstKind) -> Option<Operand> {
+    let is = |operand: &Operand, value: u128| matches!(operand, Operand::Const(constant) if constant.as_u128() == Some(value));
+
+    let (op, lhs, rhs) = match kind {
+        InstKind::Binary { op, lhs, rhs, .. } => (op, lhs, rhs),
+        InstKind::PtrAdd { ptr, offset } if is(offset, 0) => return Some(ptr.clone()),
+        _ => return None,
+    };
+
+    match op {
+        BinaryOp::Add | BinaryOp::Or | BinaryOp::Xor if is(lhs, 0) => Some(rhs.clone()),
+        BinaryOp::Mul if is(lhs, 1) => Some(rhs.clone()),
+        BinaryOp::Add
+        | BinaryOp::Sub
+        | BinaryOp::Or
+        | BinaryOp::Xor
+        | BinaryOp::Shl
+        | BinaryOp::Lshr
+        | BinaryOp::Ashr
+            if is(rhs, 0) =>
+        {
+            Some(lhs.clone())
+        }
+        BinaryOp::Mul | BinaryOp::Sdiv | BinaryOp::Udiv if is(rhs, 1) => Some(lhs.clone()),
+        _ => None,
+    }
+}
+
+// Adds the instruction before the terminator of the block, or gives its result right away
+// when it folds to a constant or to one of its operands
+pub fn build_folded(function: &mut Function, block: Block, kind: InstKind) -> Operand {
+    if let Some(constant) = fold_inst(&kind) {
+        return Operand::Const(constant);
+    }
+
+    if let Some(operand) = identity(&kind) {
+        return operand;
+    }
+
+    let value = function.add_value(kind.result_ty(), None);
+    let terminator = function
+        .terminator(block)
+        .expect("the block has a terminator");
+
+    function.insert_inst(block, terminator, kind, Some(value));
+    Operand::Value(value)
+}
+
+// Replaces every instruction whose operands are all constants with its result
+pub fn run(function: &mut Function) -> bool {
+    let mut changed = false;
+    let mut folded = true;
+
+    while folded {
+        folded = false;
+
+        for block in function.layout.clone() {
+            for inst in function.blocks[block.0].insts.clone() {
+                let data = &function.insts[inst.0];
+
+                let (Some(result), Some(constant)) = (data.result, fold_inst(&data.kind)) else {
+                    continue;
+                };
+
+                function.replace_uses(result, Operand::Const(constant));
+                function.remove_inst(block, inst);
+                folded = true;
+            }
+        }
+
+        changed |= folded;
+    }
+
+    changed
+}
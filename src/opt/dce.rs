@@ -0,0 +1,82 @@
+use crate::analysis::AnalysisManager;
+use crate::ir::function::{Function, ValueDef};
+use crate::ir::inst::{InstKind, Operand};
+use std::collections::HashSet;
+
+// Deletes the blocks the entry cannot reach, phis forget the edges coming from them
+fn remove_unreachable(function: &mut Function, analyses: &mut AnalysisManager) -> bool {
+    let domtree = analyses.domtree(function);
+    let unreachable: HashSet<_> = function
+        .layout
+        .iter()
+        .copied()
+        .filter(|block| !domtree.is_reachable(*block))
+        .collect();
+
+    if unreachable.is_empty() {
+        return false;
+    }
+
+    for block in &unreachable {
+        function.remove_block(*block);
+    }
+
+    for block in function.layout.clone() {
+        for inst in &function.blocks[block.0].insts {
+            if let InstKind::Phi { incoming, .. } = &mut function.insts[inst.0].kind {
+                incoming.retain(|(_, pred)| !unreachable.contains(pred));
+            }
+        }
+    }
+
+    true
+}
+
+// Everything a side effect does not depend on is dead, so values only feeding each
+// other, like the phis of an unused induction variable, are removed too
+fn remove_dead_insts(function: &mut Function) -> bool {
+    let mut live = HashSet::new();
+    let mut work = Vec::new();
+
+    for block in &function.layout {
+        for inst in &function.blocks[block.0].insts {
+            if function.insts[inst.0].kind.has_side_effects() {
+                live.insert(*inst);
+                work.push(*inst);
+            }
+        }
+    }
+
+    while let Some(inst) = work.pop() {
+        for operand in function.insts[inst.0].kind.operands() {
+            let Operand::Value(value) = operand else {
+                continue;
+            };
+
+            if let ValueDef::Inst(def) = function.values[value.0].def {
+                if live.insert(def) {
+                    work.push(def);
+                }
+            }
+        }
+    }
+
+    let mut changed = false;
+
+    for block in function.layout.clone() {
+        for inst in function.blocks[block.0].insts.clone() {
+            if !live.contains(&inst) {
+                function.remove_inst(block, inst);
+                changed = true;
+            }
+        }
+    }
+
+    changed
+}
+
+pub fn run(function: &mut Function, analyses: &mut AnalysisManager) -> bool {
+    let removed_blocks = remove_unreachable(function, analyses);
+
+    remove_dead_insts(function) || removed_blocks
+}
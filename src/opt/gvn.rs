@@ -0,0 +1,108 @@
+use crate::analysis::alias::may_alias;
+use crate::analysis::AnalysisManager;
+use crate::ir::function::{Block, Function, Value};
+use crate::ir::inst::{InstKind, IntCC, Operand};
+use crate::ir::types::Ty;
+use std::collections::HashMap;
+
+// Puts the operands of commutative operations in a fixed order, so `a + b` and `b + a`
+// are the same expression
+fn canonical(kind: &InstKind) -> InstKind {
+    let mut kind = kind.clone();
+
+    let swap = match &kind {
+        InstKind::Binary { op, .. } => op.is_commutative(),
+        InstKind::Icmp { cond, .. } => matches!(cond, IntCC::Eq | IntCC::Ne),
+        _ => false,
+    };
+
+    if let InstKind::Binary { lhs, rhs, .. } | InstKind::Icmp { lhs, rhs, .. } = &mut kind {
+        // Constants go to the right and values by id
+        let ordered = match (&*lhs, &*rhs) {
+            (Operand::Value(a), Operand::Value(b)) => a <= b,
+            (Operand::Const(_), Operand::Value(_)) => false,
+            _ => true,
+        };
+
+        if swap && !ordered {
+            std::mem::swap(lhs, rhs);
+        }
+    }
+
+    kind
+}
+
+// What memory is known to hold, by pointer and type
+type Memory = HashMap<(Operand, Ty), Operand>;
+
+// Walks the dominator tree replacing each pure instruction computed by a dominating one
+// with its result. Loads reuse an earlier load or store of the same pointer when nothing
+// in between may have written to it, which is only tracked along straight-line paths
+pub fn run(function: &mut Function, analyses: &mut AnalysisManager) -> bool {
+    let domtree = analyses.domtree(function);
+    let cfg = analyses.cfg(function);
+
+    let mut expressions: HashMap<InstKind, Vec<(Block, Value)>> = HashMap::new();
+    let mut memories: HashMap<Block, Memory> = HashMap::new();
+    let mut changed = false;
+
+    for block in domtree.preorder() {
+        // Memory flows in from the dominator only when it is the only way in
+        let mut memory = match domtree.idom(block) {
+            Some(idom) if cfg.preds(block) == [idom] => memories[&idom].clone(),
+            _ => Memory::new(),
+        };
+
+        for inst in function.blocks[block.0].insts.clone() {
+            let data = &function.insts[inst.0];
+            let kind = canonical(&data.kind);
+
+            let replacement = match (&kind, data.result) {
+                (kind, Some(result)) if kind.is_pure() => {
+                    let candidates = expressions.entry(kind.clone()).or_default();
+                    let found = candidates
+                        .iter()
+                        .find(|(def, _)| domtree.dominates(*def, block))
+                        .map(|(_, value)| *value);
+
+                    if found.is_none() {
+                        candidates.push((block, result));
+                    }
+
+                    found.map(|value| (result, Operand::Value(value)))
+                }
+                (InstKind::Load { ty, ptr }, Some(result)) => {
+                    let key = (ptr.clone(), *ty);
+
+                    match memory.get(&key) {
+                        Some(known) => Some((result, known.clone())),
+                        None => {
+                            memory.insert(key, Operand::Value(result));
+                            None
+                        }
+                    }
+                }
+                (InstKind::Store { ty, value, ptr }, _) => {
+                    memory.retain(|(known, _), _| !may_alias(function, known, ptr));
+                    memory.insert((ptr.clone(), *ty), value.clone());
+                    None
+                }
+                (InstKind::Call { .. } | InstKind::Syscall { .. }, _) => {
+                    memory.clear();
+                    None
+                }
+                _ => None,
+            };
+
+            if let Some((result, with)) = replacement {
+                function.replace_uses(result, with);
+                function.remove_inst(block, inst);
+                changed = true;
+            }
+        }
+
+        memories.insert(block, memory);
+    }
+
+    changed
+}
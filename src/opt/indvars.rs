@@ -0,0 +1,202 @@
+use crate::analysis::induction::{inductions, loop_values};
+use crate::analysis::AnalysisManager;
+use crate::ir::function::{Block, Function, Value};
+use crate::ir::inst::{BinaryOp, Flags, InstKind, Operand};
+use crate::ir::types::Ty;
+use crate::opt::constfold::{build_folded, fold_binary};
+use crate::parser::types::Type;
+use std::collections::{HashMap, HashSet};
+
+// `start + k * step` in iteration k of the loop
+#[derive(Debug, Clone)]
+struct Affine {
+    start: Operand,
+    step: Type,
+}
+
+fn binary(op: BinaryOp, ty: Ty, lhs: Operand, rhs: Operand) -> InstKind {
+    InstKind::Binary {
+        op,
+        flags: Flags::default(),
+        ty,
+        lhs,
+        rhs,
+    }
+}
+
+struct Reducer {
+    header: Block,
+    preheader: Block,
+    latch: Block,
+    // Values computed inside the loop, including the variables added to it
+    variant: HashSet<Value>,
+    // The phis and increments added, they are left as they are
+    added: HashSet<Value>,
+    // Header phis growing by a constant
+    basic: HashMap<Value, Affine>,
+    // Variables added by this pass, only these take in invariant additions, a basic
+    // variable plus something is usually cheaper as it is
+    derived: HashMap<Value, Affine>,
+}
+
+impl Reducer {
+    fn is_invariant(&self, operand: &Operand) -> bool {
+        match operand {
+            Operand::Value(value) => !self.variant.contains(value),
+            Operand::Const(_) => true,
+        }
+    }
+
+    // What the instruction computes as a variable of the loop, its start is built in the
+    // preheader. `i * stride`, `i << k` and `base + i * stride` are reduced
+    fn reduce(&self, function: &mut Function, kind: &InstKind) -> Option<Affine> {
+        let InstKind::Binary {
+            op, ty, lhs, rhs, ..
+        } = kind
+        else {
+            return None;
+        };
+
+        let find = |set: &HashMap<Value, Affine>, operand: &Operand| match operand {
+            Operand::Value(value) => set.get(value).cloned(),
+            Operand::Const(_) => None,
+        };
+
+        let (iv, other) = match op {
+            BinaryOp::Mul => match (lhs, rhs) {
+                (iv, Operand::Const(_)) => (find(&self.basic, iv)?, rhs),
+                (Operand::Const(_), iv) => (find(&self.basic, iv)?, lhs),
+                _ => return None,
+            },
+            // Shifting by the width or more is not defined, it does not fold below
+            BinaryOp::Shl => match rhs {
+                Operand::Const(_) => (find(&self.basic, lhs)?, rhs),
+                _ => return None,
+            },
+            BinaryOp::Add | BinaryOp::Sub => {
+                match (find(&self.derived, lhs), find(&self.derived, rhs)) {
+                    (Some(iv), _) if self.is_invariant(rhs) => (iv, rhs),
+                    (_, Some(iv)) if *op == BinaryOp::Add && self.is_invariant(lhs) => (iv, lhs),
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        };
+
+        let step = match (op, other) {
+            (BinaryOp::Mul | BinaryOp::Shl, Operand::Const(amount)) => {
+                fold_binary(*op, Flags::default(), &iv.step, amount)?
+            }
+            _ => iv.step,
+        };
+
+        let start = binary(*op, *ty, iv.start, other.clone());
+        let start = build_folded(function, self.preheader, start);
+
+        Some(Affine { start, step })
+    }
+
+    // A header phi taking the start from the preheader and adding the step in the latch
+    fn add_variable(&mut self, function: &mut Function, replaced: Value, affine: &Affine) -> Value {
+        let ty = function.values[replaced.0].ty;
+        let name = function.values[replaced.0]
+            .name
+            .clone()
+            .map(|name| function.unique_value_name(&format!("{}.iv", name)));
+
+        let phi = function.add_value(ty, name);
+        let next = function.add_value(ty, None);
+
+        let first = function.blocks[self.header.0].insts[0];
+        let kind = InstKind::Phi {
+            ty,
+            incoming: vec![
+                (affine.start.clone(), self.preheader),
+                (Operand::Value(next), self.latch),
+            ],
+        };
+        function.insert_inst(self.header, first, kind, Some(phi));
+
+        let terminator = function.terminator(self.latch).unwrap();
+        let kind = binary(
+            BinaryOp::Add,
+            ty,
+            Operand::Value(phi),
+            Operand::Const(affine.step.clone()),
+        );
+        function.insert_inst(self.latch, terminator, kind, Some(next));
+
+        self.variant.extend([phi, next]);
+        self.added.extend([phi, next]);
+        phi
+    }
+}
+
+// Induction variable strength reduction: multiples of an induction variable, with an
+// invariant added, become variables of their own growing by an addition per iteration
+pub fn run(function: &mut Function, analyses: &mut AnalysisManager) -> bool {
+    let cfg = analyses.cfg(function);
+    let loops = analyses.loops(function);
+    let mut changed = false;
+
+    for lp in loops.loops() {
+        let data = loops.data(lp);
+
+        let (Some(preheader), [latch]) = (loops.preheader(lp, &cfg), &data.latches[..]) else {
+            continue;
+        };
+
+        let basic: HashMap<Value, Affine> = inductions(function, data, preheader)
+            .into_iter()
+            .map(|iv| {
+                let affine = Affine {
+                    start: iv.start,
+                    step: iv.step,
+                };
+
+                (iv.phi, affine)
+            })
+            .collect();
+
+        if basic.is_empty() {
+            continue;
+        }
+
+        let mut reducer = Reducer {
+            header: data.header,
+            preheader,
+            latch: *latch,
+            variant: loop_values(function, data),
+            added: HashSet::new(),
+            basic,
+            derived: HashMap::new(),
+        };
+
+        for block in &data.blocks {
+            for inst in function.blocks[block.0].insts.clone() {
+                let Some(result) = function.insts[inst.0].result else {
+                    continue;
+                };
+
+                if reducer.added.contains(&result) {
+                    continue;
+                }
+
+                let kind = function.insts[inst.0].kind.clone();
+
+                let Some(affine) = reducer.reduce(function, &kind) else {
+                    continue;
+                };
+
+                let phi = reducer.add_variable(function, result, &affine);
+
+                function.replace_uses(result, Operand::Value(phi));
+                function.remove_inst(*block, inst);
+                reducer.derived.insert(phi, affine);
+                changed = true;
+            }
+        }
+    }
+
+    changed
+}
@@ -0,0 +1,360 @@
+use super::OptLevel;
+use crate::binary::symbol::SymbolType;
+use crate::ir::function::{Block, Function, Inst};
+use crate::ir::inst::{InstKind, Operand};
+use crate::ir::Module;
+use std::collections::{HashMap, HashSet};
+
+// Largest cost inlined at each level, -O0 only inlines alwaysinline functions
+fn threshold(level: OptLevel) -> Option<usize> {
+    match level {
+        OptLevel::O0 => None,
+        OptLevel::O1 => Some(12),
+        OptLevel::O2 => Some(40),
+        OptLevel::Os => Some(4),
+    }
+}
+
+// Instructions the callee adds to the caller, minus the call sequence it replaces.
+// Calls weigh more since they also save and restore what is live around them
+fn cost(callee: &Function) -> usize {
+    let size: usize = callee
+        .layout
+        .iter()
+        .flat_map(|block| &callee.blocks[block.0].insts)
+        .map(|inst| match callee.insts[inst.0].kind {
+            InstKind::Call { .. } => 3,
+            InstKind::Ret { .. } => 0,
+            _ => 1,
+        })
+        .sum();
+
+    size.saturating_sub(callee.params.len() + 1)
+}
+
+fn call_sites(module: &Module) -> HashMap<String, usize> {
+    let mut calls = HashMap::new();
+
+    for function in &module.functions {
+        for block in &function.layout {
+            for inst in &function.blocks[block.0].insts {
+                if let InstKind::Call { callee, .. } = &function.insts[inst.0].kind {
+                    *calls.entry(callee.clone()).or_default() += 1;
+                }
+            }
+        }
+    }
+
+    calls
+}
+
+// Calls that may be inlined, musttail ones are left to become tail calls
+fn calls_of(function: &Function) -> Vec<(Inst, String)> {
+    let mut calls = Vec::new();
+
+    for block in &function.layout {
+        for inst in &function.blocks[block.0].insts {
+            if let InstKind::Call {
+                callee,
+                musttail: false,
+                ..
+            } = &function.insts[inst.0].kind
+            {
+                calls.push((*inst, callee.clone()));
+            }
+        }
+    }
+
+    calls
+}
+
+// Callees before their callers, so a function is inlined once its own calls were
+fn bottom_up(module: &Module) -> Vec<usize> {
+    let index: HashMap<&str, usize> = module
+        .functions
+        .iter()
+        .enumerate()
+        .map(|(i, function)| (function.name.as_str(), i))
+        .collect();
+
+    let callees: Vec<Vec<usize>> = module
+        .functions
+        .iter()
+        .map(|function| {
+            calls_of(function)
+                .iter()
+                .filter_map(|(_, callee)| index.get(callee.as_str()).copied())
+                .collect()
+        })
+        .collect();
+
+    let mut visited = vec![false; module.functions.len()];
+    let mut order = Vec::new();
+
+    for root in 0..module.functions.len() {
+        if visited[root] {
+            continue;
+        }
+
+        visited[root] = true;
+        let mut stack = vec![(root, 0)];
+
+        while let Some((function, next)) = stack.pop() {
+            match callees[function].get(next) {
+                Some(callee) => {
+                    stack.push((function, next + 1));
+
+                    if !visited[*callee] {
+                        visited[*callee] = true;
+                        stack.push((*callee, 0));
+                    }
+                }
+                None => order.push(function),
+            }
+        }
+    }
+
+    order
+}
+
+fn should_inline(
+    caller: &Function,
+    callee: &Function,
+    calls: &HashMap<String, usize>,
+    level: OptLevel,
+) -> bool {
+    let returns = callee.layout.iter().any(|block| {
+        callee
+            .terminator(*block)
+            .is_some_and(|inst| matches!(callee.insts[inst.0].kind, InstKind::Ret { .. }))
+    });
+
+    // Its musttail calls would no longer be followed by a return
+    let musttail = callee.layout.iter().any(|block| {
+        callee.blocks[block.0].insts.iter().any(|inst| {
+            matches!(
+                callee.insts[inst.0].kind,
+                InstKind::Call { musttail: true, .. }
+            )
+        })
+    });
+
+    if callee.name == caller.name || callee.attributes.noinline || !returns || musttail {
+        return false;
+    }
+
+    if callee.attributes.alwaysinline {
+        return true;
+    }
+
+    let Some(threshold) = threshold(level) else {
+        return false;
+    };
+
+    // The only call of an internal function, the function is deleted afterwards
+    let single = callee.linkage == SymbolType::Private && calls.get(&callee.name) == Some(&1);
+
+    single || cost(callee) <= threshold
+}
+
+// Names already taken in the caller, new ones get a numeric suffix when they collide
+struct Names {
+    taken: HashSet<String>,
+}
+
+impl Names {
+    fn new(function: &Function) -> Self {
+        let values = function
+            .values
+            .iter()
+            .filter_map(|value| value.name.clone());
+        let blocks = function.blocks.iter().map(|block| block.name.clone());
+
+        Self {
+            taken: values.chain(blocks).collect(),
+        }
+    }
+
+    fn unique(&mut self, name: String) -> String {
+        let mut unique = name.clone();
+        let mut suffix = 1;
+
+        while self.taken.contains(&unique) {
+            unique = format!("{}.{}", name, suffix);
+            suffix += 1;
+        }
+
+        self.taken.insert(unique.clone());
+        unique
+    }
+}
+
+// Replaces the call with a copy of the callee. The block is split after the call, the
+// copy is placed in between and its returns jump to the second half
+fn inline_call(caller: &mut Function, call: Inst, callee: &Function) {
+    let Some(block) = caller
+        .layout
+        .iter()
+        .copied()
+        .find(|block| caller.blocks[block.0].insts.contains(&call))
+    else {
+        return;
+    };
+
+    let InstKind::Call { args, .. } = caller.insts[call.0].kind.clone() else {
+        return;
+    };
+
+    let result = caller.insts[call.0].result;
+    let mut names = Names::new(caller);
+
+    let insts = &mut caller.blocks[block.0].insts;
+    let position = insts.iter().position(|inst| *inst == call).unwrap();
+    let tail = insts.split_off(position + 1);
+    insts.pop();
+
+    let name = names.unique(format!("{}.cont", caller.blocks[block.0].name));
+    let cont = caller.create_block(name);
+    caller.blocks[cont.0].insts = tail;
+
+    // The edges leaving the block now leave from its second half
+    for succ in caller.successors(cont) {
+        for inst in &caller.blocks[succ.0].insts {
+            if let InstKind::Phi { incoming, .. } = &mut caller.insts[inst.0].kind {
+                for (_, pred) in incoming.iter_mut() {
+                    if *pred == block {
+                        *pred = cont;
+                    }
+                }
+            }
+        }
+    }
+
+    let mut values: HashMap<_, Operand> = HashMap::new();
+    let mut blocks: HashMap<Block, Block> = HashMap::new();
+
+    for (param, (_, arg)) in callee.params.iter().zip(args) {
+        values.insert(*param, arg);
+    }
+
+    for old in &callee.layout {
+        let name = names.unique(format!("{}.{}", callee.name, callee.blocks[old.0].name));
+        blocks.insert(*old, caller.create_block(name));
+
+        for inst in &callee.blocks[old.0].insts {
+            if let Some(value) = callee.insts[inst.0].result {
+                let data = &callee.values[value.0];
+                let name = data
+                    .name
+                    .as_ref()
+                    .map(|name| names.unique(format!("{}.{}", callee.name, name)));
+
+                values.insert(value, Operand::Value(caller.add_value(data.ty, name)));
+            }
+        }
+    }
+
+    let mut returns = Vec::new();
+
+    for old in &callee.layout {
+        let new = blocks[old];
+
+        for inst in &callee.blocks[old.0].insts {
+            let data = &callee.insts[inst.0];
+            let mut kind = data.kind.clone();
+
+            for operand in kind.operands_mut() {
+                if let Operand::Value(value) = operand {
+                    *operand = values[value].clone();
+                }
+            }
+
+            for target in kind.successors_mut() {
+                *target = blocks[target];
+            }
+
+            if let InstKind::Phi { incoming, .. } = &mut kind {
+                for (_, pred) in incoming.iter_mut() {
+                    *pred = blocks[pred];
+                }
+            }
+
+            if let InstKind::Ret { value, .. } = kind {
+                returns.push((value, new));
+                kind = InstKind::Jump { target: cont };
+            }
+
+            let result = data.result.map(|value| match values[&value] {
+                Operand::Value(value) => value,
+                Operand::Const(_) => unreachable!(),
+            });
+
+            caller.append_inst(new, kind, result);
+        }
+    }
+
+    let position = caller.layout.iter().position(|b| *b == block).unwrap();
+    let spliced = callee.layout.iter().map(|old| blocks[old]).chain([cont]);
+    caller.layout.splice(position + 1..position + 1, spliced);
+
+    let entry = blocks[&callee.entry().unwrap()];
+    caller.append_inst(block, InstKind::Jump { target: entry }, None);
+
+    let Some(result) = result else {
+        return;
+    };
+
+    let incoming: Vec<(Operand, Block)> = returns
+        .into_iter()
+        .filter_map(|(value, block)| Some((value?, block)))
+        .collect();
+
+    let value = match incoming.as_slice() {
+        [(value, _)] => value.clone(),
+        _ => {
+            let ty = caller.values[result.0].ty;
+            let phi = caller.add_value(ty, None);
+            let first = caller.blocks[cont.0].insts[0];
+            let kind = InstKind::Phi { ty, incoming };
+
+            caller.insert_inst(cont, first, kind, Some(phi));
+            Operand::Value(phi)
+        }
+    };
+
+    caller.replace_uses(result, value);
+}
+
+// Inlines the calls the cost model accepts and deletes the internal functions left
+// without callers
+pub fn run(module: &mut Module, level: OptLevel) -> bool {
+    let mut inlined = HashSet::new();
+
+    for caller in bottom_up(module) {
+        for (call, callee) in calls_of(&module.functions[caller]) {
+            let Some(callee) = module.get_function(&callee) else {
+                continue;
+            };
+
+            let calls = call_sites(module);
+
+            if !should_inline(&module.functions[caller], callee, &calls, level) {
+                continue;
+            }
+
+            let callee = callee.clone();
+            inline_call(&mut module.functions[caller], call, &callee);
+            inlined.insert(callee.name);
+        }
+    }
+
+    let calls = call_sites(module);
+
+    module.functions.retain(|function| {
+        function.linkage != SymbolType::Private
+            || !inlined.contains(&function.name)
+            || calls.contains_key(&function.name)
+    });
+
+    !inlined.is_empty()
+}
@@ -0,0 +1,285 @@
+use super::OptLevel;
+use crate::ir::function::{Block, Function, Inst, ValueDef};
+use crate::ir::inst::{BinaryOp, Flags, InstKind, Operand};
+use crate::ir::types::Ty;
+use crate::ir::Module;
+use crate::opt::constfold::{fold_binary, fold_inst, identity};
+use crate::parser::types::{mask, sign_extend, Type};
+
+fn binary(op: BinaryOp, ty: Ty, lhs: Operand, rhs: Operand) -> InstKind {
+    InstKind::Binary {
+        op,
+        flags: Flags::default(),
+        ty,
+        lhs,
+        rhs,
+    }
+}
+
+// Exponent of a constant that is a power of two, read unsigned
+fn log2(constant: &Type) -> Option<u32> {
+    let value = constant.as_u128()?;
+
+    value.is_power_of_two().then(|| value.trailing_zeros())
+}
+
+// Multiplier and shift that divide by `d` through the high half of a product, from
+// Hacker's Delight 10-1. Needs 2 <= |d| < 2^(bits - 1)
+fn magic(d: i128, bits: u32) -> (i128, u32) {
+    let two = 1u128 << (bits - 1);
+    let ad = d.unsigned_abs();
+    let t = two + u128::from(d < 0);
+    let anc = t - 1 - t % ad;
+
+    let mut p = bits - 1;
+    let (mut q1, mut r1) = (two / anc, two % anc);
+    let (mut q2, mut r2) = (two / ad, two % ad);
+
+    loop {
+        p += 1;
+
+        q1 = (q1 * 2) & mask(bits);
+        r1 *= 2;
+        if r1 >= anc {
+            q1 += 1;
+            r1 -= anc;
+        }
+
+        q2 = (q2 * 2) & mask(bits);
+        r2 *= 2;
+        if r2 >= ad {
+            q2 += 1;
+            r2 -= ad;
+        }
+
+        let delta = ad - r2;
+
+        if q1 > delta || (q1 == delta && r1 != 0) {
+            break;
+        }
+    }
+
+    let multiplier = sign_extend((q2 + 1) & mask(bits), bits);
+    let multiplier = if d < 0 {
+        sign_extend(multiplier.wrapping_neg() as u128 & mask(bits), bits)
+    } else {
+        multiplier
+    };
+
+    (multiplier, p - bits)
+}
+
+// The same operation in a cheaper or more canonical form
+fn rewrite(function: &Function, kind: &InstKind) -> Option<InstKind> {
+    let InstKind::Binary {
+        op,
+        flags,
+        ty,
+        lhs,
+        rhs,
+    } = kind
+    else {
+        return None;
+    };
+
+    let Ty::Int { bits, signed } = *ty else {
+        return None;
+    };
+
+    let constant = |value: u128| Operand::Const(Type::int(bits, signed, value));
+
+    match (op, lhs, rhs) {
+        // Constants go to the right, where the rules below look for them
+        (op, Operand::Const(_), Operand::Value(_)) if op.is_commutative() => {
+            Some(InstKind::Binary {
+                op: *op,
+                flags: *flags,
+                ty: *ty,
+                lhs: rhs.clone(),
+                rhs: lhs.clone(),
+            })
+        }
+        // `x - C` is `x + -C`, so it merges with other additions
+        (BinaryOp::Sub, x, Operand::Const(c)) => {
+            let negated = c.as_u128()?.wrapping_neg();
+            Some(binary(BinaryOp::Add, *ty, x.clone(), constant(negated)))
+        }
+        // `(x + C1) + C2` is `x + (C1 + C2)`
+        (BinaryOp::Add, Operand::Value(x), Operand::Const(c2)) => {
+            let ValueDef::Inst(def) = function.values[x.0].def else {
+                return None;
+            };
+
+            let InstKind::Binary {
+                op: BinaryOp::Add,
+                ty: inner_ty,
+                lhs: inner,
+                rhs: Operand::Const(c1),
+                ..
+            } = &function.insts[def.0].kind
+            else {
+                return None;
+            };
+
+            if inner_ty != ty {
+                return None;
+            }
+
+            let sum = fold_binary(BinaryOp::Add, Flags::default(), c1, c2)?;
+            Some(binary(
+                BinaryOp::Add,
+                *ty,
+                inner.clone(),
+                Operand::Const(sum),
+            ))
+        }
+        (BinaryOp::Mul, x, Operand::Const(c)) => {
+            let k = log2(c)?;
+            Some(binary(BinaryOp::Shl, *ty, x.clone(), constant(k.into())))
+        }
+        (BinaryOp::Udiv, x, Operand::Const(c)) => {
+            let k = log2(c)?;
+            Some(binary(BinaryOp::Lshr, *ty, x.clone(), constant(k.into())))
+        }
+        (BinaryOp::Urem, x, Operand::Const(c)) => {
+            log2(c)?;
+            let low = c.as_u128()? - 1;
+            Some(binary(BinaryOp::And, *ty, x.clone(), constant(low)))
+        }
+        _ => None,
+    }
+}
+
+// Signed division by a constant without a division instruction, the instructions are
+// added before the division and the quotient returned
+fn divide(function: &mut Function, block: Block, inst: Inst, kind: &InstKind) -> Option<Operand> {
+    let InstKind::Binary {
+        op: BinaryOp::Sdiv,
+        ty,
+        lhs: x,
+        rhs: Operand::Const(c),
+        ..
+    } = kind
+    else {
+        return None;
+    };
+
+    // The backend only multiplies to the high half at these widths
+    let Ty::Int {
+        bits: bits @ (32 | 64),
+        signed,
+    } = *ty
+    else {
+        return None;
+    };
+
+    let d = c.as_i128()?;
+    let magnitude = d.unsigned_abs();
+
+    // Dividing by the minimum is a comparison, and 0 and 1 are left alone
+    if !(2..1 << (bits - 1)).contains(&magnitude) && d != -1 {
+        return None;
+    }
+
+    let ty = *ty;
+    let constant = |value: i128| Operand::Const(Type::int(bits, signed, value as u128));
+    let mut emit = |op: BinaryOp, lhs: Operand, rhs: Operand| {
+        let value = function.add_value(ty, None);
+        function.insert_inst(block, inst, binary(op, ty, lhs, rhs), Some(value));
+        Operand::Value(value)
+    };
+
+    if d == -1 {
+        return Some(emit(BinaryOp::Sub, constant(0), x.clone()));
+    }
+
+    let shift = |amount: u32| constant(amount.into());
+
+    if !magnitude.is_power_of_two() {
+        let (multiplier, s) = magic(d, bits);
+
+        let mut q = emit(BinaryOp::Smulh, x.clone(), constant(multiplier));
+
+        if d > 0 && multiplier < 0 {
+            q = emit(BinaryOp::Add, q, x.clone());
+        } else if d < 0 && multiplier > 0 {
+            q = emit(BinaryOp::Sub, q, x.clone());
+        }
+
+        if s > 0 {
+            q = emit(BinaryOp::Ashr, q, shift(s));
+        }
+
+        // Adds one to negative quotients, which were rounded down
+        let sign = emit(BinaryOp::Lshr, q.clone(), shift(bits - 1));
+        return Some(emit(BinaryOp::Add, q, sign));
+    }
+
+    // Negative values are biased by 2^k - 1 so the shift rounds towards zero
+    let k = magnitude.trailing_zeros();
+    let sign = emit(BinaryOp::Ashr, x.clone(), shift(bits - 1));
+    let bias = emit(BinaryOp::Lshr, sign, shift(bits - k));
+    let biased = emit(BinaryOp::Add, x.clone(), bias);
+    let quotient = emit(BinaryOp::Ashr, biased, shift(k));
+
+    if d < 0 {
+        return Some(emit(BinaryOp::Sub, constant(0), quotient));
+    }
+
+    Some(quotient)
+}
+
+// Replaces arithmetic with cheaper equivalents: multiplications and unsigned divisions by
+// powers of two become shifts, neutral operations go away and additions of constants are
+// merged. Signed divisions by constants become multiplications, except at -Os
+fn combine(function: &mut Function, level: OptLevel) -> bool {
+    let mut changed = false;
+
+    for block in function.layout.clone() {
+        for inst in function.blocks[block.0].insts.clone() {
+            let Some(result) = function.insts[inst.0].result else {
+                continue;
+            };
+
+            loop {
+                let kind = function.insts[inst.0].kind.clone();
+
+                let replacement = match fold_inst(&kind) {
+                    Some(constant) => Some(Operand::Const(constant)),
+                    None => identity(&kind),
+                };
+
+                let replacement = match replacement {
+                    None if level != OptLevel::Os => divide(function, block, inst, &kind),
+                    replacement => replacement,
+                };
+
+                if let Some(replacement) = replacement {
+                    function.replace_uses(result, replacement);
+                    function.remove_inst(block, inst);
+                    changed = true;
+                    break;
+                }
+
+                let Some(kind) = rewrite(function, &kind) else {
+                    break;
+                };
+
+                function.insts[inst.0].kind = kind;
+                changed = true;
+            }
+        }
+    }
+
+    changed
+}
+
+pub fn run(module: &mut Module, level: OptLevel) -> bool {
+    let mut changed = false;
+
+    for function in &mut module.functions {
+        changed |= combine(function, level);
+    }
+
+    changed
+}
@@ -0,0 +1,197 @@
+use crate::analysis::dominators::{DomTree, DominanceFrontiers};
+use crate::analysis::loops::LoopData;
+use crate::analysis::AnalysisManager;
+use crate::ir::cfg::ControlFlowGraph;
+use crate::ir::function::{Block, Function, Inst, Value};
+use crate::ir::inst::{InstKind, Operand};
+use crate::ir::uses::UseLists;
+use crate::opt::mem2reg::remove_dead_phis;
+use std::collections::{HashMap, HashSet};
+
+struct Lcssa<'a> {
+    cfg: &'a ControlFlowGraph,
+    domtree: &'a DomTree,
+    frontiers: &'a DominanceFrontiers,
+    block_of: HashMap<Inst, Block>,
+    // Phis created so far, the unused ones are removed at the end
+    created: Vec<(Block, Inst)>,
+}
+
+impl Lcssa<'_> {
+    // Uses from outside the loop, phis read the value at the end of the incoming block
+    // so a phi after an exit already reads it inside the loop
+    fn outside_uses(
+        &self,
+        function: &Function,
+        uses: &UseLists,
+        data: &LoopData,
+        value: Value,
+    ) -> Vec<Inst> {
+        uses.uses(value)
+            .iter()
+            .copied()
+            .filter(|user| match &function.insts[user.0].kind {
+                InstKind::Phi { incoming, .. } => incoming
+                    .iter()
+                    .any(|(op, pred)| *op == Operand::Value(value) && !data.contains(*pred)),
+                _ => !data.contains(self.block_of[user]),
+            })
+            .collect()
+    }
+
+    // The value reaching the end of the block, from the closest dominator defining it
+    fn reaching(&self, phis: &HashMap<Block, Value>, def: (Block, Value), block: Block) -> Operand {
+        let mut current = block;
+
+        loop {
+            if let Some(phi) = phis.get(&current) {
+                return Operand::Value(*phi);
+            }
+
+            if current == def.0 {
+                return Operand::Value(def.1);
+            }
+
+            // Only unreachable blocks have no dominator, anything can be used there
+            match self.domtree.idom(current) {
+                Some(idom) => current = idom,
+                None => return Operand::Value(def.1),
+            }
+        }
+    }
+
+    // Routes the value through phis in the exits of the loop, with more phis where the
+    // exits join again, and makes the uses outside read them
+    fn rewrite(
+        &mut self,
+        function: &mut Function,
+        data: &LoopData,
+        exits: &[Block],
+        def: (Block, Value),
+        users: Vec<Inst>,
+    ) {
+        let (def_block, value) = def;
+        let ty = function.values[value.0].ty;
+
+        let mut work: Vec<Block> = exits
+            .iter()
+            .copied()
+            .filter(|exit| self.domtree.dominates(def_block, *exit))
+            .collect();
+        let mut placed: HashSet<Block> = work.iter().copied().collect();
+        let mut phis = HashMap::new();
+        let mut created = Vec::new();
+
+        while let Some(block) = work.pop() {
+            let first = function.blocks[block.0].insts[0];
+            let result = function.add_value(ty, None);
+            let kind = InstKind::Phi {
+                ty,
+                incoming: Vec::new(),
+            };
+
+            created.push((
+                block,
+                function.insert_inst(block, first, kind, Some(result)),
+            ));
+            phis.insert(block, result);
+
+            for frontier in self.frontiers.frontier(block) {
+                // The value does not exist where its definition does not reach
+                if !data.contains(*frontier)
+                    && self.domtree.dominates(def_block, *frontier)
+                    && placed.insert(*frontier)
+                {
+                    work.push(*frontier);
+                }
+            }
+        }
+
+        for (block, phi) in &created {
+            let incoming = self
+                .cfg
+                .preds(*block)
+                .iter()
+                .map(|pred| (self.reaching(&phis, def, *pred), *pred))
+                .collect();
+
+            if let InstKind::Phi { incoming: phi, .. } = &mut function.insts[phi.0].kind {
+                *phi = incoming;
+            }
+        }
+
+        for user in users {
+            let reaching = self.reaching(&phis, def, self.block_of[&user]);
+
+            match &mut function.insts[user.0].kind {
+                InstKind::Phi { incoming, .. } => {
+                    for (operand, pred) in incoming.iter_mut() {
+                        if *operand == Operand::Value(value) && !data.contains(*pred) {
+                            *operand = self.reaching(&phis, def, *pred);
+                        }
+                    }
+                }
+                kind => {
+                    for operand in kind.operands_mut() {
+                        if *operand == Operand::Value(value) {
+                            *operand = reaching.clone();
+                        }
+                    }
+                }
+            }
+        }
+
+        self.created.extend(created);
+    }
+}
+
+// Loop closed SSA: values defined in a loop are only used outside of it through phis in
+// its exit blocks, so passes changing a loop only have to look at its exits
+pub fn run(function: &mut Function, analyses: &mut AnalysisManager) -> bool {
+    let cfg = analyses.cfg(function);
+    let domtree = analyses.domtree(function);
+    let frontiers = analyses.frontiers(function);
+    let loops = analyses.loops(function);
+
+    let mut lcssa = Lcssa {
+        cfg: &cfg,
+        domtree: &domtree,
+        frontiers: &frontiers,
+        block_of: HashMap::new(),
+        created: Vec::new(),
+    };
+
+    for lp in loops.loops() {
+        let data = loops.data(lp);
+        let exits = loops.exits(lp, &cfg);
+        let uses = UseLists::new(function);
+
+        lcssa.block_of.clear();
+
+        for block in &function.layout {
+            for inst in &function.blocks[block.0].insts {
+                lcssa.block_of.insert(*inst, *block);
+            }
+        }
+
+        for block in &data.blocks {
+            for inst in function.blocks[block.0].insts.clone() {
+                let Some(value) = function.insts[inst.0].result else {
+                    continue;
+                };
+
+                let users = lcssa.outside_uses(function, &uses, data, value);
+
+                if !users.is_empty() {
+                    lcssa.rewrite(function, data, &exits, (*block, value), users);
+                }
+            }
+        }
+    }
+
+    let created = lcssa.created;
+    let changed = !created.is_empty();
+    remove_dead_phis(function, created);
+
+    changed
+}
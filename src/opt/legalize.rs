@@ -0,0 +1,337 @@
+use crate::ir::function::{Block, Function, Inst};
+use crate::ir::inst::{BinaryOp, CastOp, Flags, InstKind, IntCC, Operand};
+use crate::ir::types::Ty;
+use crate::opt::constfold::fold_cast;
+use crate::parser::types::Type;
+
+// Widths the backend handles directly, 32 bits use the *W instructions and 128 bits a register pair
+fn legal_bits(bits: u32) -> u32 {
+    match bits {
+        0..=32 => 32,
+        33..=64 => 64,
+        _ => 128,
+    }
+}
+
+fn needs_promotion(ty: Ty) -> Option<Ty> {
+    let Ty::Int { bits, signed } = ty else {
+        return None;
+    };
+
+    let legal = legal_bits(bits);
+
+    (legal != bits).then_some(Ty::Int {
+        bits: legal,
+        signed,
+    })
+}
+
+// Extends an operand before the instruction, constants are extended in place
+fn extend(
+    function: &mut Function,
+    block: Block,
+    before: Inst,
+    value: Operand,
+    from: Ty,
+    to: Ty,
+    op: CastOp,
+) -> Operand {
+    match value {
+        Operand::Const(constant) => {
+            Operand::Const(fold_cast(op, &constant, to).expect("integer extension always folds"))
+        }
+        Operand::Value(_) => {
+            let result = function.add_value(to, None);
+            let kind = InstKind::Cast {
+                op,
+                from,
+                value,
+                to,
+            };
+
+            function.insert_inst(block, before, kind, Some(result));
+
+            Operand::Value(result)
+        }
+    }
+}
+
+// Adds the instruction before `before` and gives its result
+fn insert(function: &mut Function, block: Block, before: Inst, kind: InstKind) -> Operand {
+    let result = function.add_value(kind.result_ty(), None);
+    function.insert_inst(block, before, kind, Some(result));
+
+    Operand::Value(result)
+}
+
+fn constant(ty: Ty, value: u128) -> Operand {
+    Operand::Const(Type::int(ty.bits(), ty.is_signed(), value))
+}
+
+fn binary(op: BinaryOp, ty: Ty, lhs: Operand, rhs: Operand) -> InstKind {
+    InstKind::Binary {
+        op,
+        flags: Flags::default(),
+        ty,
+        lhs,
+        rhs,
+    }
+}
+
+// The offsets and types of the accesses an integer of a size the backend can not load
+// or store at once is split into, largest first so each one stays aligned
+fn memory_pieces(ty: Ty) -> Option<Vec<(u32, Ty)>> {
+    let Ty::Int { bits, .. } = ty else {
+        return None;
+    };
+
+    let bytes = bits.div_ceil(8);
+
+    if bytes.is_power_of_two() {
+        return None;
+    }
+
+    let mut pieces = Vec::new();
+    let mut offset = 0;
+
+    while offset < bytes {
+        let size = 1 << (bytes - offset).min(8).ilog2();
+        let piece = Ty::Int {
+            bits: size * 8,
+            signed: false,
+        };
+
+        pieces.push((offset, piece));
+        offset += size;
+    }
+
+    Some(pieces)
+}
+
+fn address(
+    function: &mut Function,
+    block: Block,
+    before: Inst,
+    ptr: &Operand,
+    offset: u32,
+) -> Operand {
+    if offset == 0 {
+        return ptr.clone();
+    }
+
+    let offset = constant(Ty::I64, offset.into());
+    insert(
+        function,
+        block,
+        before,
+        InstKind::PtrAdd {
+            ptr: ptr.clone(),
+            offset,
+        },
+    )
+}
+
+// Loads every piece, zero extended to the legal width, and puts them together
+fn split_load(
+    function: &mut Function,
+    block: Block,
+    inst: Inst,
+    ty: Ty,
+    ptr: Operand,
+    pieces: Vec<(u32, Ty)>,
+) {
+    let wide = Ty::Int {
+        bits: legal_bits(ty.bits()),
+        signed: false,
+    };
+    let mut value = None;
+
+    for (offset, piece) in pieces {
+        let ptr = address(function, block, inst, &ptr, offset);
+        let part = insert(function, block, inst, InstKind::Load { ty: piece, ptr });
+        let mut part = extend(function, block, inst, part, piece, wide, CastOp::Zext);
+
+        if offset > 0 {
+            let amount = constant(wide, (offset * 8).into());
+            part = insert(
+                function,
+                block,
+                inst,
+                binary(BinaryOp::Shl, wide, part, amount),
+            );
+        }
+
+        value = Some(match value {
+            Some(value) => insert(
+                function,
+                block,
+                inst,
+                binary(BinaryOp::Or, wide, value, part),
+            ),
+            None => part,
+        });
+    }
+
+    function.insts[inst.0].kind = InstKind::Cast {
+        op: CastOp::Trunc,
+        from: wide,
+        value: value.expect("a split access has pieces"),
+        to: ty,
+    };
+}
+
+// Stores every piece of the value zero extended to the legal width, the last store
+// takes the place of the original one
+fn split_store(
+    function: &mut Function,
+    block: Block,
+    inst: Inst,
+    ty: Ty,
+    value: Operand,
+    ptr: Operand,
+    pieces: Vec<(u32, Ty)>,
+) {
+    let wide = Ty::Int {
+        bits: legal_bits(ty.bits()),
+        signed: false,
+    };
+    let value = extend(function, block, inst, value, ty, wide, CastOp::Zext);
+    let last = pieces.len() - 1;
+
+    for (i, (offset, piece)) in pieces.into_iter().enumerate() {
+        let mut part = value.clone();
+
+        if offset > 0 {
+            let amount = constant(wide, (offset * 8).into());
+            part = insert(
+                function,
+                block,
+                inst,
+                binary(BinaryOp::Lshr, wide, part, amount),
+            );
+        }
+
+        let cast = InstKind::Cast {
+            op: CastOp::Trunc,
+            from: wide,
+            value: part,
+            to: piece,
+        };
+        let part = insert(function, block, inst, cast);
+        let ptr = address(function, block, inst, &ptr, offset);
+        let kind = InstKind::Store {
+            ty: piece,
+            value: part,
+            ptr,
+        };
+
+        if i == last {
+            function.insts[inst.0].kind = kind;
+        } else {
+            function.insert_inst(block, inst, kind, None);
+        }
+    }
+}
+
+fn binary_extension(op: BinaryOp, ty: Ty) -> CastOp {
+    match op {
+        BinaryOp::Sdiv | BinaryOp::Srem | BinaryOp::Ashr => CastOp::Sext,
+        BinaryOp::Udiv | BinaryOp::Urem | BinaryOp::Lshr => CastOp::Zext,
+        _ if ty.is_signed() => CastOp::Sext,
+        _ => CastOp::Zext,
+    }
+}
+
+fn icmp_extension(cond: IntCC, ty: Ty) -> CastOp {
+    match cond {
+        IntCC::Slt | IntCC::Sle | IntCC::Sgt | IntCC::Sge => CastOp::Sext,
+        IntCC::Ult | IntCC::Ule | IntCC::Ugt | IntCC::Uge => CastOp::Zext,
+        IntCC::Eq | IntCC::Ne if ty.is_signed() => CastOp::Sext,
+        IntCC::Eq | IntCC::Ne => CastOp::Zext,
+    }
+}
+
+// Promotes integer arithmetic and comparisons to the widths the backend supports,
+// the result is truncated back so the rest of the function keeps its types. Loads and
+// stores of sizes that are not a power of two bytes are split so they only touch the
+// bytes of the value
+pub fn run(function: &mut Function) -> bool {
+    let mut changed = false;
+
+    for block in function.layout.clone() {
+        for inst in function.blocks[block.0].insts.clone() {
+            match function.insts[inst.0].kind.clone() {
+                InstKind::Binary {
+                    op, ty, lhs, rhs, ..
+                } if !op.is_float() => {
+                    let Some(wide) = needs_promotion(ty) else {
+                        continue;
+                    };
+
+                    let ext = binary_extension(op, ty);
+                    // Only the low bits of a shift amount matter
+                    let rhs_ext = match op {
+                        BinaryOp::Shl | BinaryOp::Lshr | BinaryOp::Ashr => CastOp::Zext,
+                        _ => ext,
+                    };
+
+                    let lhs = extend(function, block, inst, lhs, ty, wide, ext);
+                    let rhs = extend(function, block, inst, rhs, ty, wide, rhs_ext);
+                    let result = function.add_value(wide, None);
+                    let kind = InstKind::Binary {
+                        op,
+                        flags: Flags::default(),
+                        ty: wide,
+                        lhs,
+                        rhs,
+                    };
+
+                    function.insert_inst(block, inst, kind, Some(result));
+                    function.insts[inst.0].kind = InstKind::Cast {
+                        op: CastOp::Trunc,
+                        from: wide,
+                        value: Operand::Value(result),
+                        to: ty,
+                    };
+                    changed = true;
+                }
+                InstKind::Icmp { cond, ty, lhs, rhs } => {
+                    let Some(wide) = needs_promotion(ty) else {
+                        continue;
+                    };
+
+                    let ext = icmp_extension(cond, ty);
+                    let lhs = extend(function, block, inst, lhs, ty, wide, ext);
+                    let rhs = extend(function, block, inst, rhs, ty, wide, ext);
+
+                    function.insts[inst.0].kind = InstKind::Icmp {
+                        cond,
+                        ty: wide,
+                        lhs,
+                        rhs,
+                    };
+                    changed = true;
+                }
+                InstKind::Load { ty, ptr } => {
+                    let Some(pieces) = memory_pieces(ty) else {
+                        continue;
+                    };
+
+                    split_load(function, block, inst, ty, ptr, pieces);
+                    changed = true;
+                }
+                InstKind::Store { ty, value, ptr } => {
+                    let Some(pieces) = memory_pieces(ty) else {
+                        continue;
+                    };
+
+                    split_store(function, block, inst, ty, value, ptr, pieces);
+                    changed = true;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    changed
+}
@@ -0,0 +1,241 @@
+use crate::analysis::alias::{is_alloca, may_write};
+use crate::analysis::dominators::DomTree;
+use crate::analysis::loops::{Loop, LoopData};
+use crate::analysis::AnalysisManager;
+use crate::ir::cfg::ControlFlowGraph;
+use crate::ir::function::{Block, Function, Inst, Value};
+use crate::ir::inst::{BinaryOp, Flags, InstKind, Operand};
+use crate::ir::types::Ty;
+use crate::parser::types::Type;
+use std::collections::{HashMap, HashSet};
+
+// Constants that do not fit in the 12 bit immediate of addi take several instructions
+fn is_wide(constant: &Type) -> bool {
+    constant
+        .as_i128()
+        .is_some_and(|value| !(-2048..=2047).contains(&value))
+}
+
+// Places the instruction before the terminator of the block
+fn move_to_end(function: &mut Function, from: Block, inst: Inst, to: Block) {
+    function.remove_inst(from, inst);
+
+    let insts = &mut function.blocks[to.0].insts;
+    let position = insts.len().saturating_sub(1);
+    insts.insert(position, inst);
+}
+
+struct Licm<'a> {
+    cfg: &'a ControlFlowGraph,
+    domtree: &'a DomTree,
+    data: &'a LoopData,
+    preheader: Block,
+    // Results of the instructions still in the loop
+    variant: HashSet<Value>,
+}
+
+impl Licm<'_> {
+    fn is_invariant(&self, operand: &Operand) -> bool {
+        match operand {
+            Operand::Value(value) => !self.variant.contains(value),
+            Operand::Const(_) => true,
+        }
+    }
+
+    // A load can move when nothing in the loop may write its memory and it would have
+    // run anyway, either because every exit passes through it or because it reads a
+    // local of the function that is always there to be read
+    fn can_hoist_load(&self, function: &Function, block: Block, ptr: &Operand) -> bool {
+        let written = self.data.blocks.iter().any(|block| {
+            function.blocks[block.0]
+                .insts
+                .iter()
+                .any(|inst| may_write(function, &function.insts[inst.0].kind, ptr))
+        });
+
+        if written {
+            return false;
+        }
+
+        let always_runs = self.data.blocks.iter().all(|exiting| {
+            let exits = self
+                .cfg
+                .succs(*exiting)
+                .iter()
+                .any(|succ| !self.data.contains(*succ));
+
+            !exits || self.domtree.dominates(block, *exiting)
+        });
+
+        always_runs || is_alloca(function, ptr)
+    }
+
+    fn can_hoist(&self, function: &Function, block: Block, inst: Inst) -> bool {
+        let kind = &function.insts[inst.0].kind;
+
+        if !kind
+            .operands()
+            .iter()
+            .all(|operand| self.is_invariant(operand))
+        {
+            return false;
+        }
+
+        match kind {
+            InstKind::Load { ptr, .. } => self.can_hoist_load(function, block, ptr),
+            kind => kind.is_pure(),
+        }
+    }
+
+    // Moves invariant instructions to the preheader until none is left, an instruction
+    // becomes invariant once the ones it uses were moved
+    fn hoist(&mut self, function: &mut Function) -> bool {
+        let mut changed = false;
+
+        loop {
+            let mut moved = false;
+
+            for block in &self.data.blocks {
+                for inst in function.blocks[block.0].insts.clone() {
+                    let Some(result) = function.insts[inst.0].result else {
+                        continue;
+                    };
+
+                    if self.can_hoist(function, *block, inst) {
+                        move_to_end(function, *block, inst, self.preheader);
+                        self.variant.remove(&result);
+                        moved = true;
+                    }
+                }
+            }
+
+            if !moved {
+                return changed;
+            }
+
+            changed = true;
+        }
+    }
+
+    // Builds the wide constants used in the loop once in the preheader, instead of on
+    // every iteration
+    fn materialize(&mut self, function: &mut Function) -> bool {
+        let mut built: HashMap<Type, Value> = HashMap::new();
+        let mut changed = false;
+
+        for block in &self.data.blocks {
+            for inst in function.blocks[block.0].insts.clone() {
+                let mut kind = function.insts[inst.0].kind.clone();
+
+                let operands = match &mut kind {
+                    // Incoming values from outside are built before the loop already
+                    InstKind::Phi { incoming, .. } => incoming
+                        .iter_mut()
+                        .filter(|(_, pred)| self.data.contains(*pred))
+                        .map(|(operand, _)| operand)
+                        .collect(),
+                    kind => kind.operands_mut(),
+                };
+
+                let mut replaced = false;
+
+                for operand in operands {
+                    let Operand::Const(constant) = operand else {
+                        continue;
+                    };
+
+                    let Some(ty @ Ty::Int { .. }) = Ty::of(constant) else {
+                        continue;
+                    };
+
+                    if !is_wide(constant) {
+                        continue;
+                    }
+
+                    let value = *built.entry(constant.clone()).or_insert_with(|| {
+                        let value = function.add_value(ty, None);
+                        let kind = InstKind::Binary {
+                            op: BinaryOp::Add,
+                            flags: Flags::default(),
+                            ty,
+                            lhs: Operand::Const(constant.clone()),
+                            rhs: Operand::Const(ty.zero().unwrap()),
+                        };
+                        let terminator = function.terminator(self.preheader).unwrap();
+
+                        function.insert_inst(self.preheader, terminator, kind, Some(value));
+                        value
+                    });
+
+                    *operand = Operand::Value(value);
+                    replaced = true;
+                }
+
+                if replaced {
+                    function.insts[inst.0].kind = kind;
+                    changed = true;
+                }
+            }
+        }
+
+        changed
+    }
+}
+
+// Runs `step` on every loop that has a preheader, inner loops first so what leaves them
+// can leave the loops around them too
+fn each_loop(
+    function: &mut Function,
+    analyses: &mut AnalysisManager,
+    step: impl Fn(&mut Licm, &mut Function) -> bool,
+) -> bool {
+    let cfg = analyses.cfg(function);
+    let domtree = analyses.domtree(function);
+    let loops = analyses.loops(function);
+
+    let mut changed = false;
+    let order: Vec<Loop> = loops.loops().collect();
+
+    for lp in order.into_iter().rev() {
+        let data = loops.data(lp);
+
+        // Loops without a preheader are skipped, loop-simplify gives one to every loop
+        let Some(preheader) = loops.preheader(lp, &cfg) else {
+            continue;
+        };
+
+        let variant = data
+            .blocks
+            .iter()
+            .flat_map(|block| &function.blocks[block.0].insts)
+            .filter_map(|inst| function.insts[inst.0].result)
+            .collect();
+
+        let mut licm = Licm {
+            cfg: &cfg,
+            domtree: &domtree,
+            data,
+            preheader,
+            variant,
+        };
+
+        changed |= step(&mut licm, function);
+    }
+
+    changed
+}
+
+// Loop invariant code motion: pure instructions and loads of memory the loop does not
+// write move to the preheader
+pub fn run(function: &mut Function, analyses: &mut AnalysisManager) -> bool {
+    each_loop(function, analyses, |licm, function| licm.hoist(function))
+}
+
+// Builds the wide constants of every loop in its preheader. The `add C, 0` that holds
+// one folds back into the loop, so this runs after the last instcombine. The adds of an
+// inner loop are invariant in the loops around it and move out with them
+pub fn hoist_constants(function: &mut Function, analyses: &mut AnalysisManager) -> bool {
+    each_loop(function, analyses, |licm, function| {
+        licm.hoist(function) | licm.materialize(function)
+    })
+}
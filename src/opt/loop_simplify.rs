@@ -0,0 +1,134 @@
+use crate::analysis::dominators::DomTree;
+use crate::analysis::loops::LoopInfo;
+use crate::analysis::AnalysisManager;
+use crate::ir::cfg::ControlFlowGraph;
+use crate::ir::function::{Block, Function};
+use crate::ir::inst::{InstKind, Operand};
+
+// Moves the edges from `preds` to `target` onto a new block that jumps to `target`. The
+// phis of `target` take the values of those edges from the new block, merged in a phi
+// when they differ
+pub fn split_edges(function: &mut Function, target: Block, preds: &[Block], name: &str) -> Block {
+    let name = function.unique_block_name(name);
+    let new = function.create_block(name);
+
+    for pred in preds {
+        if let Some(terminator) = function.terminator(*pred) {
+            for succ in function.insts[terminator.0].kind.successors_mut() {
+                if *succ == target {
+                    *succ = new;
+                }
+            }
+        }
+    }
+
+    let jump = function.append_inst(new, InstKind::Jump { target }, None);
+
+    for inst in function.blocks[target.0].insts.clone() {
+        let InstKind::Phi { ty, incoming } = function.insts[inst.0].kind.clone() else {
+            continue;
+        };
+
+        let (moved, mut kept): (Vec<_>, Vec<_>) = incoming
+            .into_iter()
+            .partition(|(_, pred)| preds.contains(pred));
+
+        let value = match moved.as_slice() {
+            [] => continue,
+            [(first, _), rest @ ..] if rest.iter().all(|(value, _)| value == first) => {
+                first.clone()
+            }
+            _ => {
+                let result = function.add_value(ty, None);
+                let kind = InstKind::Phi {
+                    ty,
+                    incoming: moved,
+                };
+
+                function.insert_inst(new, jump, kind, Some(result));
+                Operand::Value(result)
+            }
+        };
+
+        kept.push((value, new));
+        function.insts[inst.0].kind = InstKind::Phi { ty, incoming: kept };
+    }
+
+    // After the last of the preds, so it can fall through from there
+    let position = function
+        .layout
+        .iter()
+        .rposition(|block| preds.contains(block))
+        .map_or(0, |position| position + 1);
+    function.layout.insert(position, new);
+
+    new
+}
+
+// Makes one change to the first loop that needs it, the analyses are stale afterwards
+fn simplify_one(function: &mut Function) -> bool {
+    let cfg = ControlFlowGraph::new(function);
+    let domtree = DomTree::new(function, &cfg);
+    let loops = LoopInfo::new(&cfg, &domtree);
+
+    for lp in loops.loops() {
+        let data = loops.data(lp);
+        let header = data.header;
+        let name = function.blocks[header.0].name.clone();
+
+        // A preheader is the only way into the loop and only leads to the header
+        let outside: Vec<Block> = cfg
+            .preds(header)
+            .iter()
+            .copied()
+            .filter(|pred| !data.contains(*pred))
+            .collect();
+
+        let has_preheader = match outside.as_slice() {
+            [pred] => cfg.succs(*pred) == [header],
+            _ => false,
+        };
+
+        if !has_preheader && !outside.is_empty() {
+            split_edges(function, header, &outside, &format!("{}.preheader", name));
+            return true;
+        }
+
+        if data.latches.len() > 1 {
+            split_edges(function, header, &data.latches, &format!("{}.latch", name));
+            return true;
+        }
+
+        // Every exit is only reached from inside the loop, so code can be sunk into it
+        for exit in loops.exits(lp, &cfg) {
+            let preds = cfg.preds(exit);
+
+            if preds.iter().all(|pred| data.contains(*pred)) {
+                continue;
+            }
+
+            let inside: Vec<Block> = preds
+                .iter()
+                .copied()
+                .filter(|pred| data.contains(*pred))
+                .collect();
+            let exit_name = function.blocks[exit.0].name.clone();
+
+            split_edges(function, exit, &inside, &format!("{}.loopexit", exit_name));
+            return true;
+        }
+    }
+
+    false
+}
+
+// Gives every loop a preheader, a single latch and exits reached only from the loop
+pub fn run(function: &mut Function, _analyses: &mut AnalysisManager) -> bool {
+    let mut changed = false;
+
+    while simplify_one(function) {
+        changed = true;
+    }
+
+    changed
+}
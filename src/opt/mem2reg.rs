@@ -0,0 +1,220 @@
+use crate::analysis::AnalysisManager;
+use crate::ir::function::{Block, Function, Inst, Value};
+use crate::ir::inst::{InstKind, Operand};
+use crate::ir::types::Ty;
+use crate::ir::uses::UseLists;
+use std::collections::{HashMap, HashSet};
+
+// An alloca can become a register when it is only loaded and stored with its own type,
+// a pointer escaping to a call or stored somewhere could be read behind our back
+fn promotable(function: &Function, uses: &UseLists, slot: Value, ty: Ty) -> bool {
+    if ty.zero().is_none() {
+        return false;
+    }
+
+    uses.uses(slot)
+        .iter()
+        .all(|inst| match &function.insts[inst.0].kind {
+            InstKind::Load { ty: load_ty, .. } => *load_ty == ty,
+            InstKind::Store {
+                ty: store_ty,
+                value,
+                ..
+            } => *store_ty == ty && *value != Operand::Value(slot),
+            _ => false,
+        })
+}
+
+fn slot_of(kind: &InstKind) -> Option<Value> {
+    match kind {
+        InstKind::Load {
+            ptr: Operand::Value(slot),
+            ..
+        }
+        | InstKind::Store {
+            ptr: Operand::Value(slot),
+            ..
+        } => Some(*slot),
+        _ => None,
+    }
+}
+
+// Promotes the stack slots to SSA values, phis are placed at the iterated dominance
+// frontier of the stores and loads of slots never stored read zero
+pub fn run(function: &mut Function, analyses: &mut AnalysisManager) -> bool {
+    let uses = UseLists::new(function);
+    let domtree = analyses.domtree(function);
+    let frontiers = analyses.frontiers(function);
+    let cfg = analyses.cfg(function);
+
+    // Promotable slots and their types, in the order they are defined
+    let mut slots: Vec<(Value, Ty)> = Vec::new();
+
+    for block in domtree.reverse_postorder() {
+        for inst in &function.blocks[block.0].insts {
+            let data = &function.insts[inst.0];
+
+            if let (InstKind::Alloca { ty }, Some(slot)) = (&data.kind, data.result) {
+                if promotable(function, &uses, slot, *ty) {
+                    slots.push((slot, *ty));
+                }
+            }
+        }
+    }
+
+    if slots.is_empty() {
+        return false;
+    }
+
+    let types: HashMap<Value, Ty> = slots.iter().copied().collect();
+    let mut block_of: HashMap<Inst, Block> = HashMap::new();
+
+    for block in &function.layout {
+        for inst in &function.blocks[block.0].insts {
+            block_of.insert(*inst, *block);
+        }
+    }
+
+    let zero = |slot: &Value| Operand::Const(types[slot].zero().unwrap());
+
+    // Phis placed for each slot, indexed by the block holding them
+    let mut phis: HashMap<(Block, Value), Inst> = HashMap::new();
+
+    for (slot, ty) in &slots {
+        let mut work: Vec<Block> = Vec::new();
+
+        for inst in uses.uses(*slot) {
+            if let InstKind::Store { .. } = function.insts[inst.0].kind {
+                work.push(block_of[inst]);
+            }
+        }
+
+        let mut placed: HashSet<Block> = HashSet::new();
+
+        while let Some(block) = work.pop() {
+            for frontier in frontiers.frontier(block) {
+                if !placed.insert(*frontier) {
+                    continue;
+                }
+
+                let result = function.add_value(*ty, None);
+                let kind = InstKind::Phi {
+                    ty: *ty,
+                    incoming: Vec::new(),
+                };
+                // After the phis already there, so they keep the order of the slots
+                let first = function.blocks[frontier.0]
+                    .insts
+                    .iter()
+                    .copied()
+                    .find(|inst| !matches!(function.insts[inst.0].kind, InstKind::Phi { .. }))
+                    .unwrap();
+                let phi = function.insert_inst(*frontier, first, kind, Some(result));
+
+                phis.insert((*frontier, *slot), phi);
+                work.push(*frontier);
+            }
+        }
+    }
+
+    // Value of each slot at the end of each block, a block without a phi for a slot
+    // starts with the value its immediate dominator ends with
+    let mut ends: HashMap<Block, HashMap<Value, Operand>> = HashMap::new();
+    let mut blocks = domtree.preorder();
+
+    for block in &function.layout {
+        if !domtree.is_reachable(*block) {
+            blocks.push(*block);
+        }
+    }
+
+    for block in blocks {
+        let mut current: HashMap<Value, Operand> = match domtree.idom(block) {
+            Some(idom) => ends[&idom].clone(),
+            None => slots.iter().map(|(slot, _)| (*slot, zero(slot))).collect(),
+        };
+
+        for (slot, _) in &slots {
+            if let Some(phi) = phis.get(&(block, *slot)) {
+                let result = function.insts[phi.0].result.unwrap();
+                current.insert(*slot, Operand::Value(result));
+            }
+        }
+
+        for inst in function.blocks[block.0].insts.clone() {
+            let kind = &function.insts[inst.0].kind;
+
+            let Some(slot) = slot_of(kind).filter(|slot| types.contains_key(slot)) else {
+                continue;
+            };
+
+            match kind.clone() {
+                InstKind::Load { .. } => {
+                    let result = function.insts[inst.0].result.unwrap();
+                    function.replace_uses(result, current[&slot].clone());
+                }
+                InstKind::Store { value, .. } => {
+                    current.insert(slot, value);
+                }
+                _ => unreachable!(),
+            }
+
+            function.remove_inst(block, inst);
+        }
+
+        ends.insert(block, current);
+    }
+
+    // Every predecessor gives an incoming value, even the unreachable ones
+    for ((block, slot), phi) in &phis {
+        let incoming = cfg
+            .preds(*block)
+            .iter()
+            .map(|pred| (ends[pred][slot].clone(), *pred))
+            .collect();
+
+        if let InstKind::Phi { incoming: phi, .. } = &mut function.insts[phi.0].kind {
+            *phi = incoming;
+        }
+    }
+
+    for block in function.layout.clone() {
+        for inst in function.blocks[block.0].insts.clone() {
+            let data = &function.insts[inst.0];
+
+            if let (InstKind::Alloca { .. }, Some(slot)) = (&data.kind, data.result) {
+                if types.contains_key(&slot) {
+                    function.remove_inst(block, inst);
+                }
+            }
+        }
+    }
+
+    let phis = phis.into_iter().map(|((block, _), phi)| (block, phi));
+    remove_dead_phis(function, phis.collect());
+
+    true
+}
+
+// Minimal placement also adds phis where the slot is dead, the ones left unused or only
+// feeding themselves are removed
+pub(crate) fn remove_dead_phis(function: &mut Function, mut phis: Vec<(Block, Inst)>) {
+    let mut removed = true;
+
+    while removed {
+        removed = false;
+        let uses = UseLists::new(function);
+
+        phis.retain(|(block, phi)| {
+            let result = function.insts[phi.0].result.unwrap();
+            let dead = uses.uses(result).iter().all(|inst| inst == phi);
+
+            if dead {
+                function.remove_inst(*block, *phi);
+                removed = true;
+            }
+
+            !dead
+        });
+    }
+}
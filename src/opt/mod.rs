@@ -0,0 +1,17 @@
+pub mod constfold;
+pub mod dce;
+pub mod gvn;
+pub mod indvars;
+pub mod inline;
+pub mod instcombine;
+pub mod lcssa;
+pub mod legalize;
+pub mod licm;
+pub mod loop_simplify;
+pub mod mem2reg;
+pub mod pass;
+pub mod sccp;
+pub mod simplifycfg;
+pub mod unroll;
+
+pub use pass::{OptLevel, Pass, PassError, PassManager};
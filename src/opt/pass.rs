@@ -0,0 +1,340 @@
+use super::{
+    constfold, dce, gvn, indvars, inline, instcombine, lcssa, legalize, licm, loop_simplify,
+    mem2reg, sccp, simplifycfg, unroll,
+};
+use crate::analysis::{AnalysisManager, Preserved};
+use crate::ir::function::Function;
+use crate::ir::verify::{verify_module, VerifyError};
+use crate::ir::Module;
+use std::fmt::Display;
+use std::str::FromStr;
+use thiserror::Error;
+use tracing::info;
+
+#[derive(Error, Debug)]
+pub enum PassError {
+    #[error("Unknown pass {0}")]
+    UnknownPass(String),
+
+    #[error("Unknown optimization level -O{0}")]
+    UnknownLevel(String),
+
+    #[error("After {pass}: {error}")]
+    InvalidIr { pass: String, error: VerifyError },
+}
+
+#[derive(Clone, Copy)]
+pub enum Pass {
+    // Runs once on every function, `preserves` says which analyses survive a change
+    Function {
+        name: &'static str,
+        run: fn(&mut Function, &mut AnalysisManager) -> bool,
+        preserves: Preserved,
+    },
+    // Runs once on the whole module, every analysis is dropped when it changes something
+    Module {
+        name: &'static str,
+        run: fn(&mut Module, OptLevel) -> bool,
+    },
+}
+
+impl Pass {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Pass::Function { name, .. } | Pass::Module { name, .. } => name,
+        }
+    }
+}
+
+// Every pass that can be named in --passes and --print-after
+pub const PASSES: &[Pass] = &[
+    Pass::Module {
+        name: "inline",
+        run: inline::run,
+    },
+    Pass::Function {
+        name: "mem2reg",
+        run: mem2reg::run,
+        preserves: Preserved::Cfg,
+    },
+    Pass::Function {
+        name: "constfold",
+        run: |function, _| constfold::run(function),
+        preserves: Preserved::Cfg,
+    },
+    Pass::Function {
+        name: "sccp",
+        run: sccp::run,
+        preserves: Preserved::None,
+    },
+    // A module pass only to know the level, -Os keeps divisions as they are
+    Pass::Module {
+        name: "instcombine",
+        run: instcombine::run,
+    },
+    Pass::Function {
+        name: "simplifycfg",
+        run: simplifycfg::run,
+        preserves: Preserved::None,
+    },
+    Pass::Function {
+        name: "gvn",
+        run: gvn::run,
+        preserves: Preserved::Cfg,
+    },
+    Pass::Function {
+        name: "loop-simplify",
+        run: loop_simplify::run,
+        preserves: Preserved::None,
+    },
+    Pass::Function {
+        name: "lcssa",
+        run: lcssa::run,
+        preserves: Preserved::Cfg,
+    },
+    Pass::Function {
+        name: "licm",
+        run: licm::run,
+        preserves: Preserved::Cfg,
+    },
+    Pass::Function {
+        name: "licm-constants",
+        run: licm::hoist_constants,
+        preserves: Preserved::Cfg,
+    },
+    Pass::Function {
+        name: "indvars",
+        run: indvars::run,
+        preserves: Preserved::Cfg,
+    },
+    Pass::Function {
+        name: "loop-unroll",
+        run: unroll::run,
+        preserves: Preserved::None,
+    },
+    Pass::Function {
+        name: "dce",
+        run: dce::run,
+        preserves: Preserved::None,
+    },
+    Pass::Function {
+        name: "legalize",
+        run: |function, _| legalize::run(function),
+        preserves: Preserved::Cfg,
+    },
+];
+
+pub fn get_pass(name: &str) -> Result<Pass, PassError> {
+    PASSES
+        .iter()
+        .find(|pass| pass.name() == name)
+        .copied()
+        .ok_or_else(|| PassError::UnknownPass(name.to_string()))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OptLevel {
+    #[default]
+    O0,
+    O1,
+    O2,
+    Os,
+}
+
+impl OptLevel {
+    // Names of the passes run at this level, legalize is added by the compiler at every level
+    pub fn pipeline(&self) -> &'static [&'static str] {
+        match self {
+            // Only alwaysinline functions are inlined at -O0
+            OptLevel::O0 => &["inline"],
+            OptLevel::O1 => &[
+                "inline",
+                "mem2reg",
+                "constfold",
+                "instcombine",
+                "simplifycfg",
+                "dce",
+            ],
+            OptLevel::O2 => &[
+                "inline",
+                "mem2reg",
+                "sccp",
+                "instcombine",
+                "simplifycfg",
+                "gvn",
+                "loop-simplify",
+                "lcssa",
+                "licm",
+                "indvars",
+                "loop-unroll",
+                "instcombine",
+                "simplifycfg",
+                "loop-simplify",
+                "licm-constants",
+                "dce",
+            ],
+            OptLevel::Os => &[
+                "inline",
+                "mem2reg",
+                "sccp",
+                "instcombine",
+                "simplifycfg",
+                "gvn",
+                "loop-simplify",
+                "lcssa",
+                "licm",
+                "indvars",
+                "instcombine",
+                "simplifycfg",
+                "loop-simplify",
+                "licm-constants",
+                "dce",
+            ],
+        }
+    }
+}
+
+impl FromStr for OptLevel {
+    type Err = PassError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "0" => Ok(OptLevel::O0),
+            "1" => Ok(OptLevel::O1),
+            "2" => Ok(OptLevel::O2),
+            "s" => Ok(OptLevel::Os),
+            _ => Err(PassError::UnknownLevel(s.to_string())),
+        }
+    }
+}
+
+impl Display for OptLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OptLevel::O0 => write!(f, "-O0"),
+            OptLevel::O1 => write!(f, "-O1"),
+            OptLevel::O2 => write!(f, "-O2"),
+            OptLevel::Os => write!(f, "-Os"),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct PassManager {
+    passes: Vec<Pass>,
+    // Passes with thresholds, like the inliner, tune them by level
+    level: OptLevel,
+    // Passes whose result is logged, "all" logs after every pass
+    print_after: Vec<String>,
+    // Verifies the module after every pass that changed it
+    verify: bool,
+}
+
+impl PassManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_names<S: AsRef<str>>(names: &[S]) -> Result<Self, PassError> {
+        let mut manager = Self::new();
+
+        for name in names {
+            manager = manager.add_pass(get_pass(name.as_ref())?);
+        }
+
+        Ok(manager)
+    }
+
+    pub fn from_level(level: OptLevel) -> Self {
+        Self::from_names(level.pipeline())
+            .expect("pipelines only name registered passes")
+            .set_level(level)
+    }
+
+    #[must_use]
+    pub fn set_level(mut self, level: OptLevel) -> Self {
+        self.level = level;
+        self
+    }
+
+    #[must_use]
+    pub fn add_pass(mut self, pass: Pass) -> Self {
+        self.passes.push(pass);
+        self
+    }
+
+    #[must_use]
+    pub fn set_print_after(mut self, print_after: Vec<String>) -> Self {
+        self.print_after = print_after;
+        self
+    }
+
+    #[must_use]
+    pub fn set_verify(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    pub fn passes(&self) -> &[Pass] {
+        &self.passes
+    }
+
+    fn prints_after(&self, pass: &Pass) -> bool {
+        self.print_after
+            .iter()
+            .any(|name| name == "all" || name == pass.name())
+    }
+
+    pub fn run(&self, module: &mut Module) -> Result<bool, PassError> {
+        for name in &self.print_after {
+            if name != "all" {
+                get_pass(name)?;
+            }
+        }
+
+        let mut analyses = vec![AnalysisManager::new(); module.functions.len()];
+        let mut changed = false;
+
+        for pass in &self.passes {
+            let pass_changed = match pass {
+                Pass::Function { run, preserves, .. } => {
+                    let mut pass_changed = false;
+
+                    for (function, analyses) in module.functions.iter_mut().zip(&mut analyses) {
+                        if run(function, analyses) {
+                            analyses.invalidate(*preserves);
+                            pass_changed = true;
+                        }
+                    }
+
+                    pass_changed
+                }
+                Pass::Module { run, .. } => {
+                    let pass_changed = run(module, self.level);
+
+                    // Functions may have been added or removed
+                    if pass_changed {
+                        analyses = vec![AnalysisManager::new(); module.functions.len()];
+                    }
+
+                    pass_changed
+                }
+            };
+
+            if self.prints_after(pass) {
+                info!("IR after {}:\n{}", pass.name(), module);
+            }
+
+            if pass_changed && self.verify {
+                verify_module(module).map_err(|error| PassError::InvalidIr {
+                    pass: pass.name().to_string(),
+                    error,
+                })?;
+            }
+
+            changed |= pass_changed;
+        }
+
+        Ok(changed)
+    }
+}
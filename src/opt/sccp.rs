@@ -0,0 +1,263 @@
+use crate::analysis::AnalysisManager;
+use crate::ir::function::{Block, Function, Inst, Value, ValueDef};
+use crate::ir::inst::{InstKind, Operand};
+use crate::ir::uses::UseLists;
+use crate::opt::constfold::fold_inst;
+use crate::parser::types::Type;
+use std::collections::{HashMap, HashSet};
+
+// What is known about a value, it only moves down from Unknown to Overdefined
+#[derive(Debug, Clone, PartialEq)]
+enum Lattice {
+    // Not reached yet, it may still become anything
+    Unknown,
+    Const(Type),
+    // More than one value reaches it
+    Overdefined,
+}
+
+impl Lattice {
+    fn meet(&self, other: &Lattice) -> Lattice {
+        match (self, other) {
+            (Lattice::Unknown, other) | (other, Lattice::Unknown) => other.clone(),
+            (Lattice::Const(a), Lattice::Const(b)) if a == b => self.clone(),
+            _ => Lattice::Overdefined,
+        }
+    }
+}
+
+struct Solver<'a> {
+    function: &'a Function,
+    uses: UseLists,
+    block_of: HashMap<Inst, Block>,
+    values: Vec<Lattice>,
+    executable: HashSet<Block>,
+    edges: HashSet<(Block, Block)>,
+    blocks: Vec<Block>,
+    insts: Vec<Inst>,
+}
+
+impl<'a> Solver<'a> {
+    fn new(function: &'a Function) -> Self {
+        let mut block_of = HashMap::new();
+
+        for block in &function.layout {
+            for inst in &function.blocks[block.0].insts {
+                block_of.insert(*inst, *block);
+            }
+        }
+
+        let mut values = vec![Lattice::Unknown; function.values.len()];
+
+        // Arguments can be anything
+        for param in &function.params {
+            values[param.0] = Lattice::Overdefined;
+        }
+
+        Self {
+            function,
+            uses: UseLists::new(function),
+            block_of,
+            values,
+            executable: HashSet::new(),
+            edges: HashSet::new(),
+            blocks: Vec::new(),
+            insts: Vec::new(),
+        }
+    }
+
+    fn solve(&mut self) {
+        let Some(entry) = self.function.entry() else {
+            return;
+        };
+
+        self.executable.insert(entry);
+        self.blocks.push(entry);
+
+        while !self.blocks.is_empty() || !self.insts.is_empty() {
+            while let Some(inst) = self.insts.pop() {
+                self.visit(inst);
+            }
+
+            if let Some(block) = self.blocks.pop() {
+                for inst in &self.function.blocks[block.0].insts {
+                    self.visit(*inst);
+                }
+            }
+        }
+    }
+
+    fn operand(&self, operand: &Operand) -> Lattice {
+        match operand {
+            Operand::Value(value) => self.values[value.0].clone(),
+            Operand::Const(constant) => Lattice::Const(constant.clone()),
+        }
+    }
+
+    fn mark_edge(&mut self, from: Block, to: Block) {
+        if !self.edges.insert((from, to)) {
+            return;
+        }
+
+        if self.executable.insert(to) {
+            self.blocks.push(to);
+        } else {
+            // Only the phis see the new edge
+            for inst in &self.function.blocks[to.0].insts {
+                if let InstKind::Phi { .. } = self.function.insts[inst.0].kind {
+                    self.insts.push(*inst);
+                }
+            }
+        }
+    }
+
+    fn set(&mut self, value: Value, lattice: Lattice) {
+        let lattice = self.values[value.0].meet(&lattice);
+
+        if lattice == self.values[value.0] {
+            return;
+        }
+
+        self.values[value.0] = lattice;
+
+        for user in self.uses.uses(value) {
+            if self.executable.contains(&self.block_of[user]) {
+                self.insts.push(*user);
+            }
+        }
+    }
+
+    fn visit(&mut self, inst: Inst) {
+        let block = self.block_of[&inst];
+        let data = &self.function.insts[inst.0];
+
+        match &data.kind {
+            InstKind::Jump { target } => self.mark_edge(block, *target),
+            InstKind::Branch {
+                cond,
+                then_dest,
+                else_dest,
+            } => match self.operand(cond) {
+                Lattice::Unknown => {}
+                Lattice::Const(cond) if cond.is_zero() => self.mark_edge(block, *else_dest),
+                Lattice::Const(_) => self.mark_edge(block, *then_dest),
+                Lattice::Overdefined => {
+                    self.mark_edge(block, *then_dest);
+                    self.mark_edge(block, *else_dest);
+                }
+            },
+            InstKind::Phi { incoming, .. } => {
+                let mut lattice = Lattice::Unknown;
+
+                for (operand, pred) in incoming {
+                    if self.edges.contains(&(*pred, block)) {
+                        lattice = lattice.meet(&self.operand(operand));
+                    }
+                }
+
+                if let Some(result) = data.result {
+                    self.set(result, lattice);
+                }
+            }
+            kind => {
+                let Some(result) = data.result else {
+                    return;
+                };
+
+                let mut folded = kind.clone();
+                let mut lattice = None;
+
+                for operand in folded.operands_mut() {
+                    match self.operand(operand) {
+                        Lattice::Const(constant) => *operand = Operand::Const(constant),
+                        Lattice::Overdefined => lattice = Some(Lattice::Overdefined),
+                        Lattice::Unknown => lattice = lattice.or(Some(Lattice::Unknown)),
+                    }
+                }
+
+                // Results the evaluators cannot compute, like loads or division by zero,
+                // are left to run
+                let lattice = lattice.unwrap_or_else(|| match fold_inst(&folded) {
+                    Some(constant) => Lattice::Const(constant),
+                    None => Lattice::Overdefined,
+                });
+
+                self.set(result, lattice);
+            }
+        }
+    }
+}
+
+// Propagates constants through the instructions, phis and the branches they decide,
+// branches taken only one way become jumps and the unreachable blocks are left to dce
+pub fn run(function: &mut Function, _analyses: &mut AnalysisManager) -> bool {
+    let mut solver = Solver::new(function);
+    solver.solve();
+
+    let Solver {
+        values,
+        edges,
+        block_of,
+        ..
+    } = solver;
+    let mut changed = false;
+
+    for (i, lattice) in values.iter().enumerate() {
+        let Lattice::Const(constant) = lattice else {
+            continue;
+        };
+
+        let ValueDef::Inst(inst) = function.values[i].def else {
+            continue;
+        };
+
+        if function.insts[inst.0].kind.has_side_effects() {
+            continue;
+        }
+
+        function.replace_uses(Value(i), Operand::Const(constant.clone()));
+        function.remove_inst(block_of[&inst], inst);
+        changed = true;
+    }
+
+    for block in function.layout.clone() {
+        let Some(terminator) = function.terminator(block) else {
+            continue;
+        };
+
+        let InstKind::Branch {
+            then_dest,
+            else_dest,
+            ..
+        } = function.insts[terminator.0].kind
+        else {
+            continue;
+        };
+
+        let taken = (
+            edges.contains(&(block, then_dest)),
+            edges.contains(&(block, else_dest)),
+        );
+
+        let (target, dropped) = match taken {
+            (true, false) => (then_dest, else_dest),
+            (false, true) => (else_dest, then_dest),
+            _ => continue,
+        };
+
+        function.insts[terminator.0].kind = InstKind::Jump { target };
+
+        // The phis of the other block lose this predecessor
+        if dropped != target {
+            for inst in &function.blocks[dropped.0].insts {
+                if let InstKind::Phi { incoming, .. } = &mut function.insts[inst.0].kind {
+                    incoming.retain(|(_, pred)| *pred != block);
+                }
+            }
+        }
+
+        changed = true;
+    }
+
+    changed
+}
@@ -0,0 +1,505 @@
+use crate::analysis::AnalysisManager;
+use crate::ir::cfg::ControlFlowGraph;
+use crate::ir::function::{Block, Function, Inst, Value};
+use crate::ir::inst::{InstKind, Operand};
+use crate::ir::uses::UseLists;
+use std::collections::{HashMap, HashSet};
+
+fn phis(function: &Function, block: Block) -> Vec<Inst> {
+    function.blocks[block.0]
+        .insts
+        .iter()
+        .copied()
+        .take_while(|inst| matches!(function.insts[inst.0].kind, InstKind::Phi { .. }))
+        .collect()
+}
+
+fn incoming(function: &Function, phi: Inst, pred: Block) -> Option<&Operand> {
+    let InstKind::Phi { incoming, .. } = &function.insts[phi.0].kind else {
+        return None;
+    };
+
+    incoming
+        .iter()
+        .find(|(_, from)| *from == pred)
+        .map(|(value, _)| value)
+}
+
+// Points the edges from `pred` to `from` at `to`
+fn retarget(function: &mut Function, pred: Block, from: Block, to: Block) {
+    let Some(terminator) = function.terminator(pred) else {
+        return;
+    };
+
+    for succ in function.insts[terminator.0].kind.successors_mut() {
+        if *succ == from {
+            *succ = to;
+        }
+    }
+}
+
+fn forget_pred(function: &mut Function, block: Block, pred: Block) {
+    for phi in phis(function, block) {
+        if let InstKind::Phi { incoming, .. } = &mut function.insts[phi.0].kind {
+            incoming.retain(|(_, from)| *from != pred);
+        }
+    }
+}
+
+// Whether `pred` can jump to `block` with the values the phis take from `from`, an edge
+// that is already there must bring the same ones
+fn can_add_edge(
+    function: &Function,
+    cfg: &ControlFlowGraph,
+    block: Block,
+    from: Block,
+    pred: Block,
+) -> bool {
+    !cfg.preds(block).contains(&pred)
+        || phis(function, block)
+            .into_iter()
+            .all(|phi| incoming(function, phi, from) == incoming(function, phi, pred))
+}
+
+// The phis of `block` take from `pred` what they take from `from`
+fn copy_incoming(function: &mut Function, block: Block, from: Block, pred: Block) {
+    for phi in phis(function, block) {
+        let Some(value) = incoming(function, phi, from).cloned() else {
+            continue;
+        };
+
+        if incoming(function, phi, pred).is_some() {
+            continue;
+        }
+
+        if let InstKind::Phi { incoming, .. } = &mut function.insts[phi.0].kind {
+            incoming.push((value, pred));
+        }
+    }
+}
+
+// Branches on a constant, or to the same block on both sides, become jumps
+fn fold_branches(function: &mut Function) -> bool {
+    let mut changed = false;
+
+    for block in function.layout.clone() {
+        let Some(terminator) = function.terminator(block) else {
+            continue;
+        };
+
+        let InstKind::Branch {
+            cond,
+            then_dest,
+            else_dest,
+        } = function.insts[terminator.0].kind.clone()
+        else {
+            continue;
+        };
+
+        let (taken, dropped) = match cond {
+            _ if then_dest == else_dest => (then_dest, None),
+            Operand::Const(constant) if constant.is_zero() => (else_dest, Some(then_dest)),
+            Operand::Const(_) => (then_dest, Some(else_dest)),
+            Operand::Value(_) => continue,
+        };
+
+        function.insts[terminator.0].kind = InstKind::Jump { target: taken };
+
+        if let Some(dropped) = dropped {
+            forget_pred(function, dropped, block);
+        }
+
+        changed = true;
+    }
+
+    changed
+}
+
+fn remove_unreachable(function: &mut Function, cfg: &ControlFlowGraph) -> bool {
+    let reachable: HashSet<Block> = cfg.reverse_postorder(function).into_iter().collect();
+    let unreachable: Vec<Block> = function
+        .layout
+        .iter()
+        .copied()
+        .filter(|block| !reachable.contains(block))
+        .collect();
+
+    for block in &unreachable {
+        for succ in cfg.succs(*block) {
+            forget_pred(function, *succ, *block);
+        }
+
+        function.remove_block(*block);
+    }
+
+    !unreachable.is_empty()
+}
+
+// Appends a block to its only predecessor when it is also the only successor of it
+fn merge_into_pred(function: &mut Function, cfg: &ControlFlowGraph) -> bool {
+    for block in function.layout.iter().skip(1).copied() {
+        let [pred] = *cfg.preds(block) else {
+            continue;
+        };
+
+        if pred == block || cfg.succs(pred) != [block] {
+            continue;
+        }
+
+        // With a single predecessor each phi has a single value
+        for phi in phis(function, block) {
+            let value = incoming(function, phi, pred).cloned().unwrap();
+            let result = function.insts[phi.0].result.unwrap();
+
+            function.replace_uses(result, value);
+            function.remove_inst(block, phi);
+        }
+
+        let jump = function.terminator(pred).unwrap();
+        function.remove_inst(pred, jump);
+
+        let insts = std::mem::take(&mut function.blocks[block.0].insts);
+        function.blocks[pred.0].insts.extend(insts);
+
+        for succ in cfg.succs(block) {
+            for phi in phis(function, *succ) {
+                if let InstKind::Phi { incoming, .. } = &mut function.insts[phi.0].kind {
+                    for (_, from) in incoming.iter_mut() {
+                        if *from == block {
+                            *from = pred;
+                        }
+                    }
+                }
+            }
+        }
+
+        function.remove_block(block);
+        return true;
+    }
+
+    false
+}
+
+// Blocks holding only a jump are skipped by their predecessors
+fn remove_forwarding(function: &mut Function, cfg: &ControlFlowGraph) -> bool {
+    for block in function.layout.iter().skip(1).copied() {
+        let [jump] = function.blocks[block.0].insts[..] else {
+            continue;
+        };
+
+        let InstKind::Jump { target } = function.insts[jump.0].kind else {
+            continue;
+        };
+
+        let preds = cfg.preds(block);
+
+        if target == block
+            || preds.is_empty()
+            || !preds
+                .iter()
+                .all(|pred| can_add_edge(function, cfg, target, block, *pred))
+        {
+            continue;
+        }
+
+        for pred in preds {
+            retarget(function, *pred, block, target);
+            copy_incoming(function, target, block, *pred);
+        }
+
+        forget_pred(function, target, block);
+        function.remove_block(block);
+        return true;
+    }
+
+    false
+}
+
+// Where the branch ending `block` goes when its condition is `value`
+fn branch_target(function: &Function, block: Block, value: bool) -> Option<Block> {
+    match function.insts[function.terminator(block)?.0].kind {
+        InstKind::Branch {
+            then_dest,
+            else_dest,
+            ..
+        } if then_dest != else_dest => Some(if value { then_dest } else { else_dest }),
+        _ => None,
+    }
+}
+
+// Predecessors of `block` for which its branch is known to go one way, with that way.
+// Either the condition is a phi of the block holding a constant on the edge, or the
+// predecessor branched on the same condition to get here
+fn known_edges(function: &Function, uses: &UseLists, block: Block) -> Vec<(Block, Block)> {
+    let insts = &function.blocks[block.0].insts;
+    let terminator = *insts.last().unwrap();
+
+    let InstKind::Branch {
+        cond: Operand::Value(cond),
+        ..
+    } = function.insts[terminator.0].kind
+    else {
+        return Vec::new();
+    };
+
+    match insts[..] {
+        [phi, _] if function.insts[phi.0].result == Some(cond) => {
+            if uses.uses(cond) != [terminator] {
+                return Vec::new();
+            }
+
+            let InstKind::Phi { incoming, .. } = &function.insts[phi.0].kind else {
+                return Vec::new();
+            };
+
+            incoming
+                .iter()
+                .filter_map(|(value, pred)| {
+                    let Operand::Const(constant) = value else {
+                        return None;
+                    };
+
+                    let target = branch_target(function, block, !constant.is_zero())?;
+                    Some((*pred, target))
+                })
+                .collect()
+        }
+        [_] => {
+            let mut edges = Vec::new();
+
+            for pred in function.layout.iter().copied() {
+                let Some(pred_terminator) = function.terminator(pred) else {
+                    continue;
+                };
+
+                let InstKind::Branch {
+                    cond: Operand::Value(pred_cond),
+                    then_dest,
+                    else_dest,
+                } = function.insts[pred_terminator.0].kind
+                else {
+                    continue;
+                };
+
+                if pred_cond != cond || then_dest == else_dest {
+                    continue;
+                }
+
+                let value = match block {
+                    _ if then_dest == block => true,
+                    _ if else_dest == block => false,
+                    _ => continue,
+                };
+
+                if let Some(target) = branch_target(function, block, value) {
+                    edges.push((pred, target));
+                }
+            }
+
+            edges
+        }
+        _ => Vec::new(),
+    }
+}
+
+// Sends predecessors straight to the side of the branch they are known to take, when
+// the block has nothing else to run on the way
+fn thread_jumps(function: &mut Function, cfg: &ControlFlowGraph) -> bool {
+    let uses = UseLists::new(function);
+
+    for block in function.layout.iter().skip(1).copied() {
+        for (pred, target) in known_edges(function, &uses, block) {
+            if target == block || !can_add_edge(function, cfg, target, block, pred) {
+                continue;
+            }
+
+            retarget(function, pred, block, target);
+            copy_incoming(function, target, block, pred);
+            forget_pred(function, block, pred);
+            return true;
+        }
+    }
+
+    false
+}
+
+// Length of the longest common run of instructions ending both blocks, results of `b`
+// are mapped to the ones of `a` as the run is compared
+fn common_tail(function: &Function, a: Block, b: Block) -> (usize, HashMap<Value, Value>) {
+    let (a_insts, b_insts) = (&function.blocks[a.0].insts, &function.blocks[b.0].insts);
+    let longest = a_insts.len().min(b_insts.len());
+
+    'length: for length in (1..=longest).rev() {
+        let a_tail = &a_insts[a_insts.len() - length..];
+        let b_tail = &b_insts[b_insts.len() - length..];
+        let mut map = HashMap::new();
+
+        for (a_inst, b_inst) in a_tail.iter().zip(b_tail) {
+            let (a_data, b_data) = (&function.insts[a_inst.0], &function.insts[b_inst.0]);
+
+            if matches!(a_data.kind, InstKind::Phi { .. }) {
+                continue 'length;
+            }
+
+            let mut kind = b_data.kind.clone();
+
+            for operand in kind.operands_mut() {
+                if let Operand::Value(value) = operand {
+                    *value = map.get(value).copied().unwrap_or(*value);
+                }
+            }
+
+            if kind != a_data.kind {
+                continue 'length;
+            }
+
+            match (a_data.result, b_data.result) {
+                (Some(a_value), Some(b_value)) => {
+                    map.insert(b_value, a_value);
+                }
+                (None, None) => {}
+                _ => continue 'length,
+            }
+        }
+
+        return (length, map);
+    }
+
+    (0, HashMap::new())
+}
+
+// Shares the instructions ending two blocks that jump to the same place, or return.
+// When one of them is made of the tail alone the other jumps to it, otherwise the tail
+// moves to a block of its own
+fn merge_tails(function: &mut Function, cfg: &ControlFlowGraph) -> bool {
+    let entry = function.entry();
+    let layout = function.layout.clone();
+
+    for (index, a) in layout.iter().copied().enumerate() {
+        for b in layout[index + 1..].iter().copied() {
+            let succs = cfg.succs(a);
+
+            if succs.len() > 1 || succs != cfg.succs(b) || succs.contains(&a) || succs.contains(&b)
+            {
+                continue;
+            }
+
+            let (length, map) = common_tail(function, a, b);
+            let (a_len, b_len) = (
+                function.blocks[a.0].insts.len(),
+                function.blocks[b.0].insts.len(),
+            );
+
+            // A tail of a single jump or return is not worth one more jump, unless it is
+            // all there is in both blocks
+            if length == 0 || (length == 1 && (a_len, b_len) != (1, 1)) {
+                continue;
+            }
+
+            // The successor must see the same values coming from either block
+            let same_values = cfg.succs(a).iter().all(|succ| {
+                phis(function, *succ).into_iter().all(|phi| {
+                    let from_b = incoming(function, phi, b).map(|value| match value {
+                        Operand::Value(value) => {
+                            Operand::Value(map.get(value).copied().unwrap_or(*value))
+                        }
+                        constant => constant.clone(),
+                    });
+
+                    incoming(function, phi, a) == from_b.as_ref()
+                })
+            });
+
+            if !same_values {
+                continue;
+            }
+
+            // The block everything jumps to must not be the entry
+            let (shared, other) = match () {
+                _ if length == a_len && Some(a) != entry => (a, b),
+                _ if length == b_len && Some(b) != entry => (b, a),
+                _ => {
+                    let name = format!("{}.tail", function.blocks[a.0].name);
+                    let name = function.unique_block_name(&name);
+                    let tail = function.create_block(name);
+
+                    let moved = function.blocks[a.0].insts.split_off(a_len - length);
+                    function.blocks[tail.0].insts = moved;
+                    function.append_inst(a, InstKind::Jump { target: tail }, None);
+
+                    for succ in cfg.succs(a) {
+                        for phi in phis(function, *succ) {
+                            if let InstKind::Phi { incoming, .. } = &mut function.insts[phi.0].kind
+                            {
+                                for (_, from) in incoming.iter_mut() {
+                                    if *from == a {
+                                        *from = tail;
+                                    }
+                                }
+                            }
+                        }
+                    }
+
+                    let position = function
+                        .layout
+                        .iter()
+                        .position(|block| *block == a)
+                        .unwrap();
+                    function.layout.insert(position + 1, tail);
+
+                    (tail, b)
+                }
+            };
+
+            let other_len = function.blocks[other.0].insts.len();
+
+            for succ in cfg.succs(other) {
+                forget_pred(function, *succ, other);
+            }
+
+            if other_len == length && Some(other) != entry {
+                // The whole block is shared, its predecessors go to the other one
+                for pred in cfg.preds(other) {
+                    retarget(function, *pred, other, shared);
+                }
+
+                function.remove_block(other);
+            } else {
+                function.blocks[other.0].insts.truncate(other_len - length);
+                function.append_inst(other, InstKind::Jump { target: shared }, None);
+            }
+
+            return true;
+        }
+    }
+
+    false
+}
+
+// Makes one change, the control flow graph is stale afterwards
+fn simplify_one(function: &mut Function) -> bool {
+    if fold_branches(function) {
+        return true;
+    }
+
+    let cfg = ControlFlowGraph::new(function);
+
+    remove_unreachable(function, &cfg)
+        || merge_into_pred(function, &cfg)
+        || remove_forwarding(function, &cfg)
+        || thread_jumps(function, &cfg)
+        || merge_tails(function, &cfg)
+}
+
+// Control flow simplification: constant branches become jumps, unreachable blocks go
+// away, straight-line blocks are merged and empty ones skipped, jumps are threaded
+// through branches whose direction is known and identical tails are shared
+pub fn run(function: &mut Function, _: &mut AnalysisManager) -> bool {
+    let mut changed = false;
+
+    while simplify_one(function) {
+        changed = true;
+    }
+
+    changed
+}
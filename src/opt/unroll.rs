@@ -0,0 +1,706 @@
+use crate::analysis::induction::{inductions, loop_values, Induction};
+use crate::analysis::loops::LoopData;
+use crate::analysis::AnalysisManager;
+use crate::ir::cfg::ControlFlowGraph;
+use crate::ir::function::{Block, Function, Value, ValueDef};
+use crate::ir::inst::{BinaryOp, Flags, InstKind, IntCC, Operand};
+use crate::ir::types::Ty;
+use crate::opt::constfold::{build_folded, fold_inst};
+use crate::parser::types::Type;
+use std::collections::{HashMap, HashSet};
+
+// Largest number of instructions a loop may grow to when unrolled
+const UNROLLED_SIZE: usize = 64;
+
+// Iterations per trip of a partially unrolled loop, the first one that fits in the size
+const FACTORS: [u32; 2] = [4, 2];
+
+// A loop that only leaves from its latch, once its induction variable passes a bound
+struct Counted {
+    iv: Induction,
+    // The loop goes on while `next cond bound` holds, one of slt, ult, sgt, ugt or ne
+    cond: IntCC,
+    bound: Operand,
+    latch: Block,
+    exit: Block,
+}
+
+impl Counted {
+    fn constant(&self, value: i128) -> Operand {
+        let Ty::Int { bits, signed } = self.iv.ty else {
+            unreachable!("induction variables are integers");
+        };
+
+        Operand::Const(Type::int(bits, signed, value as u128))
+    }
+
+    fn binary(&self, op: BinaryOp, lhs: Operand, rhs: Operand) -> InstKind {
+        InstKind::Binary {
+            op,
+            flags: Flags::default(),
+            ty: self.iv.ty,
+            lhs,
+            rhs,
+        }
+    }
+
+    fn icmp(&self, cond: IntCC, lhs: Operand, rhs: Operand) -> InstKind {
+        InstKind::Icmp {
+            cond,
+            ty: self.iv.ty,
+            lhs,
+            rhs,
+        }
+    }
+
+    // Whether the loop runs more than once, the body always runs the first time
+    fn build_repeats(&self, function: &mut Function, block: Block) -> Operand {
+        let step = Operand::Const(self.iv.step.clone());
+        let first = self.binary(BinaryOp::Add, self.iv.start.clone(), step);
+        let first = build_folded(function, block, first);
+
+        build_folded(
+            function,
+            block,
+            self.icmp(self.cond, first, self.bound.clone()),
+        )
+    }
+
+    // Iterations of the loop when it runs more than once, the distance to the bound
+    // divided by the step and rounded up
+    fn build_trip_count(&self, function: &mut Function, block: Block) -> Operand {
+        let step = self.iv.step_value();
+        let (start, bound) = (self.iv.start.clone(), self.bound.clone());
+
+        let distance = match step > 0 {
+            true => self.binary(BinaryOp::Sub, bound, start),
+            false => self.binary(BinaryOp::Sub, start, bound),
+        };
+        let distance = build_folded(function, block, distance);
+
+        if step.unsigned_abs() == 1 {
+            return distance;
+        }
+
+        let before = self.binary(BinaryOp::Sub, distance, self.constant(1));
+        let before = build_folded(function, block, before);
+        let steps = self.binary(
+            BinaryOp::Udiv,
+            before,
+            self.constant(step.unsigned_abs() as i128),
+        );
+        let steps = build_folded(function, block, steps);
+
+        build_folded(
+            function,
+            block,
+            self.binary(BinaryOp::Add, steps, self.constant(1)),
+        )
+    }
+}
+
+// Recognizes a counted loop. The trip count is only computed when the variable cannot
+// wrap before reaching the bound, which a step of one or a no-wrap flag guarantee
+fn counted(
+    function: &Function,
+    cfg: &ControlFlowGraph,
+    data: &LoopData,
+    preheader: Block,
+    variant: &HashSet<Value>,
+) -> Option<Counted> {
+    let [latch] = data.latches[..] else {
+        return None;
+    };
+
+    let leaves = |block: &Block| cfg.succs(*block).iter().any(|succ| !data.contains(*succ));
+
+    if data
+        .blocks
+        .iter()
+        .any(|block| *block != latch && leaves(block))
+    {
+        return None;
+    }
+
+    let terminator = function.terminator(latch)?;
+
+    let InstKind::Branch {
+        cond: Operand::Value(cond),
+        then_dest,
+        else_dest,
+    } = &function.insts[terminator.0].kind
+    else {
+        return None;
+    };
+
+    let (stays, exit) = match (*then_dest == data.header, *else_dest == data.header) {
+        (true, false) => (true, *else_dest),
+        (false, true) => (false, *then_dest),
+        _ => return None,
+    };
+
+    let ValueDef::Inst(compare) = function.values[cond.0].def else {
+        return None;
+    };
+
+    let InstKind::Icmp { cond, lhs, rhs, .. } = &function.insts[compare.0].kind else {
+        return None;
+    };
+
+    for iv in inductions(function, data, preheader) {
+        if iv.ty.bits() > 64 {
+            continue;
+        }
+
+        let next = Operand::Value(iv.next);
+
+        let (cond, bound) = if *lhs == next {
+            (*cond, rhs)
+        } else if *rhs == next {
+            (cond.swapped(), lhs)
+        } else {
+            continue;
+        };
+
+        let cond = if stays { cond } else { cond.inverse() };
+
+        if let Operand::Value(value) = bound {
+            if variant.contains(value) {
+                continue;
+            }
+        }
+
+        let step = iv.step_value();
+
+        let valid = match cond {
+            IntCC::Slt => step > 0 && (step == 1 || iv.no_wrap(true)),
+            IntCC::Sgt => step < 0 && (step == -1 || iv.no_wrap(true)),
+            IntCC::Ult => step > 0 && (step == 1 || iv.no_wrap(false)),
+            IntCC::Ugt => step < 0 && (step == -1 || iv.no_wrap(false)),
+            // Any other step may jump over the bound
+            IntCC::Ne => step.unsigned_abs() == 1,
+            _ => false,
+        };
+
+        if valid {
+            return Some(Counted {
+                iv,
+                cond,
+                bound: bound.clone(),
+                latch,
+                exit,
+            });
+        }
+    }
+
+    None
+}
+
+// Whether the values of the loop are only used outside of it by the phis of the exit,
+// which is what LCSSA leaves
+fn is_closed(
+    function: &Function,
+    data: &LoopData,
+    counted: &Counted,
+    variant: &HashSet<Value>,
+) -> bool {
+    function
+        .layout
+        .iter()
+        .filter(|block| !data.contains(**block))
+        .flat_map(|block| {
+            function.blocks[block.0]
+                .insts
+                .iter()
+                .map(move |inst| (*block, *inst))
+        })
+        .all(|(block, inst)| match &function.insts[inst.0].kind {
+            InstKind::Phi { incoming, .. } if block == counted.exit => {
+                incoming.iter().all(|(operand, pred)| {
+                    *pred == counted.latch
+                        || !matches!(operand, Operand::Value(v) if variant.contains(v))
+                })
+            }
+            kind => kind
+                .operands()
+                .iter()
+                .all(|operand| !matches!(operand, Operand::Value(v) if variant.contains(v))),
+        })
+}
+
+fn size(function: &Function, data: &LoopData) -> usize {
+    data.blocks
+        .iter()
+        .map(|block| function.blocks[block.0].insts.len())
+        .sum()
+}
+
+fn header_phis(function: &Function, header: Block) -> Vec<(Value, Vec<(Operand, Block)>)> {
+    function.blocks[header.0]
+        .insts
+        .iter()
+        .filter_map(|inst| {
+            let data = &function.insts[inst.0];
+
+            match &data.kind {
+                InstKind::Phi { incoming, .. } => Some((data.result?, incoming.clone())),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+fn map(values: &HashMap<Value, Operand>, operand: &Operand) -> Operand {
+    match operand {
+        Operand::Value(value) => values.get(value).cloned().unwrap_or(operand.clone()),
+        Operand::Const(_) => operand.clone(),
+    }
+}
+
+fn set_jump(function: &mut Function, block: Block, target: Block) {
+    let terminator = function.terminator(block).unwrap();
+    function.insts[terminator.0].kind = InstKind::Jump { target };
+}
+
+// Copies the blocks of the loop for one iteration. `values` gives what the header phis
+// hold in it and gets the copies of the values defined by the loop
+fn clone_iteration(
+    function: &mut Function,
+    data: &LoopData,
+    values: &mut HashMap<Value, Operand>,
+    suffix: &str,
+) -> HashMap<Block, Block> {
+    let mut blocks = HashMap::new();
+
+    for block in &data.blocks {
+        let name = format!("{}.{}", function.blocks[block.0].name, suffix);
+        let name = function.unique_block_name(&name);
+        blocks.insert(*block, function.create_block(name));
+    }
+
+    let copied: Vec<_> = data
+        .blocks
+        .iter()
+        .flat_map(|block| {
+            function.blocks[block.0]
+                .insts
+                .iter()
+                .map(move |inst| (*block, *inst))
+        })
+        .filter(|(block, inst)| {
+            *block != data.header || !matches!(function.insts[inst.0].kind, InstKind::Phi { .. })
+        })
+        .collect();
+
+    // Blocks are in reverse postorder and only the header phis take values from later
+    // iterations, so every operand is copied before it is used
+    for (block, inst) in copied {
+        let mut kind = function.insts[inst.0].kind.clone();
+
+        for operand in kind.operands_mut() {
+            *operand = map(values, operand);
+        }
+
+        for target in kind.successors_mut() {
+            *target = blocks.get(target).copied().unwrap_or(*target);
+        }
+
+        if let InstKind::Phi { incoming, .. } = &mut kind {
+            for (_, pred) in incoming.iter_mut() {
+                *pred = blocks.get(pred).copied().unwrap_or(*pred);
+            }
+        }
+
+        let Some(result) = function.insts[inst.0].result else {
+            function.append_inst(blocks[&block], kind, None);
+            continue;
+        };
+
+        // The header phis of the first iteration are often constants
+        if let Some(constant) = fold_inst(&kind) {
+            values.insert(result, Operand::Const(constant));
+            continue;
+        }
+
+        let value = &function.values[result.0];
+        let name = value
+            .name
+            .clone()
+            .map(|name| function.unique_value_name(&format!("{}.{}", name, suffix)));
+        let copy = function.add_value(value.ty, name);
+
+        function.append_inst(blocks[&block], kind, Some(copy));
+        values.insert(result, Operand::Value(copy));
+    }
+
+    blocks
+}
+
+// What the header phis hold in the iteration after the one `values` describes
+fn next_iteration(
+    phis: &[(Value, Vec<(Operand, Block)>)],
+    latch: Block,
+    values: &HashMap<Value, Operand>,
+) -> HashMap<Value, Operand> {
+    phis.iter()
+        .map(|(phi, incoming)| {
+            let (operand, _) = incoming.iter().find(|(_, pred)| *pred == latch).unwrap();
+            (*phi, map(values, operand))
+        })
+        .collect()
+}
+
+// Makes the exit phis take the values of the last iteration from `from`
+fn add_exit_edge(
+    function: &mut Function,
+    counted: &Counted,
+    values: &HashMap<Value, Operand>,
+    from: Block,
+    keep_latch: bool,
+) {
+    for inst in function.blocks[counted.exit.0].insts.clone() {
+        if let InstKind::Phi { incoming, .. } = &mut function.insts[inst.0].kind {
+            let Some(position) = incoming.iter().position(|(_, pred)| *pred == counted.latch)
+            else {
+                continue;
+            };
+
+            let operand = map(values, &incoming[position].0);
+
+            if keep_latch {
+                incoming.push((operand, from));
+            } else {
+                incoming[position] = (operand, from);
+            }
+        }
+    }
+}
+
+fn replace_loop(function: &mut Function, data: &LoopData, blocks: Vec<Block>) {
+    let position = function
+        .layout
+        .iter()
+        .position(|block| *block == data.header)
+        .unwrap();
+
+    function.layout.splice(position..position, blocks);
+
+    for block in &data.blocks {
+        function.remove_block(*block);
+    }
+}
+
+fn layout_order(function: &Function, data: &LoopData) -> Vec<Block> {
+    function
+        .layout
+        .iter()
+        .copied()
+        .filter(|block| data.contains(*block))
+        .collect()
+}
+
+// Replaces the loop with one copy of its body per iteration
+fn unroll_fully(
+    function: &mut Function,
+    data: &LoopData,
+    preheader: Block,
+    counted: &Counted,
+    trips: usize,
+) {
+    let phis = header_phis(function, data.header);
+    let order = layout_order(function, data);
+
+    let mut values: HashMap<Value, Operand> = phis
+        .iter()
+        .map(|(phi, incoming)| {
+            let (operand, _) = incoming
+                .iter()
+                .find(|(_, pred)| *pred == preheader)
+                .unwrap();
+            (*phi, operand.clone())
+        })
+        .collect();
+
+    let mut previous = preheader;
+    let mut placed = Vec::new();
+
+    for trip in 0..trips {
+        let blocks = clone_iteration(function, data, &mut values, &format!("unroll{}", trip));
+
+        set_jump(function, previous, blocks[&data.header]);
+        placed.extend(order.iter().map(|block| blocks[block]));
+        previous = blocks[&counted.latch];
+
+        if trip + 1 < trips {
+            values = next_iteration(&phis, counted.latch, &values);
+        }
+    }
+
+    set_jump(function, previous, counted.exit);
+    add_exit_edge(function, counted, &values, previous, false);
+    replace_loop(function, data, placed);
+}
+
+// Runs `factor` iterations per trip while enough are left and the original loop for the
+// rest:
+//
+//   preheader:   br repeats, trips, remainder
+//   trips:       trip count, br (count / factor) != 0, unrolled, remainder
+//   unrolled:    `factor` copies of the body, counting the trips down
+//   check:       br (count % factor) != 0, remainder, exit
+//   remainder:   the phis of the values so far, into the original loop
+fn unroll_partially(
+    function: &mut Function,
+    data: &LoopData,
+    preheader: Block,
+    counted: &Counted,
+    factor: u32,
+) {
+    let header = data.header;
+    let header_name = function.blocks[header.0].name.clone();
+    let ty = counted.iv.ty;
+    let phis = header_phis(function, header);
+    let order = layout_order(function, data);
+
+    let new_block = |function: &mut Function, name: &str, target: Block| {
+        let name = function.unique_block_name(&format!("{}.{}", header_name, name));
+        let block = function.create_block(name);
+        function.append_inst(block, InstKind::Jump { target }, None);
+        block
+    };
+
+    let trips = new_block(function, "trips", header);
+    let check = new_block(function, "check", header);
+    let remainder = new_block(function, "remainder", header);
+
+    // Only loops running more than once are worth counting
+    let repeats = counted.build_repeats(function, preheader);
+    let terminator = function.terminator(preheader).unwrap();
+    function.insts[terminator.0].kind = InstKind::Branch {
+        cond: repeats,
+        then_dest: trips,
+        else_dest: remainder,
+    };
+
+    let count = counted.build_trip_count(function, trips);
+    let shift = counted.constant(factor.trailing_zeros() as i128);
+    let unrolled_trips = counted.binary(BinaryOp::Lshr, count.clone(), shift);
+    let unrolled_trips = build_folded(function, trips, unrolled_trips);
+    let left = counted.binary(BinaryOp::And, count, counted.constant(factor as i128 - 1));
+    let left = build_folded(function, trips, left);
+
+    // The copies, the header phis of the first one are the only phis left
+    let starts: Vec<Operand> = phis
+        .iter()
+        .map(|(_, incoming)| {
+            let (operand, _) = incoming
+                .iter()
+                .find(|(_, pred)| *pred == preheader)
+                .unwrap();
+            operand.clone()
+        })
+        .collect();
+
+    let first_phis: Vec<Value> = phis
+        .iter()
+        .map(|(phi, _)| {
+            let name = function.values[phi.0]
+                .name
+                .clone()
+                .map(|name| function.unique_value_name(&format!("{}.unrolled", name)));
+            function.add_value(function.values[phi.0].ty, name)
+        })
+        .collect();
+
+    let mut values: HashMap<Value, Operand> = phis
+        .iter()
+        .zip(&first_phis)
+        .map(|((phi, _), first)| (*phi, Operand::Value(*first)))
+        .collect();
+
+    let mut first_header = header;
+    let mut previous = trips;
+    let mut placed = vec![trips];
+
+    for copy in 0..factor {
+        let blocks = clone_iteration(function, data, &mut values, &format!("unroll{}", copy));
+
+        if copy == 0 {
+            first_header = blocks[&header];
+        } else {
+            set_jump(function, previous, blocks[&header]);
+        }
+
+        placed.extend(order.iter().map(|block| blocks[block]));
+        previous = blocks[&counted.latch];
+        values = match copy + 1 < factor {
+            true => next_iteration(&phis, counted.latch, &values),
+            false => values,
+        };
+    }
+
+    let last = previous;
+    let after = next_iteration(&phis, counted.latch, &values);
+
+    let counter = function.add_value(ty, None);
+    let decrement = counted.binary(BinaryOp::Sub, Operand::Value(counter), counted.constant(1));
+    let decremented = build_folded(function, last, decrement);
+    let more = counted.icmp(IntCC::Ne, decremented.clone(), counted.constant(0));
+    let more = build_folded(function, last, more);
+
+    let terminator = function.terminator(last).unwrap();
+    function.insts[terminator.0].kind = InstKind::Branch {
+        cond: more,
+        then_dest: first_header,
+        else_dest: check,
+    };
+
+    let first = function.blocks[first_header.0].insts[0];
+    let counter_phi = InstKind::Phi {
+        ty,
+        incoming: vec![(unrolled_trips.clone(), trips), (decremented, last)],
+    };
+    function.insert_inst(first_header, first, counter_phi, Some(counter));
+
+    for (((phi, _), start), value) in phis.iter().zip(&starts).zip(&first_phis) {
+        let kind = InstKind::Phi {
+            ty: function.values[phi.0].ty,
+            incoming: vec![(start.clone(), trips), (after[phi].clone(), last)],
+        };
+        function.insert_inst(first_header, first, kind, Some(*value));
+    }
+
+    let has_unrolled = counted.icmp(IntCC::Ne, unrolled_trips, counted.constant(0));
+    let has_unrolled = build_folded(function, trips, has_unrolled);
+    let terminator = function.terminator(trips).unwrap();
+    function.insts[terminator.0].kind = InstKind::Branch {
+        cond: has_unrolled,
+        then_dest: first_header,
+        else_dest: remainder,
+    };
+
+    // Straight to the exit when no iteration is left
+    let has_left = counted.icmp(IntCC::Ne, left, counted.constant(0));
+    let has_left = build_folded(function, check, has_left);
+    let terminator = function.terminator(check).unwrap();
+    function.insts[terminator.0].kind = InstKind::Branch {
+        cond: has_left,
+        then_dest: remainder,
+        else_dest: counted.exit,
+    };
+
+    add_exit_edge(function, counted, &values, check, true);
+
+    // The original loop continues from where the copies left it
+    for ((phi, _), start) in phis.iter().zip(&starts) {
+        let ty = function.values[phi.0].ty;
+        let value = function.add_value(ty, None);
+        let kind = InstKind::Phi {
+            ty,
+            incoming: vec![
+                (start.clone(), preheader),
+                (start.clone(), trips),
+                (after[phi].clone(), check),
+            ],
+        };
+        let first = function.blocks[remainder.0].insts[0];
+        function.insert_inst(remainder, first, kind, Some(value));
+
+        let ValueDef::Inst(inst) = function.values[phi.0].def else {
+            unreachable!("header phis are instructions");
+        };
+
+        if let InstKind::Phi { incoming, .. } = &mut function.insts[inst.0].kind {
+            for (operand, pred) in incoming.iter_mut() {
+                if *pred == preheader {
+                    *operand = Operand::Value(value);
+                    *pred = remainder;
+                }
+            }
+        }
+    }
+
+    placed.extend([check, remainder]);
+
+    let position = function
+        .layout
+        .iter()
+        .position(|block| *block == header)
+        .unwrap();
+    function.layout.splice(position..position, placed);
+}
+
+// Unrolls the innermost counted loops, fully when the trip count is a small constant and
+// by a factor with a remainder loop otherwise. Needs loop-simplify and LCSSA form
+pub fn run(function: &mut Function, analyses: &mut AnalysisManager) -> bool {
+    let cfg = analyses.cfg(function);
+    let loops = analyses.loops(function);
+    let mut changed = false;
+
+    for lp in loops.loops() {
+        let data = loops.data(lp);
+
+        let Some(preheader) = loops.preheader(lp, &cfg) else {
+            continue;
+        };
+
+        if !data.children.is_empty() {
+            continue;
+        }
+
+        let variant = loop_values(function, data);
+
+        let Some(counted) = counted(function, &cfg, data, preheader, &variant) else {
+            continue;
+        };
+
+        if !is_closed(function, data, &counted, &variant) {
+            continue;
+        }
+
+        let size = size(function, data);
+
+        // Known iterations when the loop starts and ends at constants, everything folds
+        // so nothing is added to the preheader. Zero stands for the whole range of the type
+        let known = matches!(
+            (&counted.iv.start, &counted.bound),
+            (Operand::Const(_), Operand::Const(_))
+        );
+
+        let trips = match known {
+            true => match counted.build_repeats(function, preheader) {
+                Operand::Const(repeats) if repeats.is_zero() => Some(1),
+                _ => match counted.build_trip_count(function, preheader) {
+                    Operand::Const(count) => count.as_u128(),
+                    Operand::Value(_) => None,
+                },
+            },
+            false => None,
+        };
+
+        let limit = (UNROLLED_SIZE / size) as u128;
+
+        if let Some(trips) = trips.filter(|trips| (1..=limit).contains(trips)) {
+            unroll_fully(function, data, preheader, &counted, trips as usize);
+            changed = true;
+            continue;
+        }
+
+        let factor = FACTORS
+            .iter()
+            .copied()
+            .find(|factor| *factor as usize * size <= UNROLLED_SIZE);
+
+        // A known trip count smaller than the factor would never run the copies
+        let Some(factor) =
+            factor.filter(|factor| trips.is_none_or(|trips| trips >= *factor as u128))
+        else {
+            continue;
+        };
+
+        unroll_partially(function, data, preheader, &counted, factor);
+        changed = true;
+    }
+
+    changed
+}
@@ -53,26 +53,9 @@ pub enum AstNode {
     Nop,
 }
 
+// The assembly-like syntax has no parser yet, so it produces no functions
 pub fn get_from_tokens(
-    tokens: Vec<Token>,
+    _tokens: Vec<Token>,
 ) -> Result<(Vec<AstNode>, HashMap<String, u64>), AstError> {
-    #[derive(Debug)]
-    struct CurrentFunction {
-        name: String,
-        body: Vec<AstNode>,
-        pc: u64,
-    }
-
-    let mut stype = SymbolType::Private;
-    let mut functions = Vec::new();
-    let mut current_function: Option<CurrentFunction> = None;
-    let mut current_pc = 0x0;
-    // Contains the PC addr of all functions
-    let mut functions_hashmap = HashMap::new();
-
-    if current_function.is_some() {
-        return Err(AstError::FnNotClosed);
-    }
-
-    Ok((functions, functions_hashmap))
+    Ok((Vec::new(), HashMap::new()))
 }
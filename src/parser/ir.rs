@@ -0,0 +1,658 @@
+use super::token::Token;
+use crate::binary::symbol::SymbolType;
+use crate::ir::function::{Block, Function, Value};
+use crate::ir::inst::{Flags, InstKind, Operand};
+use crate::ir::types::Ty;
+use crate::ir::Module;
+use crate::parser::types::{f64_to_f16, Type};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum IrParseError {
+    #[error("Unexpected token {0:?}")]
+    UnexpectedToken(Token),
+
+    #[error("Unexpected end of input")]
+    UnexpectedEnd,
+
+    #[error("{0}")]
+    UnknownName(String),
+
+    #[error("{0} is not a valid constant of type {1}")]
+    InvalidConstant(String, Ty),
+}
+
+// Reads the textual form of the IR (.tir files) into a Module
+pub fn get_module(tokens: Vec<Token>) -> Result<Module, IrParseError> {
+    let mut reader = Reader { tokens, pos: 0 };
+    let mut module = Module::default();
+
+    while reader.peek().is_some() {
+        module.functions.push(reader.function()?);
+    }
+
+    Ok(module)
+}
+
+fn is_integer(number: &str) -> bool {
+    let digits = number.strip_prefix('-').unwrap_or(number);
+
+    !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit())
+}
+
+// Bits of the mantissa and of the exponent of a float type
+fn float_format(ty: Ty) -> Option<(u32, u32)> {
+    match ty {
+        Ty::F16 => Some((10, 5)),
+        Ty::F32 => Some((23, 8)),
+        Ty::F64 => Some((52, 11)),
+        _ => None,
+    }
+}
+
+// Rounds mantissa * 2^exponent to the nearest float of the format, ties to even. `sticky`
+// tells that bits below the mantissa were dropped, which then must be wider than the
+// format. Too large values give infinity
+fn round_float(
+    negative: bool,
+    mantissa: u128,
+    exponent: i64,
+    sticky: bool,
+    (mantissa_bits, exponent_bits): (u32, u32),
+) -> u64 {
+    let sign = (negative as u64) << (mantissa_bits + exponent_bits);
+    let infinity = ((1 << exponent_bits) - 1) << mantissa_bits;
+
+    if mantissa == 0 {
+        return sign;
+    }
+
+    let bias = (1 << (exponent_bits - 1)) - 1;
+    let leading = exponent + 127 - mantissa.leading_zeros() as i64;
+    // Subnormals keep the exponent of the smallest normal
+    let scale = leading.max(1 - bias);
+    let shift = scale - mantissa_bits as i64 - exponent;
+
+    let rounded = if shift <= 0 {
+        // Exact, the value is a multiple of the last bit of the format
+        mantissa << -shift
+    } else if shift > 128 {
+        0
+    } else {
+        let quotient = mantissa.checked_shr(shift as u32).unwrap_or(0);
+        let remainder = mantissa - quotient.checked_shl(shift as u32).unwrap_or(0);
+        let half = 1 << (shift - 1);
+
+        let up = remainder > half || (remainder == half && (sticky || quotient & 1 == 1));
+        quotient + up as u128
+    };
+
+    // A carry out of the mantissa moves to the next exponent
+    let bits = (((scale + bias - 1) as u128) << mantissa_bits) + rounded;
+
+    if bits >= infinity as u128 {
+        sign | infinity
+    } else {
+        sign | bits as u64
+    }
+}
+
+// Hexadecimal floats like 0x1.8p3, the exponent is a power of two. The digits are rounded
+// once to the format, literals that overflow or round to zero are rejected
+fn hex_float(number: &str, format: (u32, u32)) -> Option<u64> {
+    let (negative, number) = match number.strip_prefix('-') {
+        Some(number) => (true, number),
+        None => (false, number),
+    };
+    let (significand, exponent) = number.strip_prefix("0x")?.split_once(['p', 'P'])?;
+    let (integer, fraction) = significand.split_once('.').unwrap_or((significand, ""));
+
+    // Beyond any exponent of the formats, so the sum below can not overflow
+    let mut exponent = exponent.parse::<i64>().ok()?.clamp(-100_000, 100_000);
+    let mut mantissa: u128 = 0;
+    let mut sticky = false;
+
+    if integer.is_empty() && fraction.is_empty() {
+        return None;
+    }
+
+    for (index, digit) in integer.chars().chain(fraction.chars()).enumerate() {
+        let digit = digit.to_digit(16)?;
+
+        if index >= integer.len() {
+            exponent -= 4;
+        }
+
+        // Digits past the width of the mantissa only matter for rounding
+        if mantissa >> 120 == 0 {
+            mantissa = mantissa << 4 | digit as u128;
+        } else {
+            exponent += 4;
+            sticky |= digit != 0;
+        }
+    }
+
+    let bits = round_float(negative, mantissa, exponent, sticky, format);
+    let magnitude = bits & !(1 << (format.0 + format.1));
+
+    if magnitude == ((1 << format.1) - 1) << format.0 || (magnitude == 0 && mantissa != 0) {
+        return None;
+    }
+
+    Some(bits)
+}
+
+// Significant digits without leading or trailing zeros and the power of ten of the first
+// one, None for anything but a plain decimal
+fn decimal_digits(number: &str) -> Option<(String, i64)> {
+    let number = number.strip_prefix('-').unwrap_or(number);
+    let (significand, exponent) = match number.split_once(['e', 'E']) {
+        Some((significand, exponent)) => (significand, exponent.parse::<i64>().ok()?),
+        None => (number, 0),
+    };
+    let (integer, fraction) = significand.split_once('.').unwrap_or((significand, ""));
+    let digits = format!("{}{}", integer, fraction);
+
+    if !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let zeros = digits.len() - digits.trim_start_matches('0').len();
+    let digits = digits.trim_matches('0').to_string();
+
+    Some((digits, integer.len() as i64 - 1 - zeros as i64 + exponent))
+}
+
+// Compares the magnitude of a decimal literal with the exact value of a float
+fn compare_decimal(number: &str, value: f64) -> Ordering {
+    // Every f64 has an exact decimal expansion of at most 767 significant digits
+    let exact = format!("{:.800e}", value.abs());
+
+    let (Some(literal), Some(exact)) = (decimal_digits(number), decimal_digits(&exact)) else {
+        return Ordering::Equal;
+    };
+
+    match (literal.0.is_empty(), exact.0.is_empty()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Less,
+        (false, true) => Ordering::Greater,
+        (false, false) => literal.1.cmp(&exact.1).then(literal.0.cmp(&exact.0)),
+    }
+}
+
+// Decimal half literal rounded once. The nearest double only rounds the wrong way when it
+// lands on a midpoint of halves, so the side of it the literal is on breaks the tie
+fn half_constant(number: &str) -> Option<u16> {
+    let value = number.parse::<f64>().ok()?;
+
+    if !value.is_finite() {
+        return Some(f64_to_f16(value));
+    }
+
+    let bits = value.to_bits();
+    let field = (bits >> 52 & 0x7FF) as i64;
+    let fraction = (bits & ((1 << 52) - 1)) as u128;
+    let (mantissa, exponent) = match field {
+        0 => (fraction, -1074),
+        _ => (fraction | 1 << 52, field - 1075),
+    };
+
+    // A quarter of the last bit of the double on the side of the literal
+    let mantissa = match compare_decimal(number, value) {
+        Ordering::Less => (mantissa << 2) - 1,
+        Ordering::Equal => mantissa << 2,
+        Ordering::Greater => (mantissa << 2) + 1,
+    };
+
+    let half = round_float(
+        value.is_sign_negative(),
+        mantissa,
+        exponent - 2,
+        false,
+        (10, 5),
+    );
+
+    Some(half as u16)
+}
+
+// Floats are written in decimal, as hexadecimal floats or as the raw bits of the type (0x3F800000)
+fn float_constant(number: &str, ty: Ty) -> Option<Type> {
+    let format = float_format(ty)?;
+
+    let bits = if number.contains(['p', 'P']) {
+        hex_float(number, format)?
+    } else if let Some(bits) = number.strip_prefix("0x") {
+        u64::from_str_radix(bits, 16).ok()?
+    } else {
+        // The standard parsers round once, halves need their own
+        match ty {
+            Ty::F16 => half_constant(number)?.into(),
+            Ty::F32 => number.parse::<f32>().ok()?.to_bits().into(),
+            _ => number.parse::<f64>().ok()?.to_bits(),
+        }
+    };
+
+    match ty {
+        Ty::F16 => u16::try_from(bits).ok().map(Type::F16),
+        Ty::F32 => u32::try_from(bits).ok().map(Type::F32),
+        _ => Some(Type::F64(bits)),
+    }
+}
+
+struct Reader {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+// Names of the function being read, values and blocks can be used before being defined
+#[derive(Default)]
+struct Scope {
+    values: HashMap<String, Value>,
+    blocks: HashMap<String, Block>,
+}
+
+impl Scope {
+    fn value(&mut self, function: &mut Function, name: &str, ty: Ty) -> Value {
+        let name = name.trim_start_matches('%');
+
+        *self
+            .values
+            .entry(name.to_string())
+            .or_insert_with(|| function.add_value(ty, Some(name.to_string())))
+    }
+
+    fn block(&mut self, function: &mut Function, name: &str) -> Block {
+        let name = name.trim_start_matches('%');
+
+        *self
+            .blocks
+            .entry(name.to_string())
+            .or_insert_with(|| function.create_block(name.to_string()))
+    }
+}
+
+impl Reader {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Result<Token, IrParseError> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or(IrParseError::UnexpectedEnd)?;
+        self.pos += 1;
+
+        Ok(token)
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), IrParseError> {
+        let token = self.next()?;
+
+        if token != expected {
+            return Err(IrParseError::UnexpectedToken(token));
+        }
+
+        Ok(())
+    }
+
+    fn eat(&mut self, expected: Token) -> bool {
+        if self.peek() == Some(&expected) {
+            self.pos += 1;
+            return true;
+        }
+
+        false
+    }
+
+    fn identifier(&mut self) -> Result<String, IrParseError> {
+        match self.next()? {
+            Token::Identifier(name) => Ok(name),
+            token => Err(IrParseError::UnexpectedToken(token)),
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str) -> Result<(), IrParseError> {
+        match self.next()? {
+            Token::Identifier(name) if name == keyword => Ok(()),
+            token => Err(IrParseError::UnexpectedToken(token)),
+        }
+    }
+
+    fn ty(&mut self) -> Result<Ty, IrParseError> {
+        self.identifier()?
+            .parse()
+            .map_err(IrParseError::UnknownName)
+    }
+
+    fn local(&mut self) -> Result<String, IrParseError> {
+        match self.next()? {
+            Token::LocalEntity(name) => Ok(name),
+            token => Err(IrParseError::UnexpectedToken(token)),
+        }
+    }
+
+    fn global(&mut self) -> Result<String, IrParseError> {
+        match self.next()? {
+            Token::GlobalEntity(name) => Ok(name.trim_start_matches('@').to_string()),
+            token => Err(IrParseError::UnexpectedToken(token)),
+        }
+    }
+
+    fn function(&mut self) -> Result<Function, IrParseError> {
+        self.expect(Token::Define)?;
+
+        let mut linkage = SymbolType::Global;
+        let mut ret = self.identifier()?;
+
+        if ret == "private" || ret == "internal" {
+            linkage = SymbolType::Private;
+            ret = self.identifier()?;
+        }
+
+        let ret = ret.parse().map_err(IrParseError::UnknownName)?;
+        let name = self.global()?;
+        let mut function = Function::new(name, linkage, ret);
+        let mut scope = Scope::default();
+
+        self.expect(Token::ParenthesesStart)?;
+
+        while !self.eat(Token::ParenthesesEnd) {
+            if !function.params.is_empty() {
+                self.expect(Token::Comma)?;
+            }
+
+            let ty = self.ty()?;
+            let name = self.local()?;
+            let name = name.trim_start_matches('%').to_string();
+            let param = function.add_param(ty, Some(name.clone()));
+
+            scope.values.insert(name, param);
+        }
+
+        while !self.eat(Token::CurlyBracketStart) {
+            let attribute = self.identifier()?;
+
+            if !function.attributes.set(&attribute) {
+                return Err(IrParseError::UnknownName(format!(
+                    "Unknown attribute: {}",
+                    attribute
+                )));
+            }
+        }
+
+        let mut current = None;
+
+        while !self.eat(Token::CurlyBracketEnd) {
+            if let Some(Token::Label(label)) = self.peek() {
+                let label = label.clone();
+                let block = scope.block(&mut function, &label);
+
+                self.pos += 1;
+                function.append_block(block);
+                current = Some(block);
+                continue;
+            }
+
+            let block = match current {
+                Some(block) => block,
+                None => {
+                    let block = scope.block(&mut function, "entry");
+                    function.append_block(block);
+                    current = Some(block);
+                    block
+                }
+            };
+
+            self.inst(&mut function, &mut scope, block)?;
+        }
+
+        Ok(function)
+    }
+
+    fn operand(
+        &mut self,
+        function: &mut Function,
+        scope: &mut Scope,
+        ty: Ty,
+    ) -> Result<Operand, IrParseError> {
+        match self.next()? {
+            Token::LocalEntity(name) => Ok(Operand::Value(scope.value(function, &name, ty))),
+            Token::Identifier(name) if ty == Ty::I1 && (name == "true" || name == "false") => {
+                Ok(Operand::Const(Type::bool(name == "true")))
+            }
+            Token::Number(number) if ty.is_float() => float_constant(&number, ty)
+                .map(Operand::Const)
+                .ok_or(IrParseError::InvalidConstant(number, ty)),
+            Token::Number(number) if ty.is_integer() && is_integer(&number) => {
+                Type::parse(&ty.to_string(), &number)
+                    .map(Operand::Const)
+                    .map_err(|_| IrParseError::InvalidConstant(number, ty))
+            }
+            Token::Number(number) => Err(IrParseError::InvalidConstant(number, ty)),
+            token => Err(IrParseError::UnexpectedToken(token)),
+        }
+    }
+
+    fn label(&mut self, function: &mut Function, scope: &mut Scope) -> Result<Block, IrParseError> {
+        self.keyword("label")?;
+        let name = self.local()?;
+
+        Ok(scope.block(function, &name))
+    }
+
+    fn inst(
+        &mut self,
+        function: &mut Function,
+        scope: &mut Scope,
+        block: Block,
+    ) -> Result<(), IrParseError> {
+        let mut result = None;
+
+        if let Some(Token::LocalEntity(name)) = self.peek() {
+            result = Some(name.clone());
+            self.pos += 1;
+            self.expect(Token::Equal)?;
+        }
+
+        let mut opcode = self.identifier()?;
+        let musttail = opcode == "musttail";
+
+        if musttail {
+            opcode = self.identifier()?;
+
+            if opcode != "call" {
+                return Err(IrParseError::UnexpectedToken(Token::Identifier(opcode)));
+            }
+        }
+
+        let kind = match opcode.as_str() {
+            "icmp" => {
+                let cond = self
+                    .identifier()?
+                    .parse()
+                    .map_err(IrParseError::UnknownName)?;
+                let ty = self.ty()?;
+                let lhs = self.operand(function, scope, ty)?;
+                self.expect(Token::Comma)?;
+                let rhs = self.operand(function, scope, ty)?;
+
+                InstKind::Icmp { cond, ty, lhs, rhs }
+            }
+            "fcmp" => {
+                let cond = self
+                    .identifier()?
+                    .parse()
+                    .map_err(IrParseError::UnknownName)?;
+                let ty = self.ty()?;
+                let lhs = self.operand(function, scope, ty)?;
+                self.expect(Token::Comma)?;
+                let rhs = self.operand(function, scope, ty)?;
+
+                InstKind::Fcmp { cond, ty, lhs, rhs }
+            }
+            "fptosi" | "sitofp" | "trunc" | "zext" | "sext" => {
+                let op = opcode.parse().map_err(IrParseError::UnknownName)?;
+                let from = self.ty()?;
+                let value = self.operand(function, scope, from)?;
+                self.keyword("to")?;
+                let to = self.ty()?;
+
+                InstKind::Cast {
+                    op,
+                    from,
+                    value,
+                    to,
+                }
+            }
+            "alloca" => InstKind::Alloca { ty: self.ty()? },
+            "load" => {
+                let ty = self.ty()?;
+                self.expect(Token::Comma)?;
+                self.keyword("ptr")?;
+                let ptr = self.operand(function, scope, Ty::Ptr)?;
+
+                InstKind::Load { ty, ptr }
+            }
+            "store" => {
+                let ty = self.ty()?;
+                let value = self.operand(function, scope, ty)?;
+                self.expect(Token::Comma)?;
+                self.keyword("ptr")?;
+                let ptr = self.operand(function, scope, Ty::Ptr)?;
+
+                InstKind::Store { ty, value, ptr }
+            }
+            "ptradd" => {
+                self.keyword("ptr")?;
+                let ptr = self.operand(function, scope, Ty::Ptr)?;
+                self.expect(Token::Comma)?;
+                self.keyword("i64")?;
+                let offset = self.operand(function, scope, Ty::I64)?;
+
+                InstKind::PtrAdd { ptr, offset }
+            }
+            "call" => {
+                let ret = self.ty()?;
+                let callee = self.global()?;
+                let mut args = Vec::new();
+
+                self.expect(Token::ParenthesesStart)?;
+
+                while !self.eat(Token::ParenthesesEnd) {
+                    if !args.is_empty() {
+                        self.expect(Token::Comma)?;
+                    }
+
+                    let ty = self.ty()?;
+                    args.push((ty, self.operand(function, scope, ty)?));
+                }
+
+                InstKind::Call {
+                    callee,
+                    ret,
+                    args,
+                    musttail,
+                }
+            }
+            "phi" => {
+                let ty = self.ty()?;
+                let mut incoming = Vec::new();
+
+                loop {
+                    self.expect(Token::SquareBracketStart)?;
+                    let value = self.operand(function, scope, ty)?;
+                    self.expect(Token::Comma)?;
+                    let pred = self.local()?;
+                    self.expect(Token::SquareBracketEnd)?;
+
+                    incoming.push((value, scope.block(function, &pred)));
+
+                    if !self.eat(Token::Comma) {
+                        break;
+                    }
+                }
+
+                InstKind::Phi { ty, incoming }
+            }
+            "syscall" => {
+                let ty = self.ty()?;
+                let mut args = vec![self.operand(function, scope, ty)?];
+
+                while self.eat(Token::Comma) {
+                    args.push(self.operand(function, scope, ty)?);
+                }
+
+                InstKind::Syscall { args }
+            }
+            "br" => {
+                if let Some(Token::Identifier(label)) = self.peek() {
+                    if label == "label" {
+                        let target = self.label(function, scope)?;
+
+                        function.append_inst(block, InstKind::Jump { target }, None);
+                        return Ok(());
+                    }
+                }
+
+                self.keyword("i1")?;
+                let cond = self.operand(function, scope, Ty::I1)?;
+                self.expect(Token::Comma)?;
+                let then_dest = self.label(function, scope)?;
+                self.expect(Token::Comma)?;
+                let else_dest = self.label(function, scope)?;
+
+                InstKind::Branch {
+                    cond,
+                    then_dest,
+                    else_dest,
+                }
+            }
+            "ret" => {
+                let ty = self.ty()?;
+                let value = if ty == Ty::Void {
+                    None
+                } else {
+                    Some(self.operand(function, scope, ty)?)
+                };
+
+                InstKind::Ret { ty, value }
+            }
+            op => {
+                let op = op.parse().map_err(IrParseError::UnknownName)?;
+                let mut flags = Flags::default();
+
+                loop {
+                    match self.peek() {
+                        Some(Token::Identifier(flag)) if flag == "nsw" => flags.nsw = true,
+                        Some(Token::Identifier(flag)) if flag == "nuw" => flags.nuw = true,
+                        _ => break,
+                    }
+
+                    self.pos += 1;
+                }
+
+                let ty = self.ty()?;
+                let lhs = self.operand(function, scope, ty)?;
+                self.expect(Token::Comma)?;
+                let rhs = self.operand(function, scope, ty)?;
+
+                InstKind::Binary {
+                    op,
+                    flags,
+                    ty,
+                    lhs,
+                    rhs,
+                }
+            }
+        };
+
+        let result = result.map(|name| scope.value(function, &name, kind.result_ty()));
+        function.append_inst(block, kind, result);
+
+        Ok(())
+    }
+}
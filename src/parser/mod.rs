@@ -1,3 +1,4 @@
 pub mod ast;
+pub mod ir;
 pub mod token;
 pub mod types;
@@ -1,5 +1,3 @@
-use super::types::Type;
-use crate::type_from_string;
 use logos::Logos;
 use std::fmt::Display;
 use thiserror::Error;
@@ -16,18 +14,19 @@ impl Display for LexerError {
     }
 }
 
-#[derive(Logos, Debug, PartialEq)]
+#[derive(Logos, Debug, PartialEq, Clone)]
 #[logos(skip r"[ \t\n\f]+")]
-pub enum Token { 
-    #[regex(r"[A-Za-z_][A-Za-z0-9_]*:", |lex| {
+#[logos(skip r";[^\n]*")]
+pub enum Token {
+    #[regex(r"[A-Za-z_.][A-Za-z0-9_.]*:", |lex| {
         lex.slice().replace(":", "").to_string()
-    })] 
+    })]
     Label(String),
 
     #[token("{")]
     CurlyBracketStart,
 
-    #[token("}")] 
+    #[token("}")]
     CurlyBracketEnd,
 
     #[token("(")]
@@ -36,7 +35,22 @@ pub enum Token {
     #[token(")")]
     ParenthesesEnd,
 
-    #[regex(r"\d+(\.\d+)?\s", |lex| {
+    #[token("[")]
+    SquareBracketStart,
+
+    #[token("]")]
+    SquareBracketEnd,
+
+    #[token(",")]
+    Comma,
+
+    #[token("=")]
+    Equal,
+
+    #[regex(r"-?0x[0-9A-Fa-f]+(\.[0-9A-Fa-f]*)?([pP][-+]?\d+)?", |lex| {
+        lex.slice().to_string()
+    })]
+    #[regex(r"-?\d+(\.\d+)?([eE][-+]?\d+)?", |lex| {
         lex.slice().trim().to_string()
     })]
     Number(String),
@@ -46,13 +60,18 @@ pub enum Token {
 
     #[regex(r"[A-Za-z_][A-Za-z0-9_]*", |lex| {
         lex.slice().to_string()
-    })] 
+    })]
     Identifier(String),
 
     #[regex(r"@[A-Za-z_][A-Za-z0-9_]*", |lex| {
         lex.slice().to_string()
-    })] 
-    GlobalEntity(String)
+    })]
+    GlobalEntity(String),
+
+    #[regex(r"%[A-Za-z0-9_.]+", |lex| {
+        lex.slice().to_string()
+    })]
+    LocalEntity(String),
 }
 
 pub fn get_tokens(input: String) -> Result<Vec<Token>, LexerError> {
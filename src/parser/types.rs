@@ -2,140 +2,499 @@ use thiserror::Error;
 
 #[derive(Error, Debug, Clone)]
 pub enum TypeError {
-    #[error("The sum could not be made because the types do not match.")]
+    #[error("The operation could not be made because the types do not match.")]
     MismatchedTypes,
 
-    #[error("there was an overflow in the sum")]
+    #[error("there was an overflow in the operation")]
     Overflow,
 
+    #[error("division by zero")]
+    DivisionByZero,
+
     #[error("there was an error trying to cast {0}")]
     CannotCast(Type),
-}
 
-#[derive(Debug, PartialEq, Eq, Clone)]
-pub enum Type {
-    I8(i8),
-    U8(u8),
+    #[error("Unsupported type {0}")]
+    UnsupportedType(String),
+}
 
-    I16(i16),
-    U16(u16),
+// How arithmetic behaves when the result does not fit in the type
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum OverflowMode {
+    Wrapping,
+    Checked,
+}
 
-    I32(i32),
-    U32(u32),
+pub const MAX_INT_BITS: u32 = 128;
 
-    I64(i64),
-    U64(u64),
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub enum Type {
+    // Integers of 1 to 128 bits, value holds their bits zero extended
+    Int {
+        bits: u32,
+        signed: bool,
+        value: u128,
+    },
 
-    I128(i128),
-    U128(u128),
+    // Floats keep their IEEE 754 bits, so constants are compared bit by bit
+    F16(u16),
+    F32(u32),
+    F64(u64),
 
     // Registers or Variables, starts with %
     Value(String),
 }
 
-impl Type {
-    pub fn try_add(&mut self, val: Type) -> Result<(), TypeError> {
-        match (self, val) {
-            (Type::I8(a), Type::I8(b)) => {
-                *a = a.checked_add(b).ok_or(TypeError::Overflow)?;
-            }
-            (Type::U8(a), Type::U8(b)) => {
-                *a = a.checked_add(b).ok_or(TypeError::Overflow)?;
-            }
-            (Type::I16(a), Type::I16(b)) => {
-                *a = a.checked_add(b).ok_or(TypeError::Overflow)?;
+pub fn mask(bits: u32) -> u128 {
+    if bits >= MAX_INT_BITS {
+        u128::MAX
+    } else {
+        (1 << bits) - 1
+    }
+}
+
+pub fn sign_extend(value: u128, bits: u32) -> i128 {
+    let shift = MAX_INT_BITS - bits;
+
+    ((value << shift) as i128) >> shift
+}
+
+fn fits_signed(value: i128, bits: u32) -> bool {
+    bits >= MAX_INT_BITS || sign_extend(value as u128 & mask(bits), bits) == value
+}
+
+// Parses the name of an integer type, i24 is a signed integer of 24 bits and u24 an unsigned one
+pub fn int_type(name: &str) -> Option<(u32, bool)> {
+    let signed = match name.chars().next()? {
+        'i' => true,
+        'u' => false,
+        _ => return None,
+    };
+    let bits = name[1..].parse::<u32>().ok()?;
+
+    if name[1..].starts_with('0') || bits == 0 || bits > MAX_INT_BITS {
+        return None;
+    }
+
+    Some((bits, signed))
+}
+
+// Applies $e to a pair of floats of the same type, half floats are computed in single precision
+macro_rules! float_op {
+    ($self:expr, $val:expr, |$a:ident, $b:ident| $e:expr) => {
+        match ($self, $val) {
+            (Type::F16(x), Type::F16(y)) => {
+                let ($a, $b) = (f16_to_f32(*x), f16_to_f32(y));
+                *x = f32_to_f16($e);
             }
-            (Type::U16(a), Type::U16(b)) => {
-                *a = a.checked_add(b).ok_or(TypeError::Overflow)?;
+            (Type::F32(x), Type::F32(y)) => {
+                let ($a, $b) = (f32::from_bits(*x), f32::from_bits(y));
+                *x = ($e).to_bits();
             }
-            (Type::I32(a), Type::I32(b)) => {
-                *a = a.checked_add(b).ok_or(TypeError::Overflow)?;
+            (Type::F64(x), Type::F64(y)) => {
+                let ($a, $b) = (f64::from_bits(*x), f64::from_bits(y));
+                *x = ($e).to_bits();
             }
-            (Type::U32(a), Type::U32(b)) => {
-                *a = a.checked_add(b).ok_or(TypeError::Overflow)?;
+            _ => return Err(TypeError::MismatchedTypes),
+        }
+    };
+}
+
+impl Type {
+    pub fn int(bits: u32, signed: bool, value: u128) -> Type {
+        Type::Int {
+            bits,
+            signed,
+            value: value & mask(bits),
+        }
+    }
+
+    pub fn bool(value: bool) -> Type {
+        Type::int(1, true, value as u128)
+    }
+
+    // Reads a constant of the integer type `ty`, anything that is not a number is a Value
+    pub fn parse(ty: &str, value: &str) -> Result<Type, TypeError> {
+        let digits = value.strip_prefix('-').unwrap_or(value);
+
+        if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+            return Ok(Type::from(value));
+        }
+
+        let (bits, signed) = int_type(ty).ok_or(TypeError::UnsupportedType(ty.to_string()))?;
+        let magnitude = digits.parse::<u128>().map_err(|_| TypeError::Overflow)?;
+
+        // Both the signed and the unsigned range are accepted, as bit patterns
+        let value = if value.starts_with('-') {
+            if magnitude > 1 << (bits - 1) {
+                return Err(TypeError::Overflow);
             }
-            (Type::I64(a), Type::I64(b)) => {
-                *a = a.checked_add(b).ok_or(TypeError::Overflow)?;
+
+            magnitude.wrapping_neg()
+        } else {
+            if magnitude > mask(bits) {
+                return Err(TypeError::Overflow);
             }
-            (Type::U64(a), Type::U64(b)) => {
-                *a = a.checked_add(b).ok_or(TypeError::Overflow)?;
+
+            magnitude
+        };
+
+        Ok(Type::int(bits, signed, value))
+    }
+
+    pub fn is_zero(&self) -> bool {
+        matches!(self, Type::Int { value: 0, .. })
+    }
+
+    // Value of an integer read with its signedness
+    pub fn as_i128(&self) -> Option<i128> {
+        match self {
+            Type::Int { bits, value, .. } => Some(sign_extend(*value, *bits)),
+            _ => None,
+        }
+    }
+
+    pub fn as_u128(&self) -> Option<u128> {
+        match self {
+            Type::Int { value, .. } => Some(*value),
+            _ => None,
+        }
+    }
+
+    // Reinterprets the bits of an integer with the other signedness
+    pub fn to_signed(&self) -> Type {
+        match self {
+            Type::Int { bits, value, .. } => Type::int(*bits, true, *value),
+            other => other.clone(),
+        }
+    }
+
+    pub fn to_unsigned(&self) -> Type {
+        match self {
+            Type::Int { bits, value, .. } => Type::int(*bits, false, *value),
+            other => other.clone(),
+        }
+    }
+
+    // Both operands must be integers of the same type
+    fn int_operands(&self, val: &Type) -> Result<(u32, bool, u128, u128), TypeError> {
+        match (self, val) {
+            (
+                Type::Int {
+                    bits,
+                    signed,
+                    value: a,
+                },
+                Type::Int {
+                    bits: other_bits,
+                    signed: other_signed,
+                    value: b,
+                },
+            ) if bits == other_bits && signed == other_signed => Ok((*bits, *signed, *a, *b)),
+            _ => Err(TypeError::MismatchedTypes),
+        }
+    }
+
+    // The wrapping result is the same for both signedness, the checked one is not
+    fn arith(
+        &mut self,
+        val: Type,
+        mode: OverflowMode,
+        wrapping: fn(u128, u128) -> u128,
+        checked_signed: fn(i128, i128) -> Option<i128>,
+        checked_unsigned: fn(u128, u128) -> Option<u128>,
+    ) -> Result<(), TypeError> {
+        let (bits, signed, a, b) = self.int_operands(&val)?;
+
+        let value = match mode {
+            OverflowMode::Wrapping => wrapping(a, b),
+            OverflowMode::Checked if signed => {
+                checked_signed(sign_extend(a, bits), sign_extend(b, bits))
+                    .filter(|value| fits_signed(*value, bits))
+                    .ok_or(TypeError::Overflow)? as u128
             }
-            (Type::I128(a), Type::I128(b)) => {
-                *a = a.checked_add(b).ok_or(TypeError::Overflow)?;
+            OverflowMode::Checked => checked_unsigned(a, b)
+                .filter(|value| *value <= mask(bits))
+                .ok_or(TypeError::Overflow)?,
+        };
+
+        *self = Type::int(bits, signed, value);
+        Ok(())
+    }
+
+    fn division(&mut self, val: Type, mode: OverflowMode, rem: bool) -> Result<(), TypeError> {
+        let (bits, signed, a, b) = self.int_operands(&val)?;
+
+        if b == 0 {
+            return Err(TypeError::DivisionByZero);
+        }
+
+        let value = if signed {
+            let (a, b) = (sign_extend(a, bits), sign_extend(b, bits));
+            let quotient = a.wrapping_div(b);
+
+            // Only the minimum divided by -1 overflows, its remainder overflows too
+            if mode == OverflowMode::Checked
+                && (a.checked_div(b).is_none() || !fits_signed(quotient, bits))
+            {
+                return Err(TypeError::Overflow);
             }
-            (Type::U128(a), Type::U128(b)) => {
-                *a = a.checked_add(b).ok_or(TypeError::Overflow)?;
+
+            if rem {
+                a.wrapping_rem(b) as u128
+            } else {
+                quotient as u128
             }
-            _ => return Err(TypeError::MismatchedTypes),
+        } else if rem {
+            a % b
+        } else {
+            a / b
+        };
+
+        *self = Type::int(bits, signed, value);
+        Ok(())
+    }
+
+    pub fn try_add(&mut self, val: Type, mode: OverflowMode) -> Result<(), TypeError> {
+        self.arith(
+            val,
+            mode,
+            u128::wrapping_add,
+            i128::checked_add,
+            u128::checked_add,
+        )
+    }
+
+    pub fn try_sub(&mut self, val: Type, mode: OverflowMode) -> Result<(), TypeError> {
+        self.arith(
+            val,
+            mode,
+            u128::wrapping_sub,
+            i128::checked_sub,
+            u128::checked_sub,
+        )
+    }
+
+    pub fn try_mul(&mut self, val: Type, mode: OverflowMode) -> Result<(), TypeError> {
+        self.arith(
+            val,
+            mode,
+            u128::wrapping_mul,
+            i128::checked_mul,
+            u128::checked_mul,
+        )
+    }
+
+    pub fn try_div(&mut self, val: Type, mode: OverflowMode) -> Result<(), TypeError> {
+        self.division(val, mode, false)
+    }
+
+    pub fn try_rem(&mut self, val: Type, mode: OverflowMode) -> Result<(), TypeError> {
+        self.division(val, mode, true)
+    }
+
+    pub fn try_and(&mut self, val: Type) -> Result<(), TypeError> {
+        self.arith(
+            val,
+            OverflowMode::Wrapping,
+            |a, b| a & b,
+            |_, _| None,
+            |_, _| None,
+        )
+    }
+
+    pub fn try_or(&mut self, val: Type) -> Result<(), TypeError> {
+        self.arith(
+            val,
+            OverflowMode::Wrapping,
+            |a, b| a | b,
+            |_, _| None,
+            |_, _| None,
+        )
+    }
+
+    pub fn try_xor(&mut self, val: Type) -> Result<(), TypeError> {
+        self.arith(
+            val,
+            OverflowMode::Wrapping,
+            |a, b| a ^ b,
+            |_, _| None,
+            |_, _| None,
+        )
+    }
+
+    // Checked shifts fail when shifting by the width of the type or more, wrapping ones mask it
+    fn shift_amount(bits: u32, amount: u128, mode: OverflowMode) -> Result<u32, TypeError> {
+        match mode {
+            OverflowMode::Checked if amount >= bits as u128 => Err(TypeError::Overflow),
+            OverflowMode::Checked => Ok(amount as u32),
+            OverflowMode::Wrapping => Ok((amount % bits as u128) as u32),
         }
+    }
+
+    pub fn try_shl(&mut self, val: Type, mode: OverflowMode) -> Result<(), TypeError> {
+        let (bits, signed, a, b) = self.int_operands(&val)?;
+        let amount = Self::shift_amount(bits, b, mode)?;
+
+        *self = Type::int(bits, signed, a << amount);
         Ok(())
     }
-}
 
-impl std::fmt::Display for Type {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self)
+    // Arithmetic shift for signed types, logical shift for unsigned ones
+    pub fn try_shr(&mut self, val: Type, mode: OverflowMode) -> Result<(), TypeError> {
+        let (bits, signed, a, b) = self.int_operands(&val)?;
+        let amount = Self::shift_amount(bits, b, mode)?;
+
+        let value = if signed {
+            (sign_extend(a, bits) >> amount) as u128
+        } else {
+            a >> amount
+        };
+
+        *self = Type::int(bits, signed, value);
+        Ok(())
     }
-}
 
-#[macro_export]
-macro_rules! type_from_string {
-    ($t:expr, $v:expr) => {{
-        fn is_integer(input: &str) -> bool {
-            if input.is_empty() {
-                return false;
-            }
+    pub fn try_fadd(&mut self, val: Type) -> Result<(), TypeError> {
+        float_op!(self, val, |a, b| a + b);
+        Ok(())
+    }
 
-            let mut chars = input.chars();
+    pub fn try_fsub(&mut self, val: Type) -> Result<(), TypeError> {
+        float_op!(self, val, |a, b| a - b);
+        Ok(())
+    }
 
-            if let Some(first_char) = chars.next() {
-                if !first_char.is_digit(10) && first_char != '-' {
-                    return false;
-                }
-            } else {
-                return false;
+    pub fn try_fmul(&mut self, val: Type) -> Result<(), TypeError> {
+        float_op!(self, val, |a, b| a * b);
+        Ok(())
+    }
+
+    pub fn try_fdiv(&mut self, val: Type) -> Result<(), TypeError> {
+        float_op!(self, val, |a, b| a / b);
+        Ok(())
+    }
+
+    // None when the floats are unordered, that is when one of them is NaN
+    pub fn try_fcmp(&self, val: &Type) -> Result<Option<std::cmp::Ordering>, TypeError> {
+        match (self.to_f64(), val.to_f64()) {
+            (Some(a), Some(b)) if std::mem::discriminant(self) == std::mem::discriminant(val) => {
+                Ok(a.partial_cmp(&b))
             }
+            _ => Err(TypeError::MismatchedTypes),
+        }
+    }
 
-            chars.all(|c| c.is_digit(10))
+    // Every float type converts to f64 without losing precision
+    pub fn to_f64(&self) -> Option<f64> {
+        match self {
+            Type::F16(v) => Some(f16_to_f32(*v) as f64),
+            Type::F32(v) => Some(f32::from_bits(*v) as f64),
+            Type::F64(v) => Some(f64::from_bits(*v)),
+            _ => None,
         }
-        if !is_integer($v) {
-            $crate::parser::types::Type::from($v)
+    }
+
+    pub fn try_cmp(&self, val: &Type) -> Result<std::cmp::Ordering, TypeError> {
+        let (bits, signed, a, b) = self.int_operands(val)?;
+
+        if signed {
+            Ok(sign_extend(a, bits).cmp(&sign_extend(b, bits)))
         } else {
-            match $t {
-                "i8" => {
-                    $crate::parser::types::Type::from($v.parse::<i8>().expect("Failed to parse"))
-                }
-                "u8" => {
-                    $crate::parser::types::Type::from($v.parse::<u8>().expect("Failed to parse"))
-                }
-                "i16" => {
-                    $crate::parser::types::Type::from($v.parse::<i16>().expect("Failed to parse"))
-                }
-                "u16" => {
-                    $crate::parser::types::Type::from($v.parse::<u16>().expect("Failed to parse"))
-                }
-                "i32" => {
-                    $crate::parser::types::Type::from($v.parse::<i32>().expect("Failed to parse"))
-                }
-                "u32" => {
-                    $crate::parser::types::Type::from($v.parse::<u32>().expect("Failed to parse"))
-                }
-                "i64" => {
-                    $crate::parser::types::Type::from($v.parse::<i64>().expect("Failed to parse"))
-                }
-                "u64" => {
-                    $crate::parser::types::Type::from($v.parse::<u64>().expect("Failed to parse"))
-                }
-                "i128" => {
-                    $crate::parser::types::Type::from($v.parse::<i128>().expect("Failed to parse"))
-                }
-                "u128" => {
-                    $crate::parser::types::Type::from($v.parse::<u128>().expect("Failed to parse"))
-                }
-                _ => panic!("Unsupported type"),
+            Ok(a.cmp(&b))
+        }
+    }
+}
+
+pub fn f16_to_f32(bits: u16) -> f32 {
+    let sign = ((bits & 0x8000) as u32) << 16;
+    let exponent = ((bits >> 10) & 0x1F) as u32;
+    let mantissa = (bits & 0x3FF) as u32;
+
+    match (exponent, mantissa) {
+        (0, 0) => f32::from_bits(sign),
+        (0, _) => {
+            let value = mantissa as f32 * 2f32.powi(-24);
+
+            if sign != 0 {
+                -value
+            } else {
+                value
             }
         }
-    }};
+        (0x1F, _) => f32::from_bits(sign | 0x7F80_0000 | (mantissa << 13)),
+        _ => f32::from_bits(sign | ((exponent + 112) << 23) | (mantissa << 13)),
+    }
+}
+
+// Rounds to the nearest half float, ties to even
+pub fn f64_to_f16(value: f64) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 48) & 0x8000) as u16;
+    let exponent = ((bits >> 52) & 0x7FF) as i32;
+    let mantissa = bits & 0xF_FFFF_FFFF_FFFF;
+
+    if exponent == 0x7FF {
+        let nan = if mantissa != 0 { 0x200 } else { 0 };
+        return sign | 0x7C00 | nan;
+    }
+
+    let exponent = exponent - 1023 + 15;
+
+    if exponent >= 0x1F {
+        return sign | 0x7C00;
+    }
+
+    let (mut result, rest, half) = if exponent <= 0 {
+        if exponent < -10 {
+            return sign;
+        }
+
+        let mantissa = mantissa | 1 << 52;
+        let shift = (43 - exponent) as u32;
+
+        (
+            mantissa >> shift,
+            mantissa & ((1 << shift) - 1),
+            1 << (shift - 1),
+        )
+    } else {
+        (
+            ((exponent as u64) << 10) | (mantissa >> 42),
+            mantissa & ((1 << 42) - 1),
+            1 << 41,
+        )
+    };
+
+    // A carry out of the mantissa correctly moves to the next exponent
+    if rest > half || (rest == half && result & 1 == 1) {
+        result += 1;
+    }
+
+    sign | result as u16
+}
+
+// Every f32 is exact as an f64, so this rounds once
+pub fn f32_to_f16(value: f32) -> u16 {
+    f64_to_f16(value as f64)
+}
+
+impl std::fmt::Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl From<f32> for Type {
+    fn from(value: f32) -> Self {
+        Type::F32(value.to_bits())
+    }
+}
+
+impl From<f64> for Type {
+    fn from(value: f64) -> Self {
+        Type::F64(value.to_bits())
+    }
 }
 
 impl From<&str> for Type {
@@ -144,12 +503,29 @@ impl From<&str> for Type {
     }
 }
 
+impl From<String> for Type {
+    fn from(value: String) -> Self {
+        Type::Value(value)
+    }
+}
+
+impl TryInto<String> for Type {
+    type Error = TypeError;
+
+    fn try_into(self) -> Result<String, Self::Error> {
+        match self {
+            Self::Value(v) => Ok(v),
+            _ => Err(TypeError::CannotCast(self)),
+        }
+    }
+}
+
 macro_rules! to_type {
-    ($($t:ty, $variant:ident),*) => {
+    ($($t:ty, $bits:expr, $signed:expr),*) => {
         $(
             impl From<$t> for Type {
                 fn from(value: $t) -> Self {
-                    Type::$variant(value)
+                    Type::int($bits, $signed, value as u128)
                 }
             }
         )*
@@ -157,15 +533,15 @@ macro_rules! to_type {
 }
 
 macro_rules! into_val {
-    ($($t:ty, $variant:ident),*) => {
+    ($($t:ty, $bits:expr, $signed:expr),*) => {
         $(
             impl TryInto<$t> for Type {
                 type Error = TypeError;
 
                 fn try_into(self) -> Result<$t, Self::Error> {
                     match self {
-                        Self::$variant(v) => {
-                            Ok(v)
+                        Self::Int { bits: $bits, signed: $signed, value } => {
+                            Ok(value as $t)
                         },
                         _ => {
                             Err(TypeError::CannotCast(self))
@@ -178,11 +554,22 @@ macro_rules! into_val {
 }
 
 to_type!(
-    i8, I8, u8, U8, i16, I16, u16, U16, i32, I32, u32, U32, i64, I64, u64, U64, i128, I128, u128,
-    U128, String, Value
+    bool, 1, true, i8, 8, true, u8, 8, false, i16, 16, true, u16, 16, false, i32, 32, true, u32,
+    32, false, i64, 64, true, u64, 64, false, i128, 128, true, u128, 128, false
 );
 
 into_val!(
-    i8, I8, u8, U8, i16, I16, u16, U16, i32, I32, u32, U32, i64, I64, u64, U64, i128, I128, u128,
-    U128, String, Value
+    i8, 8, true, u8, 8, false, i16, 16, true, u16, 16, false, i32, 32, true, u32, 32, false, i64,
+    64, true, u64, 64, false, i128, 128, true, u128, 128, false
 );
+
+impl TryInto<bool> for Type {
+    type Error = TypeError;
+
+    fn try_into(self) -> Result<bool, Self::Error> {
+        match self {
+            Self::Int { bits: 1, value, .. } => Ok(value != 0),
+            _ => Err(TypeError::CannotCast(self)),
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use super::jmp::JmpError;
+use super::regs::Reg;
+
+const BRANCH: u32 = 0x63;
+
+// B-type, the offset is relative to the branch and must be a multiple of 2 within ±4 KiB
+pub fn branch_to_endian(
+    name: &'static str,
+    funct3: u32,
+    rs1: Reg,
+    rs2: Reg,
+    offset: i64,
+) -> Result<Vec<u8>, JmpError> {
+    if !(-4096..=4094).contains(&offset) {
+        return Err(JmpError::OutOfRange(name, offset));
+    }
+
+    if offset % 2 != 0 {
+        return Err(JmpError::NonAlignedAddress(name, offset));
+    }
+
+    let rs1: u64 = rs1.into();
+    let rs2: u64 = rs2.into();
+    let imm = offset as u32;
+    let instruction = (imm >> 12 & 0x1) << 31
+        | (imm >> 5 & 0x3F) << 25
+        | (rs2 as u32) << 20
+        | (rs1 as u32) << 15
+        | funct3 << 12
+        | (imm >> 1 & 0xF) << 8
+        | (imm >> 11 & 0x1) << 7
+        | BRANCH;
+
+    Ok(instruction.to_le_bytes().to_vec())
+}
+
+pub fn beq(rs1: Reg, rs2: Reg, offset: i64) -> Result<Vec<u8>, JmpError> {
+    branch_to_endian("beq", 0b000, rs1, rs2, offset)
+}
+
+pub fn bne(rs1: Reg, rs2: Reg, offset: i64) -> Result<Vec<u8>, JmpError> {
+    branch_to_endian("bne", 0b001, rs1, rs2, offset)
+}
+
+pub fn blt(rs1: Reg, rs2: Reg, offset: i64) -> Result<Vec<u8>, JmpError> {
+    branch_to_endian("blt", 0b100, rs1, rs2, offset)
+}
+
+pub fn bge(rs1: Reg, rs2: Reg, offset: i64) -> Result<Vec<u8>, JmpError> {
+    branch_to_endian("bge", 0b101, rs1, rs2, offset)
+}
+
+pub fn bltu(rs1: Reg, rs2: Reg, offset: i64) -> Result<Vec<u8>, JmpError> {
+    branch_to_endian("bltu", 0b110, rs1, rs2, offset)
+}
+
+pub fn bgeu(rs1: Reg, rs2: Reg, offset: i64) -> Result<Vec<u8>, JmpError> {
+    branch_to_endian("bgeu", 0b111, rs1, rs2, offset)
+}
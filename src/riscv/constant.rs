@@ -0,0 +1,146 @@
+use super::immediate::{addi, addiw, slli, srli, ImmediateError};
+use super::machine::{ImmOp, MachineInst};
+use super::regs::Reg;
+use super::upper::lui;
+
+// One instruction of a constant, each one after the first works on the destination
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    Lui(i64),
+    // `addi rd, zero, imm` when it comes first
+    Addi(i64),
+    Addiw(i64),
+    Slli(u32),
+    Srli(u32),
+}
+
+fn sign_extend(value: i64, bits: u32) -> i64 {
+    (value << (64 - bits)) >> (64 - bits)
+}
+
+fn fits(value: i64, bits: u32) -> bool {
+    sign_extend(value, bits) == value
+}
+
+// `lui` and `addiw` for 32 bit values, bigger ones are built from their upper bits,
+// shifted into place and completed with an `addi`
+fn generate(value: i64, steps: &mut Vec<Step>) {
+    if fits(value, 32) {
+        let hi20 = (value.wrapping_add(0x800) >> 12) & 0xFFFFF;
+        let lo12 = sign_extend(value, 12);
+
+        if hi20 != 0 {
+            steps.push(Step::Lui(hi20));
+        }
+
+        if lo12 != 0 || hi20 == 0 {
+            steps.push(if hi20 != 0 {
+                Step::Addiw(lo12)
+            } else {
+                Step::Addi(lo12)
+            });
+        }
+
+        return;
+    }
+
+    let lo12 = sign_extend(value, 12);
+    let mut upper = value.wrapping_sub(lo12);
+    let mut shift = 0;
+
+    if !fits(upper, 32) {
+        shift = upper.trailing_zeros();
+        upper >>= shift;
+
+        // Shifting 12 less leaves zeros a `lui` can produce
+        if shift > 12 && !fits(upper, 12) && fits(upper << 12, 32) {
+            shift -= 12;
+            upper <<= 12;
+        }
+    }
+
+    generate(upper, steps);
+
+    if shift != 0 {
+        steps.push(Step::Slli(shift));
+    }
+
+    if lo12 != 0 {
+        steps.push(Step::Addi(lo12));
+    }
+}
+
+fn with(value: i64, last: Step) -> Vec<Step> {
+    let mut steps = Vec::new();
+    generate(value, &mut steps);
+    steps.push(last);
+    steps
+}
+
+// The shortest sequence that leaves `value` in a register. Besides the direct
+// expansion, the value is tried with its trailing zeros removed and shifted back
+// with `slli`, and with its leading zeros removed and shifted back with `srli`
+pub fn sequence(value: i64) -> Vec<Step> {
+    let mut best = Vec::new();
+    generate(value, &mut best);
+
+    if value & 0xFFF != 0 && value & 1 == 0 && best.len() >= 2 {
+        let zeros = value.trailing_zeros();
+        let candidate = with(value >> zeros, Step::Slli(zeros));
+
+        if candidate.len() < best.len() {
+            best = candidate;
+        }
+    }
+
+    if value > 0 && best.len() > 2 {
+        let zeros = value.leading_zeros();
+        let shifted = value << zeros;
+
+        // The bits shifted out can be ones or zeros, whichever is shorter
+        for filled in [shifted | ((1u64 << zeros) - 1) as i64, shifted] {
+            let candidate = with(filled, Step::Srli(zeros));
+
+            if candidate.len() < best.len() {
+                best = candidate;
+            }
+        }
+    }
+
+    best
+}
+
+pub fn encode(rd: Reg, step: Step, first: bool) -> Result<Vec<u8>, ImmediateError> {
+    let rs1 = if first { Reg::Zero } else { rd };
+
+    match step {
+        Step::Lui(imm) => lui(rd, imm),
+        Step::Addi(imm) => addi(rd, rs1, imm),
+        Step::Addiw(imm) => addiw(rd, rs1, imm),
+        Step::Slli(shamt) => slli(rd, rs1, shamt),
+        Step::Srli(shamt) => srli(rd, rs1, shamt),
+    }
+}
+
+pub fn instruction(rd: Reg, step: Step, first: bool) -> MachineInst {
+    let rs1 = if first { Reg::Zero } else { rd };
+
+    match step {
+        Step::Lui(imm) => MachineInst::Lui { rd: rd.into(), imm },
+        Step::Addi(imm) => MachineInst::imm(ImmOp::Addi, rd, rs1, imm),
+        Step::Addiw(imm) => MachineInst::imm(ImmOp::Addiw, rd, rs1, imm),
+        Step::Slli(shamt) => MachineInst::imm(ImmOp::Slli, rd, rs1, shamt.into()),
+        Step::Srli(shamt) => MachineInst::imm(ImmOp::Srli, rd, rs1, shamt.into()),
+    }
+}
+
+// Machine code that loads `value` into `rd`
+pub fn materialize(rd: Reg, value: i64) -> Result<Vec<u8>, ImmediateError> {
+    let mut code = Vec::new();
+
+    for (index, step) in sequence(value).into_iter().enumerate() {
+        code.extend(encode(rd, step, index == 0)?);
+    }
+
+    Ok(code)
+}
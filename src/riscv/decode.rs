@@ -1,10 +1,10 @@
-use super::immediate::{addi, ecall};
+use super::constant::materialize;
+use super::immediate::{addi, ecall, ImmediateError};
 use super::jmp::{jal, jalr, JmpError};
 use super::register::{add, sub};
 use super::regs::Reg;
 use crate::parser::ast::AstNode;
-use crate::parser::types::{Type, TypeError};
-use crate::type_from_string;
+use crate::parser::types::{OverflowMode, Type, TypeError};
 use std::collections::HashMap;
 use std::convert::TryFrom;
 use thiserror::Error;
@@ -20,6 +20,9 @@ pub enum DecodeError {
     #[error("{0}")]
     JmpError(#[from] JmpError),
 
+    #[error("{0}")]
+    ImmediateError(#[from] ImmediateError),
+
     #[error("{0}")]
     TypeError(#[from] TypeError),
 }
@@ -38,7 +41,7 @@ pub fn node_to_opcode(
                 Ok(r) => r,
                 Err(_) => return Err(DecodeError::InvalidRegister(dist)),
             };
-            let mut result = type_from_string!(t.as_str(), "0");
+            let mut result = Type::parse(t.as_str(), "0")?;
             let mut adds = Vec::new();
             let dist_reg = match Reg::try_from(&dist) {
                 Ok(r) => r,
@@ -58,11 +61,11 @@ pub fn node_to_opcode(
                             adds.extend(add(&dist_reg, &dist_reg, &r));
                         }
                     },
-                    num => result.try_add(num)?,
+                    num => result.try_add(num, OverflowMode::Checked)?,
                 }
             }
 
-            opcode.extend(addi(reg, Reg::Zero, result.try_into()?));
+            opcode.extend(materialize(reg, result.try_into()?)?);
             opcode.extend(adds);
         }
         AstNode::Radd { target, rs1 } => {
@@ -96,7 +99,7 @@ pub fn node_to_opcode(
                 Ok(r) => r,
                 Err(_) => return Err(DecodeError::InvalidRegister(dist)),
             };
-            opcode.extend(addi(reg, Reg::Zero, value));
+            opcode.extend(materialize(reg, value)?);
         }
         AstNode::Syscall => {
             opcode.extend(ecall());
@@ -109,10 +112,10 @@ pub fn node_to_opcode(
             opcode.extend(jal(*target_address, pc, Reg::Ra)?);
         }
         AstNode::Ret => {
-            opcode.extend(jalr(Reg::Zero, Reg::Ra, 0));
+            opcode.extend(jalr(Reg::Zero, Reg::Ra, 0)?);
         }
         AstNode::Nop => {
-            opcode.extend(addi(Reg::Zero, Reg::Zero, 0));
+            opcode.extend(addi(Reg::Zero, Reg::Zero, 0)?);
         }
     }
     Ok(opcode)
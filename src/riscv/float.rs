@@ -0,0 +1,171 @@
+use super::register::{register_to_endian, RegisterInstruction};
+use super::regs::{FReg, Reg};
+
+const OP_FP: u64 = 0b1010011;
+
+// Precision of the operands, the fmt field of the instruction
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum FloatFormat {
+    // F extension
+    S,
+    // D extension
+    D,
+    // Zfh extension
+    H,
+}
+
+// Integer side of a conversion, the rs2 field of fcvt
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum IntFormat {
+    W,
+    Wu,
+    L,
+    Lu,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum RoundingMode {
+    // Round to nearest, ties to even
+    Rne,
+    // Round towards zero
+    Rtz,
+    // Round down
+    Rdn,
+    // Round up
+    Rup,
+    // Round to nearest, ties to max magnitude
+    Rmm,
+    // Use the mode in the frm register
+    Dyn,
+}
+
+impl From<FloatFormat> for u64 {
+    fn from(value: FloatFormat) -> Self {
+        match value {
+            FloatFormat::S => 0b00,
+            FloatFormat::D => 0b01,
+            FloatFormat::H => 0b10,
+        }
+    }
+}
+
+impl From<IntFormat> for u64 {
+    fn from(value: IntFormat) -> Self {
+        match value {
+            IntFormat::W => 0,
+            IntFormat::Wu => 1,
+            IntFormat::L => 2,
+            IntFormat::Lu => 3,
+        }
+    }
+}
+
+impl From<RoundingMode> for u64 {
+    fn from(value: RoundingMode) -> Self {
+        match value {
+            RoundingMode::Rne => 0b000,
+            RoundingMode::Rtz => 0b001,
+            RoundingMode::Rdn => 0b010,
+            RoundingMode::Rup => 0b011,
+            RoundingMode::Rmm => 0b100,
+            RoundingMode::Dyn => 0b111,
+        }
+    }
+}
+
+// Every OP-FP instruction, funct7 holds funct5 and the format
+pub fn op_fp(funct5: u64, fmt: FloatFormat, funct3: u64, rd: u64, rs1: u64, rs2: u64) -> Vec<u8> {
+    let fmt: u64 = fmt.into();
+
+    register_to_endian(RegisterInstruction {
+        funct3,
+        funct7: funct5 << 2 | fmt,
+        rs1,
+        rs2,
+        rd,
+        opcode: OP_FP,
+    })
+}
+
+pub fn fadd(fmt: FloatFormat, rd: &FReg, rs1: &FReg, rs2: &FReg) -> Vec<u8> {
+    let rm = RoundingMode::Dyn.into();
+    op_fp(0b00000, fmt, rm, rd.into(), rs1.into(), rs2.into())
+}
+
+pub fn fsub(fmt: FloatFormat, rd: &FReg, rs1: &FReg, rs2: &FReg) -> Vec<u8> {
+    let rm = RoundingMode::Dyn.into();
+    op_fp(0b00001, fmt, rm, rd.into(), rs1.into(), rs2.into())
+}
+
+pub fn fmul(fmt: FloatFormat, rd: &FReg, rs1: &FReg, rs2: &FReg) -> Vec<u8> {
+    let rm = RoundingMode::Dyn.into();
+    op_fp(0b00010, fmt, rm, rd.into(), rs1.into(), rs2.into())
+}
+
+pub fn fdiv(fmt: FloatFormat, rd: &FReg, rs1: &FReg, rs2: &FReg) -> Vec<u8> {
+    let rm = RoundingMode::Dyn.into();
+    op_fp(0b00011, fmt, rm, rd.into(), rs1.into(), rs2.into())
+}
+
+pub fn fsgnj(fmt: FloatFormat, rd: &FReg, rs1: &FReg, rs2: &FReg) -> Vec<u8> {
+    op_fp(0b00100, fmt, 0b000, rd.into(), rs1.into(), rs2.into())
+}
+
+pub fn fsgnjn(fmt: FloatFormat, rd: &FReg, rs1: &FReg, rs2: &FReg) -> Vec<u8> {
+    op_fp(0b00100, fmt, 0b001, rd.into(), rs1.into(), rs2.into())
+}
+
+pub fn feq(fmt: FloatFormat, rd: &Reg, rs1: &FReg, rs2: &FReg) -> Vec<u8> {
+    op_fp(0b10100, fmt, 0b010, rd.into(), rs1.into(), rs2.into())
+}
+
+pub fn flt(fmt: FloatFormat, rd: &Reg, rs1: &FReg, rs2: &FReg) -> Vec<u8> {
+    op_fp(0b10100, fmt, 0b001, rd.into(), rs1.into(), rs2.into())
+}
+
+pub fn fle(fmt: FloatFormat, rd: &Reg, rs1: &FReg, rs2: &FReg) -> Vec<u8> {
+    op_fp(0b10100, fmt, 0b000, rd.into(), rs1.into(), rs2.into())
+}
+
+// fcvt.{w,wu,l,lu}.{s,d,h}, from float to integer
+pub fn fcvt_to_int(
+    fmt: FloatFormat,
+    int: IntFormat,
+    rm: RoundingMode,
+    rd: &Reg,
+    rs1: &FReg,
+) -> Vec<u8> {
+    op_fp(0b11000, fmt, rm.into(), rd.into(), rs1.into(), int.into())
+}
+
+// fcvt.{s,d,h}.{w,wu,l,lu}, from integer to float
+pub fn fcvt_from_int(
+    fmt: FloatFormat,
+    int: IntFormat,
+    rm: RoundingMode,
+    rd: &FReg,
+    rs1: &Reg,
+) -> Vec<u8> {
+    op_fp(0b11010, fmt, rm.into(), rd.into(), rs1.into(), int.into())
+}
+
+// fcvt between two float formats, `fmt` is the destination
+pub fn fcvt_float(
+    fmt: FloatFormat,
+    from: FloatFormat,
+    rm: RoundingMode,
+    rd: &FReg,
+    rs1: &FReg,
+) -> Vec<u8> {
+    op_fp(0b01000, fmt, rm.into(), rd.into(), rs1.into(), from.into())
+}
+
+// fmv.x.{w,d,h}, moves the bits of a float to an integer register
+pub fn fmv_to_int(fmt: FloatFormat, rd: &Reg, rs1: &FReg) -> Vec<u8> {
+    op_fp(0b11100, fmt, 0b000, rd.into(), rs1.into(), 0)
+}
+
+// fmv.{w,d,h}.x, moves the bits of an integer register to a float
+pub fn fmv_from_int(fmt: FloatFormat, rd: &FReg, rs1: &Reg) -> Vec<u8> {
+    op_fp(0b11110, fmt, 0b000, rd.into(), rs1.into(), 0)
+}
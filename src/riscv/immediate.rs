@@ -1,38 +1,219 @@
 use super::regs::Reg;
+use std::ops::RangeInclusive;
+use thiserror::Error;
+
+const OP_IMM: u32 = 0x13;
+const OP_IMM_32: u32 = 0x1B;
+const LOAD: u32 = 0x03;
+const MISC_MEM: u32 = 0x0F;
+const SYSTEM: u32 = 0x73;
+
+// Range of the 12 bit signed immediate of I and S types
+pub const IMM12: RangeInclusive<i64> = -2048..=2047;
+
+#[derive(Error, Debug)]
+pub enum ImmediateError {
+    #[error("`{0}` takes an immediate in {2:?}, {1} is out of range")]
+    OutOfRange(&'static str, i64, RangeInclusive<i64>),
+
+    #[error("There is no {0} of 2^{1} bytes")]
+    InvalidWidth(&'static str, u32),
+}
+
+// Fails instead of letting the value be truncated into the instruction
+pub fn check_range(
+    name: &'static str,
+    value: i64,
+    range: RangeInclusive<i64>,
+) -> Result<i64, ImmediateError> {
+    if range.contains(&value) {
+        Ok(value)
+    } else {
+        Err(ImmediateError::OutOfRange(name, value, range))
+    }
+}
 
 #[derive(Debug)]
 pub struct ImmediateInstruction {
     pub opcode: u32,
+    pub funct3: u32,
     pub rd: Reg,
     pub rs1: Reg,
     pub imm: i64,
 }
 
-pub fn immediate_to_endian(ins: ImmediateInstruction) -> Vec<u8> {
+pub(crate) fn immediate_to_endian(ins: ImmediateInstruction) -> Vec<u8> {
     let rd: u64 = ins.rd.into();
     let rs1: u64 = ins.rs1.into();
     let instruction = (ins.imm as u32) << 20
         | (rs1 as u32) << 15
-        | (0x00 as u32) << 12
+        | ins.funct3 << 12
         | (rd as u32) << 7
-        | ins.opcode as u32;
+        | ins.opcode;
     instruction.to_le_bytes().to_vec()
 }
 
-pub fn ecall() -> Vec<u8> {
+fn op_imm(opcode: u32, funct3: u32, rd: Reg, rs1: Reg, imm: i64) -> Vec<u8> {
     immediate_to_endian(ImmediateInstruction {
-        opcode: 0x73,
-        rd: Reg::Zero,
-        rs1: Reg::Zero,
-        imm: 0,
+        opcode,
+        funct3,
+        rd,
+        rs1,
+        imm,
     })
 }
 
-pub fn addi(dist: Reg, rs1: Reg, val: i64) -> Vec<u8> {
-    immediate_to_endian(ImmediateInstruction {
-        opcode: 0x13,
-        rs1,
-        rd: dist,
-        imm: val,
-    })
+fn checked(
+    name: &'static str,
+    opcode: u32,
+    funct3: u32,
+    rd: Reg,
+    rs1: Reg,
+    imm: i64,
+) -> Result<Vec<u8>, ImmediateError> {
+    let imm = check_range(name, imm, IMM12)?;
+    Ok(op_imm(opcode, funct3, rd, rs1, imm))
+}
+
+// The amount goes in the low bits of the immediate and funct6 above it, 0x400 selects
+// the arithmetic shift
+fn shift(
+    name: &'static str,
+    opcode: u32,
+    funct3: u32,
+    rd: Reg,
+    rs1: Reg,
+    shamt: u32,
+    high: i64,
+) -> Result<Vec<u8>, ImmediateError> {
+    let max = if opcode == OP_IMM_32 { 31 } else { 63 };
+    let shamt = check_range(name, shamt.into(), 0..=max)?;
+    Ok(op_imm(opcode, funct3, rd, rs1, high | shamt))
+}
+
+pub fn ecall() -> Vec<u8> {
+    op_imm(SYSTEM, 0b000, Reg::Zero, Reg::Zero, 0)
+}
+
+pub fn ebreak() -> Vec<u8> {
+    op_imm(SYSTEM, 0b000, Reg::Zero, Reg::Zero, 1)
+}
+
+// `pred` and `succ` are sets of the I, O, R and W bits, from high to low
+pub fn fence(pred: u32, succ: u32) -> Result<Vec<u8>, ImmediateError> {
+    let pred = check_range("fence", pred.into(), 0..=0xF)?;
+    let succ = check_range("fence", succ.into(), 0..=0xF)?;
+    Ok(op_imm(
+        MISC_MEM,
+        0b000,
+        Reg::Zero,
+        Reg::Zero,
+        pred << 4 | succ,
+    ))
+}
+
+pub fn addi(dist: Reg, rs1: Reg, val: i64) -> Result<Vec<u8>, ImmediateError> {
+    checked("addi", OP_IMM, 0b000, dist, rs1, val)
+}
+
+pub fn addiw(dist: Reg, rs1: Reg, val: i64) -> Result<Vec<u8>, ImmediateError> {
+    checked("addiw", OP_IMM_32, 0b000, dist, rs1, val)
+}
+
+pub fn slti(dist: Reg, rs1: Reg, val: i64) -> Result<Vec<u8>, ImmediateError> {
+    checked("slti", OP_IMM, 0b010, dist, rs1, val)
+}
+
+pub fn sltiu(dist: Reg, rs1: Reg, val: i64) -> Result<Vec<u8>, ImmediateError> {
+    checked("sltiu", OP_IMM, 0b011, dist, rs1, val)
+}
+
+pub fn xori(dist: Reg, rs1: Reg, val: i64) -> Result<Vec<u8>, ImmediateError> {
+    checked("xori", OP_IMM, 0b100, dist, rs1, val)
+}
+
+pub fn ori(dist: Reg, rs1: Reg, val: i64) -> Result<Vec<u8>, ImmediateError> {
+    checked("ori", OP_IMM, 0b110, dist, rs1, val)
+}
+
+pub fn andi(dist: Reg, rs1: Reg, val: i64) -> Result<Vec<u8>, ImmediateError> {
+    checked("andi", OP_IMM, 0b111, dist, rs1, val)
+}
+
+pub fn slli(dist: Reg, rs1: Reg, shamt: u32) -> Result<Vec<u8>, ImmediateError> {
+    shift("slli", OP_IMM, 0b001, dist, rs1, shamt, 0)
+}
+
+pub fn srli(dist: Reg, rs1: Reg, shamt: u32) -> Result<Vec<u8>, ImmediateError> {
+    shift("srli", OP_IMM, 0b101, dist, rs1, shamt, 0)
+}
+
+pub fn srai(dist: Reg, rs1: Reg, shamt: u32) -> Result<Vec<u8>, ImmediateError> {
+    shift("srai", OP_IMM, 0b101, dist, rs1, shamt, 0x400)
+}
+
+// Shifts of the low 32 bits, the result is sign extended
+pub fn slliw(dist: Reg, rs1: Reg, shamt: u32) -> Result<Vec<u8>, ImmediateError> {
+    shift("slliw", OP_IMM_32, 0b001, dist, rs1, shamt, 0)
+}
+
+pub fn srliw(dist: Reg, rs1: Reg, shamt: u32) -> Result<Vec<u8>, ImmediateError> {
+    shift("srliw", OP_IMM_32, 0b101, dist, rs1, shamt, 0)
+}
+
+pub fn sraiw(dist: Reg, rs1: Reg, shamt: u32) -> Result<Vec<u8>, ImmediateError> {
+    shift("sraiw", OP_IMM_32, 0b101, dist, rs1, shamt, 0x400)
+}
+
+// Loads, `width` is log2 of the size in bytes
+pub fn load(
+    dist: Reg,
+    base: Reg,
+    offset: i64,
+    width: u32,
+    unsigned: bool,
+) -> Result<Vec<u8>, ImmediateError> {
+    let name = match (width, unsigned) {
+        (0, false) => "lb",
+        (1, false) => "lh",
+        (2, false) => "lw",
+        (3, false) => "ld",
+        (0, true) => "lbu",
+        (1, true) => "lhu",
+        (2, true) => "lwu",
+        // 0b111 is reserved, there is no unsigned load of a whole register
+        (_, false) => return Err(ImmediateError::InvalidWidth("load", width)),
+        (_, true) => return Err(ImmediateError::InvalidWidth("unsigned load", width)),
+    };
+
+    let funct3 = width | if unsigned { 0b100 } else { 0 };
+    checked(name, LOAD, funct3, dist, base, offset)
+}
+
+pub fn ld(dist: Reg, base: Reg, offset: i64) -> Result<Vec<u8>, ImmediateError> {
+    load(dist, base, offset, 3, false)
+}
+
+pub fn lb(dist: Reg, base: Reg, offset: i64) -> Result<Vec<u8>, ImmediateError> {
+    load(dist, base, offset, 0, false)
+}
+
+pub fn lh(dist: Reg, base: Reg, offset: i64) -> Result<Vec<u8>, ImmediateError> {
+    load(dist, base, offset, 1, false)
+}
+
+pub fn lw(dist: Reg, base: Reg, offset: i64) -> Result<Vec<u8>, ImmediateError> {
+    load(dist, base, offset, 2, false)
+}
+
+pub fn lbu(dist: Reg, base: Reg, offset: i64) -> Result<Vec<u8>, ImmediateError> {
+    load(dist, base, offset, 0, true)
+}
+
+pub fn lhu(dist: Reg, base: Reg, offset: i64) -> Result<Vec<u8>, ImmediateError> {
+    load(dist, base, offset, 1, true)
+}
+
+pub fn lwu(dist: Reg, base: Reg, offset: i64) -> Result<Vec<u8>, ImmediateError> {
+    load(dist, base, offset, 2, true)
 }
@@ -1,42 +1,51 @@
-use super::immediate::{immediate_to_endian, ImmediateInstruction};
+use super::immediate::{
+    check_range, immediate_to_endian, ImmediateError, ImmediateInstruction, IMM12,
+};
 use super::regs::Reg;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum JmpError {
-    #[error("Target is not aligned correctly")]
-    NonAlignedAddress,
+    #[error("The offset {1} of `{0}` is not a multiple of 2")]
+    NonAlignedAddress(&'static str, i64),
+
+    #[error("The offset {1} of `{0}` is out of range")]
+    OutOfRange(&'static str, i64),
+
+    #[error("{0}")]
+    ImmediateError(#[from] ImmediateError),
 }
 
-pub fn jalr(dist: Reg, rs1: Reg, offset: i64) -> Vec<u8> {
-    immediate_to_endian(ImmediateInstruction {
+pub fn jalr(dist: Reg, rs1: Reg, offset: i64) -> Result<Vec<u8>, ImmediateError> {
+    Ok(immediate_to_endian(ImmediateInstruction {
         rs1,
         rd: dist,
-        imm: offset,
+        imm: check_range("jalr", offset, IMM12)?,
+        funct3: 0b000,
         opcode: 0x67,
-    })
+    }))
 }
 
 pub fn jal(target_pc: u64, current_pc: u64, rd: Reg) -> Result<Vec<u8>, JmpError> {
-    let offset = target_pc.wrapping_sub(current_pc) as i32;
+    let offset = target_pc.wrapping_sub(current_pc) as i64;
 
     let rd: u64 = rd.into();
 
-    if offset < -1048576 || offset > 1048574 {
-        // This is supposed to be replaced with a long jal
-        panic!("invalid offset: too big");
+    // Farther targets need auipc and jalr
+    if !(-1048576..=1048574).contains(&offset) {
+        return Err(JmpError::OutOfRange("jal", offset));
     }
 
     if (offset % 2) != 0 {
-        return Err(JmpError::NonAlignedAddress);
+        return Err(JmpError::NonAlignedAddress("jal", offset));
     }
 
-    let offset_in_units = offset / 2;
+    let offset_in_units = offset as i32 / 2;
 
     let imm = ((offset_in_units & 0x80000) << 12)
         | ((offset_in_units & 0x3FF) << 21)
         | ((offset_in_units & 0x400) << 10)
-        | ((offset_in_units & 0x7F800) >> 11);
+        | ((offset_in_units & 0x7F800) << 1);
 
     let instruction = imm as u32 | (rd as u32) << 7 | 0x6F;
 
@@ -0,0 +1,1195 @@
+use super::float::{op_fp, FloatFormat, IntFormat, RoundingMode};
+use super::immediate::{self, ImmediateError};
+use super::jmp::{jal, jalr, JmpError};
+use super::register::{register_to_endian, RegisterInstruction};
+use super::regs::{FReg, Reg};
+use super::{branch, store, upper};
+use std::fmt::Display;
+use thiserror::Error;
+
+const OP: u32 = 0x33;
+const OP_32: u32 = 0x3B;
+const OP_IMM: u32 = 0x13;
+const OP_IMM_32: u32 = 0x1B;
+const OP_FP: u32 = 0x53;
+const LOAD: u32 = 0x03;
+const STORE: u32 = 0x23;
+const BRANCH: u32 = 0x63;
+const MISC_MEM: u32 = 0x0F;
+const LUI: u32 = 0x37;
+const AUIPC: u32 = 0x17;
+const JAL: u32 = 0x6F;
+const JALR: u32 = 0x67;
+const SYSTEM: u32 = 0x73;
+const EBREAK: u32 = 0x0010_0073;
+
+#[derive(Error, Debug)]
+pub enum MachineError {
+    #[error("Register v{0} has not been allocated")]
+    VirtualRegister(u32),
+
+    #[error("The target of `{0}` has not been resolved")]
+    UnresolvedTarget(String),
+
+    #[error("{0:#010x} is not a supported instruction")]
+    InvalidInstruction(u32),
+
+    #[error("{0}")]
+    JmpError(#[from] JmpError),
+
+    #[error("{0}")]
+    ImmediateError(#[from] ImmediateError),
+}
+
+// Integer register operand, virtual registers are numbered per function until the
+// register allocator replaces them
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum MReg {
+    Virtual(u32),
+    Physical(Reg),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum MFReg {
+    Virtual(u32),
+    Physical(FReg),
+}
+
+impl From<Reg> for MReg {
+    fn from(value: Reg) -> Self {
+        MReg::Physical(value)
+    }
+}
+
+impl From<FReg> for MFReg {
+    fn from(value: FReg) -> Self {
+        MFReg::Physical(value)
+    }
+}
+
+impl MReg {
+    fn physical(self) -> Result<Reg, MachineError> {
+        match self {
+            MReg::Physical(reg) => Ok(reg),
+            MReg::Virtual(index) => Err(MachineError::VirtualRegister(index)),
+        }
+    }
+}
+
+impl MFReg {
+    fn physical(self) -> Result<FReg, MachineError> {
+        match self {
+            MFReg::Physical(reg) => Ok(reg),
+            MFReg::Virtual(index) => Err(MachineError::VirtualRegister(index)),
+        }
+    }
+}
+
+// A block of the function being generated
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct Label(pub usize);
+
+// Destination of a jump or branch, labels and symbols are fixups that become offsets
+// once the code is laid out
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub enum Target {
+    Label(Label),
+    Symbol(String),
+    // Relative to the instruction
+    Offset(i64),
+}
+
+// R-type integer operations, with the M extension
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum AluOp {
+    Add,
+    Sub,
+    Sll,
+    Slt,
+    Sltu,
+    Xor,
+    Srl,
+    Sra,
+    Or,
+    And,
+    Addw,
+    Subw,
+    Sllw,
+    Srlw,
+    Sraw,
+    Mul,
+    Mulh,
+    Mulhu,
+    Div,
+    Divu,
+    Rem,
+    Remu,
+    Mulw,
+    Divw,
+    Divuw,
+    Remw,
+    Remuw,
+}
+
+impl AluOp {
+    const ALL: [AluOp; 27] = [
+        AluOp::Add,
+        AluOp::Sub,
+        AluOp::Sll,
+        AluOp::Slt,
+        AluOp::Sltu,
+        AluOp::Xor,
+        AluOp::Srl,
+        AluOp::Sra,
+        AluOp::Or,
+        AluOp::And,
+        AluOp::Addw,
+        AluOp::Subw,
+        AluOp::Sllw,
+        AluOp::Srlw,
+        AluOp::Sraw,
+        AluOp::Mul,
+        AluOp::Mulh,
+        AluOp::Mulhu,
+        AluOp::Div,
+        AluOp::Divu,
+        AluOp::Rem,
+        AluOp::Remu,
+        AluOp::Mulw,
+        AluOp::Divw,
+        AluOp::Divuw,
+        AluOp::Remw,
+        AluOp::Remuw,
+    ];
+
+    // opcode, funct7 and funct3
+    fn fields(self) -> (u32, u32, u32) {
+        match self {
+            AluOp::Add => (OP, 0x00, 0b000),
+            AluOp::Sub => (OP, 0x20, 0b000),
+            AluOp::Sll => (OP, 0x00, 0b001),
+            AluOp::Slt => (OP, 0x00, 0b010),
+            AluOp::Sltu => (OP, 0x00, 0b011),
+            AluOp::Xor => (OP, 0x00, 0b100),
+            AluOp::Srl => (OP, 0x00, 0b101),
+            AluOp::Sra => (OP, 0x20, 0b101),
+            AluOp::Or => (OP, 0x00, 0b110),
+            AluOp::And => (OP, 0x00, 0b111),
+            AluOp::Addw => (OP_32, 0x00, 0b000),
+            AluOp::Subw => (OP_32, 0x20, 0b000),
+            AluOp::Sllw => (OP_32, 0x00, 0b001),
+            AluOp::Srlw => (OP_32, 0x00, 0b101),
+            AluOp::Sraw => (OP_32, 0x20, 0b101),
+            AluOp::Mul => (OP, 0x01, 0b000),
+            AluOp::Mulh => (OP, 0x01, 0b001),
+            AluOp::Mulhu => (OP, 0x01, 0b011),
+            AluOp::Div => (OP, 0x01, 0b100),
+            AluOp::Divu => (OP, 0x01, 0b101),
+            AluOp::Rem => (OP, 0x01, 0b110),
+            AluOp::Remu => (OP, 0x01, 0b111),
+            AluOp::Mulw => (OP_32, 0x01, 0b000),
+            AluOp::Divw => (OP_32, 0x01, 0b100),
+            AluOp::Divuw => (OP_32, 0x01, 0b101),
+            AluOp::Remw => (OP_32, 0x01, 0b110),
+            AluOp::Remuw => (OP_32, 0x01, 0b111),
+        }
+    }
+}
+
+// I-type integer operations, shifts take the amount as the immediate
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum ImmOp {
+    Addi,
+    Slti,
+    Sltiu,
+    Xori,
+    Ori,
+    Andi,
+    Slli,
+    Srli,
+    Srai,
+    Addiw,
+    Slliw,
+    Srliw,
+    Sraiw,
+}
+
+impl ImmOp {
+    const ALL: [ImmOp; 13] = [
+        ImmOp::Addi,
+        ImmOp::Slti,
+        ImmOp::Sltiu,
+        ImmOp::Xori,
+        ImmOp::Ori,
+        ImmOp::Andi,
+        ImmOp::Slli,
+        ImmOp::Srli,
+        ImmOp::Srai,
+        ImmOp::Addiw,
+        ImmOp::Slliw,
+        ImmOp::Srliw,
+        ImmOp::Sraiw,
+    ];
+
+    // opcode, funct3 and for shifts the mask of the amount and the bits above it
+    fn fields(self) -> (u32, u32, Option<(i64, i64)>) {
+        match self {
+            ImmOp::Addi => (OP_IMM, 0b000, None),
+            ImmOp::Slti => (OP_IMM, 0b010, None),
+            ImmOp::Sltiu => (OP_IMM, 0b011, None),
+            ImmOp::Xori => (OP_IMM, 0b100, None),
+            ImmOp::Ori => (OP_IMM, 0b110, None),
+            ImmOp::Andi => (OP_IMM, 0b111, None),
+            ImmOp::Slli => (OP_IMM, 0b001, Some((0x3F, 0))),
+            ImmOp::Srli => (OP_IMM, 0b101, Some((0x3F, 0))),
+            ImmOp::Srai => (OP_IMM, 0b101, Some((0x3F, 0x400))),
+            ImmOp::Addiw => (OP_IMM_32, 0b000, None),
+            ImmOp::Slliw => (OP_IMM_32, 0b001, Some((0x1F, 0))),
+            ImmOp::Srliw => (OP_IMM_32, 0b101, Some((0x1F, 0))),
+            ImmOp::Sraiw => (OP_IMM_32, 0b101, Some((0x1F, 0x400))),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum BranchCond {
+    Eq,
+    Ne,
+    Lt,
+    Ge,
+    Ltu,
+    Geu,
+}
+
+impl BranchCond {
+    const ALL: [BranchCond; 6] = [
+        BranchCond::Eq,
+        BranchCond::Ne,
+        BranchCond::Lt,
+        BranchCond::Ge,
+        BranchCond::Ltu,
+        BranchCond::Geu,
+    ];
+
+    fn funct3(self) -> u32 {
+        match self {
+            BranchCond::Eq => 0b000,
+            BranchCond::Ne => 0b001,
+            BranchCond::Lt => 0b100,
+            BranchCond::Ge => 0b101,
+            BranchCond::Ltu => 0b110,
+            BranchCond::Geu => 0b111,
+        }
+    }
+
+    pub fn encode(self, rs1: Reg, rs2: Reg, offset: i64) -> Result<Vec<u8>, JmpError> {
+        match self {
+            BranchCond::Eq => branch::beq(rs1, rs2, offset),
+            BranchCond::Ne => branch::bne(rs1, rs2, offset),
+            BranchCond::Lt => branch::blt(rs1, rs2, offset),
+            BranchCond::Ge => branch::bge(rs1, rs2, offset),
+            BranchCond::Ltu => branch::bltu(rs1, rs2, offset),
+            BranchCond::Geu => branch::bgeu(rs1, rs2, offset),
+        }
+    }
+
+    // Taken exactly when the original is not
+    pub fn invert(self) -> BranchCond {
+        match self {
+            BranchCond::Eq => BranchCond::Ne,
+            BranchCond::Ne => BranchCond::Eq,
+            BranchCond::Lt => BranchCond::Ge,
+            BranchCond::Ge => BranchCond::Lt,
+            BranchCond::Ltu => BranchCond::Geu,
+            BranchCond::Geu => BranchCond::Ltu,
+        }
+    }
+}
+
+// OP-FP operations between two floats of the same format
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum FloatOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Sgnj,
+    Sgnjn,
+}
+
+impl FloatOp {
+    const ALL: [FloatOp; 6] = [
+        FloatOp::Add,
+        FloatOp::Sub,
+        FloatOp::Mul,
+        FloatOp::Div,
+        FloatOp::Sgnj,
+        FloatOp::Sgnjn,
+    ];
+
+    // funct5 and funct3, arithmetic uses the dynamic rounding mode
+    fn fields(self) -> (u64, u64) {
+        match self {
+            FloatOp::Add => (0b00000, 0b111),
+            FloatOp::Sub => (0b00001, 0b111),
+            FloatOp::Mul => (0b00010, 0b111),
+            FloatOp::Div => (0b00011, 0b111),
+            FloatOp::Sgnj => (0b00100, 0b000),
+            FloatOp::Sgnjn => (0b00100, 0b001),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum FloatCmp {
+    Eq,
+    Lt,
+    Le,
+}
+
+impl FloatCmp {
+    const ALL: [FloatCmp; 3] = [FloatCmp::Eq, FloatCmp::Lt, FloatCmp::Le];
+
+    fn funct3(self) -> u64 {
+        match self {
+            FloatCmp::Eq => 0b010,
+            FloatCmp::Lt => 0b001,
+            FloatCmp::Le => 0b000,
+        }
+    }
+}
+
+// A RV64 instruction before encoding. Code generation produces these with virtual
+// registers and labels, which are replaced before `encode`
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub enum MachineInst {
+    Op {
+        op: AluOp,
+        rd: MReg,
+        rs1: MReg,
+        rs2: MReg,
+    },
+    OpImm {
+        op: ImmOp,
+        rd: MReg,
+        rs1: MReg,
+        imm: i64,
+    },
+    // `width` is log2 of the size in bytes
+    Load {
+        width: u32,
+        unsigned: bool,
+        rd: MReg,
+        base: MReg,
+        offset: i64,
+    },
+    Store {
+        width: u32,
+        src: MReg,
+        base: MReg,
+        offset: i64,
+    },
+    // `imm` holds bits 12..31 of the value
+    Lui {
+        rd: MReg,
+        imm: i64,
+    },
+    Auipc {
+        rd: MReg,
+        imm: i64,
+    },
+    Branch {
+        cond: BranchCond,
+        rs1: MReg,
+        rs2: MReg,
+        target: Target,
+    },
+    Jal {
+        rd: MReg,
+        target: Target,
+    },
+    Jalr {
+        rd: MReg,
+        rs1: MReg,
+        offset: i64,
+    },
+    Ecall,
+    Ebreak,
+    // `pred` and `succ` are sets of the I, O, R and W bits
+    Fence {
+        pred: u32,
+        succ: u32,
+    },
+    FloatOp {
+        op: FloatOp,
+        fmt: FloatFormat,
+        rd: MFReg,
+        rs1: MFReg,
+        rs2: MFReg,
+    },
+    FloatCmp {
+        cond: FloatCmp,
+        fmt: FloatFormat,
+        rd: MReg,
+        rs1: MFReg,
+        rs2: MFReg,
+    },
+    FcvtToInt {
+        fmt: FloatFormat,
+        int: IntFormat,
+        rm: RoundingMode,
+        rd: MReg,
+        rs1: MFReg,
+    },
+    FcvtFromInt {
+        fmt: FloatFormat,
+        int: IntFormat,
+        rm: RoundingMode,
+        rd: MFReg,
+        rs1: MReg,
+    },
+    // `fmt` is the destination
+    FcvtFloat {
+        fmt: FloatFormat,
+        from: FloatFormat,
+        rm: RoundingMode,
+        rd: MFReg,
+        rs1: MFReg,
+    },
+    FmvToInt {
+        fmt: FloatFormat,
+        rd: MReg,
+        rs1: MFReg,
+    },
+    FmvFromInt {
+        fmt: FloatFormat,
+        rd: MFReg,
+        rs1: MReg,
+    },
+}
+
+// Fields of an encoded instruction, immediates are sign extended
+struct Fields(u32);
+
+impl Fields {
+    fn opcode(&self) -> u32 {
+        self.0 & 0x7F
+    }
+
+    fn rd(&self) -> u32 {
+        self.0 >> 7 & 0x1F
+    }
+
+    fn funct3(&self) -> u32 {
+        self.0 >> 12 & 0x7
+    }
+
+    fn rs1(&self) -> u32 {
+        self.0 >> 15 & 0x1F
+    }
+
+    fn rs2(&self) -> u32 {
+        self.0 >> 20 & 0x1F
+    }
+
+    fn funct7(&self) -> u32 {
+        self.0 >> 25
+    }
+
+    fn imm_i(&self) -> i64 {
+        (self.0 as i32 >> 20) as i64
+    }
+
+    fn imm_s(&self) -> i64 {
+        ((self.0 as i32 >> 25) << 5 | (self.0 >> 7 & 0x1F) as i32) as i64
+    }
+
+    fn imm_b(&self) -> i64 {
+        let sign = (self.0 as i32 >> 31) << 12;
+        let imm = (self.0 >> 7 & 0x1) << 11 | (self.0 >> 25 & 0x3F) << 5 | (self.0 >> 8 & 0xF) << 1;
+
+        (sign | imm as i32) as i64
+    }
+
+    fn imm_u(&self) -> i64 {
+        (self.0 as i32 >> 12) as i64
+    }
+
+    fn imm_j(&self) -> i64 {
+        let sign = (self.0 as i32 >> 31) << 20;
+        let imm =
+            (self.0 >> 12 & 0xFF) << 12 | (self.0 >> 20 & 0x1) << 11 | (self.0 >> 21 & 0x3FF) << 1;
+
+        (sign | imm as i32) as i64
+    }
+
+    fn reg(&self, index: u32) -> Result<MReg, MachineError> {
+        Reg::from_index(index)
+            .map(MReg::Physical)
+            .ok_or(MachineError::InvalidInstruction(self.0))
+    }
+
+    fn freg(&self, index: u32) -> Result<MFReg, MachineError> {
+        FReg::from_index(index)
+            .map(MFReg::Physical)
+            .ok_or(MachineError::InvalidInstruction(self.0))
+    }
+}
+
+fn float_format(bits: u32) -> Option<FloatFormat> {
+    match bits {
+        0b00 => Some(FloatFormat::S),
+        0b01 => Some(FloatFormat::D),
+        0b10 => Some(FloatFormat::H),
+        _ => None,
+    }
+}
+
+fn int_format(bits: u32) -> Option<IntFormat> {
+    match bits {
+        0 => Some(IntFormat::W),
+        1 => Some(IntFormat::Wu),
+        2 => Some(IntFormat::L),
+        3 => Some(IntFormat::Lu),
+        _ => None,
+    }
+}
+
+fn rounding_mode(bits: u32) -> Option<RoundingMode> {
+    match bits {
+        0b000 => Some(RoundingMode::Rne),
+        0b001 => Some(RoundingMode::Rtz),
+        0b010 => Some(RoundingMode::Rdn),
+        0b011 => Some(RoundingMode::Rup),
+        0b100 => Some(RoundingMode::Rmm),
+        0b111 => Some(RoundingMode::Dyn),
+        _ => None,
+    }
+}
+
+impl MachineInst {
+    pub fn op(op: AluOp, rd: Reg, rs1: Reg, rs2: Reg) -> Self {
+        MachineInst::Op {
+            op,
+            rd: rd.into(),
+            rs1: rs1.into(),
+            rs2: rs2.into(),
+        }
+    }
+
+    pub fn imm(op: ImmOp, rd: Reg, rs1: Reg, imm: i64) -> Self {
+        MachineInst::OpImm {
+            op,
+            rd: rd.into(),
+            rs1: rs1.into(),
+            imm,
+        }
+    }
+
+    pub fn load(width: u32, unsigned: bool, rd: Reg, base: Reg, offset: i64) -> Self {
+        MachineInst::Load {
+            width,
+            unsigned,
+            rd: rd.into(),
+            base: base.into(),
+            offset,
+        }
+    }
+
+    pub fn store(width: u32, src: Reg, base: Reg, offset: i64) -> Self {
+        MachineInst::Store {
+            width,
+            src: src.into(),
+            base: base.into(),
+            offset,
+        }
+    }
+
+    pub fn branch(cond: BranchCond, rs1: Reg, rs2: Reg, target: Target) -> Self {
+        MachineInst::Branch {
+            cond,
+            rs1: rs1.into(),
+            rs2: rs2.into(),
+            target,
+        }
+    }
+
+    pub fn jal(rd: Reg, target: Target) -> Self {
+        MachineInst::Jal {
+            rd: rd.into(),
+            target,
+        }
+    }
+
+    // Jump or branch destination, to be replaced by an offset
+    pub fn target(&self) -> Option<&Target> {
+        match self {
+            MachineInst::Branch { target, .. } | MachineInst::Jal { target, .. } => Some(target),
+            _ => None,
+        }
+    }
+
+    pub fn target_mut(&mut self) -> Option<&mut Target> {
+        match self {
+            MachineInst::Branch { target, .. } | MachineInst::Jal { target, .. } => Some(target),
+            _ => None,
+        }
+    }
+
+    fn offset(&self) -> Result<i64, MachineError> {
+        match self.target() {
+            Some(Target::Offset(offset)) => Ok(*offset),
+            _ => Err(MachineError::UnresolvedTarget(self.to_string())),
+        }
+    }
+
+    // Every register must be physical and every target an offset
+    pub fn encode(&self) -> Result<u32, MachineError> {
+        let bytes = match self {
+            MachineInst::Op { op, rd, rs1, rs2 } => {
+                let (opcode, funct7, funct3) = op.fields();
+
+                register_to_endian(RegisterInstruction {
+                    funct3: funct3.into(),
+                    funct7: funct7.into(),
+                    rs2: rs2.physical()?.into(),
+                    rs1: rs1.physical()?.into(),
+                    rd: rd.physical()?.into(),
+                    opcode: opcode.into(),
+                })
+            }
+            MachineInst::OpImm { op, rd, rs1, imm } => {
+                let (rd, rs1) = (rd.physical()?, rs1.physical()?);
+                // Amounts that do not fit in a u32 are out of range anyway
+                let shamt = u32::try_from(*imm).unwrap_or(u32::MAX);
+
+                match op {
+                    ImmOp::Addi => immediate::addi(rd, rs1, *imm)?,
+                    ImmOp::Slti => immediate::slti(rd, rs1, *imm)?,
+                    ImmOp::Sltiu => immediate::sltiu(rd, rs1, *imm)?,
+                    ImmOp::Xori => immediate::xori(rd, rs1, *imm)?,
+                    ImmOp::Ori => immediate::ori(rd, rs1, *imm)?,
+                    ImmOp::Andi => immediate::andi(rd, rs1, *imm)?,
+                    ImmOp::Slli => immediate::slli(rd, rs1, shamt)?,
+                    ImmOp::Srli => immediate::srli(rd, rs1, shamt)?,
+                    ImmOp::Srai => immediate::srai(rd, rs1, shamt)?,
+                    ImmOp::Addiw => immediate::addiw(rd, rs1, *imm)?,
+                    ImmOp::Slliw => immediate::slliw(rd, rs1, shamt)?,
+                    ImmOp::Srliw => immediate::srliw(rd, rs1, shamt)?,
+                    ImmOp::Sraiw => immediate::sraiw(rd, rs1, shamt)?,
+                }
+            }
+            MachineInst::Load {
+                width,
+                unsigned,
+                rd,
+                base,
+                offset,
+            } => immediate::load(rd.physical()?, base.physical()?, *offset, *width, *unsigned)?,
+            MachineInst::Store {
+                width,
+                src,
+                base,
+                offset,
+            } => store::store(src.physical()?, base.physical()?, *offset, *width)?,
+            MachineInst::Lui { rd, imm } => upper::lui(rd.physical()?, *imm)?,
+            MachineInst::Auipc { rd, imm } => upper::auipc(rd.physical()?, *imm)?,
+            MachineInst::Branch { cond, rs1, rs2, .. } => {
+                cond.encode(rs1.physical()?, rs2.physical()?, self.offset()?)?
+            }
+            MachineInst::Jal { rd, .. } => jal(self.offset()? as u64, 0, rd.physical()?)?,
+            MachineInst::Jalr { rd, rs1, offset } => {
+                jalr(rd.physical()?, rs1.physical()?, *offset)?
+            }
+            MachineInst::Ecall => immediate::ecall(),
+            MachineInst::Ebreak => immediate::ebreak(),
+            MachineInst::Fence { pred, succ } => immediate::fence(*pred, *succ)?,
+            MachineInst::FloatOp {
+                op,
+                fmt,
+                rd,
+                rs1,
+                rs2,
+            } => {
+                let (funct5, funct3) = op.fields();
+                let (rd, rs1, rs2) = (rd.physical()?, rs1.physical()?, rs2.physical()?);
+                op_fp(
+                    funct5,
+                    *fmt,
+                    funct3,
+                    (&rd).into(),
+                    (&rs1).into(),
+                    (&rs2).into(),
+                )
+            }
+            MachineInst::FloatCmp {
+                cond,
+                fmt,
+                rd,
+                rs1,
+                rs2,
+            } => {
+                let (rs1, rs2) = (rs1.physical()?, rs2.physical()?);
+                let rd = rd.physical()?.into();
+                op_fp(
+                    0b10100,
+                    *fmt,
+                    cond.funct3(),
+                    rd,
+                    (&rs1).into(),
+                    (&rs2).into(),
+                )
+            }
+            MachineInst::FcvtToInt {
+                fmt,
+                int,
+                rm,
+                rd,
+                rs1,
+            } => {
+                let rs1 = rs1.physical()?;
+                let rd = rd.physical()?.into();
+                op_fp(
+                    0b11000,
+                    *fmt,
+                    (*rm).into(),
+                    rd,
+                    (&rs1).into(),
+                    (*int).into(),
+                )
+            }
+            MachineInst::FcvtFromInt {
+                fmt,
+                int,
+                rm,
+                rd,
+                rs1,
+            } => {
+                let rd = rd.physical()?;
+                let rs1 = rs1.physical()?.into();
+                op_fp(
+                    0b11010,
+                    *fmt,
+                    (*rm).into(),
+                    (&rd).into(),
+                    rs1,
+                    (*int).into(),
+                )
+            }
+            MachineInst::FcvtFloat {
+                fmt,
+                from,
+                rm,
+                rd,
+                rs1,
+            } => {
+                let (rd, rs1) = (rd.physical()?, rs1.physical()?);
+                op_fp(
+                    0b01000,
+                    *fmt,
+                    (*rm).into(),
+                    (&rd).into(),
+                    (&rs1).into(),
+                    (*from).into(),
+                )
+            }
+            MachineInst::FmvToInt { fmt, rd, rs1 } => {
+                let rs1 = rs1.physical()?;
+                op_fp(
+                    0b11100,
+                    *fmt,
+                    0b000,
+                    rd.physical()?.into(),
+                    (&rs1).into(),
+                    0,
+                )
+            }
+            MachineInst::FmvFromInt { fmt, rd, rs1 } => {
+                let rd = rd.physical()?;
+                op_fp(
+                    0b11110,
+                    *fmt,
+                    0b000,
+                    (&rd).into(),
+                    rs1.physical()?.into(),
+                    0,
+                )
+            }
+        };
+
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    // Inverse of `encode`, jumps and branches get their offset as the target
+    pub fn decode(word: u32) -> Result<MachineInst, MachineError> {
+        let fields = Fields(word);
+        let invalid = || MachineError::InvalidInstruction(word);
+
+        let inst = match fields.opcode() {
+            opcode @ (OP | OP_32) => {
+                let op = AluOp::ALL
+                    .into_iter()
+                    .find(|op| op.fields() == (opcode, fields.funct7(), fields.funct3()));
+
+                MachineInst::Op {
+                    op: op.ok_or_else(invalid)?,
+                    rd: fields.reg(fields.rd())?,
+                    rs1: fields.reg(fields.rs1())?,
+                    rs2: fields.reg(fields.rs2())?,
+                }
+            }
+            opcode @ (OP_IMM | OP_IMM_32) => {
+                let imm = fields.imm_i();
+                let op = ImmOp::ALL.into_iter().find(|op| {
+                    let (op_opcode, funct3, shift) = op.fields();
+
+                    op_opcode == opcode
+                        && funct3 == fields.funct3()
+                        && shift.is_none_or(|(mask, high)| imm & !mask == high)
+                });
+
+                let op = op.ok_or_else(invalid)?;
+                let imm = match op.fields().2 {
+                    Some((mask, _)) => imm & mask,
+                    None => imm,
+                };
+
+                MachineInst::OpImm {
+                    op,
+                    rd: fields.reg(fields.rd())?,
+                    rs1: fields.reg(fields.rs1())?,
+                    imm,
+                }
+            }
+            LOAD if fields.funct3() != 0b111 => MachineInst::Load {
+                width: fields.funct3() & 0b11,
+                unsigned: fields.funct3() & 0b100 != 0,
+                rd: fields.reg(fields.rd())?,
+                base: fields.reg(fields.rs1())?,
+                offset: fields.imm_i(),
+            },
+            STORE if fields.funct3() <= 0b011 => MachineInst::Store {
+                width: fields.funct3(),
+                src: fields.reg(fields.rs2())?,
+                base: fields.reg(fields.rs1())?,
+                offset: fields.imm_s(),
+            },
+            LUI => MachineInst::Lui {
+                rd: fields.reg(fields.rd())?,
+                imm: fields.imm_u(),
+            },
+            AUIPC => MachineInst::Auipc {
+                rd: fields.reg(fields.rd())?,
+                imm: fields.imm_u(),
+            },
+            // Only the plain fence, without the fm field or registers
+            MISC_MEM if word & 0xF00F_FF80 == 0 => MachineInst::Fence {
+                pred: word >> 24 & 0xF,
+                succ: word >> 20 & 0xF,
+            },
+            BRANCH => {
+                let cond = BranchCond::ALL
+                    .into_iter()
+                    .find(|cond| cond.funct3() == fields.funct3());
+
+                MachineInst::Branch {
+                    cond: cond.ok_or_else(invalid)?,
+                    rs1: fields.reg(fields.rs1())?,
+                    rs2: fields.reg(fields.rs2())?,
+                    target: Target::Offset(fields.imm_b()),
+                }
+            }
+            JAL => MachineInst::Jal {
+                rd: fields.reg(fields.rd())?,
+                target: Target::Offset(fields.imm_j()),
+            },
+            JALR if fields.funct3() == 0 => MachineInst::Jalr {
+                rd: fields.reg(fields.rd())?,
+                rs1: fields.reg(fields.rs1())?,
+                offset: fields.imm_i(),
+            },
+            SYSTEM if word == SYSTEM => MachineInst::Ecall,
+            SYSTEM if word == EBREAK => MachineInst::Ebreak,
+            OP_FP => {
+                let fmt = float_format(fields.funct7() & 0b11).ok_or_else(invalid)?;
+                let funct5 = u64::from(fields.funct7() >> 2);
+                let funct3 = fields.funct3();
+
+                match funct5 {
+                    0b10100 => {
+                        let cond = FloatCmp::ALL
+                            .into_iter()
+                            .find(|cond| cond.funct3() == funct3.into());
+
+                        MachineInst::FloatCmp {
+                            cond: cond.ok_or_else(invalid)?,
+                            fmt,
+                            rd: fields.reg(fields.rd())?,
+                            rs1: fields.freg(fields.rs1())?,
+                            rs2: fields.freg(fields.rs2())?,
+                        }
+                    }
+                    0b11000 => MachineInst::FcvtToInt {
+                        fmt,
+                        int: int_format(fields.rs2()).ok_or_else(invalid)?,
+                        rm: rounding_mode(funct3).ok_or_else(invalid)?,
+                        rd: fields.reg(fields.rd())?,
+                        rs1: fields.freg(fields.rs1())?,
+                    },
+                    0b11010 => MachineInst::FcvtFromInt {
+                        fmt,
+                        int: int_format(fields.rs2()).ok_or_else(invalid)?,
+                        rm: rounding_mode(funct3).ok_or_else(invalid)?,
+                        rd: fields.freg(fields.rd())?,
+                        rs1: fields.reg(fields.rs1())?,
+                    },
+                    // Converting to the same format is not an instruction
+                    0b01000 if float_format(fields.rs2()) != Some(fmt) => MachineInst::FcvtFloat {
+                        fmt,
+                        from: float_format(fields.rs2()).ok_or_else(invalid)?,
+                        rm: rounding_mode(funct3).ok_or_else(invalid)?,
+                        rd: fields.freg(fields.rd())?,
+                        rs1: fields.freg(fields.rs1())?,
+                    },
+                    0b11100 if funct3 == 0 && fields.rs2() == 0 => MachineInst::FmvToInt {
+                        fmt,
+                        rd: fields.reg(fields.rd())?,
+                        rs1: fields.freg(fields.rs1())?,
+                    },
+                    0b11110 if funct3 == 0 && fields.rs2() == 0 => MachineInst::FmvFromInt {
+                        fmt,
+                        rd: fields.freg(fields.rd())?,
+                        rs1: fields.reg(fields.rs1())?,
+                    },
+                    _ => {
+                        let op = FloatOp::ALL
+                            .into_iter()
+                            .find(|op| op.fields() == (funct5, funct3.into()));
+
+                        MachineInst::FloatOp {
+                            op: op.ok_or_else(invalid)?,
+                            fmt,
+                            rd: fields.freg(fields.rd())?,
+                            rs1: fields.freg(fields.rs1())?,
+                            rs2: fields.freg(fields.rs2())?,
+                        }
+                    }
+                }
+            }
+            _ => return Err(invalid()),
+        };
+
+        Ok(inst)
+    }
+}
+
+impl Display for MReg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MReg::Virtual(index) => write!(f, "v{}", index),
+            MReg::Physical(reg) => write!(f, "{}", reg),
+        }
+    }
+}
+
+impl Display for MFReg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MFReg::Virtual(index) => write!(f, "fv{}", index),
+            MFReg::Physical(reg) => write!(f, "{}", reg),
+        }
+    }
+}
+
+impl Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Target::Label(label) => write!(f, ".L{}", label.0),
+            Target::Symbol(name) => write!(f, "{}", name),
+            Target::Offset(offset) => write!(f, "{}", offset),
+        }
+    }
+}
+
+fn fence_set(set: u32) -> String {
+    let set: String = ['i', 'o', 'r', 'w']
+        .into_iter()
+        .enumerate()
+        .filter(|(bit, _)| set & 8 >> bit != 0)
+        .map(|(_, name)| name)
+        .collect();
+
+    if set.is_empty() {
+        "0".to_string()
+    } else {
+        set
+    }
+}
+
+// Widths without an instruction are written as they are, which no assembler accepts
+fn size_suffix(width: u32) -> String {
+    match width {
+        0 => "b".to_string(),
+        1 => "h".to_string(),
+        2 => "w".to_string(),
+        3 => "d".to_string(),
+        width => format!("<2^{} bytes>", width),
+    }
+}
+
+fn float_suffix(fmt: FloatFormat) -> &'static str {
+    match fmt {
+        FloatFormat::S => "s",
+        FloatFormat::D => "d",
+        FloatFormat::H => "h",
+    }
+}
+
+fn int_suffix(int: IntFormat) -> &'static str {
+    match int {
+        IntFormat::W => "w",
+        IntFormat::Wu => "wu",
+        IntFormat::L => "l",
+        IntFormat::Lu => "lu",
+    }
+}
+
+// The rounding mode is only written when it is not the dynamic one
+fn rounding_suffix(rm: RoundingMode) -> String {
+    match rm {
+        RoundingMode::Dyn => String::new(),
+        rm => format!(", {}", format!("{:?}", rm).to_lowercase()),
+    }
+}
+
+// Assembly syntax, as accepted by GNU as
+impl Display for MachineInst {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MachineInst::Op { op, rd, rs1, rs2 } => {
+                let name = format!("{:?}", op).to_lowercase();
+                write!(f, "{} {}, {}, {}", name, rd, rs1, rs2)
+            }
+            MachineInst::OpImm { op, rd, rs1, imm } => {
+                let name = format!("{:?}", op).to_lowercase();
+                write!(f, "{} {}, {}, {}", name, rd, rs1, imm)
+            }
+            MachineInst::Load {
+                width,
+                unsigned,
+                rd,
+                base,
+                offset,
+            } => {
+                let size = size_suffix(*width);
+                let unsigned = if *unsigned { "u" } else { "" };
+                write!(f, "l{}{} {}, {}({})", size, unsigned, rd, offset, base)
+            }
+            MachineInst::Store {
+                width,
+                src,
+                base,
+                offset,
+            } => {
+                write!(f, "s{} {}, {}({})", size_suffix(*width), src, offset, base)
+            }
+            MachineInst::Lui { rd, imm } => write!(f, "lui {}, {:#x}", rd, imm & 0xFFFFF),
+            MachineInst::Auipc { rd, imm } => write!(f, "auipc {}, {:#x}", rd, imm & 0xFFFFF),
+            MachineInst::Branch {
+                cond,
+                rs1,
+                rs2,
+                target,
+            } => {
+                let name = format!("{:?}", cond).to_lowercase();
+                write!(f, "b{} {}, {}, {}", name, rs1, rs2, target)
+            }
+            MachineInst::Jal { rd, target } => write!(f, "jal {}, {}", rd, target),
+            MachineInst::Jalr { rd, rs1, offset } => {
+                write!(f, "jalr {}, {}({})", rd, offset, rs1)
+            }
+            MachineInst::Ecall => write!(f, "ecall"),
+            MachineInst::Ebreak => write!(f, "ebreak"),
+            MachineInst::Fence { pred, succ } => {
+                write!(f, "fence {}, {}", fence_set(*pred), fence_set(*succ))
+            }
+            MachineInst::FloatOp {
+                op,
+                fmt,
+                rd,
+                rs1,
+                rs2,
+            } => {
+                let name = format!("{:?}", op).to_lowercase();
+                let fmt = float_suffix(*fmt);
+                write!(f, "f{}.{} {}, {}, {}", name, fmt, rd, rs1, rs2)
+            }
+            MachineInst::FloatCmp {
+                cond,
+                fmt,
+                rd,
+                rs1,
+                rs2,
+            } => {
+                let name = format!("{:?}", cond).to_lowercase();
+                let fmt = float_suffix(*fmt);
+                write!(f, "f{}.{} {}, {}, {}", name, fmt, rd, rs1, rs2)
+            }
+            MachineInst::FcvtToInt {
+                fmt,
+                int,
+                rm,
+                rd,
+                rs1,
+            } => {
+                let (int, fmt, rm) = (int_suffix(*int), float_suffix(*fmt), rounding_suffix(*rm));
+                write!(f, "fcvt.{}.{} {}, {}{}", int, fmt, rd, rs1, rm)
+            }
+            MachineInst::FcvtFromInt {
+                fmt,
+                int,
+                rm,
+                rd,
+                rs1,
+            } => {
+                let (fmt, int, rm) = (float_suffix(*fmt), int_suffix(*int), rounding_suffix(*rm));
+                write!(f, "fcvt.{}.{} {}, {}{}", fmt, int, rd, rs1, rm)
+            }
+            MachineInst::FcvtFloat {
+                fmt,
+                from,
+                rm,
+                rd,
+                rs1,
+            } => {
+                let (fmt, from) = (float_suffix(*fmt), float_suffix(*from));
+                write!(
+                    f,
+                    "fcvt.{}.{} {}, {}{}",
+                    fmt,
+                    from,
+                    rd,
+                    rs1,
+                    rounding_suffix(*rm)
+                )
+            }
+            // fmv.x.w is the name of the single precision move
+            MachineInst::FmvToInt { fmt, rd, rs1 } => {
+                let fmt = if *fmt == FloatFormat::S {
+                    "w"
+                } else {
+                    float_suffix(*fmt)
+                };
+                write!(f, "fmv.x.{} {}, {}", fmt, rd, rs1)
+            }
+            MachineInst::FmvFromInt { fmt, rd, rs1 } => {
+                let fmt = if *fmt == FloatFormat::S {
+                    "w"
+                } else {
+                    float_suffix(*fmt)
+                };
+                write!(f, "fmv.{}.x {}, {}", fmt, rd, rs1)
+            }
+        }
+    }
+}
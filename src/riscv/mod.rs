@@ -1,5 +1,11 @@
+pub mod branch;
+pub mod constant;
 pub mod decode;
+pub mod float;
 pub mod immediate;
 pub mod jmp;
+pub mod machine;
 pub mod register;
 pub mod regs;
+pub mod store;
+pub mod upper;
@@ -1,16 +1,21 @@
 use super::regs::Reg;
 
+const OP: u64 = 0b0110011;
+const OP_32: u64 = 0b0111011;
+// funct7 of the M extension
+const MULDIV: u64 = 0b0000001;
+
 #[derive(Debug)]
 pub struct RegisterInstruction {
-    funct3: u64,
-    funct7: u64,
-    rs2: u64,
-    rs1: u64,
-    rd: u64,
-    opcode: u64,
+    pub funct3: u64,
+    pub funct7: u64,
+    pub rs2: u64,
+    pub rs1: u64,
+    pub rd: u64,
+    pub opcode: u64,
 }
 
-fn register_to_endian(ins: RegisterInstruction) -> Vec<u8> {
+pub fn register_to_endian(ins: RegisterInstruction) -> Vec<u8> {
     let instruction: u32 = ((ins.funct7 as u32) << 25)
         | ((ins.rs2 as u32) << 20)
         | ((ins.rs1 as u32) << 15)
@@ -21,24 +26,123 @@ fn register_to_endian(ins: RegisterInstruction) -> Vec<u8> {
     instruction.to_le_bytes().to_vec()
 }
 
-pub fn add(rd: &Reg, rs1: &Reg, rs2: &Reg) -> Vec<u8> {
+fn op(opcode: u64, funct7: u64, funct3: u64, rd: &Reg, rs1: &Reg, rs2: &Reg) -> Vec<u8> {
     register_to_endian(RegisterInstruction {
-        funct3: 0x0,
-        funct7: 0x0,
+        funct3,
+        funct7,
         rs1: rs1.into(),
         rs2: rs2.into(),
         rd: rd.into(),
-        opcode: 0b0110011,
+        opcode,
     })
 }
 
+pub fn add(rd: &Reg, rs1: &Reg, rs2: &Reg) -> Vec<u8> {
+    op(OP, 0x0, 0b000, rd, rs1, rs2)
+}
+
 pub fn sub(rd: &Reg, rs1: &Reg, rs2: &Reg) -> Vec<u8> {
-    register_to_endian(RegisterInstruction {
-        funct3: 0x0,
-        funct7: 0x20,
-        rs1: rs1.into(),
-        rs2: rs2.into(),
-        rd: rd.into(),
-        opcode: 0b110011,
-    })
+    op(OP, 0x20, 0b000, rd, rs1, rs2)
+}
+
+pub fn sll(rd: &Reg, rs1: &Reg, rs2: &Reg) -> Vec<u8> {
+    op(OP, 0x0, 0b001, rd, rs1, rs2)
+}
+
+pub fn slt(rd: &Reg, rs1: &Reg, rs2: &Reg) -> Vec<u8> {
+    op(OP, 0x0, 0b010, rd, rs1, rs2)
+}
+
+pub fn sltu(rd: &Reg, rs1: &Reg, rs2: &Reg) -> Vec<u8> {
+    op(OP, 0x0, 0b011, rd, rs1, rs2)
+}
+
+pub fn xor(rd: &Reg, rs1: &Reg, rs2: &Reg) -> Vec<u8> {
+    op(OP, 0x0, 0b100, rd, rs1, rs2)
+}
+
+pub fn srl(rd: &Reg, rs1: &Reg, rs2: &Reg) -> Vec<u8> {
+    op(OP, 0x0, 0b101, rd, rs1, rs2)
+}
+
+pub fn sra(rd: &Reg, rs1: &Reg, rs2: &Reg) -> Vec<u8> {
+    op(OP, 0x20, 0b101, rd, rs1, rs2)
+}
+
+pub fn or(rd: &Reg, rs1: &Reg, rs2: &Reg) -> Vec<u8> {
+    op(OP, 0x0, 0b110, rd, rs1, rs2)
+}
+
+pub fn and(rd: &Reg, rs1: &Reg, rs2: &Reg) -> Vec<u8> {
+    op(OP, 0x0, 0b111, rd, rs1, rs2)
+}
+
+pub fn addw(rd: &Reg, rs1: &Reg, rs2: &Reg) -> Vec<u8> {
+    op(OP_32, 0x0, 0b000, rd, rs1, rs2)
+}
+
+pub fn subw(rd: &Reg, rs1: &Reg, rs2: &Reg) -> Vec<u8> {
+    op(OP_32, 0x20, 0b000, rd, rs1, rs2)
+}
+
+pub fn sllw(rd: &Reg, rs1: &Reg, rs2: &Reg) -> Vec<u8> {
+    op(OP_32, 0x0, 0b001, rd, rs1, rs2)
+}
+
+pub fn srlw(rd: &Reg, rs1: &Reg, rs2: &Reg) -> Vec<u8> {
+    op(OP_32, 0x0, 0b101, rd, rs1, rs2)
+}
+
+pub fn sraw(rd: &Reg, rs1: &Reg, rs2: &Reg) -> Vec<u8> {
+    op(OP_32, 0x20, 0b101, rd, rs1, rs2)
+}
+
+// M extension
+
+pub fn mul(rd: &Reg, rs1: &Reg, rs2: &Reg) -> Vec<u8> {
+    op(OP, MULDIV, 0b000, rd, rs1, rs2)
+}
+
+pub fn mulh(rd: &Reg, rs1: &Reg, rs2: &Reg) -> Vec<u8> {
+    op(OP, MULDIV, 0b001, rd, rs1, rs2)
+}
+
+pub fn mulhu(rd: &Reg, rs1: &Reg, rs2: &Reg) -> Vec<u8> {
+    op(OP, MULDIV, 0b011, rd, rs1, rs2)
+}
+
+pub fn div(rd: &Reg, rs1: &Reg, rs2: &Reg) -> Vec<u8> {
+    op(OP, MULDIV, 0b100, rd, rs1, rs2)
+}
+
+pub fn divu(rd: &Reg, rs1: &Reg, rs2: &Reg) -> Vec<u8> {
+    op(OP, MULDIV, 0b101, rd, rs1, rs2)
+}
+
+pub fn rem(rd: &Reg, rs1: &Reg, rs2: &Reg) -> Vec<u8> {
+    op(OP, MULDIV, 0b110, rd, rs1, rs2)
+}
+
+pub fn remu(rd: &Reg, rs1: &Reg, rs2: &Reg) -> Vec<u8> {
+    op(OP, MULDIV, 0b111, rd, rs1, rs2)
+}
+
+pub fn mulw(rd: &Reg, rs1: &Reg, rs2: &Reg) -> Vec<u8> {
+    op(OP_32, MULDIV, 0b000, rd, rs1, rs2)
+}
+
+pub fn divw(rd: &Reg, rs1: &Reg, rs2: &Reg) -> Vec<u8> {
+    op(OP_32, MULDIV, 0b100, rd, rs1, rs2)
+}
+
+pub fn divuw(rd: &Reg, rs1: &Reg, rs2: &Reg) -> Vec<u8> {
+    op(OP_32, MULDIV, 0b101, rd, rs1, rs2)
+}
+
+pub fn remw(rd: &Reg, rs1: &Reg, rs2: &Reg) -> Vec<u8> {
+    op(OP_32, MULDIV, 0b110, rd, rs1, rs2)
+}
+
+pub fn remuw(rd: &Reg, rs1: &Reg, rs2: &Reg) -> Vec<u8> {
+    op(OP_32, MULDIV, 0b111, rd, rs1, rs2)
 }
@@ -1,6 +1,7 @@
+use std::fmt::Display;
 use std::str::FromStr;
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
 pub enum Reg {
     Zero,
     Ra,
@@ -90,7 +91,7 @@ impl TryFrom<&String> for Reg {
     type Error = String;
 
     fn try_from(value: &String) -> Result<Self, Self::Error> {
-        Ok(Reg::from_str(value)?)
+        Reg::from_str(value)
     }
 }
 
@@ -137,45 +138,148 @@ impl From<&Reg> for u64 {
     }
 }
 
-impl Into<u64> for Reg {
-    fn into(self) -> u64 {
-        match self {
-            Reg::Zero => 0x00,
-            Reg::Ra => 0x01,
-            Reg::Sp => 0x02,
-            Reg::Gp => 0x03,
-            Reg::Tp => 0x04,
-            Reg::T0 => 0x05,
-            Reg::T1 => 0x06,
-            Reg::T2 => 0x07,
-            Reg::S0 => 0x08,
-            Reg::S1 => 0x09,
-            Reg::A0 => 0x0A,
-            Reg::A1 => 0x0B,
-            Reg::A2 => 0x0C,
-            Reg::A3 => 0x0D,
-            Reg::A4 => 0x0E,
-            Reg::A5 => 0x0F,
-            Reg::A6 => 0x10,
-            Reg::A7 => 0x11,
-            Reg::S2 => 0x12,
-            Reg::S3 => 0x13,
-            Reg::S4 => 0x14,
-            Reg::S5 => 0x15,
-            Reg::S6 => 0x16,
-            Reg::S7 => 0x17,
-            Reg::S8 => 0x18,
-            Reg::S9 => 0x19,
-            Reg::S10 => 0x1A,
-            Reg::S11 => 0x1B,
-            Reg::T3 => 0x1C,
-            Reg::T4 => 0x1D,
-            Reg::T5 => 0x1E,
-            Reg::T6 => 0x1F,
-            Reg::Mstatus => 0x300,
-            Reg::Mepc => 0x341,
-            Reg::Mtvec => 0x305,
-            Reg::Mcause => 0x342,
-        }
+impl From<Reg> for u64 {
+    fn from(value: Reg) -> Self {
+        u64::from(&value)
+    }
+}
+
+// General purpose registers in encoding order
+const GPRS: [Reg; 32] = [
+    Reg::Zero,
+    Reg::Ra,
+    Reg::Sp,
+    Reg::Gp,
+    Reg::Tp,
+    Reg::T0,
+    Reg::T1,
+    Reg::T2,
+    Reg::S0,
+    Reg::S1,
+    Reg::A0,
+    Reg::A1,
+    Reg::A2,
+    Reg::A3,
+    Reg::A4,
+    Reg::A5,
+    Reg::A6,
+    Reg::A7,
+    Reg::S2,
+    Reg::S3,
+    Reg::S4,
+    Reg::S5,
+    Reg::S6,
+    Reg::S7,
+    Reg::S8,
+    Reg::S9,
+    Reg::S10,
+    Reg::S11,
+    Reg::T3,
+    Reg::T4,
+    Reg::T5,
+    Reg::T6,
+];
+
+impl Reg {
+    // Register of a 5 bit field of an instruction
+    pub fn from_index(index: u32) -> Option<Reg> {
+        GPRS.get(index as usize).copied()
+    }
+}
+
+// ABI name, as written in assembly
+impl Display for Reg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", format!("{:?}", self).to_lowercase())
+    }
+}
+
+// Registers of the F and D extensions, in encoding order
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum FReg {
+    Ft0,
+    Ft1,
+    Ft2,
+    Ft3,
+    Ft4,
+    Ft5,
+    Ft6,
+    Ft7,
+    Fs0,
+    Fs1,
+    Fa0,
+    Fa1,
+    Fa2,
+    Fa3,
+    Fa4,
+    Fa5,
+    Fa6,
+    Fa7,
+    Fs2,
+    Fs3,
+    Fs4,
+    Fs5,
+    Fs6,
+    Fs7,
+    Fs8,
+    Fs9,
+    Fs10,
+    Fs11,
+    Ft8,
+    Ft9,
+    Ft10,
+    Ft11,
+}
+
+impl From<&FReg> for u64 {
+    fn from(value: &FReg) -> Self {
+        *value as u64
+    }
+}
+
+const FPRS: [FReg; 32] = [
+    FReg::Ft0,
+    FReg::Ft1,
+    FReg::Ft2,
+    FReg::Ft3,
+    FReg::Ft4,
+    FReg::Ft5,
+    FReg::Ft6,
+    FReg::Ft7,
+    FReg::Fs0,
+    FReg::Fs1,
+    FReg::Fa0,
+    FReg::Fa1,
+    FReg::Fa2,
+    FReg::Fa3,
+    FReg::Fa4,
+    FReg::Fa5,
+    FReg::Fa6,
+    FReg::Fa7,
+    FReg::Fs2,
+    FReg::Fs3,
+    FReg::Fs4,
+    FReg::Fs5,
+    FReg::Fs6,
+    FReg::Fs7,
+    FReg::Fs8,
+    FReg::Fs9,
+    FReg::Fs10,
+    FReg::Fs11,
+    FReg::Ft8,
+    FReg::Ft9,
+    FReg::Ft10,
+    FReg::Ft11,
+];
+
+impl FReg {
+    pub fn from_index(index: u32) -> Option<FReg> {
+        FPRS.get(index as usize).copied()
+    }
+}
+
+impl Display for FReg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", format!("{:?}", self).to_lowercase())
     }
 }
@@ -0,0 +1,60 @@
+use super::immediate::{check_range, ImmediateError, IMM12};
+use super::regs::Reg;
+
+const STORE: u32 = 0x23;
+
+#[derive(Debug)]
+pub struct StoreInstruction {
+    pub funct3: u32,
+    pub rs1: Reg,
+    pub rs2: Reg,
+    pub imm: i64,
+}
+
+// S-type, the immediate is split around rs1 and rs2
+fn store_to_endian(ins: StoreInstruction) -> Vec<u8> {
+    let rs1: u64 = ins.rs1.into();
+    let rs2: u64 = ins.rs2.into();
+    let imm = ins.imm as u32;
+    let instruction = (imm >> 5 & 0x7F) << 25
+        | (rs2 as u32) << 20
+        | (rs1 as u32) << 15
+        | ins.funct3 << 12
+        | (imm & 0x1F) << 7
+        | STORE;
+    instruction.to_le_bytes().to_vec()
+}
+
+// Stores the low 2^width bytes of `src`
+pub fn store(src: Reg, base: Reg, offset: i64, width: u32) -> Result<Vec<u8>, ImmediateError> {
+    let name = match width {
+        0 => "sb",
+        1 => "sh",
+        2 => "sw",
+        3 => "sd",
+        _ => return Err(ImmediateError::InvalidWidth("store", width)),
+    };
+
+    Ok(store_to_endian(StoreInstruction {
+        funct3: width,
+        rs1: base,
+        rs2: src,
+        imm: check_range(name, offset, IMM12)?,
+    }))
+}
+
+pub fn sd(src: Reg, base: Reg, offset: i64) -> Result<Vec<u8>, ImmediateError> {
+    store(src, base, offset, 3)
+}
+
+pub fn sb(src: Reg, base: Reg, offset: i64) -> Result<Vec<u8>, ImmediateError> {
+    store(src, base, offset, 0)
+}
+
+pub fn sh(src: Reg, base: Reg, offset: i64) -> Result<Vec<u8>, ImmediateError> {
+    store(src, base, offset, 1)
+}
+
+pub fn sw(src: Reg, base: Reg, offset: i64) -> Result<Vec<u8>, ImmediateError> {
+    store(src, base, offset, 2)
+}
@@ -0,0 +1,24 @@
+use super::immediate::{check_range, ImmediateError};
+use super::regs::Reg;
+use std::ops::RangeInclusive;
+
+// The upper immediate is 20 bits, written either signed or as the raw bits
+const IMM20: RangeInclusive<i64> = -0x80000..=0xFFFFF;
+
+// U-type, `imm` holds bits 12..31 of the value
+fn upper_to_endian(opcode: u32, rd: Reg, imm: i64) -> Vec<u8> {
+    let rd: u64 = rd.into();
+    let instruction = (imm as u32) << 12 | (rd as u32) << 7 | opcode;
+    instruction.to_le_bytes().to_vec()
+}
+
+pub fn lui(dist: Reg, imm: i64) -> Result<Vec<u8>, ImmediateError> {
+    let imm = check_range("lui", imm, IMM20)?;
+    Ok(upper_to_endian(0x37, dist, imm))
+}
+
+// Adds the upper immediate to the address of the instruction
+pub fn auipc(dist: Reg, imm: i64) -> Result<Vec<u8>, ImmediateError> {
+    let imm = check_range("auipc", imm, IMM20)?;
+    Ok(upper_to_endian(0x17, dist, imm))
+}
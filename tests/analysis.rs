@@ -0,0 +1,157 @@
+mod common;
+
+use common::parse;
+use tinity::analysis::induction::inductions;
+use tinity::analysis::AnalysisManager;
+use tinity::ir::function::{Block, Function, Value};
+
+const NEST: &str = "
+define i64 @nest(i64 %n) {
+entry:
+    br label %outer
+outer:
+    %i = phi i64 [ 0, %entry ], [ %i2, %latch ]
+    %s = phi i64 [ 0, %entry ], [ %t2, %latch ]
+    br label %inner
+inner:
+    %j = phi i64 [ 0, %outer ], [ %j2, %inner ]
+    %t = phi i64 [ %s, %outer ], [ %t2, %inner ]
+    %t2 = add i64 %t, %j
+    %j2 = add i64 %j, 1
+    %c = icmp slt i64 %j2, %n
+    br i1 %c, label %inner, label %latch
+latch:
+    %i2 = add i64 %i, 2
+    %d = icmp slt i64 %i2, %n
+    br i1 %d, label %outer, label %exit
+exit:
+    ret i64 %t2
+}
+";
+
+fn block(function: &Function, name: &str) -> Block {
+    *function
+        .layout
+        .iter()
+        .find(|block| function.blocks[block.0].name == name)
+        .expect("the block should exist")
+}
+
+fn value(function: &Function, name: &str) -> Value {
+    let index = function
+        .values
+        .iter()
+        .position(|value| value.name.as_deref() == Some(name))
+        .expect("the value should exist");
+
+    Value(index)
+}
+
+#[test]
+fn finds_dominators_and_frontiers() {
+    let function = &parse(NEST).functions[0];
+    let mut analyses = AnalysisManager::new();
+    let domtree = analyses.domtree(function);
+    let frontiers = analyses.frontiers(function);
+    let [entry, outer, inner, latch, exit] =
+        ["entry", "outer", "inner", "latch", "exit"].map(|name| block(function, name));
+
+    assert_eq!(domtree.idom(entry), None);
+    assert_eq!(domtree.idom(outer), Some(entry));
+    assert_eq!(domtree.idom(inner), Some(outer));
+    assert_eq!(domtree.idom(latch), Some(inner));
+    assert_eq!(domtree.idom(exit), Some(latch));
+
+    assert!(domtree.dominates(outer, exit));
+    assert!(domtree.dominates(inner, inner));
+    assert!(!domtree.strictly_dominates(inner, inner));
+    assert!(!domtree.dominates(latch, outer));
+
+    let mut frontier = frontiers.frontier(inner).to_vec();
+    frontier.sort();
+    assert_eq!(frontier, [outer, inner]);
+    assert_eq!(frontiers.frontier(latch), [outer]);
+    assert!(frontiers.frontier(exit).is_empty());
+}
+
+#[test]
+fn finds_nested_loops() {
+    let function = &parse(NEST).functions[0];
+    let mut analyses = AnalysisManager::new();
+    let cfg = analyses.cfg(function);
+    let loops = analyses.loops(function);
+    let [entry, outer, inner, latch, exit] =
+        ["entry", "outer", "inner", "latch", "exit"].map(|name| block(function, name));
+
+    let top = loops.top_level();
+    assert_eq!(top.len(), 1);
+
+    let outer_loop = loops.data(top[0]);
+    assert_eq!(outer_loop.header, outer);
+    assert_eq!(outer_loop.blocks, [outer, inner, latch]);
+    assert_eq!(outer_loop.latches, [latch]);
+    assert_eq!(outer_loop.depth, 1);
+    assert_eq!(loops.preheader(top[0], &cfg), Some(entry));
+    assert_eq!(loops.exits(top[0], &cfg), [exit]);
+
+    let inner_loop = loops.innermost(inner).expect("inner is in a loop");
+    assert_eq!(outer_loop.children, [inner_loop]);
+
+    let data = loops.data(inner_loop);
+    assert_eq!(data.header, inner);
+    assert_eq!(data.latches, [inner]);
+    assert_eq!(data.parent, Some(top[0]));
+    assert_eq!(loops.depth(inner), 2);
+    assert_eq!(loops.depth(exit), 0);
+    assert_eq!(loops.preheader(inner_loop, &cfg), Some(outer));
+    assert_eq!(loops.exits(inner_loop, &cfg), [latch]);
+}
+
+#[test]
+fn computes_liveness_around_phis() {
+    let function = &parse(NEST).functions[0];
+    let mut analyses = AnalysisManager::new();
+    let liveness = analyses.liveness(function);
+    let [outer, inner, latch] = ["outer", "inner", "latch"].map(|name| block(function, name));
+    let [n, i, s, j, t2, j2, i2] =
+        ["n", "i", "s", "j", "t2", "j2", "i2"].map(|name| value(function, name));
+
+    // Phi results are defined at the start of their block
+    assert!(!liveness.is_live_in(outer, i));
+    assert!(!liveness.is_live_in(inner, j));
+
+    // Phi operands are live out of their incoming block only
+    assert!(liveness.is_live_out(outer, s));
+    assert!(!liveness.is_live_in(inner, s));
+    assert!(liveness.is_live_out(inner, j2));
+    assert!(liveness.is_live_out(latch, i2));
+    assert!(liveness.is_live_out(latch, t2));
+
+    // Values used later in the loop stay live through it
+    assert!(liveness.is_live_in(inner, i));
+    assert!(liveness.is_live_in(inner, n));
+    assert!(liveness.is_live_in(latch, n));
+    assert!(!liveness.is_live_out(latch, j2));
+}
+
+#[test]
+fn finds_induction_variables() {
+    let function = &parse(NEST).functions[0];
+    let mut analyses = AnalysisManager::new();
+    let cfg = analyses.cfg(function);
+    let loops = analyses.loops(function);
+
+    let steps = |lp| {
+        let preheader = loops.preheader(lp, &cfg).unwrap();
+        inductions(function, loops.data(lp), preheader)
+            .iter()
+            .map(|induction| (function.value_name(induction.phi), induction.step_value()))
+            .collect::<Vec<_>>()
+    };
+
+    let outer = loops.top_level()[0];
+    let inner = loops.data(outer).children[0];
+
+    assert_eq!(steps(outer), [("%i".to_string(), 2)]);
+    assert_eq!(steps(inner), [("%j".to_string(), 1)]);
+}
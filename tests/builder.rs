@@ -0,0 +1,84 @@
+use tinity::ir::builder::{FunctionBuilder, ModuleBuilder};
+use tinity::ir::inst::{BinaryOp, IntCC};
+use tinity::ir::types::Ty;
+use tinity::ir::verify::VerifyErrorKind;
+use tinity::parser::types::Type;
+
+fn abs() -> FunctionBuilder {
+    let mut builder = FunctionBuilder::new("abs".to_string()).set_ret(Ty::I64);
+    let x = builder.add_param(Ty::I64, Some("x".to_string()));
+
+    let entry = builder.create_block("entry".to_string());
+    let negate = builder.create_block("negate".to_string());
+    let done = builder.create_block("done".to_string());
+
+    builder.switch_to_block(entry);
+    let negative = builder.ins().icmp(IntCC::Slt, x, Type::int(64, true, 0));
+    builder.ins().branch(negative, negate, done);
+
+    builder.switch_to_block(negate);
+    let minus = builder
+        .ins()
+        .binary(BinaryOp::Sub, Type::int(64, true, 0), x);
+    builder.ins().jump(done);
+
+    builder.switch_to_block(done);
+    let result = builder
+        .ins()
+        .phi(Ty::I64, vec![(x.into(), entry), (minus.into(), negate)]);
+    builder.ins().ret(Some(result.into()));
+
+    builder
+}
+
+#[test]
+fn builds_valid_functions() {
+    let function = abs().build().expect("abs should verify");
+
+    assert_eq!(function.name, "abs");
+    assert!(ModuleBuilder::new().add_function(function).build().is_ok());
+}
+
+#[test]
+fn rejects_invalid_functions() {
+    let mut builder = FunctionBuilder::new("f".to_string()).set_ret(Ty::I64);
+    let x = builder.add_param(Ty::I64, None);
+    builder
+        .ins()
+        .binary(BinaryOp::Add, x, Type::int(64, true, 1));
+
+    let error = builder.build().expect_err("the block has no terminator");
+    assert!(matches!(error.kind, VerifyErrorKind::MissingTerminator(_)));
+}
+
+#[test]
+fn rejects_operands_without_a_type() {
+    let mut builder = FunctionBuilder::new("f".to_string()).set_ret(Ty::I64);
+    let named = Type::Value("%x".to_string());
+    let result = builder
+        .ins()
+        .binary(BinaryOp::Add, named.clone(), Type::int(64, true, 1));
+    builder.ins().ret(Some(result.into()));
+
+    let error = builder.build().expect_err("%x is not a constant");
+    assert!(matches!(error.kind, VerifyErrorKind::InvalidConstant(constant) if constant == named));
+}
+
+#[test]
+fn checks_calls_in_the_module() {
+    let mut builder = FunctionBuilder::new("caller".to_string()).set_ret(Ty::I64);
+    let x = builder.add_param(Ty::I64, None);
+    let result = builder
+        .ins()
+        .call("missing".to_string(), Ty::I64, vec![x.into()]);
+    builder.ins().ret(result.map(Into::into));
+
+    // The callee may be added to the module later
+    let caller = builder.build().expect("calls are not checked alone");
+    let error = ModuleBuilder::new()
+        .add_function(caller)
+        .build()
+        .expect_err("@missing is not in the module");
+
+    assert!(matches!(error.kind, VerifyErrorKind::UnknownFunction(name) if name == "missing"));
+}
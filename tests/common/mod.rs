@@ -0,0 +1,58 @@
+#![allow(dead_code)]
+
+pub mod rv;
+
+use tinity::ir::Module;
+use tinity::opt::PassManager;
+use tinity::parser::ir::get_module;
+use tinity::parser::token::get_tokens;
+use tinity::{compile, CompileOptions, OptLevel};
+
+use rv::Program;
+
+pub fn parse(source: &str) -> Module {
+    let tokens = get_tokens(source.to_string()).expect("the source should lex");
+    get_module(tokens).expect("the source should parse")
+}
+
+// Runs the named passes with verification after each one and prints the result
+pub fn run(source: &str, passes: &[&str]) -> String {
+    let mut module = parse(source);
+    let names: Vec<String> = passes.iter().map(|name| name.to_string()).collect();
+
+    PassManager::from_names(&names)
+        .expect("the passes should exist")
+        .set_verify(true)
+        .run(&mut module)
+        .expect("the passes should keep the IR valid");
+
+    module.to_string()
+}
+
+// The body of @name as printed, without the surrounding lines
+pub fn function(module: &str, name: &str) -> String {
+    let start = module
+        .find(&format!("@{name}("))
+        .expect("the function should exist");
+    let end = module[start..].find("\n}").unwrap() + start;
+
+    module[start..end].to_string()
+}
+
+// Compiles the source at every optimization level
+pub fn programs(source: &str) -> Vec<(OptLevel, Program)> {
+    [OptLevel::O0, OptLevel::O1, OptLevel::O2, OptLevel::Os]
+        .into_iter()
+        .map(|opt_level| {
+            let options = CompileOptions {
+                verify_passes: true,
+                opt_level,
+                ..CompileOptions::default()
+            };
+            let elf = compile(source, &options)
+                .unwrap_or_else(|e| panic!("the source should compile at {opt_level:?}: {e:?}"));
+
+            (opt_level, Program::load(&elf))
+        })
+        .collect()
+}
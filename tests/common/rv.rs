@@ -0,0 +1,338 @@
+// A small RV64IM interpreter to run the compiled objects, enough for the integer tests
+use std::collections::HashMap;
+
+use object::{Object, ObjectSection, ObjectSymbol, RelocationFlags, RelocationTarget, SectionKind};
+
+const BASE: u64 = 0x10000;
+const MEMORY: usize = 8 << 20;
+const STACK: u64 = 0x7F0000;
+// Returning to this address ends the run
+const EXIT: u64 = 0x7FFFF0;
+const STEPS: u64 = 50_000_000;
+
+// The registers a call must preserve: sp, gp, tp, s0..s11
+const CALLEE_SAVED: [usize; 15] = [2, 3, 4, 8, 9, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27];
+
+pub struct Program {
+    image: Vec<u8>,
+    symbols: HashMap<String, u64>,
+}
+
+impl Program {
+    // Lays the code and the read-only data out from BASE and applies the pc-relative relocations
+    pub fn load(elf: &[u8]) -> Program {
+        let file = object::File::parse(elf).expect("the object should parse");
+        let mut image = Vec::new();
+        let mut place = HashMap::new();
+
+        for kind in [SectionKind::Text, SectionKind::ReadOnlyData] {
+            for section in file.sections().filter(|section| section.kind() == kind) {
+                image.resize(image.len().next_multiple_of(8), 0);
+                place.insert(section.index(), image.len() as u64);
+                image.extend_from_slice(section.data().unwrap());
+            }
+        }
+
+        let mut symbols = HashMap::new();
+        let mut addresses = HashMap::new();
+
+        for symbol in file.symbols() {
+            let Some(section) = symbol.section_index() else {
+                continue;
+            };
+            let Some(start) = place.get(&section) else {
+                continue;
+            };
+
+            let address = start + symbol.address();
+            addresses.insert(symbol.index(), address);
+
+            if let Ok(name) = symbol.name() {
+                symbols.insert(name.to_string(), address);
+            }
+        }
+
+        // The lo12 relocations point at the instruction holding their hi20 part
+        let mut highs = HashMap::new();
+        let mut lows = Vec::new();
+
+        for section in file.sections() {
+            let Some(start) = place.get(&section.index()) else {
+                continue;
+            };
+
+            for (offset, relocation) in section.relocations() {
+                let RelocationTarget::Symbol(symbol) = relocation.target() else {
+                    panic!("relocation without a symbol");
+                };
+                let RelocationFlags::Elf { r_type } = relocation.flags() else {
+                    panic!("not an ELF relocation");
+                };
+
+                let at = start + offset;
+                let target = addresses[&symbol] as i64 + relocation.addend();
+
+                match r_type {
+                    object::elf::R_RISCV_PCREL_HI20 => {
+                        let value = target - at as i64;
+                        highs.insert(at, value);
+                        patch(&mut image, at, 0xFFF, ((value + 0x800) >> 12) << 12);
+                    }
+                    object::elf::R_RISCV_PCREL_LO12_I => lows.push((at, target as u64)),
+                    other => panic!("unsupported relocation {other}"),
+                }
+            }
+        }
+
+        for (at, high) in lows {
+            let value = highs[&high];
+            let low = value - (((value + 0x800) >> 12) << 12);
+            patch(&mut image, at, 0xFFFFF, (low & 0xFFF) << 20);
+        }
+
+        Program { image, symbols }
+    }
+
+    // Calls the function with integer arguments and returns a0 and a1
+    pub fn call(&self, function: &str, args: &[i64]) -> (i64, i64) {
+        self.run(function, args).0
+    }
+
+    // Like `call`, also giving the most stack the function used
+    pub fn call_with_stack(&self, function: &str, args: &[i64]) -> ((i64, i64), u64) {
+        self.run(function, args)
+    }
+
+    fn run(&self, function: &str, args: &[i64]) -> ((i64, i64), u64) {
+        let mut cpu = Cpu::new(&self.image);
+        cpu.x[1] = EXIT;
+        cpu.x[2] = STACK;
+        cpu.x[3] = 0x111;
+        cpu.x[4] = 0x222;
+
+        for (i, arg) in args.iter().enumerate() {
+            cpu.x[10 + i] = *arg as u64;
+        }
+
+        cpu.pc = BASE + self.symbols[function];
+        let saved = CALLEE_SAVED.map(|r| cpu.x[r]);
+        let mut lowest = STACK;
+
+        while cpu.pc != EXIT {
+            cpu.step();
+            lowest = lowest.min(cpu.x[2]);
+            assert!(cpu.steps < STEPS, "{function} ran for too long");
+        }
+
+        assert_eq!(
+            saved,
+            CALLEE_SAVED.map(|r| cpu.x[r]),
+            "{function} clobbered a callee-saved register"
+        );
+
+        ((cpu.x[10] as i64, cpu.x[11] as i64), STACK - lowest)
+    }
+}
+
+fn patch(image: &mut [u8], at: u64, keep: u32, value: i64) {
+    let at = at as usize;
+    let word = u32::from_le_bytes(image[at..at + 4].try_into().unwrap());
+    let word = (word & keep) | (value as u32 & !keep);
+    image[at..at + 4].copy_from_slice(&word.to_le_bytes());
+}
+
+fn sext(value: u64, bits: u32) -> i64 {
+    ((value << (64 - bits)) as i64) >> (64 - bits)
+}
+
+struct Cpu {
+    memory: Vec<u8>,
+    x: [u64; 32],
+    pc: u64,
+    steps: u64,
+}
+
+impl Cpu {
+    fn new(image: &[u8]) -> Cpu {
+        let mut memory = vec![0; MEMORY];
+        memory[BASE as usize..BASE as usize + image.len()].copy_from_slice(image);
+
+        Cpu {
+            memory,
+            x: [0; 32],
+            pc: 0,
+            steps: 0,
+        }
+    }
+
+    fn read(&self, address: u64, bytes: usize) -> u64 {
+        let at = address as usize;
+        let mut buffer = [0; 8];
+        buffer[..bytes].copy_from_slice(&self.memory[at..at + bytes]);
+        u64::from_le_bytes(buffer)
+    }
+
+    fn write(&mut self, address: u64, bytes: usize, value: u64) {
+        let at = address as usize;
+        self.memory[at..at + bytes].copy_from_slice(&value.to_le_bytes()[..bytes]);
+    }
+
+    fn set(&mut self, rd: usize, value: u64) {
+        if rd != 0 {
+            self.x[rd] = value;
+        }
+    }
+
+    fn step(&mut self) {
+        let i = self.read(self.pc, 4) as u32;
+        self.steps += 1;
+
+        let opcode = i & 0x7F;
+        let rd = (i >> 7 & 31) as usize;
+        let funct3 = i >> 12 & 7;
+        let funct7 = i >> 25;
+        let a = self.x[(i >> 15 & 31) as usize];
+        let b = self.x[(i >> 20 & 31) as usize];
+        let (sa, sb) = (a as i64, b as i64);
+        let imm = sext(u64::from(i >> 20), 12);
+        let mut next = self.pc + 4;
+
+        match opcode {
+            0x13 => {
+                let shamt = i >> 20 & 63;
+                let value = match funct3 {
+                    0 => a.wrapping_add(imm as u64),
+                    1 => a << shamt,
+                    2 => (sa < imm) as u64,
+                    3 => (a < imm as u64) as u64,
+                    4 => a ^ imm as u64,
+                    5 if i >> 30 & 1 == 1 => (sa >> shamt) as u64,
+                    5 => a >> shamt,
+                    6 => a | imm as u64,
+                    _ => a & imm as u64,
+                };
+                self.set(rd, value);
+            }
+            0x1B => {
+                let shamt = i >> 20 & 31;
+                let value = match funct3 {
+                    0 => a.wrapping_add(imm as u64),
+                    1 => a << shamt,
+                    5 if i >> 30 & 1 == 1 => ((a as i32) >> shamt) as u64,
+                    5 => u64::from(a as u32 >> shamt),
+                    _ => panic!("bad op-imm-32 {i:08x}"),
+                };
+                self.set(rd, sext(value, 32) as u64);
+            }
+            0x33 if funct7 == 1 => {
+                let value = match funct3 {
+                    0 => a.wrapping_mul(b),
+                    1 => ((i128::from(sa) * i128::from(sb)) >> 64) as u64,
+                    3 => ((u128::from(a) * u128::from(b)) >> 64) as u64,
+                    4 if b == 0 => u64::MAX,
+                    4 => sa.wrapping_div(sb) as u64,
+                    5 => a.checked_div(b).unwrap_or(u64::MAX),
+                    6 if b == 0 => a,
+                    6 => sa.wrapping_rem(sb) as u64,
+                    7 => a.checked_rem(b).unwrap_or(a),
+                    _ => panic!("bad mul {i:08x}"),
+                };
+                self.set(rd, value);
+            }
+            0x33 => {
+                let value = match (funct3, funct7) {
+                    (0, 0) => a.wrapping_add(b),
+                    (0, 0x20) => a.wrapping_sub(b),
+                    (1, 0) => a << (b & 63),
+                    (2, 0) => (sa < sb) as u64,
+                    (3, 0) => (a < b) as u64,
+                    (4, 0) => a ^ b,
+                    (5, 0) => a >> (b & 63),
+                    (5, 0x20) => (sa >> (b & 63)) as u64,
+                    (6, 0) => a | b,
+                    (7, 0) => a & b,
+                    _ => panic!("bad op {i:08x}"),
+                };
+                self.set(rd, value);
+            }
+            0x3B => {
+                let (a32, b32) = (a as u32, b as u32);
+                let (s1, s2) = (a32 as i32, b32 as i32);
+                let value = match (funct3, funct7) {
+                    (0, 0) => a32.wrapping_add(b32),
+                    (0, 0x20) => a32.wrapping_sub(b32),
+                    (1, 0) => a32 << (b32 & 31),
+                    (5, 0) => a32 >> (b32 & 31),
+                    (5, 0x20) => (s1 >> (b32 & 31)) as u32,
+                    (0, 1) => a32.wrapping_mul(b32),
+                    (4, 1) if s2 == 0 => u32::MAX,
+                    (4, 1) => s1.wrapping_div(s2) as u32,
+                    (5, 1) => a32.checked_div(b32).unwrap_or(u32::MAX),
+                    (6, 1) if s2 == 0 => a32,
+                    (6, 1) => s1.wrapping_rem(s2) as u32,
+                    (7, 1) => a32.checked_rem(b32).unwrap_or(a32),
+                    _ => panic!("bad op-32 {i:08x}"),
+                };
+                self.set(rd, value as i32 as u64);
+            }
+            0x03 => {
+                let bytes = 1 << (funct3 & 3);
+                let value = self.read(a.wrapping_add(imm as u64), bytes);
+                let value = if funct3 & 4 != 0 {
+                    value
+                } else {
+                    sext(value, 8 * bytes as u32) as u64
+                };
+                self.set(rd, value);
+            }
+            0x23 => {
+                let offset = sext(u64::from((i >> 25) << 5 | (i >> 7 & 31)), 12);
+                self.write(a.wrapping_add(offset as u64), 1 << funct3, b);
+            }
+            0x37 => self.set(rd, sext(u64::from(i & 0xFFFFF000), 32) as u64),
+            0x17 => self.set(
+                rd,
+                self.pc
+                    .wrapping_add(sext(u64::from(i & 0xFFFFF000), 32) as u64),
+            ),
+            0x6F => {
+                let offset = (i >> 31) << 20
+                    | (i >> 12 & 0xFF) << 12
+                    | (i >> 20 & 1) << 11
+                    | (i >> 21 & 0x3FF) << 1;
+                self.set(rd, next);
+                next = self.pc.wrapping_add(sext(u64::from(offset), 21) as u64);
+            }
+            0x67 => {
+                let target = a.wrapping_add(imm as u64) & !1;
+                self.set(rd, next);
+                next = target;
+            }
+            0x63 => {
+                let offset = (i >> 31) << 12
+                    | (i >> 7 & 1) << 11
+                    | (i >> 25 & 0x3F) << 5
+                    | (i >> 8 & 0xF) << 1;
+                let taken = match funct3 {
+                    0 => a == b,
+                    1 => a != b,
+                    4 => sa < sb,
+                    5 => sa >= sb,
+                    6 => a < b,
+                    7 => a >= b,
+                    _ => panic!("bad branch {i:08x}"),
+                };
+
+                if taken {
+                    next = self.pc.wrapping_add(sext(u64::from(offset), 13) as u64);
+                }
+            }
+            // Syscalls return 0
+            0x73 if i == 0x73 => self.set(10, 0),
+            0x0F => {}
+            _ => panic!("unsupported instruction {i:08x} at {:#x}", self.pc),
+        }
+
+        self.pc = next;
+    }
+}
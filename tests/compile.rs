@@ -0,0 +1,102 @@
+mod common;
+
+use common::rv::Program;
+use object::{Object, ObjectSection, ObjectSymbol, SymbolKind};
+use tinity::ir::builder::{FunctionBuilder, ModuleBuilder};
+use tinity::ir::inst::BinaryOp;
+use tinity::ir::types::Ty;
+use tinity::ir::Module;
+use tinity::parser::types::Type;
+use tinity::{compile, compile_module, CompileOptions, OptLevel};
+
+// i64 @scale(i64 %x) returning x * 3 + 1
+fn scale() -> Module {
+    let mut builder = FunctionBuilder::new("scale".to_string()).set_ret(Ty::I64);
+    let x = builder.add_param(Ty::I64, Some("x".to_string()));
+    let product = builder
+        .ins()
+        .binary(BinaryOp::Mul, x, Type::int(64, true, 3));
+    let sum = builder
+        .ins()
+        .binary(BinaryOp::Add, product, Type::int(64, true, 1));
+    builder.ins().ret(Some(sum.into()));
+
+    let function = builder.build().expect("scale should verify");
+
+    ModuleBuilder::new()
+        .add_function(function)
+        .build()
+        .expect("the module should verify")
+}
+
+#[test]
+fn compiles_built_modules() {
+    let module = scale();
+
+    for opt_level in [OptLevel::O0, OptLevel::O2] {
+        let options = CompileOptions {
+            opt_level,
+            ..CompileOptions::default()
+        };
+        let elf = compile_module(&module, &options).expect("the module should compile");
+        let program = Program::load(&elf);
+
+        assert_eq!(program.call("scale", &[14]).0, 43);
+        assert_eq!(program.call("scale", &[-5]).0, -14);
+    }
+
+    // The passes work on a copy
+    assert_eq!(module.to_string(), scale().to_string());
+}
+
+#[test]
+fn reports_invalid_modules() {
+    let mut module = scale();
+    module.functions[0].ret = Ty::I1;
+
+    let diagnostics = compile_module(&module, &CompileOptions::default())
+        .expect_err("the return type does not match");
+
+    assert_eq!(diagnostics.errors.len(), 1);
+}
+
+// The pool and private functions are local, sections have their usual names
+#[test]
+fn writes_local_symbols() {
+    let source = "
+define i64 @pick(i64 %a) {
+entry:
+    %x = call i64 @big(i64 %a)
+    %y = add i64 %x, 1
+    ret i64 %y
+}
+
+define private i64 @big(i64 %a) {
+entry:
+    %x = xor i64 %a, 81985529216486895
+    ret i64 %x
+}
+";
+
+    let elf = compile(source, &CompileOptions::default()).expect("the source should compile");
+    let file = object::File::parse(&*elf).unwrap();
+
+    let names: Vec<&str> = file
+        .sections()
+        .map(|section| section.name().unwrap())
+        .collect();
+    assert!(names.contains(&".text"));
+    assert!(names.contains(&".rodata"));
+
+    let symbol = |name: &str| file.symbol_by_name(name).expect("the symbol should exist");
+
+    assert!(symbol("pick").is_global());
+    assert_eq!(symbol("pick").kind(), SymbolKind::Text);
+    assert!(symbol("big").is_local());
+    assert_eq!(symbol("big").kind(), SymbolKind::Text);
+    assert!(symbol(".Lconstants").is_local());
+    assert_eq!(symbol(".Lconstants").kind(), SymbolKind::Data);
+
+    let program = Program::load(&elf);
+    assert_eq!(program.call("pick", &[0]).0, 81985529216486896);
+}
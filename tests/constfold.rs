@@ -0,0 +1,163 @@
+mod common;
+
+use common::{function, run};
+
+#[test]
+fn folds_float_to_unsigned_above_the_signed_range() {
+    let source = "
+define u32 @f() {
+entry:
+    %x = fptosi double 3000000000.5 to u32
+    ret u32 %x
+}
+";
+
+    assert!(function(&run(source, &["constfold"]), "f").contains("ret u32 3000000000"));
+}
+
+#[test]
+fn keeps_float_to_unsigned_out_of_range() {
+    let source = "
+define u32 @f() {
+entry:
+    %x = fptosi double -1.0 to u32
+    ret u32 %x
+}
+";
+
+    assert!(function(&run(source, &["constfold"]), "f").contains("fptosi"));
+}
+
+#[test]
+fn wraps_without_overflow_flags() {
+    let source = "
+define i32 @f() {
+entry:
+    %x = add i32 2147483647, 1
+    ret i32 %x
+}
+";
+
+    assert!(function(&run(source, &["constfold"]), "f").contains("ret i32 -2147483648"));
+}
+
+#[test]
+fn keeps_overflowing_nsw_and_nuw() {
+    let source = "
+define i32 @signed() {
+entry:
+    %x = add nsw i32 2147483647, 1
+    ret i32 %x
+}
+
+define i32 @unsigned() {
+entry:
+    %x = sub nuw i32 0, 1
+    ret i32 %x
+}
+
+define i32 @fits() {
+entry:
+    %x = mul nuw nsw i32 65535, 65535
+    ret i32 %x
+}
+";
+
+    let module = run(source, &["constfold"]);
+
+    assert!(function(&module, "signed").contains("add nsw"));
+    assert!(function(&module, "unsigned").contains("sub nuw"));
+    // The unsigned product fits while the signed one overflows
+    assert!(function(&module, "fits").contains("mul nuw nsw"));
+}
+
+#[test]
+fn wraps_signed_division_overflow() {
+    let source = "
+define i32 @f() {
+entry:
+    %x = sdiv i32 -2147483648, -1
+    ret i32 %x
+}
+";
+
+    assert!(function(&run(source, &["constfold"]), "f").contains("ret i32 -2147483648"));
+}
+
+#[test]
+fn keeps_division_by_zero() {
+    let source = "
+define i64 @f() {
+entry:
+    %x = udiv i64 7, 0
+    %y = srem i64 7, 0
+    %z = add i64 %x, %y
+    ret i64 %z
+}
+";
+
+    let body = function(&run(source, &["constfold"]), "f");
+
+    assert!(body.contains("udiv"));
+    assert!(body.contains("srem"));
+}
+
+#[test]
+fn keeps_shifts_past_the_width() {
+    let source = "
+define i32 @f() {
+entry:
+    %x = shl i32 1, 32
+    %y = lshr i32 %x, 31
+    ret i32 %y
+}
+
+define i32 @g() {
+entry:
+    %x = ashr i32 -8, 31
+    ret i32 %x
+}
+";
+
+    let module = run(source, &["constfold"]);
+
+    assert!(function(&module, "f").contains("shl i32 1, 32"));
+    assert!(function(&module, "g").contains("ret i32 -1"));
+}
+
+#[test]
+fn folds_in_the_width_of_the_type() {
+    let source = "
+define i24 @f() {
+entry:
+    %x = add i24 8388607, 1
+    %y = mul i24 %x, 2
+    ret i24 %y
+}
+
+define i1 @g() {
+entry:
+    %c = icmp ult i24 -1, 16777215
+    ret i1 %c
+}
+";
+
+    let module = run(source, &["constfold"]);
+
+    assert!(function(&module, "f").contains("ret i24 0"));
+    assert!(function(&module, "g").contains("ret i1 false"));
+}
+
+#[test]
+fn rounds_half_literals_once() {
+    // Just above the midpoint of 1.0 and the next half, rounding through f32 loses the
+    // low bit and ties to 1.0
+    let source = "
+define half @f() {
+entry:
+    ret half 1.0004882812509094947017729282379150390625
+}
+";
+
+    assert!(function(&run(source, &[]), "f").contains("ret half 1.0009766"));
+}
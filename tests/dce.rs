@@ -0,0 +1,71 @@
+mod common;
+
+use common::{function, run};
+
+#[test]
+fn removes_dead_code_and_unreachable_blocks() {
+    let output = run(
+        "
+define i64 @f(i64 %x, ptr %p) {
+entry:
+    %a = add i64 %x, 1
+    %b = mul i64 %a, 3
+    %s = syscall i64 172
+    store i64 %x, ptr %p
+    %l = load i64, ptr %p
+    br label %exit
+dead:
+    %d = add i64 %x, 2
+    br label %dead2
+dead2:
+    br label %exit
+exit:
+    %r = phi i64 [ %x, %entry ], [ %d, %dead2 ]
+    ret i64 %r
+}
+",
+        &["dce"],
+    );
+
+    // Side effects stay even when their result is unused
+    assert_eq!(
+        function(&output, "f"),
+        "@f(i64 %x, ptr %p) {
+entry:
+    %s = syscall i64 172
+    store i64 %x, ptr %p
+    br label %exit
+exit:
+    %r = phi i64 [ %x, %entry ]
+    ret i64 %r"
+    );
+}
+
+#[test]
+fn removes_values_that_only_feed_each_other() {
+    let output = run(
+        "
+define i64 @f(i64 %n) {
+entry:
+    br label %head
+head:
+    %i = phi i64 [ 0, %entry ], [ %i2, %head ]
+    %k = phi i64 [ 1, %entry ], [ %k2, %head ]
+    %k2 = mul i64 %k, 3
+    %i2 = add i64 %i, 1
+    %c = icmp slt i64 %i2, %n
+    br i1 %c, label %head, label %exit
+exit:
+    ret i64 %i2
+}
+",
+        &["dce"],
+    );
+    let f = function(&output, "f");
+
+    assert!(!f.contains("%k"), "{f}");
+    assert!(
+        f.contains("%i = phi i64 [ 0, %entry ], [ %i2, %head ]"),
+        "{f}"
+    );
+}
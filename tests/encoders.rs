@@ -0,0 +1,150 @@
+use tinity::riscv::branch::{beq, bge, bgeu, blt, bltu, bne};
+use tinity::riscv::immediate::{
+    addi, addiw, andi, ebreak, ecall, fence, lb, lbu, ld, lh, lhu, load, lw, lwu, ori, slli, slliw,
+    slti, sltiu, srai, sraiw, srli, srliw, xori, ImmediateError,
+};
+use tinity::riscv::jmp::{jal, jalr};
+use tinity::riscv::register::{add, mulhu, remuw, sltu, sub};
+use tinity::riscv::regs::Reg::*;
+use tinity::riscv::store::{sb, sd, sh, store, sw};
+use tinity::riscv::upper::{auipc, lui};
+
+fn word(code: Vec<u8>) -> u32 {
+    u32::from_le_bytes(code.try_into().expect("instructions are 4 bytes"))
+}
+
+// Each pair is the encoder output and the word llvm-mc gives for the same instruction
+fn check(cases: Vec<(Vec<u8>, u32, &str)>) {
+    for (code, expected, text) in cases {
+        assert_eq!(word(code), expected, "{text}");
+    }
+}
+
+#[test]
+fn immediate() {
+    check(vec![
+        (
+            addi(A0, Zero, 2047).unwrap(),
+            0x7ff00513,
+            "addi a0, zero, 2047",
+        ),
+        (
+            addiw(T1, T1, -2048).unwrap(),
+            0x8003031b,
+            "addiw t1, t1, -2048",
+        ),
+        (slti(A0, A1, -1).unwrap(), 0xfff5a513, "slti a0, a1, -1"),
+        (sltiu(T0, T0, 1).unwrap(), 0x0012b293, "sltiu t0, t0, 1"),
+        (xori(T6, T2, -1).unwrap(), 0xfff3cf93, "xori t6, t2, -1"),
+        (ori(T0, T0, 1).unwrap(), 0x0012e293, "ori t0, t0, 1"),
+        (andi(A0, A0, 255).unwrap(), 0x0ff57513, "andi a0, a0, 255"),
+        (slli(T5, T1, 63).unwrap(), 0x03f31f13, "slli t5, t1, 63"),
+        (srli(T1, T1, 32).unwrap(), 0x02035313, "srli t1, t1, 32"),
+        (srai(T1, T1, 63).unwrap(), 0x43f35313, "srai t1, t1, 63"),
+        (slliw(A0, A0, 31).unwrap(), 0x01f5151b, "slliw a0, a0, 31"),
+        (srliw(S2, S4, 16).unwrap(), 0x010a591b, "srliw s2, s4, 16"),
+        (sraiw(A0, A0, 1).unwrap(), 0x4015551b, "sraiw a0, a0, 1"),
+        (ecall(), 0x00000073, "ecall"),
+        (ebreak(), 0x00100073, "ebreak"),
+        (fence(0xF, 0xF).unwrap(), 0x0ff0000f, "fence iorw, iorw"),
+        (lui(T1, 0xFFFFF).unwrap(), 0xfffff337, "lui t1, 0xfffff"),
+        (auipc(Ra, 0x80000).unwrap(), 0x80000097, "auipc ra, 0x80000"),
+    ]);
+}
+
+#[test]
+fn memory() {
+    check(vec![
+        (lb(T0, T1, -1).unwrap(), 0xfff30283, "lb t0, -1(t1)"),
+        (lh(A0, Sp, 2).unwrap(), 0x00211503, "lh a0, 2(sp)"),
+        (lw(A0, A1, -2048).unwrap(), 0x8005a503, "lw a0, -2048(a1)"),
+        (ld(Ra, Sp, 24).unwrap(), 0x01813083, "ld ra, 24(sp)"),
+        (lbu(S1, A0, 0).unwrap(), 0x00054483, "lbu s1, 0(a0)"),
+        (lhu(T0, T1, 2).unwrap(), 0x00235283, "lhu t0, 2(t1)"),
+        (lwu(A0, Sp, 2047).unwrap(), 0x7ff16503, "lwu a0, 2047(sp)"),
+        (sb(T0, T1, 0).unwrap(), 0x00530023, "sb t0, 0(t1)"),
+        (sh(T0, T1, -2048).unwrap(), 0x80531023, "sh t0, -2048(t1)"),
+        (sw(A1, A0, 4).unwrap(), 0x00b52223, "sw a1, 4(a0)"),
+        (sd(Ra, Sp, 2047).unwrap(), 0x7e113fa3, "sd ra, 2047(sp)"),
+    ]);
+}
+
+#[test]
+fn control() {
+    check(vec![
+        (beq(T0, Zero, 8).unwrap(), 0x00028463, "beq t0, zero, 8"),
+        (bne(A0, A1, -4).unwrap(), 0xfeb51ee3, "bne a0, a1, -4"),
+        (blt(A0, A1, 4094).unwrap(), 0x7eb54fe3, "blt a0, a1, 4094"),
+        (
+            bge(T6, Zero, -4096).unwrap(),
+            0x800fd063,
+            "bge t6, zero, -4096",
+        ),
+        (bltu(T5, T3, 16).unwrap(), 0x01cf6863, "bltu t5, t3, 16"),
+        (bgeu(T4, T2, 12).unwrap(), 0x007ef663, "bgeu t4, t2, 12"),
+        (jalr(Zero, Ra, 0).unwrap(), 0x00008067, "jalr zero, 0(ra)"),
+        (
+            jalr(Ra, T1, -2048).unwrap(),
+            0x800300e7,
+            "jalr ra, -2048(t1)",
+        ),
+        (
+            jal(1048574, 0, Zero).unwrap(),
+            0x7ffff06f,
+            "jal zero, 1048574",
+        ),
+        (jal(0, 1048576, Ra).unwrap(), 0x800000ef, "jal ra, -1048576"),
+    ]);
+}
+
+#[test]
+fn register() {
+    check(vec![
+        (add(&A0, &A1, &A2), 0x00c58533, "add a0, a1, a2"),
+        (sub(&T1, &Zero, &T1), 0x40600333, "sub t1, zero, t1"),
+        (sltu(&T0, &Zero, &T0), 0x005032b3, "sltu t0, zero, t0"),
+        (mulhu(&T5, &T0, &T2), 0x0272bf33, "mulhu t5, t0, t2"),
+        (remuw(&A0, &A0, &A1), 0x02b5753b, "remuw a0, a0, a1"),
+    ]);
+}
+
+#[test]
+fn out_of_range() {
+    assert!(addi(A0, A0, 2048).is_err());
+    assert!(andi(A0, A0, -2049).is_err());
+    assert!(slli(A0, A0, 64).is_err());
+    assert!(slliw(A0, A0, 32).is_err());
+    assert!(ld(A0, Sp, 2048).is_err());
+    assert!(sd(A0, Sp, -2049).is_err());
+    assert!(fence(0x10, 0).is_err());
+    assert!(lui(A0, 0x100000).is_err());
+    assert!(beq(A0, A1, 4096).is_err());
+    assert!(beq(A0, A1, 3).is_err());
+    assert!(jal(1048576, 0, Zero).is_err());
+    assert!(jal(1, 0, Zero).is_err());
+}
+
+// Widths are log2 of the size, loads of a whole register have no unsigned variant
+#[test]
+fn invalid_width() {
+    assert!(matches!(
+        load(A0, A1, 0, 3, true),
+        Err(ImmediateError::InvalidWidth("unsigned load", 3))
+    ));
+    assert!(matches!(
+        load(A0, A1, 0, 4, false),
+        Err(ImmediateError::InvalidWidth("load", 4))
+    ));
+    assert!(matches!(
+        load(A0, A1, 0, 7, true),
+        Err(ImmediateError::InvalidWidth("unsigned load", 7))
+    ));
+    assert!(matches!(
+        store(A0, A1, 0, 4),
+        Err(ImmediateError::InvalidWidth("store", 4))
+    ));
+    assert!(matches!(
+        store(A0, A1, 0, 7),
+        Err(ImmediateError::InvalidWidth("store", 7))
+    ));
+}
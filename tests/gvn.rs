@@ -0,0 +1,113 @@
+mod common;
+
+use common::{function, run};
+
+#[test]
+fn reuses_dominating_expressions() {
+    let output = run(
+        "
+define i64 @f(i64 %a, i64 %b, i1 %c) {
+entry:
+    %x = add i64 %a, %b
+    %y = add i64 %b, %a
+    %s = sub i64 %a, %b
+    %t = sub i64 %b, %a
+    br i1 %c, label %left, label %join
+left:
+    %z = add i64 %a, %b
+    br label %join
+join:
+    %w = add i64 %a, %b
+    %r1 = add i64 %x, %y
+    %r2 = add i64 %s, %t
+    %r3 = add i64 %r1, %r2
+    %r4 = add i64 %r3, %w
+    ret i64 %r4
+}
+",
+        &["gvn"],
+    );
+
+    // Operands of commutative operations are put in order, a sub is not commutative
+    assert_eq!(
+        function(&output, "f"),
+        "@f(i64 %a, i64 %b, i1 %c) {
+entry:
+    %x = add i64 %a, %b
+    %s = sub i64 %a, %b
+    %t = sub i64 %b, %a
+    br i1 %c, label %left, label %join
+left:
+    br label %join
+join:
+    %r1 = add i64 %x, %x
+    %r2 = add i64 %s, %t
+    %r3 = add i64 %r1, %r2
+    %r4 = add i64 %r3, %x
+    ret i64 %r4"
+    );
+}
+
+#[test]
+fn forwards_stores_to_loads() {
+    let output = run(
+        "
+define i64 @mem(ptr %p, ptr %q, i64 %v) {
+entry:
+    %a = alloca i64
+    %b = alloca i64
+    store i64 %v, ptr %a
+    store i64 5, ptr %b
+    %x = load i64, ptr %a
+    %y = load i64, ptr %b
+    store i64 9, ptr %p
+    %z = load i64, ptr %a
+    %l1 = load i64, ptr %q
+    %s = syscall i64 1
+    %l2 = load i64, ptr %q
+    %r = add i64 %x, %y
+    %r2 = add i64 %r, %z
+    %r3 = add i64 %l1, %l2
+    %r4 = add i64 %r2, %r3
+    ret i64 %r4
+}
+",
+        &["gvn"],
+    );
+    let f = function(&output, "mem");
+
+    // The two slots are apart, so %x and %y are the stored values
+    assert!(!f.contains("%x = load"), "{f}");
+    assert!(!f.contains("%y = load"), "{f}");
+    assert!(f.contains("%r = add i64 %v, 5"), "{f}");
+
+    // %p may point to %a and the syscall may write to %q
+    assert!(f.contains("%z = load i64, ptr %a"), "{f}");
+    assert!(f.contains("%l2 = load i64, ptr %q"), "{f}");
+}
+
+#[test]
+fn does_not_reuse_expressions_from_other_paths() {
+    let output = run(
+        "
+define i64 @f(i64 %a, i1 %c) {
+entry:
+    br i1 %c, label %left, label %right
+left:
+    %x = mul i64 %a, 3
+    br label %join
+right:
+    %y = mul i64 %a, 3
+    br label %join
+join:
+    %r = phi i64 [ %x, %left ], [ %y, %right ]
+    ret i64 %r
+}
+",
+        &["gvn"],
+    );
+    let f = function(&output, "f");
+
+    assert!(f.contains("%x = mul i64 %a, 3"), "{f}");
+    assert!(f.contains("%y = mul i64 %a, 3"), "{f}");
+}
@@ -0,0 +1,169 @@
+mod common;
+
+use common::{function, parse, programs, run};
+use tinity::opt::{OptLevel, PassManager};
+
+const SOURCE: &str = "
+define i64 @abs(i64 %x) alwaysinline {
+entry:
+    %c = icmp slt i64 %x, 0
+    br i1 %c, label %neg, label %pos
+neg:
+    %n = sub i64 0, %x
+    ret i64 %n
+pos:
+    ret i64 %x
+}
+
+define i64 @caller(i64 %a, i64 %b) {
+entry:
+    %x = call i64 @abs(i64 %a)
+    %y = call i64 @abs(i64 %b)
+    %r = add i64 %x, %y
+    ret i64 %r
+}
+
+define i64 @phis(i64 %a, i1 %c) {
+entry:
+    br i1 %c, label %call, label %join
+call:
+    %x = call i64 @abs(i64 %a)
+    br label %join
+join:
+    %r = phi i64 [ %x, %call ], [ 0, %entry ]
+    ret i64 %r
+}
+
+define i64 @inc(i64 %x) {
+entry:
+    %r = add i64 %x, 1
+    ret i64 %r
+}
+
+define i64 @small(i64 %x) {
+entry:
+    %r = call i64 @inc(i64 %x)
+    ret i64 %r
+}
+
+define i64 @rec(i64 %x) {
+entry:
+    %r = call i64 @rec(i64 %x)
+    ret i64 %r
+}
+
+define i64 @big(i64 %x) noinline {
+entry:
+    %r = add i64 %x, 1
+    ret i64 %r
+}
+
+define i64 @user(i64 %x) {
+entry:
+    %r = call i64 @big(i64 %x)
+    %s = call i64 @rec(i64 %r)
+    ret i64 %s
+}
+";
+
+#[test]
+fn merges_the_returns_of_the_callee_in_a_phi() {
+    let output = run(SOURCE, &["inline"]);
+
+    assert_eq!(
+        function(&output, "caller"),
+        "@caller(i64 %a, i64 %b) {
+entry:
+    br label %abs.entry
+abs.entry:
+    %abs.c = icmp slt i64 %a, 0
+    br i1 %abs.c, label %abs.neg, label %abs.pos
+abs.neg:
+    %abs.n = sub i64 0, %a
+    br label %entry.cont
+abs.pos:
+    br label %entry.cont
+entry.cont:
+    %7 = phi i64 [ %abs.n, %abs.neg ], [ %a, %abs.pos ]
+    br label %abs.entry.1
+abs.entry.1:
+    %abs.c.1 = icmp slt i64 %b, 0
+    br i1 %abs.c.1, label %abs.neg.1, label %abs.pos.1
+abs.neg.1:
+    %abs.n.1 = sub i64 0, %b
+    br label %entry.cont.cont
+abs.pos.1:
+    br label %entry.cont.cont
+entry.cont.cont:
+    %10 = phi i64 [ %abs.n.1, %abs.neg.1 ], [ %b, %abs.pos.1 ]
+    %r = add i64 %7, %10
+    ret i64 %r"
+    );
+}
+
+#[test]
+fn moves_the_phis_of_the_successors_to_the_continuation() {
+    let output = run(SOURCE, &["inline"]);
+    let f = function(&output, "phis");
+
+    assert!(f.contains("call.cont:\n    %6 = phi i64 [ %abs.n, %abs.neg ], [ %a, %abs.pos ]\n    br label %join"), "{f}");
+    assert!(
+        f.contains("%r = phi i64 [ %6, %call.cont ], [ 0, %entry ]"),
+        "{f}"
+    );
+}
+
+#[test]
+fn uses_the_single_return_value_directly() {
+    let mut module = parse(SOURCE);
+    PassManager::from_names(&["inline"])
+        .unwrap()
+        .set_level(OptLevel::O2)
+        .set_verify(true)
+        .run(&mut module)
+        .unwrap();
+
+    assert_eq!(
+        function(&module.to_string(), "small"),
+        "@small(i64 %x) {
+entry:
+    br label %inc.entry
+inc.entry:
+    %inc.r = add i64 %x, 1
+    br label %entry.cont
+entry.cont:
+    ret i64 %inc.r"
+    );
+}
+
+#[test]
+fn respects_the_cost_model_and_attributes() {
+    // -O0 only inlines alwaysinline functions
+    let output = run(SOURCE, &["inline"]);
+    assert!(function(&output, "small").contains("call i64 @inc(i64 %x)"));
+
+    let mut module = parse(SOURCE);
+    PassManager::from_names(&["inline"])
+        .unwrap()
+        .set_level(OptLevel::O2)
+        .run(&mut module)
+        .unwrap();
+
+    let f = function(&module.to_string(), "user");
+    assert!(f.contains("call i64 @big(i64 %x)"), "{f}");
+    assert!(f.contains("call i64 @rec(i64 %r)"), "{f}");
+}
+
+#[test]
+fn inlined_code_runs_the_same() {
+    for (level, program) in programs(SOURCE) {
+        for (a, b) in [(-3, 4), (5, -6), (0, 0)] {
+            let expected = i64::abs(a) + i64::abs(b);
+            assert_eq!(program.call("caller", &[a, b]).0, expected, "at {level:?}");
+        }
+
+        assert_eq!(program.call("phis", &[-8, 1]).0, 8, "at {level:?}");
+        assert_eq!(program.call("phis", &[-8, 0]).0, 0, "at {level:?}");
+        assert_eq!(program.call("small", &[41]).0, 42, "at {level:?}");
+    }
+}
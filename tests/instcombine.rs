@@ -0,0 +1,186 @@
+mod common;
+
+use common::{function, programs, run};
+
+const DIVISORS: [i64; 10] = [2, 3, 7, 8, 10, 641, -1, -3, -7, -8];
+
+const VALUES: [i64; 9] = [0, 1, -1, 6, -6, 1000, -1001, i64::MAX, i64::MIN];
+
+fn source() -> String {
+    let mut source = String::new();
+
+    for d in DIVISORS {
+        let name = d.to_string().replace('-', "m");
+
+        source += &format!(
+            "
+define i64 @sdiv{name}(i64 %x) {{
+entry:
+    %r = sdiv i64 %x, {d}
+    ret i64 %r
+}}
+
+define i32 @sdiv32_{name}(i32 %x) {{
+entry:
+    %r = sdiv i32 %x, {d}
+    ret i32 %r
+}}
+"
+        );
+    }
+
+    source
+        + "
+define u64 @udiv16(u64 %x) {
+entry:
+    %r = udiv u64 %x, 16
+    ret u64 %r
+}
+
+define u64 @urem16(u64 %x) {
+entry:
+    %r = urem u64 %x, 16
+    ret u64 %r
+}
+
+define u64 @udiv7(u64 %x) {
+entry:
+    %r = udiv u64 %x, 7
+    ret u64 %r
+}
+
+define i64 @combine(i64 %x) {
+entry:
+    %a = add i64 3, %x
+    %b = sub i64 %a, 5
+    %c = mul i64 %b, 4
+    %d = mul i64 %c, 1
+    %e = or i64 %d, 0
+    ret i64 %e
+}
+"
+}
+
+#[test]
+fn divides_by_constants_with_a_multiplication() {
+    let output = run(&source(), &["instcombine"]);
+
+    assert_eq!(
+        function(&output, "sdiv7"),
+        "@sdiv7(i64 %x) {
+entry:
+    %2 = smulh i64 %x, 5270498306774157605
+    %3 = ashr i64 %2, 1
+    %4 = lshr i64 %3, 63
+    %5 = add i64 %3, %4
+    ret i64 %5"
+    );
+
+    // A negative divisor negates the multiplier
+    assert_eq!(
+        function(&output, "sdivm7"),
+        "@sdivm7(i64 %x) {
+entry:
+    %2 = smulh i64 %x, -5270498306774157605
+    %3 = ashr i64 %2, 1
+    %4 = lshr i64 %3, 63
+    %5 = add i64 %3, %4
+    ret i64 %5"
+    );
+
+    assert_eq!(
+        function(&output, "sdiv32_3"),
+        "@sdiv32_3(i32 %x) {
+entry:
+    %2 = smulh i32 %x, 1431655766
+    %3 = lshr i32 %2, 31
+    %4 = add i32 %2, %3
+    ret i32 %4"
+    );
+}
+
+#[test]
+fn divides_by_powers_of_two_with_shifts() {
+    let output = run(&source(), &["instcombine"]);
+
+    // Negative values are biased so the quotient rounds towards zero
+    assert_eq!(
+        function(&output, "sdivm8"),
+        "@sdivm8(i64 %x) {
+entry:
+    %2 = ashr i64 %x, 63
+    %3 = lshr i64 %2, 61
+    %4 = add i64 %x, %3
+    %5 = ashr i64 %4, 3
+    %6 = sub i64 0, %5
+    ret i64 %6"
+    );
+
+    assert_eq!(
+        function(&output, "udiv16"),
+        "@udiv16(u64 %x) {\nentry:\n    %r = lshr u64 %x, 4\n    ret u64 %r"
+    );
+    assert_eq!(
+        function(&output, "urem16"),
+        "@urem16(u64 %x) {\nentry:\n    %r = and u64 %x, 15\n    ret u64 %r"
+    );
+
+    // Other unsigned divisors keep the division
+    assert!(function(&output, "udiv7").contains("%r = udiv u64 %x, 7"));
+}
+
+#[test]
+fn merges_constants_and_removes_neutral_operations() {
+    let output = run(&source(), &["instcombine", "dce"]);
+
+    assert_eq!(
+        function(&output, "combine"),
+        "@combine(i64 %x) {
+entry:
+    %b = add i64 %x, -2
+    %c = shl i64 %b, 2
+    ret i64 %c"
+    );
+}
+
+#[test]
+fn divides_like_the_division_instruction() {
+    for (level, program) in programs(&source()) {
+        for d in DIVISORS {
+            let name = d.to_string().replace('-', "m");
+
+            for x in VALUES {
+                let got = program.call(&format!("sdiv{name}"), &[x]).0;
+                assert_eq!(got, x.wrapping_div(d), "{x} / {d} at {level:?}");
+
+                let x = x as i32;
+                let got = program.call(&format!("sdiv32_{name}"), &[x.into()]).0;
+                assert_eq!(
+                    got as i32,
+                    x.wrapping_div(d as i32),
+                    "{x} / {d} at {level:?}"
+                );
+            }
+        }
+
+        for x in VALUES {
+            let u = x as u64;
+
+            assert_eq!(
+                program.call("udiv16", &[x]).0 as u64,
+                u / 16,
+                "at {level:?}"
+            );
+            assert_eq!(
+                program.call("urem16", &[x]).0 as u64,
+                u % 16,
+                "at {level:?}"
+            );
+            assert_eq!(program.call("udiv7", &[x]).0 as u64, u / 7, "at {level:?}");
+            assert_eq!(
+                program.call("combine", &[x]).0,
+                x.wrapping_sub(2).wrapping_mul(4)
+            );
+        }
+    }
+}
@@ -0,0 +1,163 @@
+mod common;
+
+use common::parse;
+use tinity::ir::cfg::ControlFlowGraph;
+use tinity::ir::function::{Block, Function};
+use tinity::ir::inst::{InstKind, Operand};
+use tinity::parser::ir::get_module;
+use tinity::parser::token::get_tokens;
+use tinity::parser::types::Type;
+
+const SOURCE: &str = "define i64 @max(i64 %a, i64 %b) alwaysinline {
+entry:
+    %c = icmp sgt i64 %a, %b
+    br i1 %c, label %left, label %join
+left:
+    br label %join
+join:
+    %r = phi i64 [ %a, %left ], [ %b, %entry ]
+    ret i64 %r
+}
+
+define double @mix(ptr %p, i32 %x) {
+entry:
+    %w = sext i32 %x to i64
+    %q = ptradd ptr %p, i64 8
+    store i64 %w, ptr %q
+    %l = load u16, ptr %p
+    %f = sitofp u16 %l to double
+    %g = fadd double %f, 0.5
+    %s = syscall i64 64, 1, %w
+    %m = call i64 @max(i64 %w, i64 %s)
+    ret double %g
+}
+";
+
+fn block(function: &Function, name: &str) -> Block {
+    *function
+        .layout
+        .iter()
+        .find(|block| function.blocks[block.0].name == name)
+        .expect("the block should exist")
+}
+
+// The constant returned by a function holding only `ret <ty> <literal>`, None if the
+// literal is rejected
+fn literal(ty: &str, literal: &str) -> Option<Type> {
+    let source = format!("define {ty} @f() {{\nentry:\n    ret {ty} {literal}\n}}\n");
+    let module = get_module(get_tokens(source).ok()?).ok()?;
+    let function = &module.functions[0];
+
+    match &function.insts[0].kind {
+        InstKind::Ret {
+            value: Some(Operand::Const(constant)),
+            ..
+        } => Some(constant.clone()),
+        _ => None,
+    }
+}
+
+#[test]
+fn prints_what_it_parses() {
+    let printed = parse(SOURCE).to_string();
+
+    assert_eq!(printed.trim(), SOURCE.trim());
+    assert_eq!(parse(&printed).to_string(), printed);
+}
+
+#[test]
+fn builds_the_control_flow_graph() {
+    let module = parse(SOURCE);
+    let max = &module.functions[0];
+    let cfg = ControlFlowGraph::new(max);
+    let (entry, left, join) = (block(max, "entry"), block(max, "left"), block(max, "join"));
+
+    assert_eq!(cfg.succs(entry), [left, join]);
+    assert_eq!(cfg.preds(join), [entry, left]);
+    assert!(cfg.preds(entry).is_empty());
+    assert_eq!(cfg.reverse_postorder(max), [entry, left, join]);
+}
+
+#[test]
+fn counts_a_branch_to_one_block_once() {
+    let module = parse(
+        "
+define void @f(i1 %c) {
+entry:
+    br i1 %c, label %next, label %next
+next:
+    ret void
+}
+
+define void @g() {
+entry:
+    ret void
+dead:
+    br label %entry2
+entry2:
+    ret void
+}
+",
+    );
+
+    let f = &module.functions[0];
+    let cfg = ControlFlowGraph::new(f);
+    assert_eq!(cfg.preds(block(f, "next")), [block(f, "entry")]);
+
+    // Unreachable blocks are left out of the order
+    let g = &module.functions[1];
+    let cfg = ControlFlowGraph::new(g);
+    assert_eq!(cfg.reverse_postorder(g), [block(g, "entry")]);
+}
+
+#[test]
+fn rounds_decimal_floats_once() {
+    assert_eq!(
+        literal("float", "1.0000000596046447753906251"),
+        Some(Type::F32(0x3f800001))
+    );
+    // The nearest doubles are the midpoints of halves, the digits after them decide
+    assert_eq!(
+        literal("half", "1.00048828125000000001"),
+        Some(Type::F16(0x3c01))
+    );
+    assert_eq!(literal("half", "1.00048828125"), Some(Type::F16(0x3c00)));
+    assert_eq!(
+        literal("half", "1.00146484374999999999"),
+        Some(Type::F16(0x3c01))
+    );
+    assert_eq!(literal("half", "-1.00146484375"), Some(Type::F16(0xbc02)));
+}
+
+#[test]
+fn rounds_hexadecimal_floats_once() {
+    assert_eq!(literal("double", "0x1p-1074"), Some(Type::F64(1)));
+    assert_eq!(literal("float", "0x1p-149"), Some(Type::F32(1)));
+    assert_eq!(literal("half", "0x1p-24"), Some(Type::F16(1)));
+    assert_eq!(literal("half", "0x1.8p1"), Some(Type::F16(0x4200)));
+    // Ties go to even, anything past the tie rounds up
+    assert_eq!(
+        literal("double", "0x1.00000000000008p0"),
+        Some(Type::F64(0x3ff0000000000000))
+    );
+    assert_eq!(
+        literal("double", "0x1.000000000000080000001p0"),
+        Some(Type::F64(0x3ff0000000000001))
+    );
+    assert_eq!(
+        literal("double", "-0x1.0000000000000800000000000000000000000001p0"),
+        Some(Type::F64(0xbff0000000000001))
+    );
+    assert_eq!(
+        literal("double", "0x1fffffffffffff8p-4"),
+        Some(Type::F64(0x4340000000000000))
+    );
+}
+
+#[test]
+fn rejects_unrepresentable_hexadecimal_floats() {
+    assert_eq!(literal("double", "0x1p1024"), None);
+    assert_eq!(literal("half", "0x1p16"), None);
+    assert_eq!(literal("float", "0x1p-150"), None);
+    assert_eq!(literal("double", "0x1p99999999999"), None);
+}
@@ -0,0 +1,84 @@
+mod common;
+
+use common::{function, run};
+
+#[test]
+fn promotes_odd_arithmetic() {
+    let output = run(
+        "
+define i24 @f(i24 %a, i24 %b) {
+entry:
+    %c = sdiv i24 %a, %b
+    ret i24 %c
+}
+",
+        &["legalize"],
+    );
+    let f = function(&output, "f");
+
+    assert!(f.contains("sext i24 %a to i32"), "{f}");
+    assert!(f.contains("sdiv i32"), "{f}");
+    assert!(f.contains("%c = trunc i32"), "{f}");
+}
+
+#[test]
+fn splits_odd_loads_into_pieces() {
+    let output = run(
+        "
+define i56 @f(ptr %p) {
+entry:
+    %x = load i56, ptr %p
+    ret i56 %x
+}
+",
+        &["legalize"],
+    );
+    let f = function(&output, "f");
+
+    assert!(f.contains("load u32, ptr %p"), "{f}");
+    assert!(f.contains("ptradd ptr %p, i64 4"), "{f}");
+    assert!(f.contains("load u16"), "{f}");
+    assert!(f.contains("ptradd ptr %p, i64 6"), "{f}");
+    assert!(f.contains("load u8"), "{f}");
+    assert!(f.contains("%x = trunc u64"), "{f}");
+    assert!(!f.contains("load i56"), "{f}");
+}
+
+#[test]
+fn splits_odd_stores_into_pieces() {
+    let output = run(
+        "
+define void @f(ptr %p, i96 %v) {
+entry:
+    store i96 %v, ptr %p
+    ret void
+}
+",
+        &["legalize"],
+    );
+    let f = function(&output, "f");
+
+    assert!(f.contains("zext i96 %v to u128"), "{f}");
+    assert!(f.contains("store u64"), "{f}");
+    assert!(f.contains("lshr u128"), "{f}");
+    assert!(f.contains("ptradd ptr %p, i64 8"), "{f}");
+    assert!(f.contains("store u32"), "{f}");
+    assert!(!f.contains("store i96"), "{f}");
+}
+
+#[test]
+fn keeps_power_of_two_accesses() {
+    let source = "
+define i12 @f(ptr %p) {
+entry:
+    %x = load i12, ptr %p
+    store i12 %x, ptr %p
+    ret i12 %x
+}
+";
+    let f = function(&run(source, &["legalize"]), "f");
+
+    assert!(f.contains("%x = load i12, ptr %p"), "{f}");
+    assert!(f.contains("store i12 %x, ptr %p"), "{f}");
+    assert!(!f.contains("ptradd"), "{f}");
+}
@@ -0,0 +1,276 @@
+mod common;
+
+use common::{function, programs, run};
+use std::collections::{HashMap, HashSet};
+use tinity::{compile_to_assembly, CompileOptions, OptLevel};
+
+const SOURCE: &str = "
+define i64 @f(i64 %n, i64 %a, i64 %b, ptr %p, i1 %c) {
+entry:
+    br i1 %c, label %head, label %other
+other:
+    br label %head
+head:
+    %i = phi i64 [ 0, %entry ], [ 1, %other ], [ %i2, %head ]
+    %s = phi i64 [ 0, %entry ], [ 0, %other ], [ %s2, %head ]
+    %k = mul i64 %a, %b
+    %l = load i64, ptr %p
+    %t = add i64 %k, %l
+    %s2 = add i64 %s, %t
+    %i2 = add i64 %i, 1
+    %d = icmp slt i64 %i2, %n
+    br i1 %d, label %head, label %exit
+exit:
+    ret i64 %s2
+}
+
+define i64 @g(i64 %n, ptr %p, ptr %q) {
+entry:
+    br label %head
+head:
+    %i = phi i64 [ 0, %entry ], [ %i2, %body ]
+    %s = phi i64 [ 0, %entry ], [ %s2, %body ]
+    %d = icmp slt i64 %i, %n
+    br i1 %d, label %body, label %exit
+body:
+    %l = load i64, ptr %p
+    store i64 %i, ptr %q
+    %s2 = add i64 %s, %l
+    %i2 = add i64 %i, 1
+    br label %head
+exit:
+    ret i64 %s
+}
+
+define i64 @h(i64 %n, i64 %x) {
+entry:
+    br label %head
+head:
+    %i = phi i64 [ 0, %entry ], [ %i2, %body ]
+    %d = icmp slt i64 %i, %n
+    br i1 %d, label %body, label %exit
+body:
+    %v = sdiv i64 %x, %n
+    %i2 = add i64 %i, %v
+    br label %head
+exit:
+    ret i64 %i
+}
+";
+
+#[test]
+fn gives_loops_a_preheader() {
+    let output = run(SOURCE, &["loop-simplify"]);
+
+    // The values from outside the loop are merged in the preheader
+    assert_eq!(
+        function(&output, "f"),
+        "@f(i64 %n, i64 %a, i64 %b, ptr %p, i1 %c) {
+entry:
+    br i1 %c, label %head.preheader, label %other
+other:
+    br label %head.preheader
+head.preheader:
+    %13 = phi i64 [ 0, %entry ], [ 1, %other ]
+    br label %head
+head:
+    %i = phi i64 [ %i2, %head ], [ %13, %head.preheader ]
+    %s = phi i64 [ %s2, %head ], [ 0, %head.preheader ]
+    %k = mul i64 %a, %b
+    %l = load i64, ptr %p
+    %t = add i64 %k, %l
+    %s2 = add i64 %s, %t
+    %i2 = add i64 %i, 1
+    %d = icmp slt i64 %i2, %n
+    br i1 %d, label %head, label %exit
+exit:
+    ret i64 %s2"
+    );
+}
+
+#[test]
+fn closes_values_used_after_the_loop() {
+    let output = run(SOURCE, &["loop-simplify", "lcssa"]);
+    let f = function(&output, "f");
+
+    assert!(
+        f.ends_with("exit:\n    %14 = phi i64 [ %s2, %head ]\n    ret i64 %14"),
+        "{f}"
+    );
+}
+
+#[test]
+fn hoists_invariant_code() {
+    let output = run(SOURCE, &["loop-simplify", "lcssa", "licm"]);
+
+    // The loop body always runs, so the load can move too
+    assert_eq!(
+        function(&output, "f"),
+        "@f(i64 %n, i64 %a, i64 %b, ptr %p, i1 %c) {
+entry:
+    br i1 %c, label %head.preheader, label %other
+other:
+    br label %head.preheader
+head.preheader:
+    %13 = phi i64 [ 0, %entry ], [ 1, %other ]
+    %k = mul i64 %a, %b
+    %l = load i64, ptr %p
+    %t = add i64 %k, %l
+    br label %head
+head:
+    %i = phi i64 [ %i2, %head ], [ %13, %head.preheader ]
+    %s = phi i64 [ %s2, %head ], [ 0, %head.preheader ]
+    %s2 = add i64 %s, %t
+    %i2 = add i64 %i, 1
+    %d = icmp slt i64 %i2, %n
+    br i1 %d, label %head, label %exit
+exit:
+    %14 = phi i64 [ %s2, %head ]
+    ret i64 %14"
+    );
+}
+
+#[test]
+fn keeps_loads_that_a_store_may_change() {
+    let output = run(SOURCE, &["loop-simplify", "lcssa", "licm"]);
+    let f = function(&output, "g");
+
+    assert!(
+        f.contains("body:\n    %l = load i64, ptr %p\n    store i64 %i, ptr %q"),
+        "{f}"
+    );
+
+    // Pure code moves even when the loop may not run
+    let f = function(&output, "h");
+    assert!(
+        f.contains("entry:\n    %v = sdiv i64 %x, %n\n    br label %head"),
+        "{f}"
+    );
+}
+
+const CONSTANTS: &str = "
+define i64 @mix(i64 %n, i64 %x) {
+entry:
+    br label %head
+head:
+    %i = phi i64 [ 0, %entry ], [ %i2, %body ]
+    %s = phi i64 [ %x, %entry ], [ %s2, %body ]
+    %d = icmp slt i64 %i, %n
+    br i1 %d, label %body, label %exit
+body:
+    %m = mul i64 %s, 305419896
+    %s2 = xor i64 %m, %i
+    %i2 = add i64 %i, 1
+    br label %head
+exit:
+    ret i64 %s
+}
+
+define i64 @guarded(i64 %n, i64 %x) {
+entry:
+    %g = icmp sgt i64 %n, 0
+    br i1 %g, label %loop, label %exit
+loop:
+    %i = phi i64 [ 0, %entry ], [ %i2, %loop ]
+    %s = phi i64 [ %x, %entry ], [ %s2, %loop ]
+    %m = mul i64 %s, 305419896
+    %s2 = xor i64 %m, %i
+    %i2 = add i64 %i, 1
+    %d = icmp slt i64 %i2, %n
+    br i1 %d, label %loop, label %exit
+exit:
+    %r = phi i64 [ %x, %entry ], [ %s2, %loop ]
+    ret i64 %r
+}
+";
+
+// Whether a block of the assembly with `inst` in it can reach itself again. Blocks
+// start at the labels and end at them or at jumps, without calls in between
+fn in_loop(assembly: &str, inst: &str) -> bool {
+    let mut blocks: Vec<Vec<&str>> = vec![Vec::new()];
+    let mut labels = HashMap::new();
+
+    for line in assembly.lines() {
+        if let Some(label) = line.strip_suffix(':') {
+            blocks.push(Vec::new());
+            labels.insert(label.to_string(), blocks.len() - 1);
+        } else {
+            blocks.last_mut().unwrap().push(line.trim());
+        }
+    }
+
+    let succs = |index: usize| {
+        let mut succs = Vec::new();
+
+        for line in &blocks[index] {
+            if let Some((_, target)) = line.rsplit_once(", ") {
+                if let Some(target) = labels.get(target) {
+                    succs.push(*target);
+                }
+            }
+        }
+
+        let falls = blocks[index]
+            .last()
+            .is_none_or(|last| !last.starts_with("jal zero") && !last.starts_with("jalr zero"));
+
+        if falls && index + 1 < blocks.len() {
+            succs.push(index + 1);
+        }
+
+        succs
+    };
+
+    (0..blocks.len())
+        .filter(|index| blocks[*index].iter().any(|line| line.starts_with(inst)))
+        .any(|start| {
+            let mut seen = HashSet::new();
+            let mut work = succs(start);
+
+            while let Some(block) = work.pop() {
+                if block == start {
+                    return true;
+                }
+
+                if seen.insert(block) {
+                    work.extend(succs(block));
+                }
+            }
+
+            false
+        })
+}
+
+#[test]
+fn builds_wide_constants_before_the_loop() {
+    for opt_level in [OptLevel::O2, OptLevel::Os] {
+        let options = CompileOptions {
+            opt_level,
+            ..CompileOptions::default()
+        };
+        let assembly = compile_to_assembly(CONSTANTS, &options).unwrap();
+
+        assert!(assembly.contains("lui "), "{assembly}");
+        assert!(!in_loop(&assembly, "lui "), "{opt_level:?}: {assembly}");
+    }
+
+    for (level, program) in programs(CONSTANTS) {
+        let mut s: i64 = 7;
+
+        for i in 0..5 {
+            s = s.wrapping_mul(305419896) ^ i;
+        }
+
+        assert_eq!(program.call("mix", &[5, 7]).0, s, "mix at {level:?}");
+        assert_eq!(
+            program.call("guarded", &[5, 7]).0,
+            s,
+            "guarded at {level:?}"
+        );
+        assert_eq!(
+            program.call("guarded", &[0, 7]).0,
+            7,
+            "guarded at {level:?}"
+        );
+    }
+}
@@ -0,0 +1,400 @@
+use tinity::compile_to_assembly;
+use tinity::riscv::float::{FloatFormat, IntFormat, RoundingMode};
+use tinity::riscv::machine::{
+    AluOp, BranchCond, FloatCmp, FloatOp, ImmOp, MFReg, MReg, MachineInst, Target,
+};
+use tinity::riscv::regs::{FReg, Reg};
+use tinity::CompileOptions;
+
+fn float_op(op: FloatOp, fmt: FloatFormat, rd: FReg, rs1: FReg, rs2: FReg) -> MachineInst {
+    MachineInst::FloatOp {
+        op,
+        fmt,
+        rd: rd.into(),
+        rs1: rs1.into(),
+        rs2: rs2.into(),
+    }
+}
+
+fn float_cmp(cond: FloatCmp, fmt: FloatFormat, rd: Reg, rs1: FReg, rs2: FReg) -> MachineInst {
+    MachineInst::FloatCmp {
+        cond,
+        fmt,
+        rd: rd.into(),
+        rs1: rs1.into(),
+        rs2: rs2.into(),
+    }
+}
+
+// Words and syntax from llvm-mc, with the rounding mode left out when it is dynamic
+fn known() -> Vec<(MachineInst, &'static str, u32)> {
+    use Reg::*;
+
+    vec![
+        (
+            MachineInst::op(AluOp::Add, A0, A1, A2),
+            "add a0, a1, a2",
+            0x00c58533,
+        ),
+        (
+            MachineInst::op(AluOp::Sub, T0, T1, T2),
+            "sub t0, t1, t2",
+            0x407302b3,
+        ),
+        (
+            MachineInst::op(AluOp::Mulhu, T5, T0, T2),
+            "mulhu t5, t0, t2",
+            0x0272bf33,
+        ),
+        (
+            MachineInst::op(AluOp::Divuw, S1, S2, S3),
+            "divuw s1, s2, s3",
+            0x033954bb,
+        ),
+        (
+            MachineInst::op(AluOp::Remw, A0, A0, A1),
+            "remw a0, a0, a1",
+            0x02b5653b,
+        ),
+        (
+            MachineInst::op(AluOp::Sra, T4, T1, T2),
+            "sra t4, t1, t2",
+            0x40735eb3,
+        ),
+        (
+            MachineInst::imm(ImmOp::Addi, Sp, Sp, -32),
+            "addi sp, sp, -32",
+            0xfe010113,
+        ),
+        (
+            MachineInst::imm(ImmOp::Sltiu, T0, T0, 1),
+            "sltiu t0, t0, 1",
+            0x0012b293,
+        ),
+        (
+            MachineInst::imm(ImmOp::Xori, T6, T2, -1),
+            "xori t6, t2, -1",
+            0xfff3cf93,
+        ),
+        (
+            MachineInst::imm(ImmOp::Slli, T5, T1, 1),
+            "slli t5, t1, 1",
+            0x00131f13,
+        ),
+        (
+            MachineInst::imm(ImmOp::Srai, T1, T1, 63),
+            "srai t1, t1, 63",
+            0x43f35313,
+        ),
+        (
+            MachineInst::imm(ImmOp::Addiw, T1, T1, -1),
+            "addiw t1, t1, -1",
+            0xfff3031b,
+        ),
+        (
+            MachineInst::imm(ImmOp::Sraiw, A0, A0, 31),
+            "sraiw a0, a0, 31",
+            0x41f5551b,
+        ),
+        (
+            MachineInst::load(0, false, T0, T1, -1),
+            "lb t0, -1(t1)",
+            0xfff30283,
+        ),
+        (
+            MachineInst::load(1, true, T0, T1, 2),
+            "lhu t0, 2(t1)",
+            0x00235283,
+        ),
+        (
+            MachineInst::load(2, true, A0, Sp, 2047),
+            "lwu a0, 2047(sp)",
+            0x7ff16503,
+        ),
+        (
+            MachineInst::load(3, false, Ra, Sp, 24),
+            "ld ra, 24(sp)",
+            0x01813083,
+        ),
+        (MachineInst::store(0, T0, T1, 0), "sb t0, 0(t1)", 0x00530023),
+        (
+            MachineInst::store(1, T0, T1, -2048),
+            "sh t0, -2048(t1)",
+            0x80531023,
+        ),
+        (MachineInst::store(2, A1, A0, 4), "sw a1, 4(a0)", 0x00b52223),
+        (
+            MachineInst::store(3, Ra, Sp, 24),
+            "sd ra, 24(sp)",
+            0x00113c23,
+        ),
+        (
+            MachineInst::Lui {
+                rd: T1.into(),
+                imm: -0x80000,
+            },
+            "lui t1, 0x80000",
+            0x80000337,
+        ),
+        (
+            MachineInst::Auipc {
+                rd: Ra.into(),
+                imm: -1,
+            },
+            "auipc ra, 0xfffff",
+            0xfffff097,
+        ),
+        (
+            MachineInst::branch(BranchCond::Eq, T0, Zero, Target::Offset(8)),
+            "beq t0, zero, 8",
+            0x00028463,
+        ),
+        (
+            MachineInst::branch(BranchCond::Geu, T4, T2, Target::Offset(-4096)),
+            "bgeu t4, t2, -4096",
+            0x807ef063,
+        ),
+        (
+            MachineInst::branch(BranchCond::Lt, A0, A1, Target::Offset(4094)),
+            "blt a0, a1, 4094",
+            0x7eb54fe3,
+        ),
+        (
+            MachineInst::jal(Zero, Target::Offset(1048574)),
+            "jal zero, 1048574",
+            0x7ffff06f,
+        ),
+        (
+            MachineInst::jal(Ra, Target::Offset(-1048576)),
+            "jal ra, -1048576",
+            0x800000ef,
+        ),
+        (
+            MachineInst::Jalr {
+                rd: Zero.into(),
+                rs1: Ra.into(),
+                offset: 0,
+            },
+            "jalr zero, 0(ra)",
+            0x00008067,
+        ),
+        (
+            MachineInst::Jalr {
+                rd: Ra.into(),
+                rs1: T1.into(),
+                offset: -16,
+            },
+            "jalr ra, -16(t1)",
+            0xff0300e7,
+        ),
+        (MachineInst::Ecall, "ecall", 0x00000073),
+        (MachineInst::Ebreak, "ebreak", 0x00100073),
+        (
+            MachineInst::Fence { pred: 3, succ: 1 },
+            "fence rw, w",
+            0x0310000f,
+        ),
+        (
+            float_op(
+                FloatOp::Add,
+                FloatFormat::D,
+                FReg::Ft0,
+                FReg::Ft0,
+                FReg::Ft1,
+            ),
+            "fadd.d ft0, ft0, ft1",
+            0x02107053,
+        ),
+        (
+            float_op(
+                FloatOp::Sgnjn,
+                FloatFormat::S,
+                FReg::Fa0,
+                FReg::Fa1,
+                FReg::Fa1,
+            ),
+            "fsgnjn.s fa0, fa1, fa1",
+            0x20b59553,
+        ),
+        (
+            float_op(
+                FloatOp::Div,
+                FloatFormat::H,
+                FReg::Ft0,
+                FReg::Ft1,
+                FReg::Ft2,
+            ),
+            "fdiv.h ft0, ft1, ft2",
+            0x1c20f053,
+        ),
+        (
+            float_cmp(FloatCmp::Eq, FloatFormat::D, T0, FReg::Ft0, FReg::Ft1),
+            "feq.d t0, ft0, ft1",
+            0xa21022d3,
+        ),
+        (
+            float_cmp(FloatCmp::Le, FloatFormat::S, T1, FReg::Ft1, FReg::Ft0),
+            "fle.s t1, ft1, ft0",
+            0xa0008353,
+        ),
+        (
+            MachineInst::FcvtToInt {
+                fmt: FloatFormat::D,
+                int: IntFormat::L,
+                rm: RoundingMode::Rtz,
+                rd: T0.into(),
+                rs1: FReg::Ft0.into(),
+            },
+            "fcvt.l.d t0, ft0, rtz",
+            0xc22012d3,
+        ),
+        (
+            MachineInst::FcvtToInt {
+                fmt: FloatFormat::S,
+                int: IntFormat::Wu,
+                rm: RoundingMode::Rne,
+                rd: A0.into(),
+                rs1: FReg::Fa0.into(),
+            },
+            "fcvt.wu.s a0, fa0, rne",
+            0xc0150553,
+        ),
+        (
+            MachineInst::FcvtFromInt {
+                fmt: FloatFormat::D,
+                int: IntFormat::L,
+                rm: RoundingMode::Dyn,
+                rd: FReg::Ft0.into(),
+                rs1: T0.into(),
+            },
+            "fcvt.d.l ft0, t0",
+            0xd222f053,
+        ),
+        (
+            MachineInst::FcvtFloat {
+                fmt: FloatFormat::S,
+                from: FloatFormat::D,
+                rm: RoundingMode::Rup,
+                rd: FReg::Ft0.into(),
+                rs1: FReg::Ft1.into(),
+            },
+            "fcvt.s.d ft0, ft1, rup",
+            0x4010b053,
+        ),
+        (
+            MachineInst::FmvToInt {
+                fmt: FloatFormat::D,
+                rd: T0.into(),
+                rs1: FReg::Ft0.into(),
+            },
+            "fmv.x.d t0, ft0",
+            0xe20002d3,
+        ),
+        (
+            MachineInst::FmvFromInt {
+                fmt: FloatFormat::S,
+                rd: FReg::Fa0.into(),
+                rs1: A0.into(),
+            },
+            "fmv.w.x fa0, a0",
+            0xf0050553,
+        ),
+        (
+            MachineInst::FmvToInt {
+                fmt: FloatFormat::H,
+                rd: A0.into(),
+                rs1: FReg::Ft3.into(),
+            },
+            "fmv.x.h a0, ft3",
+            0xe4018553,
+        ),
+    ]
+}
+
+#[test]
+fn encode() {
+    for (inst, text, word) in known() {
+        assert_eq!(inst.to_string(), text);
+        assert_eq!(inst.encode().unwrap(), word, "{text}");
+    }
+}
+
+#[test]
+fn round_trip() {
+    for (inst, text, word) in known() {
+        let decoded = MachineInst::decode(word).unwrap();
+        assert_eq!(decoded, inst, "{text}");
+        assert_eq!(decoded.encode().unwrap(), word, "{text}");
+    }
+}
+
+#[test]
+fn unresolved() {
+    let virtual_reg = MachineInst::Op {
+        op: AluOp::Add,
+        rd: MReg::Virtual(0),
+        rs1: Reg::A0.into(),
+        rs2: Reg::A1.into(),
+    };
+    let virtual_freg = MachineInst::FmvToInt {
+        fmt: FloatFormat::D,
+        rd: Reg::A0.into(),
+        rs1: MFReg::Virtual(1),
+    };
+    let label = MachineInst::jal(Reg::Zero, Target::Symbol("f".to_string()));
+
+    assert_eq!(virtual_reg.to_string(), "add v0, a0, a1");
+    assert_eq!(virtual_freg.to_string(), "fmv.x.d a0, fv1");
+
+    for inst in [virtual_reg, virtual_freg, label] {
+        assert!(inst.encode().is_err(), "{inst}");
+    }
+
+    // Reserved funct3 of the loads
+    assert!(MachineInst::decode(0x00007003).is_err());
+}
+
+#[test]
+fn assembly() {
+    let source = "
+define i64 @pick(i64 %a, i64 %b) {
+entry:
+    %c = icmp slt i64 %a, %b
+    br i1 %c, label %less, label %more
+less:
+    %x = call i64 @big()
+    ret i64 %x
+more:
+    ret i64 %b
+}
+
+define private i64 @big() {
+entry:
+    ret i64 81985529216486895
+}
+";
+
+    let assembly = compile_to_assembly(source, &CompileOptions::default()).unwrap();
+
+    assert!(assembly.contains("    .globl pick\npick:\n"));
+    assert!(!assembly.contains(".globl big"));
+    assert!(assembly.contains("\nbig:\n"));
+    // The call returns its result right away, so it is a tail call
+    assert!(assembly.contains("    jal zero, big\n"));
+    assert!(assembly.contains(", .L1\n"));
+    assert!(assembly.contains("\n.L1:\n"));
+    assert!(assembly.contains(".Lpcrel0:\n    auipc a0, %pcrel_hi(.Lconstants+0)\n"));
+    assert!(assembly.contains("    ld a0, %pcrel_lo(.Lpcrel0)(a0)\n"));
+    assert!(assembly.contains("    .section .rodata\n    .p2align 3\n.Lconstants:\n"));
+    assert!(assembly.contains("    .dword 81985529216486895\n"));
+}
+
+#[test]
+fn invalid_width() {
+    let unsigned = MachineInst::load(3, true, Reg::A0, Reg::A1, 0);
+    let wide = MachineInst::store(4, Reg::A0, Reg::A1, 0);
+
+    assert!(unsigned.encode().is_err());
+    assert!(wide.encode().is_err());
+    assert_eq!(wide.to_string(), "s<2^4 bytes> a0, 0(a1)");
+}
@@ -0,0 +1,122 @@
+mod common;
+
+use common::{function, parse, run};
+
+#[test]
+fn places_phis_where_stores_meet() {
+    let output = run(
+        "
+define i64 @pick(i1 %c, i64 %a) {
+entry:
+    %p = alloca i64
+    %u = alloca i64
+    br i1 %c, label %left, label %right
+left:
+    store i64 %a, ptr %p
+    br label %join
+right:
+    store i64 7, ptr %p
+    br label %join
+join:
+    %x = load i64, ptr %p
+    %z = load i64, ptr %u
+    %r = add i64 %x, %z
+    ret i64 %r
+}
+",
+        &["mem2reg"],
+    );
+
+    // The slot never stored reads zero
+    assert_eq!(
+        function(&output, "pick"),
+        "@pick(i1 %c, i64 %a) {
+entry:
+    br i1 %c, label %left, label %right
+left:
+    br label %join
+right:
+    br label %join
+join:
+    %7 = phi i64 [ %a, %left ], [ 7, %right ]
+    %r = add i64 %7, 0
+    ret i64 %r"
+    );
+}
+
+#[test]
+fn promotes_slots_carried_around_loops() {
+    let output = run(
+        "
+define i64 @sum(i64 %n) {
+entry:
+    %s = alloca i64
+    %i = alloca i64
+    store i64 0, ptr %s
+    store i64 0, ptr %i
+    br label %head
+head:
+    %iv = load i64, ptr %i
+    %c = icmp slt i64 %iv, %n
+    br i1 %c, label %body, label %exit
+body:
+    %sv = load i64, ptr %s
+    %s2 = add i64 %sv, %iv
+    store i64 %s2, ptr %s
+    %i2 = add i64 %iv, 1
+    store i64 %i2, ptr %i
+    br label %head
+exit:
+    %r = load i64, ptr %s
+    ret i64 %r
+}
+",
+        &["mem2reg"],
+    );
+
+    assert_eq!(
+        function(&output, "sum"),
+        "@sum(i64 %n) {
+entry:
+    br label %head
+head:
+    %9 = phi i64 [ 0, %entry ], [ %s2, %body ]
+    %10 = phi i64 [ 0, %entry ], [ %i2, %body ]
+    %c = icmp slt i64 %10, %n
+    br i1 %c, label %body, label %exit
+body:
+    %s2 = add i64 %9, %10
+    %i2 = add i64 %10, 1
+    br label %head
+exit:
+    ret i64 %9"
+    );
+}
+
+#[test]
+fn keeps_slots_that_escape() {
+    let source = "
+define i64 @escapes(i64 %x) {
+entry:
+    %p = alloca i64
+    %q = alloca i64
+    %r = alloca i64
+    store i64 %x, ptr %p
+    store i64 %x, ptr %q
+    store i64 %x, ptr %r
+    store ptr %p, ptr %r
+    %e = ptradd ptr %q, i64 4
+    %a = load i64, ptr %p
+    %b = load i32, ptr %r
+    %c = load i64, ptr %e
+    ret i64 %a
+}
+";
+    let output = run(source, &["mem2reg"]);
+
+    // %p is stored somewhere, %q has its address taken and %r is read with another type
+    assert_eq!(
+        function(&output, "escapes"),
+        function(&parse(source).to_string(), "escapes")
+    );
+}
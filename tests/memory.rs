@@ -0,0 +1,96 @@
+mod common;
+
+use common::programs;
+
+const WIDTHS: [u32; 7] = [24, 40, 48, 56, 72, 96, 120];
+
+// Fills 16 bytes, so the bytes past the value show when a store writes too much
+const FILL: i128 = 0x0123_4567_89AB_CDEF_FEDC_BA98_7654_3210;
+
+const VALUES: [i128; 4] = [
+    0x7766_5544_3322_1100_FFEE_DDCC_BBAA_9988,
+    -2,
+    0x0080_0000_8000_0080_0000_8080_0080_8080,
+    1,
+];
+
+fn source() -> String {
+    let mut source = String::new();
+
+    for bits in WIDTHS {
+        source += &format!(
+            "
+define i128 @store{bits}(i128 %x) {{
+entry:
+    %p = alloca i128
+    store i128 {FILL}, ptr %p
+    %t = trunc i128 %x to i{bits}
+    store i{bits} %t, ptr %p
+    %r = load i128, ptr %p
+    ret i128 %r
+}}
+
+define i128 @sload{bits}(i128 %x) {{
+entry:
+    %p = alloca i128
+    store i128 %x, ptr %p
+    %v = load i{bits}, ptr %p
+    %r = sext i{bits} %v to i128
+    ret i128 %r
+}}
+
+define i128 @zload{bits}(i128 %x) {{
+entry:
+    %p = alloca i128
+    store i128 %x, ptr %p
+    %v = load u{bits}, ptr %p
+    %r = zext u{bits} %v to i128
+    ret i128 %r
+}}
+"
+        );
+    }
+
+    source
+}
+
+fn call(program: &common::rv::Program, name: &str, value: i128) -> i128 {
+    let (lo, hi) = program.call(name, &[value as i64, (value >> 64) as i64]);
+    (hi as i128) << 64 | lo as u64 as i128
+}
+
+#[test]
+fn odd_stores_keep_the_following_bytes() {
+    for (level, program) in programs(&source()) {
+        for bits in WIDTHS {
+            let mask = (1 << bits) - 1;
+
+            for value in VALUES {
+                let expected = FILL & !mask | value & mask;
+                let got = call(&program, &format!("store{bits}"), value);
+
+                assert_eq!(got, expected, "store{bits}({value:#x}) at {level:?}");
+            }
+        }
+    }
+}
+
+#[test]
+fn odd_loads_extend_only_their_bits() {
+    for (level, program) in programs(&source()) {
+        for bits in WIDTHS {
+            let shift = 128 - bits;
+
+            for value in VALUES {
+                let zext = (value << shift) as u128 >> shift;
+                let sext = value << shift >> shift;
+
+                let got = call(&program, &format!("zload{bits}"), value);
+                assert_eq!(got, zext as i128, "zload{bits}({value:#x}) at {level:?}");
+
+                let got = call(&program, &format!("sload{bits}"), value);
+                assert_eq!(got, sext, "sload{bits}({value:#x}) at {level:?}");
+            }
+        }
+    }
+}
@@ -0,0 +1,94 @@
+mod common;
+
+use common::{function, parse};
+use tinity::analysis::Preserved;
+use tinity::opt::{OptLevel, Pass, PassError, PassManager};
+
+const SOURCE: &str = "
+define i64 @f(i64 %x) {
+entry:
+    %p = alloca i64
+    store i64 20, ptr %p
+    %a = load i64, ptr %p
+    %b = add i64 %a, 1
+    %c = mul i64 %b, 2
+    ret i64 %c
+}
+";
+
+fn names(manager: &PassManager) -> Vec<&'static str> {
+    manager.passes().iter().map(Pass::name).collect()
+}
+
+#[test]
+fn builds_the_pipelines_of_each_level() {
+    assert_eq!(names(&PassManager::from_level(OptLevel::O0)), ["inline"]);
+
+    let o2 = names(&PassManager::from_level(OptLevel::O2));
+    let os = names(&PassManager::from_level(OptLevel::Os));
+
+    assert!(o2.contains(&"loop-unroll"));
+    // Unrolling grows the code
+    assert!(!os.contains(&"loop-unroll"));
+    assert_eq!(o2.first(), Some(&"inline"));
+    assert_eq!(o2.last(), Some(&"dce"));
+}
+
+#[test]
+fn parses_levels_and_pass_names() {
+    assert_eq!("s".parse::<OptLevel>().unwrap(), OptLevel::Os);
+    assert!(matches!("3".parse::<OptLevel>(), Err(PassError::UnknownLevel(level)) if level == "3"));
+
+    let manager = PassManager::from_names(&["mem2reg", "dce"]).unwrap();
+    assert_eq!(names(&manager), ["mem2reg", "dce"]);
+
+    assert!(matches!(
+        PassManager::from_names(&["mem2reg", "unknown"]),
+        Err(PassError::UnknownPass(name)) if name == "unknown"
+    ));
+}
+
+#[test]
+fn optimizes_at_o1() {
+    let mut module = parse(SOURCE);
+    let changed = PassManager::from_level(OptLevel::O1)
+        .set_verify(true)
+        .run(&mut module)
+        .unwrap();
+
+    assert!(changed);
+    assert_eq!(
+        function(&module.to_string(), "f"),
+        "@f(i64 %x) {\nentry:\n    ret i64 42"
+    );
+
+    // A second run has nothing left to do
+    assert!(!PassManager::from_level(OptLevel::O1)
+        .run(&mut module)
+        .unwrap());
+}
+
+#[test]
+fn reports_the_pass_that_broke_the_ir() {
+    let broken = Pass::Function {
+        name: "broken",
+        run: |function, _| {
+            let entry = function.entry().unwrap();
+            function.blocks[entry.0].insts.pop();
+            true
+        },
+        preserves: Preserved::None,
+    };
+
+    let mut module = parse(SOURCE);
+    let result = PassManager::new()
+        .add_pass(broken)
+        .set_verify(true)
+        .run(&mut module);
+
+    assert!(matches!(result, Err(PassError::InvalidIr { pass, .. }) if pass == "broken"));
+
+    // Without verification the pass runs unchecked
+    let mut module = parse(SOURCE);
+    assert!(PassManager::new().add_pass(broken).run(&mut module).is_ok());
+}
@@ -0,0 +1,340 @@
+mod common;
+
+use common::{parse, programs};
+use tinity::codegen::regalloc::{allocate, Allocation, ALLOCATABLE};
+use tinity::ir::function::{Function, Value, ValueDef};
+use tinity::riscv::regs::Reg;
+use tinity::{compile_to_assembly, CompileOptions};
+
+const LEAF: &str = "
+define i64 @leaf(i64 %a, i64 %b) {
+entry:
+    %s = add i64 %a, %b
+    %p = mul i64 %s, %a
+    ret i64 %p
+}
+
+define i64 @outer(i64 %a) {
+entry:
+    %x = call i64 @leaf(i64 %a, i64 3)
+    %y = add i64 %x, 1
+    ret i64 %y
+}
+";
+
+// The parameters of @rotate are live across both calls, the arguments trade places
+const ROTATE: &str = "
+define i64 @sub3(i64 %a, i64 %b, i64 %c) {
+entry:
+    %d = sub i64 %a, %b
+    %e = mul i64 %d, %c
+    ret i64 %e
+}
+
+define i64 @rotate(i64 %a, i64 %b, i64 %c) {
+entry:
+    %x = call i64 @sub3(i64 %c, i64 %a, i64 %b)
+    %y = call i64 @sub3(i64 %b, i64 %x, i64 %a)
+    %s = add i64 %y, %c
+    ret i64 %s
+}
+";
+
+const COMPARE: &str = "
+define i64 @max(i64 %a, i64 %b) {
+entry:
+    %c = icmp sgt i64 %a, %b
+    br i1 %c, label %left, label %right
+left:
+    ret i64 %a
+right:
+    ret i64 %b
+}
+
+define i64 @count(i64 %a, i64 %b, i64 %c) {
+entry:
+    %x = icmp ule i64 %a, %b
+    %y = icmp ne i64 %b, %c
+    %p = zext i1 %x to i64
+    %q = zext i1 %y to i64
+    %s = add i64 %p, %q
+    ret i64 %s
+}
+";
+
+// More values live at once than there are registers, %w0 is read last
+fn pressure(values: i64) -> String {
+    let mut source = "define i64 @pressure(i64 %x) {\nentry:\n".to_string();
+
+    for index in 0..values {
+        source += &format!("    %m{index} = mul i64 %x, {}\n", index + 3);
+        source += &format!("    %w{index} = xor i64 %m{index}, {}\n", index * 7 + 1);
+    }
+
+    let mut last = format!("%w{}", values - 1);
+
+    for index in (0..values - 1).rev() {
+        source += &format!("    %s{index} = sub i64 {last}, %w{index}\n");
+        last = format!("%s{index}");
+    }
+
+    source + &format!("    ret i64 {last}\n}}\n")
+}
+
+fn expected_pressure(values: i64, x: i64) -> i64 {
+    let words: Vec<i64> = (0..values)
+        .map(|index| x.wrapping_mul(index + 3) ^ (index * 7 + 1))
+        .collect();
+    let last = words[words.len() - 1];
+
+    words
+        .iter()
+        .rev()
+        .skip(1)
+        .fold(last, |acc, word| acc.wrapping_sub(*word))
+}
+
+// More values live across a call than there are callee-saved registers
+fn across_call(values: i64) -> String {
+    let mut source = "
+define i64 @twice(i64 %x) {
+entry:
+    %r = shl i64 %x, 1
+    ret i64 %r
+}
+
+define i64 @across(i64 %x) {
+entry:
+"
+    .to_string();
+
+    for index in 0..values {
+        source += &format!("    %v{index} = add i64 %x, {}\n", index * 5 + 2);
+    }
+
+    source += "    %c = call i64 @twice(i64 %x)\n";
+    let mut last = "%c".to_string();
+
+    for index in 0..values {
+        source += &format!("    %p{index} = mul i64 {last}, %v{index}\n");
+        last = format!("%p{index}");
+    }
+
+    source + &format!("    ret i64 {last}\n}}\n")
+}
+
+fn expected_across(values: i64, x: i64) -> i64 {
+    (0..values).fold(x.wrapping_mul(2), |acc, index| {
+        acc.wrapping_mul(x.wrapping_add(index * 5 + 2))
+    })
+}
+
+// @name as parsed and its allocation, with the blocks in the order of the source
+fn allocation(source: &str, name: &str) -> (Function, Allocation) {
+    let module = parse(source);
+    let function = module.get_function(name).unwrap().clone();
+    let allocation = allocate(&function, &function.layout);
+
+    (function, allocation)
+}
+
+fn value(function: &Function, name: &str) -> Value {
+    let index = function
+        .values
+        .iter()
+        .position(|data| data.name.as_deref() == Some(name));
+    Value(index.expect("the value should exist"))
+}
+
+// Position of the instruction defining the value
+fn defined_at(function: &Function, allocation: &Allocation, name: &str) -> usize {
+    match function.values[value(function, name).0].def {
+        ValueDef::Inst(inst) => allocation.positions[&inst],
+        _ => panic!("%{name} should be defined by an instruction"),
+    }
+}
+
+// The assembly of @name, up to the blank line after it
+fn body<'a>(assembly: &'a str, name: &str) -> &'a str {
+    let start = assembly
+        .find(&format!("\n{name}:\n"))
+        .expect("the function should exist");
+    let end = assembly[start + 1..]
+        .find("\n\n")
+        .map_or(assembly.len(), |end| end + start + 1);
+
+    &assembly[start..end]
+}
+
+// A value kept across a call and more than 2 KiB of 128 bit slots, so ra and the
+// saved register sit past the 12 bit offsets from sp
+fn large_frame() -> String {
+    let mut source = "
+define i64 @seven() {
+entry:
+    ret i64 7
+}
+
+define i128 @large(i128 %a, i128 %b) {
+entry:
+    %k = call i64 @seven()
+"
+    .to_string();
+
+    let mut last = "%a".to_string();
+
+    for index in 0..150 {
+        source += &format!("    %d{index} = sdiv i128 {last}, %b\n");
+        last = format!("%d{index}");
+    }
+
+    source
+        + &format!(
+            "    %e = sext i64 %k to i128
+    %r = add i128 {last}, %e
+    ret i128 %r
+}}
+"
+        )
+}
+
+#[test]
+fn frame_over_2_kib() {
+    let source = large_frame();
+
+    for (level, program) in programs(&source) {
+        assert_eq!(
+            program.call("large", &[1000, 0, 1, 0]),
+            (1007, 0),
+            "{level:?}"
+        );
+        assert_eq!(
+            program.call("large", &[-50, -1, 1, 0]),
+            (-43, -1),
+            "{level:?}"
+        );
+    }
+
+    let assembly = compile_to_assembly(&source, &CompileOptions::default()).unwrap();
+    assert!(assembly.contains("    sub sp, sp, t0\n"));
+    assert!(assembly.contains("    add sp, sp, t0\n"));
+}
+
+#[test]
+fn leaf_keeps_ra() {
+    for (level, program) in programs(LEAF) {
+        assert_eq!(program.call("leaf", &[4, 5]).0, 36, "{level:?}");
+        assert_eq!(program.call("outer", &[4]).0, 29, "{level:?}");
+    }
+
+    let assembly = compile_to_assembly(LEAF, &CompileOptions::default()).unwrap();
+    assert!(!body(&assembly, "leaf").contains("ra,"));
+    assert!(body(&assembly, "outer").contains("    sd ra, "));
+    assert!(body(&assembly, "outer").contains("    ld ra, "));
+}
+
+#[test]
+fn argument_registers() {
+    for (level, program) in programs(ROTATE) {
+        assert_eq!(program.call("sub3", &[7, 10, 3]).0, -9, "{level:?}");
+        assert_eq!(program.call("rotate", &[10, 3, 7]).0, 127, "{level:?}");
+        assert_eq!(program.call("rotate", &[-4, 0, 2]).0, 2, "{level:?}");
+    }
+
+    // Without calls everything stays in the argument registers, with no frame
+    let assembly = compile_to_assembly(ROTATE, &CompileOptions::default()).unwrap();
+    let sub3 = body(&assembly, "sub3");
+    assert!(!sub3.contains("sp"));
+    assert!(!sub3.contains("sd "));
+
+    // What is live across a call goes to the callee-saved registers
+    let rotate = body(&assembly, "rotate");
+    assert!(rotate.contains("    sd s1, "));
+    assert!(!rotate.contains("    sd a"));
+}
+
+#[test]
+fn compares_in_place() {
+    for (level, program) in programs(COMPARE) {
+        assert_eq!(program.call("max", &[3, -8]).0, 3, "{level:?}");
+        assert_eq!(program.call("max", &[-3, 8]).0, 8, "{level:?}");
+        assert_eq!(program.call("count", &[1, 2, 3]).0, 2, "{level:?}");
+        assert_eq!(program.call("count", &[-1, 2, 2]).0, 0, "{level:?}");
+    }
+
+    // The compare and the branch read the registers of the operands
+    let assembly = compile_to_assembly(COMPARE, &CompileOptions::default()).unwrap();
+    let max = body(&assembly, "max");
+    assert!(max.contains("    slt a2, a1, a0\n    bne a2, zero, "));
+    assert!(!max.contains(" t"));
+}
+
+#[test]
+fn spills_under_pressure() {
+    let values = ALLOCATABLE.len() as i64 + 10;
+    let source = pressure(values);
+
+    for (level, program) in programs(&source) {
+        for x in [5, -123456789, 1 << 40] {
+            let expected = expected_pressure(values, x);
+            assert_eq!(program.call("pressure", &[x]).0, expected, "{level:?}");
+        }
+    }
+
+    let (function, allocation) = allocation(&source, "pressure");
+    assert!(!allocation.spilled.is_empty());
+
+    // %w0 is read by the last subtraction, it starts in a register and gives it up
+    let w0 = value(&function, "w0");
+    let from = defined_at(&function, &allocation, "w0");
+    let to = defined_at(&function, &allocation, "s0");
+
+    assert!(allocation.spilled.contains(&w0));
+    assert!(allocation.location(w0, from).is_some());
+    assert!((from..=to)
+        .step_by(2)
+        .any(|position| allocation.location(w0, position).is_none()));
+}
+
+#[test]
+fn splits_around_calls() {
+    let values = 16;
+    let source = across_call(values);
+
+    for (level, program) in programs(&source) {
+        for x in [3, -1000, 1 << 33] {
+            let expected = expected_across(values, x);
+            assert_eq!(program.call("across", &[x]).0, expected, "{level:?}");
+        }
+    }
+
+    let (function, allocation) = allocation(&source, "across");
+    let call = defined_at(&function, &allocation, "c");
+    let caller_saved = |reg: Reg| {
+        use Reg::*;
+        [A0, A1, A2, A3, A4, A5, A6, A7].contains(&reg)
+    };
+    let mut split = 0;
+
+    for index in 0..values {
+        let name = format!("v{index}");
+        let value = value(&function, &name);
+        let before = allocation.location(value, defined_at(&function, &allocation, &name));
+        let read = defined_at(&function, &allocation, &format!("p{index}"));
+
+        // Nothing stays in an argument register past the call, it is only loaded back
+        // into one for the read
+        for position in (call + 2..read).step_by(2) {
+            let reg = allocation.location(value, position);
+            assert!(!reg.is_some_and(caller_saved), "%{name} at {position}");
+        }
+
+        if before.is_some_and(caller_saved) {
+            assert!(allocation.spilled.contains(&value));
+            split += 1;
+        }
+    }
+
+    // Twelve callee-saved registers for sixteen values, the rest wait in the stack
+    assert_eq!(split, 4);
+}
@@ -0,0 +1,174 @@
+mod common;
+
+use common::programs;
+use object::{Object, ObjectSection, ObjectSymbol};
+use tinity::riscv::machine::{MReg, MachineInst, Target};
+use tinity::riscv::regs::Reg;
+use tinity::{compile, CompileOptions};
+
+// About 220 bytes per division
+fn filler(name: &str, divisions: usize) -> String {
+    let mut source = format!("define i128 @{name}(i128 %a, i128 %b) {{\nentry:\n");
+    let mut last = "%a".to_string();
+
+    for index in 0..divisions {
+        source += &format!("    %d{index} = sdiv i128 {last}, %b\n");
+        last = format!("%d{index}");
+    }
+
+    source + &format!("    ret i128 {last}\n}}\n\n")
+}
+
+// The else block comes after more than 4 KiB of divisions
+fn far_branch() -> String {
+    let mut source = "define i64 @far(i64 %x) {
+entry:
+    %c = icmp eq i64 %x, 0
+    br i1 %c, label %zero, label %divide
+divide:
+    %a = sext i64 %x to i128
+"
+    .to_string();
+
+    let mut last = "%a".to_string();
+
+    for index in 0..20 {
+        source += &format!("    %d{index} = sdiv i128 {last}, 1\n");
+        last = format!("%d{index}");
+    }
+
+    source
+        + &format!(
+            "    %t = trunc i128 {last} to i64
+    %r = add i64 %t, 1
+    ret i64 %r
+zero:
+    ret i64 -1
+}}
+"
+        )
+}
+
+// @near and @away are more than 1 MiB apart, with calls and a tail call both ways
+fn far_calls() -> String {
+    let mut source = "
+define i64 @near(i64 %x) {
+entry:
+    %r = call i64 @away(i64 %x)
+    %s = add i64 %r, 1
+    ret i64 %s
+}
+
+define i64 @base(i64 %x) {
+entry:
+    %r = mul i64 %x, 3
+    ret i64 %r
+}
+
+"
+    .to_string();
+
+    for index in 0..50 {
+        source += &filler(&format!("filler{index}"), 100);
+    }
+
+    source
+        + "
+define i64 @away(i64 %x) {
+entry:
+    %r = call i64 @base(i64 %x)
+    %s = add i64 %r, 10
+    ret i64 %s
+}
+
+define i64 @back(i64 %x) {
+entry:
+    %r = call i64 @near(i64 %x)
+    ret i64 %r
+}
+"
+}
+
+// Instructions of `name` in the object
+fn function(elf: &[u8], name: &str) -> Vec<MachineInst> {
+    let file = object::File::parse(elf).unwrap();
+    let text = file
+        .section_by_name(".text")
+        .unwrap()
+        .data()
+        .unwrap()
+        .to_vec();
+    let symbol = file.symbol_by_name(name).unwrap();
+    let start = symbol.address() as usize;
+    let end = start + symbol.size() as usize;
+
+    text[start..end]
+        .chunks(4)
+        .map(|word| MachineInst::decode(u32::from_le_bytes(word.try_into().unwrap())).unwrap())
+        .collect()
+}
+
+// `auipc` and `jalr` linking through `link`, calls use ra as the scratch and the rest t1
+fn far_jumps(insts: &[MachineInst], link: Reg) -> usize {
+    let expected = MReg::from(if link == Reg::Ra { Reg::Ra } else { Reg::T1 });
+
+    insts
+        .windows(2)
+        .filter(|pair| match pair {
+            [MachineInst::Auipc { rd: scratch, .. }, MachineInst::Jalr { rd, rs1, .. }] => {
+                *rd == MReg::from(link) && *rs1 == expected && *scratch == expected
+            }
+            _ => false,
+        })
+        .count()
+}
+
+#[test]
+fn branch_over_4_kib() {
+    let source = far_branch();
+
+    for (level, program) in programs(&source) {
+        assert_eq!(program.call("far", &[0]).0, -1, "{level:?}");
+        assert_eq!(program.call("far", &[41]).0, 42, "{level:?}");
+        assert_eq!(program.call("far", &[-7]).0, -6, "{level:?}");
+    }
+
+    // The branch to the else block is inverted over a jump
+    let elf = compile(&source, &CompileOptions::default()).unwrap();
+    let insts = function(&elf, "far");
+    let size = insts.len() * 4;
+    assert!(size > 4096, "@far takes {size} bytes");
+
+    let relaxed = insts.windows(2).filter(|pair| {
+        matches!(
+            pair,
+            [
+                MachineInst::Branch {
+                    target: Target::Offset(8),
+                    ..
+                },
+                MachineInst::Jal { rd, .. },
+            ] if *rd == MReg::from(Reg::Zero)
+        )
+    });
+    assert_eq!(relaxed.count(), 1);
+}
+
+#[test]
+fn call_over_1_mib() {
+    let source = far_calls();
+
+    for (level, program) in programs(&source) {
+        assert_eq!(program.call("near", &[5]).0, 26, "{level:?}");
+        assert_eq!(program.call("back", &[-2]).0, 5, "{level:?}");
+    }
+
+    let elf = compile(&source, &CompileOptions::default()).unwrap();
+    let file = object::File::parse(&*elf).unwrap();
+    let address = |name: &str| file.symbol_by_name(name).unwrap().address();
+    assert!(address("away") - address("near") > 1 << 20);
+
+    assert_eq!(far_jumps(&function(&elf, "near"), Reg::Ra), 1);
+    assert_eq!(far_jumps(&function(&elf, "away"), Reg::Ra), 1);
+    assert_eq!(far_jumps(&function(&elf, "back"), Reg::Zero), 1);
+}
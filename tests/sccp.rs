@@ -0,0 +1,99 @@
+mod common;
+
+use common::{function, run};
+
+#[test]
+fn folds_branches_on_constants() {
+    let output = run(
+        "
+define i64 @f(i64 %x) {
+entry:
+    %a = add i64 2, 3
+    %c = icmp sgt i64 %a, 4
+    br i1 %c, label %yes, label %no
+yes:
+    %y = mul i64 %a, 2
+    br label %join
+no:
+    %z = add i64 %x, 1
+    br label %join
+join:
+    %r = phi i64 [ %y, %yes ], [ %z, %no ]
+    ret i64 %r
+}
+",
+        &["sccp"],
+    );
+
+    // %no is left for dce, only its edge is gone
+    assert_eq!(
+        function(&output, "f"),
+        "@f(i64 %x) {
+entry:
+    br label %yes
+yes:
+    br label %join
+no:
+    %z = add i64 %x, 1
+    br label %join
+join:
+    ret i64 10"
+    );
+}
+
+#[test]
+fn finds_constants_carried_around_loops() {
+    let output = run(
+        "
+define i64 @loop(i64 %n) {
+entry:
+    br label %head
+head:
+    %k = phi i64 [ 7, %entry ], [ %k2, %body ]
+    %i = phi i64 [ 0, %entry ], [ %i2, %body ]
+    %c = icmp slt i64 %i, %n
+    br i1 %c, label %body, label %exit
+body:
+    %k2 = add i64 %k, 0
+    %i2 = add i64 %i, 1
+    br label %head
+exit:
+    ret i64 %k
+}
+",
+        &["sccp"],
+    );
+    let f = function(&output, "loop");
+
+    assert!(!f.contains("%k"), "{f}");
+    assert!(f.contains("ret i64 7"), "{f}");
+    assert!(
+        f.contains("%i = phi i64 [ 0, %entry ], [ %i2, %body ]"),
+        "{f}"
+    );
+}
+
+#[test]
+fn keeps_values_that_depend_on_the_input() {
+    let output = run(
+        "
+define i64 @overdef(i64 %x) {
+entry:
+    %c = icmp sgt i64 %x, 4
+    br i1 %c, label %yes, label %no
+yes:
+    br label %join
+no:
+    br label %join
+join:
+    %r = phi i64 [ 1, %yes ], [ 2, %no ]
+    ret i64 %r
+}
+",
+        &["sccp"],
+    );
+    let f = function(&output, "overdef");
+
+    assert!(f.contains("%r = phi i64 [ 1, %yes ], [ 2, %no ]"), "{f}");
+    assert!(f.contains("br i1 %c"), "{f}");
+}
@@ -0,0 +1,180 @@
+mod common;
+
+use common::{function, programs, run};
+
+const SOURCE: &str = "
+define i64 @merge(i64 %x) {
+entry:
+    %a = add i64 %x, 1
+    br label %next
+next:
+    %b = mul i64 %a, 2
+    br label %skip
+skip:
+    br label %last
+last:
+    ret i64 %b
+}
+
+define i64 @thread(i1 %c, i64 %x) {
+entry:
+    br i1 %c, label %left, label %right
+left:
+    br label %test
+right:
+    br label %test
+test:
+    %k = phi i1 [ 1, %left ], [ 0, %right ]
+    br i1 %k, label %yes, label %no
+yes:
+    ret i64 1
+no:
+    ret i64 2
+}
+
+define i64 @tails(i1 %c, i64 %x, i64 %y) {
+entry:
+    br i1 %c, label %left, label %right
+left:
+    %a = add i64 %x, 1
+    %b = mul i64 %a, %y
+    ret i64 %b
+right:
+    %d = sub i64 %y, 1
+    %e = add i64 %x, 1
+    %f = mul i64 %e, %y
+    ret i64 %f
+}
+
+define i64 @constant(i64 %x) {
+entry:
+    br i1 1, label %yes, label %no
+yes:
+    ret i64 %x
+no:
+    ret i64 0
+}
+
+define i64 @tails2(i1 %c, i64 %x, i64 %y, ptr %p) {
+entry:
+    br i1 %c, label %left, label %right
+left:
+    store i64 %y, ptr %p
+    %a = add i64 %x, 1
+    %b = mul i64 %a, %y
+    ret i64 %b
+right:
+    store i64 %x, ptr %p
+    %e = add i64 %x, 1
+    %f = mul i64 %e, %y
+    ret i64 %f
+}
+
+define i64 @same(i1 %c, i64 %x) {
+entry:
+    br i1 %c, label %mid, label %other
+other:
+    %o = add i64 %x, 5
+    br label %mid
+mid:
+    br i1 %c, label %yes, label %no
+yes:
+    ret i64 1
+no:
+    ret i64 2
+}
+";
+
+fn simplified(name: &str) -> String {
+    function(&run(SOURCE, &["simplifycfg"]), name)
+}
+
+#[test]
+fn merges_straight_line_blocks() {
+    assert_eq!(
+        simplified("merge"),
+        "@merge(i64 %x) {
+entry:
+    %a = add i64 %x, 1
+    %b = mul i64 %a, 2
+    ret i64 %b"
+    );
+
+    // The branch on a constant becomes a jump, and the side not taken goes away
+    assert_eq!(
+        simplified("constant"),
+        "@constant(i64 %x) {\nentry:\n    ret i64 %x"
+    );
+}
+
+#[test]
+fn threads_jumps_through_known_branches() {
+    // The phi tells which way each predecessor goes
+    assert_eq!(
+        simplified("thread"),
+        "@thread(i1 %c, i64 %x) {
+entry:
+    br i1 %c, label %yes, label %no
+yes:
+    ret i64 1
+no:
+    ret i64 2"
+    );
+
+    // Coming from a branch on the same condition, the side is the one taken there
+    let f = simplified("same");
+    assert!(
+        f.starts_with("@same(i1 %c, i64 %x) {\nentry:\n    br i1 %c, label %yes, label %other"),
+        "{f}"
+    );
+}
+
+#[test]
+fn shares_identical_tails() {
+    // One block is the tail of the other, so it jumps there
+    assert_eq!(
+        simplified("tails"),
+        "@tails(i1 %c, i64 %x, i64 %y) {
+entry:
+    br i1 %c, label %left, label %right
+left:
+    %a = add i64 %x, 1
+    %b = mul i64 %a, %y
+    ret i64 %b
+right:
+    %d = sub i64 %y, 1
+    br label %left"
+    );
+
+    // Otherwise the tail moves to a block of its own
+    assert_eq!(
+        simplified("tails2"),
+        "@tails2(i1 %c, i64 %x, i64 %y, ptr %p) {
+entry:
+    br i1 %c, label %left, label %right
+left:
+    store i64 %y, ptr %p
+    br label %left.tail
+left.tail:
+    %a = add i64 %x, 1
+    %b = mul i64 %a, %y
+    ret i64 %b
+right:
+    store i64 %x, ptr %p
+    br label %left.tail"
+    );
+}
+
+#[test]
+fn keeps_the_results() {
+    for (level, program) in programs(SOURCE) {
+        for c in [0, 1] {
+            assert_eq!(program.call("thread", &[c, 0]).0, 2 - c, "at {level:?}");
+            assert_eq!(program.call("same", &[c, 0]).0, 2 - c, "at {level:?}");
+            assert_eq!(program.call("tails", &[c, 4, 3]).0, 15, "at {level:?}");
+        }
+
+        assert_eq!(program.call("merge", &[4]).0, 10, "at {level:?}");
+        assert_eq!(program.call("constant", &[4]).0, 4, "at {level:?}");
+    }
+}
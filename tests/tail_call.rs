@@ -0,0 +1,118 @@
+mod common;
+
+use common::programs;
+use tinity::codegen::CodegenError;
+use tinity::{compile, CompileOptions, Diagnostic};
+
+const SOURCE: &str = "
+define i64 @count(i64 %n, i64 %acc) {
+entry:
+    %c = icmp eq i64 %n, 0
+    br i1 %c, label %done, label %more
+done:
+    ret i64 %acc
+more:
+    %m = sub i64 %n, 1
+    %a = add i64 %acc, %n
+    %r = musttail call i64 @count(i64 %m, i64 %a)
+    ret i64 %r
+}
+
+define i64 @even(i64 %n) {
+entry:
+    %c = icmp eq i64 %n, 0
+    br i1 %c, label %yes, label %no
+yes:
+    ret i64 1
+no:
+    %m = sub i64 %n, 1
+    %r = call i64 @odd(i64 %m)
+    ret i64 %r
+}
+
+define i64 @odd(i64 %n) {
+entry:
+    %c = icmp eq i64 %n, 0
+    br i1 %c, label %yes, label %no
+yes:
+    ret i64 0
+no:
+    %m = sub i64 %n, 1
+    %r = call i64 @even(i64 %m)
+    ret i64 %r
+}
+";
+
+#[test]
+fn recurses_in_constant_stack() {
+    for (level, program) in programs(SOURCE) {
+        let (result, shallow) = program.call_with_stack("count", &[10, 0]);
+        assert_eq!(result.0, 55, "at {level:?}");
+
+        // A hundred thousand frames would not fit in the memory of the emulator
+        let (result, deep) = program.call_with_stack("count", &[100_000, 0]);
+        assert_eq!(result.0, 5_000_050_000, "at {level:?}");
+        assert_eq!(deep, shallow, "at {level:?}");
+
+        // Calls followed by a return are tail calls even without the marker
+        let (result, shallow) = program.call_with_stack("odd", &[11]);
+        assert_eq!(result.0, 1, "at {level:?}");
+
+        let (result, deep) = program.call_with_stack("odd", &[10_001]);
+        assert_eq!(result.0, 1, "at {level:?}");
+        assert_eq!(deep, shallow, "at {level:?}");
+    }
+}
+
+fn musttail_error(body: &str) -> (String, String, &'static str) {
+    let source = format!(
+        "
+define i64 @id(i64 %x) {{
+entry:
+    ret i64 %x
+}}
+
+define i64 @f(i64 %x) {{
+entry:
+{body}
+}}
+"
+    );
+
+    let errors = compile(&source, &CompileOptions::default())
+        .expect_err("the musttail call should be rejected")
+        .errors;
+
+    match &errors[..] {
+        [Diagnostic::Codegen(CodegenError::MustTail(function, callee, reason))] => {
+            (function.clone(), callee.clone(), reason)
+        }
+        errors => panic!("unexpected errors: {errors:?}"),
+    }
+}
+
+#[test]
+fn rejects_musttail_calls_that_are_not_tail_calls() {
+    let (function, callee, reason) = musttail_error(
+        "    %r = musttail call i64 @id(i64 %x)
+    %s = add i64 %r, 1
+    ret i64 %s",
+    );
+    assert_eq!((function.as_str(), callee.as_str()), ("f", "id"));
+    assert_eq!(reason, "it is not followed by a return");
+
+    let (_, _, reason) = musttail_error(
+        "    %r = musttail call i64 @id(i64 %x)
+    ret i64 %x",
+    );
+    assert_eq!(reason, "the return does not use its result");
+
+    // The callee could be given a pointer into the frame it replaces
+    let (_, _, reason) = musttail_error(
+        "    %p = alloca i64
+    store i64 %x, ptr %p
+    %r = musttail call i64 @id(i64 %x)
+    ret i64 %r",
+    );
+    assert_eq!(reason, "the caller has stack memory");
+}
@@ -0,0 +1,145 @@
+mod common;
+
+use common::{function, programs, run};
+
+const SOURCE: &str = "
+define i64 @full() {
+entry:
+    br label %head
+head:
+    %i = phi i64 [ 0, %entry ], [ %i2, %head ]
+    %s = phi i64 [ 0, %entry ], [ %s2, %head ]
+    %s2 = add i64 %s, %i
+    %i2 = add i64 %i, 1
+    %c = icmp slt i64 %i2, 3
+    br i1 %c, label %head, label %exit
+exit:
+    ret i64 %s2
+}
+
+define i64 @partial(i64 %n) {
+entry:
+    br label %head
+head:
+    %i = phi i64 [ 0, %entry ], [ %i2, %head ]
+    %s = phi i64 [ 0, %entry ], [ %s2, %head ]
+    %s2 = add i64 %s, %i
+    %i2 = add i64 %i, 1
+    %c = icmp slt i64 %i2, %n
+    br i1 %c, label %head, label %exit
+exit:
+    ret i64 %s2
+}
+
+define i64 @scale(i64 %n) {
+entry:
+    br label %head
+head:
+    %i = phi i64 [ 0, %entry ], [ %i2, %head ]
+    %s = phi i64 [ 0, %entry ], [ %s2, %head ]
+    %m = mul i64 %i, 8
+    %a = add i64 %m, 5
+    %s2 = add i64 %s, %a
+    %i2 = add i64 %i, 1
+    %c = icmp slt i64 %i2, %n
+    br i1 %c, label %head, label %exit
+exit:
+    ret i64 %s2
+}
+";
+
+#[test]
+fn unrolls_constant_trip_counts_fully() {
+    let output = run(
+        SOURCE,
+        &[
+            "loop-simplify",
+            "lcssa",
+            "loop-unroll",
+            "constfold",
+            "simplifycfg",
+            "dce",
+        ],
+    );
+
+    assert_eq!(
+        function(&output, "full"),
+        "@full() {\nentry:\n    ret i64 3"
+    );
+}
+
+#[test]
+fn unrolls_unknown_trip_counts_with_a_remainder() {
+    let output = run(SOURCE, &["loop-simplify", "lcssa", "loop-unroll"]);
+    let f = function(&output, "partial");
+
+    // Four iterations per trip, counted down from n / 4
+    assert!(
+        f.contains("%8 = lshr i64 %n, 2\n    %9 = and i64 %n, 3"),
+        "{f}"
+    );
+    assert!(
+        f.contains("%s2.unroll3 = add i64 %s2.unroll2, %i2.unroll2"),
+        "{f}"
+    );
+    assert!(f.contains("%25 = sub i64 %24, 1"), "{f}");
+
+    // The original loop runs what is left, from where the unrolled one stopped
+    assert!(
+        f.contains("%29 = phi i64 [ 0, %entry ], [ 0, %head.trips ], [ %i2.unroll3, %head.check ]"),
+        "{f}"
+    );
+    assert!(
+        f.contains("exit:\n    %6 = phi i64 [ %s2, %head ], [ %s2.unroll3, %head.check ]"),
+        "{f}"
+    );
+}
+
+#[test]
+fn reduces_multiples_of_induction_variables() {
+    let output = run(SOURCE, &["loop-simplify", "lcssa", "indvars", "dce"]);
+
+    assert_eq!(
+        function(&output, "scale"),
+        "@scale(i64 %n) {
+entry:
+    br label %head
+head:
+    %a.iv = phi i64 [ 5, %entry ], [ %12, %head ]
+    %i = phi i64 [ 0, %entry ], [ %i2, %head ]
+    %s = phi i64 [ 0, %entry ], [ %s2, %head ]
+    %s2 = add i64 %s, %a.iv
+    %i2 = add i64 %i, 1
+    %c = icmp slt i64 %i2, %n
+    %12 = add i64 %a.iv, 8
+    br i1 %c, label %head, label %exit
+exit:
+    %8 = phi i64 [ %s2, %head ]
+    ret i64 %8"
+    );
+}
+
+#[test]
+fn keeps_the_results_of_loops() {
+    for (level, program) in programs(SOURCE) {
+        assert_eq!(program.call("full", &[]).0, 3, "at {level:?}");
+
+        for n in 0..=11 {
+            // The body runs once before the first check
+            let iterations = n.max(1);
+            let sum = (0..iterations).sum::<i64>();
+            let scaled = (0..iterations).map(|i| i * 8 + 5).sum::<i64>();
+
+            assert_eq!(
+                program.call("partial", &[n]).0,
+                sum,
+                "partial({n}) at {level:?}"
+            );
+            assert_eq!(
+                program.call("scale", &[n]).0,
+                scaled,
+                "scale({n}) at {level:?}"
+            );
+        }
+    }
+}
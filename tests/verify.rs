@@ -0,0 +1,188 @@
+mod common;
+
+use common::parse;
+use tinity::ir::verify::{verify_module, VerifyErrorKind};
+
+fn check(source: &str) -> Result<(), VerifyErrorKind> {
+    verify_module(&parse(source)).map_err(|error| error.kind)
+}
+
+#[test]
+fn accepts_valid_functions() {
+    let source = "
+define i64 @max(i64 %a, i64 %b) {
+entry:
+    %c = icmp sgt i64 %a, %b
+    br i1 %c, label %left, label %join
+left:
+    br label %join
+join:
+    %r = phi i64 [ %a, %left ], [ %b, %entry ]
+    ret i64 %r
+}
+
+define i64 @caller(i64 %x) {
+entry:
+    %r = call i64 @max(i64 %x, i64 1)
+    ret i64 %r
+}
+";
+
+    assert!(check(source).is_ok());
+}
+
+#[test]
+fn rejects_mismatched_types() {
+    let source = "
+define i64 @f(i64 %a, i32 %b) {
+entry:
+    %r = add i64 %a, %b
+    ret i64 %r
+}
+";
+
+    assert!(matches!(
+        check(source),
+        Err(VerifyErrorKind::MismatchedTypes { .. })
+    ));
+}
+
+#[test]
+fn rejects_mismatched_return_type() {
+    let source = "
+define i64 @f(i32 %a) {
+entry:
+    ret i32 %a
+}
+";
+
+    assert!(matches!(
+        check(source),
+        Err(VerifyErrorKind::MismatchedTypes { .. })
+    ));
+}
+
+#[test]
+fn rejects_uses_not_dominated() {
+    let source = "
+define i64 @f(i64 %a) {
+entry:
+    %c = icmp eq i64 %a, 0
+    br i1 %c, label %left, label %right
+left:
+    %x = add i64 %a, 1
+    br label %right
+right:
+    ret i64 %x
+}
+";
+
+    assert!(matches!(
+        check(source),
+        Err(VerifyErrorKind::NotDominated(_))
+    ));
+}
+
+#[test]
+fn accepts_phi_operands_dominating_the_edge() {
+    let source = "
+define i64 @f(i64 %a) {
+entry:
+    %c = icmp eq i64 %a, 0
+    br i1 %c, label %left, label %right
+left:
+    %x = add i64 %a, 1
+    br label %right
+right:
+    %r = phi i64 [ %x, %left ], [ %a, %entry ]
+    ret i64 %r
+}
+";
+
+    assert!(check(source).is_ok());
+}
+
+#[test]
+fn rejects_use_before_definition() {
+    let source = "
+define i64 @f(i64 %a) {
+entry:
+    %y = add i64 %x, 1
+    %x = add i64 %a, 1
+    ret i64 %y
+}
+";
+
+    assert!(matches!(
+        check(source),
+        Err(VerifyErrorKind::NotDominated(_))
+    ));
+}
+
+#[test]
+fn rejects_missing_terminator() {
+    let source = "
+define i64 @f(i64 %a) {
+entry:
+    %x = add i64 %a, 1
+}
+";
+
+    assert!(matches!(
+        check(source),
+        Err(VerifyErrorKind::MissingTerminator(_))
+    ));
+}
+
+#[test]
+fn rejects_misplaced_terminator() {
+    let source = "
+define i64 @f(i64 %a) {
+entry:
+    ret i64 %a
+    %x = add i64 %a, 1
+    ret i64 %x
+}
+";
+
+    assert!(matches!(
+        check(source),
+        Err(VerifyErrorKind::MisplacedTerminator(_))
+    ));
+}
+
+#[test]
+fn rejects_branch_to_the_entry() {
+    let source = "
+define i64 @f(i64 %a) {
+entry:
+    br label %entry
+}
+";
+
+    assert!(matches!(
+        check(source),
+        Err(VerifyErrorKind::EntryHasPredecessors)
+    ));
+}
+
+#[test]
+fn rejects_phi_without_all_predecessors() {
+    let source = "
+define i64 @f(i64 %a) {
+entry:
+    %c = icmp eq i64 %a, 0
+    br i1 %c, label %left, label %join
+left:
+    br label %join
+join:
+    %r = phi i64 [ %a, %left ]
+    ret i64 %r
+}
+";
+
+    assert!(matches!(
+        check(source),
+        Err(VerifyErrorKind::PhiPredecessors(_))
+    ));
+}
@@ -0,0 +1,220 @@
+mod common;
+
+use common::programs;
+
+const SOURCE: &str = "
+define i128 @mul(i128 %a, i128 %b) {
+entry:
+    %r = mul i128 %a, %b
+    ret i128 %r
+}
+
+define i128 @udiv(i128 %a, i128 %b) {
+entry:
+    %r = udiv i128 %a, %b
+    ret i128 %r
+}
+
+define i128 @urem(i128 %a, i128 %b) {
+entry:
+    %r = urem i128 %a, %b
+    ret i128 %r
+}
+
+define i128 @sdiv(i128 %a, i128 %b) {
+entry:
+    %r = sdiv i128 %a, %b
+    ret i128 %r
+}
+
+define i128 @srem(i128 %a, i128 %b) {
+entry:
+    %r = srem i128 %a, %b
+    ret i128 %r
+}
+
+define i128 @shl(i128 %a, i128 %n) {
+entry:
+    %r = shl i128 %a, %n
+    ret i128 %r
+}
+
+define i128 @lshr(i128 %a, i128 %n) {
+entry:
+    %r = lshr i128 %a, %n
+    ret i128 %r
+}
+
+define i128 @ashr(i128 %a, i128 %n) {
+entry:
+    %r = ashr i128 %a, %n
+    ret i128 %r
+}
+
+define i128 @callee(i64 %x, i128 %a, i128 %b) {
+entry:
+    %d = sub i128 %a, %b
+    %e = sext i64 %x to i128
+    %r = add i128 %d, %e
+    ret i128 %r
+}
+
+define i128 @caller(i128 %a, i128 %b, i64 %x) {
+entry:
+    %r = call i128 @callee(i64 %x, i128 %a, i128 %b)
+    %s = add i128 %r, 1
+    ret i128 %s
+}
+
+define i128 @sext96(i128 %a) {
+entry:
+    %t = trunc i128 %a to i96
+    %u = add i96 %t, 1
+    %r = sext i96 %u to i128
+    ret i128 %r
+}
+
+define i128 @zext96(i128 %a) {
+entry:
+    %t = trunc i128 %a to u96
+    %u = add u96 %t, 1
+    %r = zext u96 %u to i128
+    ret i128 %r
+}
+
+define u96 @pick(i64 %x) {
+entry:
+    %c = icmp eq i64 %x, 0
+    br i1 %c, label %zero, label %other
+zero:
+    br label %exit
+other:
+    br label %exit
+exit:
+    %p = phi u96 [ 79228162514264337593543950335, %zero ], [ 39614081257132168796771975173, %other ]
+    ret u96 %p
+}
+";
+
+const VALUES: [i128; 9] = [
+    5,
+    1 << 64,
+    -3,
+    0x1_2345_6789 << 64 | 0xFFFF_FFFF_F89A_BCDF,
+    i128::MIN,
+    i128::MAX,
+    u64::MAX as i128,
+    -(1 << 64) + 1,
+    0xFFFF_FFFF_FFFF_FFFF_FFFF_FFFF,
+];
+
+fn split(value: i128) -> [i64; 2] {
+    [value as i64, (value >> 64) as i64]
+}
+
+fn join((lo, hi): (i64, i64)) -> i128 {
+    (hi as i128) << 64 | lo as u64 as i128
+}
+
+fn check(name: &str, expected: impl Fn(i128, i128) -> Option<i128>, rhs: &[i128]) {
+    for (level, program) in programs(SOURCE) {
+        for &a in &VALUES {
+            for &b in rhs {
+                let Some(expected) = expected(a, b) else {
+                    continue;
+                };
+
+                let [a0, a1] = split(a);
+                let [a2, a3] = split(b);
+                let got = join(program.call(name, &[a0, a1, a2, a3]));
+
+                assert_eq!(got, expected, "{name}({a}, {b}) at {level:?}");
+            }
+        }
+    }
+}
+
+#[test]
+fn multiplies() {
+    check("mul", |a, b| Some(a.wrapping_mul(b)), &VALUES);
+}
+
+#[test]
+fn divides_unsigned() {
+    let udiv = |a: i128, b: i128| (a as u128).checked_div(b as u128).map(|r| r as i128);
+    let urem = |a: i128, b: i128| (a as u128).checked_rem(b as u128).map(|r| r as i128);
+
+    check("udiv", udiv, &VALUES);
+    check("urem", urem, &VALUES);
+}
+
+#[test]
+fn divides_signed() {
+    let rhs = [3, -3, -7, 1 << 70, -(1 << 70), i128::MIN, -1];
+
+    check("sdiv", |a, b| a.checked_div(b), &rhs);
+    check("srem", |a, b| a.checked_rem(b), &rhs);
+}
+
+#[test]
+fn shifts_across_the_halves() {
+    let amounts = [0, 1, 63, 64, 65, 100, 127];
+
+    check("shl", |a, n| Some(a << n), &amounts);
+    check("lshr", |a, n| Some((a as u128 >> n) as i128), &amounts);
+    check("ashr", |a, n| Some(a >> n), &amounts);
+}
+
+#[test]
+fn passes_pairs_in_registers() {
+    for (level, program) in programs(SOURCE) {
+        for (a, b) in VALUES.iter().zip(VALUES.iter().rev()) {
+            for x in [-5, 7] {
+                let [a0, a1] = split(*a);
+                let [a2, a3] = split(*b);
+                let got = join(program.call("caller", &[a0, a1, a2, a3, x]));
+                let expected = a.wrapping_sub(*b).wrapping_add(x as i128).wrapping_add(1);
+
+                assert_eq!(got, expected, "caller({a}, {b}, {x}) at {level:?}");
+            }
+        }
+    }
+}
+
+#[test]
+fn extends_96_bit_values() {
+    for (level, program) in programs(SOURCE) {
+        for a in VALUES {
+            let low = a.wrapping_add(1) & ((1 << 96) - 1);
+            let signed = low << 32 >> 32;
+
+            let [a0, a1] = split(a);
+            assert_eq!(
+                join(program.call("zext96", &[a0, a1])),
+                low,
+                "zext96({a}) at {level:?}"
+            );
+            assert_eq!(
+                join(program.call("sext96", &[a0, a1])),
+                signed,
+                "sext96({a}) at {level:?}"
+            );
+        }
+    }
+}
+
+#[test]
+fn moves_unsigned_constants_into_phis() {
+    for (level, program) in programs(SOURCE) {
+        assert_eq!(
+            join(program.call("pick", &[0])),
+            (1 << 96) - 1,
+            "pick(0) at {level:?}"
+        );
+        assert_eq!(
+            join(program.call("pick", &[3])),
+            (1 << 95) + 5,
+            "pick(3) at {level:?}"
+        );
+    }
+}